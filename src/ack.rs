@@ -0,0 +1,107 @@
+use crate::alerts::Condition;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// An acknowledgment that someone is dealing with an ongoing condition of a client, which pauses its reminders until
+/// the condition ends or the acknowledgment expires.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ack
+{
+	/// The name of the client, as `group/label`.
+	pub client: String,
+	pub condition: Condition,
+	/// Who acknowledged the condition.
+	pub by: String,
+	/// When the condition was acknowledged, as a Unix timestamp.
+	pub acked_at: i64,
+	/// When the acknowledgment expires, as a Unix timestamp, or `None` if it lasts until the condition ends.
+	pub expires_at: Option<i64>,
+}
+
+impl Ack
+{
+	/// Creates an acknowledgment made now, which expires after `expires_after` seconds if that is given.
+	pub fn new(client: String, condition: Condition, by: String, expires_after: Option<u64>) -> Self
+	{
+		let acked_at = Utc::now().timestamp();
+		Self { client, condition, by, acked_at, expires_at: expires_after.map(|seconds| acked_at + seconds as i64) }
+	}
+
+	/// Returns whether the acknowledgment has expired at a Unix timestamp.
+	pub fn is_expired(&self, now: i64) -> bool
+	{
+		self.expires_at.is_some_and(|expires_at| now >= expires_at)
+	}
+}
+
+/// Loads the acknowledgments from a JSON acks file. A missing file is treated as having none.
+pub fn load_acks(path: &Path) -> Result<Vec<Ack>, Box<dyn Error>>
+{
+	if !path.exists()
+	{
+		return Ok(Vec::new());
+	}
+
+	let contents = fs::read_to_string(path)?;
+	serde_json::from_str(&contents).map_err(|e| format!("Failed to parse acks file {}: {}", path.display(), e).into())
+}
+
+/// Adds an acknowledgment to an acks file, replacing any earlier one of the same condition of the same client and
+/// dropping those that have expired. The file is replaced in one step, so the monitor never reads half of it.
+pub fn acknowledge(path: &Path, ack: Ack) -> Result<(), Box<dyn Error>>
+{
+	let now = Utc::now().timestamp();
+	let mut acks = load_acks(path)?;
+	acks.retain(|existing| !(existing.is_expired(now) || (existing.client == ack.client && existing.condition == ack.condition)));
+	acks.push(ack);
+
+	let temporary = path.with_extension("tmp");
+	fs::write(&temporary, serde_json::to_string_pretty(&acks)?)?;
+	fs::rename(&temporary, path)?;
+
+	Ok(())
+}
+
+/// Keeps the acknowledgments in an acks file loaded, reloading them whenever the file changes.
+#[derive(Debug, Default)]
+pub struct AckWatcher
+{
+	path: PathBuf,
+	modified: Option<SystemTime>,
+	acks: Vec<Ack>,
+}
+
+impl AckWatcher
+{
+	/// Reloads the acknowledgments if the file, or which file is used, has changed since they were last loaded. A file
+	/// that cannot be read keeps the acknowledgments that were loaded before.
+	pub fn refresh(&mut self, path: &Path)
+	{
+		let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+		if path == self.path && modified == self.modified
+		{
+			return;
+		}
+		self.path = path.to_path_buf();
+		self.modified = modified;
+
+		match load_acks(path)
+		{
+			Ok(acks) => self.acks = acks,
+			Err(e) => tracing::error!("{}", e),
+		}
+	}
+
+	/// Returns the acknowledgment of a condition of a client that was made since the condition started, at a Unix
+	/// timestamp, and has not expired.
+	pub fn find(&self, client: &str, condition: Condition, started_at: i64) -> Option<&Ack>
+	{
+		let now = Utc::now().timestamp();
+		self.acks.iter().find(|ack| ack.client == client && ack.condition == condition && ack.acked_at >= started_at && !ack.is_expired(now))
+	}
+}
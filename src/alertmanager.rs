@@ -0,0 +1,357 @@
+use crate::alerts::{self, Alert, Change, Condition, Sink};
+use crate::events::{MonitorEvent, Severity};
+use crate::matrix;
+use crate::retry::RetryPolicy;
+use chrono::{DateTime, Local};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
+
+/// How long Alertmanager can take to accept alerts before the attempt fails.
+const ALERTMANAGER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the alerts that are firing are sent again. Alertmanager resolves alerts that are not sent again within its
+/// `resolve_timeout`, which is 5 minutes unless it is configured otherwise.
+const ALERTMANAGER_RESEND_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many minutes alerts about events that do not start a condition, such as summaries, fire before they resolve.
+const ONE_OFF_ALERT_MINUTES: i64 = 5;
+
+/// How alerts are forwarded to Prometheus Alertmanager.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertmanagerConfig
+{
+	/// The URL of the Alertmanager, such as `http://localhost:9093`.
+	pub url: Option<String>,
+	/// The username sent with basic authentication, when the Alertmanager is behind a proxy that needs it.
+	pub username: Option<String>,
+	pub password: Option<String>,
+	/// Labels added to every alert, such as `env = "mainnet"`, which Alertmanager can route, group, and silence by.
+	#[serde(default)]
+	pub labels: HashMap<String, String>,
+	/// The link Alertmanager shows with each alert, such as the status page of the monitor.
+	pub generator_url: Option<String>,
+	/// The least severe alerts that are sent. Conditions that were sent are still resolved when they end.
+	#[serde(default)]
+	pub min_severity: Severity,
+}
+
+/// The alerts that are firing on Alertmanager, keyed by what they are about. It is kept apart from the sink, so the
+/// alerts keep being sent again, and are resolved when their conditions end, after the sink is restarted by a reload.
+#[derive(Clone, Debug, Default)]
+pub struct FiringAlerts(Arc<Mutex<HashMap<(String, String), Value>>>);
+
+/// Forwards alerts to Alertmanager in the form of its v2 API, so its silences, inhibitions, and routing tree apply to
+/// them. Each condition of a client fires as its own alert until the condition ends, and is resolved then. The task is
+/// stopped when this is dropped.
+pub struct AlertmanagerSink
+{
+	task: JoinHandle<()>,
+}
+
+impl AlertmanagerSink
+{
+	/// Starts forwarding the alerts received from `alerts`, retrying failed deliveries according to the retry policy.
+	/// There is no rate limit, since Alertmanager groups and throttles the notifications it sends. The alerts that are
+	/// firing are kept in `firing`, along with any that were firing before the sink was restarted.
+	pub fn spawn(config: AlertmanagerConfig, retry: RetryPolicy, firing: FiringAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		let alerts_url = match &config.url
+		{
+			Some(url) => alerts_url(url)?,
+			None => return Err("Alertmanager needs a URL.".into()),
+		};
+		let credentials = config.username.clone().map(|username| (username, config.password.clone()));
+		let client = reqwest::Client::builder().timeout(ALERTMANAGER_TIMEOUT).build()?;
+
+		let task = tokio::spawn(async move
+		{
+			let mut ticker = time::interval(ALERTMANAGER_RESEND_INTERVAL);
+			ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = ticker.tick() =>
+					{
+						let payload = firing.0.lock().unwrap().values().cloned().collect::<Vec<_>>();
+						if !payload.is_empty()
+						{
+							tokio::spawn(deliver(client.clone(), alerts_url.clone(), credentials.clone(), payload, retry.clone()));
+						}
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to Alertmanager because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+
+				let payload = update(&mut firing.0.lock().unwrap(), &alert, &config);
+				if !payload.is_empty()
+				{
+					tokio::spawn(deliver(client.clone(), alerts_url.clone(), credentials.clone(), payload, retry.clone()));
+				}
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for AlertmanagerSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the URL of the v2 API that alerts are posted to on an Alertmanager.
+pub fn alerts_url(url: &str) -> Result<Url, String>
+{
+	let mut alerts_url = Url::parse(url).map_err(|e| format!("Invalid Alertmanager URL {}: {}", url, e))?;
+	alerts_url.path_segments_mut()
+		.map_err(|_| format!("Invalid Alertmanager URL {}.", url))?
+		.pop_if_empty()
+		.extend(["api", "v2", "alerts"]);
+	Ok(alerts_url)
+}
+
+/// Returns whether a name is a valid Prometheus label name that is not reserved, such as `env`.
+pub fn is_label_name(name: &str) -> bool
+{
+	let mut chars = name.chars();
+	chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+		&& !name.starts_with("__")
+}
+
+/// Records the conditions an alert starts and ends among those firing, keyed by client and condition, by `fleet` and
+/// the name of a fleet condition, by `chain` and the group whose chain has halted, or by `parent` and the incident of a
+/// parent that is down, and returns the alerts to send for it: those it starts, and those it ends marked as resolved.
+/// Events that do not change a condition, such as summaries, are sent as alerts that resolve on their own. Reminders
+/// are not sent, since the alerts that are firing are sent again anyway.
+fn update(firing: &mut HashMap<(String, String), Value>, alert: &Alert, config: &AlertmanagerConfig) -> Vec<Value>
+{
+	let now = Local::now();
+	let send = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Alertmanager);
+	match &alert.event
+	{
+		MonitorEvent::FleetDegraded { condition, .. } if send =>
+		{
+			let started = payload(alert, None, now, None, config);
+			firing.insert(("fleet".to_string(), condition.clone()), started.clone());
+			return vec![started];
+		},
+		MonitorEvent::FleetDegraded { .. } => return Vec::new(),
+		MonitorEvent::FleetRecovered { condition, .. } => return firing.remove(&("fleet".to_string(), condition.clone())).map(|ended| resolved(ended, now)).into_iter().collect(),
+		MonitorEvent::ChainHalted { group, .. } if send =>
+		{
+			let started = payload(alert, None, now, None, config);
+			firing.insert(("chain".to_string(), group.clone()), started.clone());
+			return vec![started];
+		},
+		MonitorEvent::ChainHalted { .. } => return Vec::new(),
+		MonitorEvent::ChainResumed { group, .. } => return firing.remove(&("chain".to_string(), group.clone())).map(|ended| resolved(ended, now)).into_iter().collect(),
+		MonitorEvent::ParentDown { parent, .. } if send =>
+		{
+			let started = payload(alert, None, now, None, config);
+			firing.insert(("parent".to_string(), parent.incident()), started.clone());
+			return vec![started];
+		},
+		MonitorEvent::ParentDown { .. } => return Vec::new(),
+		MonitorEvent::ParentRecovered { parent, .. } => return firing.remove(&("parent".to_string(), parent.incident())).map(|ended| resolved(ended, now)).into_iter().collect(),
+		_ => {},
+	}
+
+	let changes = changes(&alert.event);
+	if changes.is_empty()
+	{
+		if !send || matches!(alert.event, MonitorEvent::StillOngoing { .. } | MonitorEvent::AlertsAggregated { .. })
+		{
+			return Vec::new();
+		}
+		return vec![payload(alert, None, now, Some(now + chrono::Duration::minutes(ONE_OFF_ALERT_MINUTES)), config)];
+	}
+
+	// Escalations start the condition again with its new severity, as of when it first started.
+	let starts_at = match &alert.event
+	{
+		MonitorEvent::Escalated { minutes, .. } => now - chrono::Duration::minutes(*minutes as i64),
+		_ => now,
+	};
+
+	let mut sent = Vec::new();
+	for (client, change) in changes
+	{
+		match change
+		{
+			Change::Start(condition) if send =>
+			{
+				let started = payload(alert, Some((&client, condition)), starts_at, None, config);
+				// An alert whose labels change, such as its severity, is a different alert to Alertmanager, so the one it
+				// replaces is resolved.
+				if let Some(replaced) = firing.insert((client, condition.name().to_string()), started.clone()).filter(|replaced| replaced["labels"] != started["labels"])
+				{
+					sent.push(resolved(replaced, now));
+				}
+				sent.push(started);
+			},
+			Change::Start(_) => {},
+			Change::End(condition) =>
+			{
+				if let Some(ended) = firing.remove(&(client, condition.name().to_string()))
+				{
+					sent.push(resolved(ended, now));
+				}
+			},
+		}
+	}
+	sent
+}
+
+/// Returns an alert that was firing, marked as resolved now.
+fn resolved(mut alert: Value, now: DateTime<Local>) -> Value
+{
+	alert["endsAt"] = json!(now.to_rfc3339());
+	alert
+}
+
+/// Returns each client an event is about with a condition it starts or ends, including every client of an aggregated
+/// alert. A client that starts flapping ends its health conditions, since changes in its health are not reported while
+/// it flaps.
+fn changes(event: &MonitorEvent) -> Vec<(String, Change)>
+{
+	let (clients, mut changes) = match event
+	{
+		MonitorEvent::Escalated { client, condition, .. } => (vec![client.clone()], vec![Change::Start(*condition)]),
+		MonitorEvent::AlertsAggregated { event_type, clients, .. } => (clients.clone(), aggregated_changes(event_type)),
+		_ => match alerts::changes(event)
+		{
+			Some((client, changes)) => (vec![client.to_string()], changes),
+			None => return Vec::new(),
+		},
+	};
+
+	if changes.iter().any(|change| matches!(change, Change::Start(Condition::Flapping)))
+	{
+		changes.extend([Change::End(Condition::Offline), Change::End(Condition::Degraded)]);
+	}
+	clients.into_iter().flat_map(|client| changes.iter().map(move |change| (client.clone(), *change))).collect()
+}
+
+/// Returns the conditions that the events collapsed into an aggregated alert of a type start and end. The health a
+/// client had before is not kept, so a change in health ends whichever other health condition it had.
+fn aggregated_changes(event_type: &str) -> Vec<Change>
+{
+	match event_type
+	{
+		"offline" => vec![Change::End(Condition::Degraded), Change::Start(Condition::Offline)],
+		"degraded" => vec![Change::End(Condition::Offline), Change::Start(Condition::Degraded)],
+		"online" | "recovered" => vec![Change::End(Condition::Offline), Change::End(Condition::Degraded)],
+		"flapping" => vec![Change::Start(Condition::Flapping)],
+		"stopped_flapping" => vec![Change::End(Condition::Flapping)],
+		"lagging" => vec![Change::Start(Condition::Lagging)],
+		"caught_up" => vec![Change::End(Condition::Lagging)],
+		"stalled" => vec![Change::Start(Condition::Stalled)],
+		"sync_resumed" => vec![Change::End(Condition::Stalled)],
+		"tip_frozen" => vec![Change::Start(Condition::Frozen)],
+		"tip_unfrozen" => vec![Change::End(Condition::Frozen)],
+		"low_peers" => vec![Change::Start(Condition::LowPeers)],
+		"peers_restored" => vec![Change::End(Condition::LowPeers)],
+		_ => Vec::new(),
+	}
+}
+
+/// Returns an alert in the form the Alertmanager API takes, about a condition of a client, or about the event itself
+/// when it does not start one. It is named `ckb_` and the condition or event type, such as `ckb_offline`.
+fn payload(alert: &Alert, condition: Option<(&str, Condition)>, starts_at: DateTime<Local>, ends_at: Option<DateTime<Local>>, config: &AlertmanagerConfig) -> Value
+{
+	let mut labels = config.labels.iter().map(|(name, value)| (name.clone(), json!(value))).collect::<Map<String, Value>>();
+	let (name, client, summary) = match condition
+	{
+		Some((client, condition)) => (condition.name(), Some(client), format!("{} {}", client, condition)),
+		None =>
+		{
+			let event_type = alerts::event_type(&alert.event).unwrap_or_default();
+			(event_type, alerts::client(&alert.event), alert.title().unwrap_or_else(|| event_type.to_string()))
+		},
+	};
+	labels.insert("alertname".to_string(), json!(format!("ckb_{}", name)));
+	labels.insert("severity".to_string(), json!(alert.severity.to_string()));
+	if let MonitorEvent::FleetDegraded { condition, .. } = &alert.event
+	{
+		labels.insert("condition".to_string(), json!(condition));
+	}
+	if let Some(client) = client
+	{
+		labels.insert("client".to_string(), json!(client));
+	}
+	if let Some(group) = client.and_then(|client| client.split_once('/')).map(|(group, _)| group).or_else(|| alerts::group(&alert.event))
+	{
+		labels.insert("group".to_string(), json!(group));
+	}
+
+	let description = alert.text.clone().or_else(|| matrix::message(&alert.event).map(|(body, _)| body)).unwrap_or_else(|| summary.clone());
+	let mut payload = json!(
+	{
+		"labels": labels,
+		"annotations": { "summary": summary, "description": description },
+		"startsAt": starts_at.to_rfc3339(),
+	});
+	if let Some(ends_at) = ends_at
+	{
+		payload["endsAt"] = json!(ends_at.to_rfc3339());
+	}
+	if let Some(generator_url) = &config.generator_url
+	{
+		payload["generatorURL"] = json!(generator_url);
+	}
+	payload
+}
+
+/// Posts alerts to Alertmanager, retrying transport failures and error statuses according to the retry policy.
+async fn deliver(client: reqwest::Client, alerts_url: Url, credentials: Option<(String, Option<String>)>, payload: Vec<Value>, retry: RetryPolicy)
+{
+	let mut attempt = 0;
+	loop
+	{
+		let mut request = client.post(alerts_url.clone()).json(&payload);
+		if let Some((username, password)) = &credentials
+		{
+			request = request.basic_auth(username, password.as_deref());
+		}
+
+		let error = match request.send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send {} alerts to Alertmanager: {}", payload.len(), error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending alerts to Alertmanager failed: {}. Retrying in {} ms.", error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
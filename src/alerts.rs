@@ -0,0 +1,1046 @@
+use crate::ack::AckWatcher;
+use crate::dependency::Parent;
+use crate::events::{MonitorEvent, Severity};
+use crate::health::Health;
+use crate::monitor::FleetSnapshot;
+use crate::template::Templates;
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+/// How often ongoing conditions are checked for reminders that are due.
+const REMINDER_TICK: Duration = Duration::from_secs(15);
+
+/// The most clients named in the text of an aggregated alert. The rest are counted.
+const MAX_AGGREGATED_CLIENTS: usize = 10;
+
+/// The types of events that are a client recovering from a condition.
+const RECOVERY_EVENT_TYPES: [&str; 10] = ["online", "recovered", "stopped_flapping", "caught_up", "sync_resumed", "tip_unfrozen", "peers_restored", "fleet_recovered", "chain_resumed", "parent_recovered"];
+
+/// The types of events that alert routes can match.
+pub const EVENT_TYPES: [&str; 26] = ["offline", "online", "degraded", "recovered", "flapping", "stopped_flapping", "lagging", "caught_up", "stalled", "sync_resumed", "tip_frozen", "tip_unfrozen", "low_peers", "peers_restored", "still_ongoing", "escalated", "aggregated", "outage_summary", "group_summary", "digest", "fleet_degraded", "fleet_recovered", "chain_halted", "chain_resumed", "parent_down", "parent_recovered"];
+
+/// The types of events that start a condition, whose severity rules can wait for it to last.
+pub const CONDITION_EVENT_TYPES: [&str; 7] = ["offline", "degraded", "flapping", "lagging", "stalled", "tip_frozen", "low_peers"];
+
+/// The types of events with a value that the thresholds of severity rules are compared with.
+pub const MEASURED_EVENT_TYPES: [&str; 3] = ["lagging", "stalled", "low_peers"];
+
+/// What alerts must share to be collapsed into one: their event type, the host of their clients, and their
+/// destinations.
+type AggregationKey = (&'static str, Option<String>, Vec<Sink>);
+
+/// A problem with a client that alerts are sent about when it starts and when it ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Condition
+{
+	Offline,
+	Degraded,
+	Lagging,
+	Stalled,
+	Frozen,
+	Flapping,
+	#[serde(rename = "low_peers")]
+	LowPeers,
+}
+
+impl Condition
+{
+	/// Returns the severity of the alert that the condition has started, when it does not escalate.
+	pub fn severity(&self) -> Severity
+	{
+		match self
+		{
+			Condition::Offline | Condition::Stalled | Condition::Flapping => Severity::Critical,
+			Condition::Degraded | Condition::Lagging | Condition::Frozen | Condition::LowPeers => Severity::Warning,
+		}
+	}
+
+	/// Returns the name of the condition as it is written in the config.
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Condition::Offline => "offline",
+			Condition::Degraded => "degraded",
+			Condition::Lagging => "lagging",
+			Condition::Stalled => "stalled",
+			Condition::Frozen => "frozen",
+			Condition::Flapping => "flapping",
+			Condition::LowPeers => "low_peers",
+		}
+	}
+}
+
+impl FromStr for Condition
+{
+	type Err = String;
+
+	fn from_str(condition: &str) -> Result<Self, Self::Err>
+	{
+		match condition
+		{
+			"offline" => Ok(Self::Offline),
+			"degraded" => Ok(Self::Degraded),
+			"lagging" => Ok(Self::Lagging),
+			"stalled" => Ok(Self::Stalled),
+			"frozen" => Ok(Self::Frozen),
+			"flapping" => Ok(Self::Flapping),
+			"low_peers" => Ok(Self::LowPeers),
+			_ => Err(format!("unknown condition: {}", condition)),
+		}
+	}
+}
+
+impl fmt::Display for Condition
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Condition::Offline => write!(f, "offline"),
+			Condition::Degraded => write!(f, "degraded"),
+			Condition::Lagging => write!(f, "lagging"),
+			Condition::Stalled => write!(f, "stalled"),
+			Condition::Frozen => write!(f, "frozen"),
+			Condition::Flapping => write!(f, "flapping"),
+			Condition::LowPeers => write!(f, "low on peers"),
+		}
+	}
+}
+
+/// A destination that alerts are sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sink
+{
+	Webhooks,
+	Slack,
+	Discord,
+	Telegram,
+	Matrix,
+	Exec,
+	Ntfy,
+	Pushover,
+	Gotify,
+	Alertmanager,
+	Desktop,
+}
+
+impl Sink
+{
+	/// Every destination, which alerts are sent to when no routes are configured.
+	pub const ALL: [Sink; 11] = [Sink::Webhooks, Sink::Slack, Sink::Discord, Sink::Telegram, Sink::Matrix, Sink::Exec, Sink::Ntfy, Sink::Pushover, Sink::Gotify, Sink::Alertmanager, Sink::Desktop];
+}
+
+impl fmt::Display for Sink
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Sink::Webhooks => write!(f, "webhooks"),
+			Sink::Slack => write!(f, "slack"),
+			Sink::Discord => write!(f, "discord"),
+			Sink::Telegram => write!(f, "telegram"),
+			Sink::Matrix => write!(f, "matrix"),
+			Sink::Exec => write!(f, "exec"),
+			Sink::Ntfy => write!(f, "ntfy"),
+			Sink::Pushover => write!(f, "pushover"),
+			Sink::Gotify => write!(f, "gotify"),
+			Sink::Alertmanager => write!(f, "alertmanager"),
+			Sink::Desktop => write!(f, "desktop"),
+		}
+	}
+}
+
+/// The most alerts each destination sends within a window of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit
+{
+	pub max_alerts: u32,
+	pub window: Duration,
+}
+
+/// Counts the alerts a destination sends against its rate limit, and those it holds back once the limit is reached, so
+/// a storm of alerts is summarized rather than flooding the destination.
+pub struct AlertLimiter
+{
+	limit: Option<RateLimit>,
+	sink: Sink,
+	/// When the current window started.
+	window_start: Instant,
+	/// The number of alerts sent in the current window.
+	sent: u32,
+	/// The number of alerts held back in the current window.
+	suppressed: usize,
+}
+
+impl AlertLimiter
+{
+	/// Creates a limiter for a destination, which lets every alert through if there is no limit.
+	pub fn new(sink: Sink, limit: Option<RateLimit>) -> Self
+	{
+		Self { limit, sink, window_start: Instant::now(), sent: 0, suppressed: 0 }
+	}
+
+	/// Returns whether another alert can be sent, counting it against the limit if it can and as held back if not. A
+	/// window in which alerts were held back lasts until its summary is sent.
+	pub fn allow(&mut self) -> bool
+	{
+		let limit = match self.limit
+		{
+			Some(limit) => limit,
+			None => return true,
+		};
+		let now = Instant::now();
+		if self.suppressed == 0 && now.duration_since(self.window_start) >= limit.window
+		{
+			self.window_start = now;
+			self.sent = 0;
+		}
+
+		if self.sent < limit.max_alerts
+		{
+			self.sent += 1;
+			return true;
+		}
+		if self.suppressed == 0
+		{
+			tracing::warn!("{} alerts were sent to {} within {} seconds, so the rest are held back until the window ends.", limit.max_alerts, self.sink, limit.window.as_secs());
+		}
+		self.suppressed += 1;
+		false
+	}
+
+	/// Waits until the end of a window in which alerts were held back, or forever if none were.
+	pub async fn summary_due(&self)
+	{
+		match self.limit
+		{
+			Some(limit) if self.suppressed > 0 => time::sleep_until(self.window_start + limit.window).await,
+			_ => std::future::pending().await,
+		}
+	}
+
+	/// Starts a new window, returning the number of alerts held back in the last.
+	pub fn end_window(&mut self) -> usize
+	{
+		let suppressed = self.suppressed;
+		self.window_start = Instant::now();
+		self.sent = 0;
+		self.suppressed = 0;
+		suppressed
+	}
+
+	/// Returns the text of the summary of the alerts held back in a window.
+	pub fn summary_text(&self, suppressed: usize) -> String
+	{
+		let (max_alerts, window) = self.limit.map_or((0, 0), |limit| (limit.max_alerts, limit.window.as_secs()));
+		let plural = if suppressed == 1 { " was" } else { "s were" };
+		format!("{} alert{} not sent because the limit of {} alerts in {} seconds was reached.", suppressed, plural, max_alerts, window)
+	}
+}
+
+/// A rule that sends the alerts it matches to a set of destinations.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRoute
+{
+	/// The group whose alerts match, or every group when omitted.
+	pub group: Option<String>,
+	/// The least severe alerts that match.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// The types of events that match, such as `offline` or `lagging`, or every type when empty.
+	#[serde(default)]
+	pub events: Vec<String>,
+	/// The destinations the matching alerts are sent to.
+	pub sinks: Vec<Sink>,
+}
+
+impl AlertRoute
+{
+	/// Returns whether an alert about an event of a group, with a severity, matches the route.
+	pub fn matches(&self, group: Option<&str>, severity: Severity, event_type: Option<&str>) -> bool
+	{
+		self.group.as_deref().is_none_or(|route_group| group == Some(route_group))
+			&& severity >= self.min_severity
+			&& (self.events.is_empty() || event_type.is_some_and(|event_type| self.events.iter().any(|route_event| route_event == event_type)))
+	}
+}
+
+/// A rule that sets the severity of the events of one type, such as `lagging`, that are past its thresholds. The
+/// thresholds are compared with the number of blocks behind of lag and stall events, and the number of peers of low peer
+/// events.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeverityRule
+{
+	/// The type of the events that match.
+	pub event: String,
+	/// The value that matching events must be above.
+	pub above: Option<u64>,
+	/// The value that matching events must be below.
+	pub below: Option<u64>,
+	/// How many seconds the condition an event starts must have lasted to match.
+	pub after: Option<u64>,
+	/// The severity of the events that match.
+	pub severity: Severity,
+}
+
+impl SeverityRule
+{
+	/// Returns whether an event of a type, with a value, about a condition that has lasted `lasted`, matches the rule.
+	pub fn matches(&self, event_type: &str, value: Option<u64>, lasted: Duration) -> bool
+	{
+		self.event == event_type
+			&& self.above.is_none_or(|above| value.is_some_and(|value| value > above))
+			&& self.below.is_none_or(|below| value.is_some_and(|value| value < below))
+			&& self.after.is_none_or(|after| lasted >= Duration::from_secs(after))
+	}
+}
+
+/// An event passed on to alert destinations, along with how urgent it is once escalation is taken into account.
+#[derive(Clone, Debug)]
+pub struct Alert
+{
+	pub event: MonitorEvent,
+	pub severity: Severity,
+	/// The destinations the alert is routed to.
+	pub sinks: Vec<Sink>,
+	/// The text of the alert rendered from the template of its event type, which replaces the text each destination
+	/// would otherwise write.
+	pub text: Option<String>,
+}
+
+impl Alert
+{
+	/// Returns whether the alert is routed to a destination.
+	pub fn is_routed_to(&self, sink: Sink) -> bool
+	{
+		self.sinks.contains(&sink)
+	}
+
+	/// Returns whether the alert is about clients recovering from a condition.
+	pub fn is_recovery(&self) -> bool
+	{
+		is_recovery(&self.event)
+	}
+
+	/// Returns the title of the alert for destinations that show one above its text, such as push notifications: the
+	/// client or group it is about and the type of event, or `None` for events that are not alerted.
+	pub fn title(&self) -> Option<String>
+	{
+		let event_type = event_type(&self.event)?.replace('_', " ");
+		// Fleet conditions are named after themselves rather than the clients they are over.
+		if let MonitorEvent::FleetDegraded { condition, .. } | MonitorEvent::FleetRecovered { condition, .. } = &self.event
+		{
+			return Some(format!("{} {}", condition, event_type));
+		}
+		if let MonitorEvent::ParentDown { parent, .. } | MonitorEvent::ParentRecovered { parent, .. } = &self.event
+		{
+			return Some(format!("{} {}", parent.incident(), if self.is_recovery() { "recovered" } else { "down" }));
+		}
+		match client(&self.event).or_else(|| group(&self.event))
+		{
+			Some(subject) => Some(format!("{} {}", subject, event_type)),
+			None => Some(event_type),
+		}
+	}
+}
+
+/// How repeated alerts about the same condition of a client are held back, and when lasting conditions escalate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AlertSettings
+{
+	/// How long after a condition of a client is alerted that it starting again is held back, along with it ending.
+	pub cooldown: Duration,
+	/// How often a reminder is sent while a condition lasts, or `None` to send no reminders.
+	pub renotify_interval: Option<Duration>,
+	/// How long each condition lasts before it escalates to critical. The conditions listed are alerted as warnings
+	/// until then.
+	pub escalate_after: HashMap<Condition, Duration>,
+	/// The routes that decide which destinations each alert is sent to, of which the first that matches is used.
+	/// Every alert is sent to every destination when empty.
+	pub routes: Vec<AlertRoute>,
+	/// The handlebars templates of the text of alerts, keyed by event type.
+	pub templates: HashMap<String, String>,
+	/// How long alerts about clients are held to collapse those of the same type on the same host into one, or `None`
+	/// to send each at once.
+	pub aggregation_window: Option<Duration>,
+	/// How long alerts that a condition of a client with a parent started are held when they are not aggregated, so
+	/// they can be dropped if the parent turns out to be down, or `None` to send them at once.
+	pub dependent_grace: Option<Duration>,
+	/// The rules that set the severity of events past thresholds, of which the most severe that matches is used.
+	pub severity_rules: Vec<SeverityRule>,
+	/// The file that acknowledgments of ongoing conditions, which pause their reminders, are read from.
+	pub acks_file: PathBuf,
+}
+
+/// Whether an event starts or ends a condition.
+#[derive(Clone, Copy, Debug)]
+pub enum Change
+{
+	Start(Condition),
+	End(Condition),
+}
+
+impl Change
+{
+	/// Returns the condition that is started or ended.
+	pub fn condition(&self) -> Condition
+	{
+		match self
+		{
+			Change::Start(condition) | Change::End(condition) => *condition,
+		}
+	}
+}
+
+/// What is known about a condition of a client.
+#[derive(Clone, Copy, Debug)]
+struct ConditionState
+{
+	/// The type of the event that started the condition.
+	event_type: &'static str,
+	/// The latest value measured while the condition lasts, such as how many blocks behind a lagging client is.
+	value: Option<u64>,
+	/// The severity the condition is alerted with, which rises as it escalates.
+	severity: Severity,
+	/// When the condition started, or `None` once it has ended.
+	since: Option<Instant>,
+	/// When an alert that the condition started was last sent.
+	alerted: Option<Instant>,
+	/// Whether the alert that the condition started was held back, in which case the alert that it ended is too.
+	suppressed: bool,
+	/// Whether something the client depends on is down, which holds back reminders and escalations about the condition.
+	dependent: bool,
+	/// When an alert or reminder about the condition was last sent.
+	notified: Instant,
+	/// Whether the condition has been acknowledged since it started.
+	acked: bool,
+}
+
+/// Passes the events of the monitor on to alert destinations, holding back those that repeat a condition within the
+/// cooldown, and sending reminders and escalations about conditions that last. The task is stopped when this is
+/// dropped.
+pub struct AlertFilter
+{
+	task: JoinHandle<()>,
+	settings: watch::Sender<AlertSettings>,
+}
+
+impl AlertFilter
+{
+	/// Starts passing the events sent on `events` on to `alerts`. Reminders and escalations are sent on `events`, so
+	/// they are logged along with every other event before being passed on. Templates are rendered with the latest
+	/// snapshot from `fleet`.
+	pub fn spawn(settings: AlertSettings, events: &broadcast::Sender<MonitorEvent>, alerts: broadcast::Sender<Alert>, fleet: watch::Receiver<Arc<FleetSnapshot>>) -> Self
+	{
+		let (settings, settings_receiver) = watch::channel(settings);
+		let mut receiver = events.subscribe();
+		let events = events.clone();
+
+		let task = tokio::spawn(async move
+		{
+			let mut conditions = HashMap::new();
+			let mut template_sources = settings_receiver.borrow().templates.clone();
+			let mut templates = compile(&template_sources);
+			let mut ticker = time::interval(REMINDER_TICK);
+			ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+			let mut pending: HashMap<AggregationKey, (Instant, Vec<Alert>)> = HashMap::new();
+			// The alerts about clients with a parent that are held until their grace period is over.
+			let mut held: Vec<(Instant, Alert)> = Vec::new();
+			let mut acks = AckWatcher::default();
+			// The clients that depend on each parent that is down.
+			let mut dependents: HashMap<Parent, Vec<String>> = HashMap::new();
+
+			loop
+			{
+				let deadline = pending.values().map(|(deadline, _)| *deadline).chain(held.iter().map(|(deadline, _)| *deadline)).min();
+				tokio::select!
+				{
+					result = receiver.recv() =>
+					{
+						let event = match result
+						{
+							Ok(event) => event,
+							Err(RecvError::Lagged(count)) =>
+							{
+								tracing::warn!("{} events were not alerted because alerting fell behind.", count);
+								continue;
+							},
+							Err(RecvError::Closed) => break,
+						};
+
+						let settings = settings_receiver.borrow().clone();
+						if settings.templates != template_sources
+						{
+							templates = compile(&settings.templates);
+							template_sources = settings.templates.clone();
+						}
+						match &event
+						{
+							MonitorEvent::ParentDown { parent, clients } =>
+							{
+								suppress_dependents(&mut conditions, &mut pending, &mut held, clients);
+								dependents.insert(parent.clone(), clients.clone());
+							},
+							MonitorEvent::ParentRecovered { parent, .. } =>
+							{
+								dependents.remove(parent);
+								for ((client, _), state) in conditions.iter_mut()
+								{
+									state.dependent &= dependents.values().any(|clients| clients.contains(client));
+								}
+							},
+							_ => {},
+						}
+						let dependent = client(&event).is_some_and(|client| dependents.values().any(|clients| clients.iter().any(|dependent| dependent == client)));
+						if let Some(severity) = record(&mut conditions, &event, &settings, dependent, Instant::now())
+						{
+							let sinks = route(&settings.routes, &event, severity);
+							if !sinks.is_empty()
+							{
+								let fleet = fleet.borrow().clone();
+								let text = templates.render(&event, severity, &fleet);
+								let alert = Alert { event, severity, sinks, text };
+								let is_held = held.iter().any(|(_, waiting)| client(&waiting.event) == client(&alert.event));
+								match (settings.aggregation_window, settings.dependent_grace, aggregation_key(&alert, &fleet))
+								{
+									(Some(window), _, Some(key)) => pending.entry(key).or_insert_with(|| (Instant::now() + window, Vec::new())).1.push(alert),
+									(None, Some(grace), _) if is_held || starts_for_dependent(&alert, &fleet) =>
+									{
+										// Later alerts about a client wait behind those held for it, so they are sent in order.
+										let deadline = held.iter().filter(|(_, waiting)| client(&waiting.event) == client(&alert.event)).map(|(deadline, _)| *deadline).max();
+										held.push((deadline.unwrap_or_else(|| Instant::now() + grace), alert));
+									},
+									_ =>
+									{
+										// There may be no alert destinations configured.
+										let _ = alerts.send(alert);
+									},
+								}
+							}
+						}
+					},
+					_ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() =>
+					{
+						let now = Instant::now();
+						let due = pending.iter().filter(|(_, (deadline, _))| *deadline <= now).map(|(key, _)| key.clone()).collect::<Vec<_>>();
+						let fleet = fleet.borrow().clone();
+						for key in due
+						{
+							if let Some((_, collected)) = pending.remove(&key)
+							{
+								let _ = alerts.send(aggregate(key, collected, &templates, &fleet));
+							}
+						}
+						for (_, alert) in held.extract_if(.., |(deadline, _)| *deadline <= now)
+						{
+							let _ = alerts.send(alert);
+						}
+					},
+					_ = ticker.tick() =>
+					{
+						let settings = settings_receiver.borrow().clone();
+						acks.refresh(&settings.acks_file);
+						for reminder in reminders(&mut conditions, &settings, &acks, Instant::now())
+						{
+							let _ = events.send(reminder);
+						}
+					},
+				}
+			}
+		});
+
+		Self { task, settings }
+	}
+
+	/// Replaces the settings, such as after the config is reloaded, keeping track of the conditions that are ongoing.
+	pub fn update(&self, settings: AlertSettings)
+	{
+		self.settings.send_replace(settings);
+	}
+}
+
+impl Drop for AlertFilter
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns how urgent an event is before escalation and severity rules are taken into account. Events that are not sent
+/// to any alert destination are info.
+pub fn severity(event: &MonitorEvent) -> Severity
+{
+	match event
+	{
+		MonitorEvent::HealthChanged { to: Health::Offline, .. } => Severity::Critical,
+		MonitorEvent::HealthChanged { to: Health::Degraded, .. } => Severity::Warning,
+		MonitorEvent::Flapping { .. } => Severity::Critical,
+		MonitorEvent::LagStarted { .. } => Severity::Warning,
+		MonitorEvent::SyncStalled { .. } => Severity::Critical,
+		MonitorEvent::TipFrozen { .. } => Severity::Warning,
+		MonitorEvent::StillOngoing { condition, .. } => condition.severity(),
+		MonitorEvent::Escalated { severity, .. } | MonitorEvent::FleetDegraded { severity, .. } => *severity,
+		MonitorEvent::LowPeersStarted { .. } => Severity::Warning,
+		MonitorEvent::GroupSummary { online, total, .. } if online < total => Severity::Warning,
+		MonitorEvent::ChainHalted { .. } => Severity::Critical,
+		MonitorEvent::ParentDown { .. } => Severity::Critical,
+		_ => Severity::Info,
+	}
+}
+
+/// Returns the severity of an event from the most severe of the rules for its type that it matches as it happens. Events
+/// of a type that has rules are info if they match none of them, and those of other types keep their usual severity.
+fn classify(event: &MonitorEvent, rules: &[SeverityRule]) -> Severity
+{
+	let event_type = match event_type(event)
+	{
+		Some(event_type) if rules.iter().any(|rule| rule.event == event_type) => event_type,
+		_ => return severity(event),
+	};
+	rules.iter().filter(|rule| rule.matches(event_type, value(event), Duration::ZERO)).map(|rule| rule.severity).max().unwrap_or(Severity::Info)
+}
+
+/// Returns the value that the thresholds of severity rules are compared with for an event that starts a condition:
+/// how many blocks behind a lagging or stalled client is, or how many peers a client low on peers has.
+fn value(event: &MonitorEvent) -> Option<u64>
+{
+	match event
+	{
+		MonitorEvent::LagStarted { blocks_behind, .. } | MonitorEvent::SyncStalled { blocks_behind, .. } => Some(*blocks_behind),
+		MonitorEvent::LowPeersStarted { peers, .. } => Some(u64::from(*peers)),
+		_ => None,
+	}
+}
+
+/// Returns the client, condition, and latest value of a report about a condition that is ongoing.
+fn measurement(event: &MonitorEvent) -> Option<(&str, Condition, u64)>
+{
+	match event
+	{
+		MonitorEvent::Lagging { client, blocks_behind, .. } => Some((client, Condition::Lagging, *blocks_behind)),
+		MonitorEvent::LowPeers { client, peers, .. } => Some((client, Condition::LowPeers, u64::from(*peers))),
+		_ => None,
+	}
+}
+
+/// Compiles the alert templates. They are checked when the config is validated, so if they cannot be compiled here
+/// alerts fall back to their usual text.
+fn compile(templates: &HashMap<String, String>) -> Templates
+{
+	Templates::new(templates).unwrap_or_else(|e|
+	{
+		tracing::error!("{}", e);
+		Templates::default()
+	})
+}
+
+/// Returns what an alert must share with others to be collapsed with them, or `None` if it is not about a client.
+fn aggregation_key(alert: &Alert, fleet: &FleetSnapshot) -> Option<AggregationKey>
+{
+	let client = client(&alert.event)?;
+	let event_type = event_type(&alert.event)?;
+	let host = fleet.client(client).and_then(|client| Url::parse(&client.url).ok()).and_then(|url| url.host_str().map(str::to_string));
+	Some((event_type, host, alert.sinks.clone()))
+}
+
+/// Collapses the alerts collected within an aggregation window into one alert with the highest of their severities. A
+/// single alert is returned as it is.
+fn aggregate(key: AggregationKey, mut collected: Vec<Alert>, templates: &Templates, fleet: &FleetSnapshot) -> Alert
+{
+	if collected.len() == 1
+	{
+		return collected.remove(0);
+	}
+
+	let (event_type, host, sinks) = key;
+	let severity = collected.iter().map(|alert| alert.severity).max().unwrap_or_default();
+	let clients = collected.iter().filter_map(|alert| client(&alert.event)).map(str::to_string).collect();
+	let event = MonitorEvent::AlertsAggregated { event_type: event_type.to_string(), host, clients };
+	let text = templates.render(&event, severity, fleet);
+	Alert { event, severity, sinks, text }
+}
+
+/// Returns the text of an aggregated alert, such as `30 clients offline on host 10.0.0.5: mainnet/1, mainnet/2, ...`.
+pub fn aggregated_text(event_type: &str, host: Option<&str>, clients: &[String]) -> String
+{
+	let mut names = clients.iter().take(MAX_AGGREGATED_CLIENTS).cloned().collect::<Vec<_>>();
+	if clients.len() > MAX_AGGREGATED_CLIENTS
+	{
+		names.push(format!("and {} more", clients.len() - MAX_AGGREGATED_CLIENTS));
+	}
+
+	let host = host.map(|host| format!(" on host {}", host)).unwrap_or_default();
+	format!("{} clients {}{}: {}", clients.len(), event_type.replace('_', " "), host, names.join(", "))
+}
+
+/// Returns the type of an event that alert routes match, or `None` for events that are not sent to any alert
+/// destination.
+pub fn event_type(event: &MonitorEvent) -> Option<&'static str>
+{
+	let event_type = match event
+	{
+		MonitorEvent::HealthChanged { to: Health::Offline, .. } => "offline",
+		MonitorEvent::HealthChanged { to: Health::Degraded, .. } => "degraded",
+		MonitorEvent::HealthChanged { from: Health::Offline, to: Health::Online, .. } => "online",
+		MonitorEvent::HealthChanged { from: Health::Degraded, to: Health::Online, .. } => "recovered",
+		MonitorEvent::Flapping { .. } => "flapping",
+		MonitorEvent::FlappingStopped { .. } => "stopped_flapping",
+		MonitorEvent::LagStarted { .. } => "lagging",
+		MonitorEvent::CaughtUp { .. } => "caught_up",
+		MonitorEvent::SyncStalled { .. } => "stalled",
+		MonitorEvent::SyncResumed { .. } => "sync_resumed",
+		MonitorEvent::TipFrozen { .. } => "tip_frozen",
+		MonitorEvent::TipUnfrozen { .. } => "tip_unfrozen",
+		MonitorEvent::LowPeersStarted { .. } => "low_peers",
+		MonitorEvent::PeersRestored { .. } => "peers_restored",
+		MonitorEvent::StillOngoing { .. } => "still_ongoing",
+		MonitorEvent::Escalated { .. } => "escalated",
+		MonitorEvent::AlertsAggregated { .. } => "aggregated",
+		MonitorEvent::OutageSummary { .. } => "outage_summary",
+		MonitorEvent::GroupSummary { .. } => "group_summary",
+		MonitorEvent::Digest { .. } => "digest",
+		MonitorEvent::FleetDegraded { .. } => "fleet_degraded",
+		MonitorEvent::FleetRecovered { .. } => "fleet_recovered",
+		MonitorEvent::ChainHalted { .. } => "chain_halted",
+		MonitorEvent::ChainResumed { .. } => "chain_resumed",
+		MonitorEvent::ParentDown { .. } => "parent_down",
+		MonitorEvent::ParentRecovered { .. } => "parent_recovered",
+		_ => return None,
+	};
+
+	Some(event_type)
+}
+
+/// Returns whether an event is a client recovering from a condition, rather than one starting or lasting.
+pub fn is_recovery(event: &MonitorEvent) -> bool
+{
+	match event
+	{
+		MonitorEvent::AlertsAggregated { event_type, .. } => RECOVERY_EVENT_TYPES.contains(&event_type.as_str()),
+		MonitorEvent::FleetRecovered { .. } | MonitorEvent::ChainResumed { .. } | MonitorEvent::ParentRecovered { .. } => true,
+		_ => changes(event).is_some_and(|(_, changes)| !changes.is_empty() && changes.iter().all(|change| matches!(change, Change::End(_)))),
+	}
+}
+
+/// Returns the condition an event is about: the one it starts, or else the one it ends, reminds of, or escalates.
+pub fn condition(event: &MonitorEvent) -> Option<Condition>
+{
+	match event
+	{
+		MonitorEvent::StillOngoing { condition, .. } | MonitorEvent::Escalated { condition, .. } => Some(*condition),
+		_ =>
+		{
+			let (_, changes) = changes(event)?;
+			changes.iter().find(|change| matches!(change, Change::Start(_))).or(changes.first()).map(Change::condition)
+		},
+	}
+}
+
+/// Returns the number of conditions an event starts, each of which is an incident.
+pub fn incidents(event: &MonitorEvent) -> usize
+{
+	changes(event).map_or(0, |(_, changes)| changes.iter().filter(|change| matches!(change, Change::Start(_))).count())
+}
+
+/// Returns the name of the client an alertable event is about, if it is about one.
+pub fn client(event: &MonitorEvent) -> Option<&str>
+{
+	match event
+	{
+		MonitorEvent::HealthChanged { client, .. }
+		| MonitorEvent::Flapping { client, .. }
+		| MonitorEvent::FlappingStopped { client, .. }
+		| MonitorEvent::LagStarted { client, .. }
+		| MonitorEvent::CaughtUp { client, .. }
+		| MonitorEvent::SyncStalled { client, .. }
+		| MonitorEvent::SyncResumed { client, .. }
+		| MonitorEvent::TipFrozen { client, .. }
+		| MonitorEvent::TipUnfrozen { client, .. }
+		| MonitorEvent::LowPeersStarted { client, .. }
+		| MonitorEvent::PeersRestored { client, .. }
+		| MonitorEvent::StillOngoing { client, .. }
+		| MonitorEvent::Escalated { client, .. } => Some(client),
+		_ => None,
+	}
+}
+
+/// Returns the group an alertable event is about. Clients are named after their group, as `group/label`.
+pub fn group(event: &MonitorEvent) -> Option<&str>
+{
+	match event
+	{
+		MonitorEvent::OutageSummary { group, .. } | MonitorEvent::GroupSummary { group, .. } => Some(group),
+		MonitorEvent::ChainHalted { group, .. } | MonitorEvent::ChainResumed { group, .. } => Some(group),
+		MonitorEvent::ParentDown { parent, .. } | MonitorEvent::ParentRecovered { parent, .. } => parent.group(),
+		MonitorEvent::FleetDegraded { group, .. } | MonitorEvent::FleetRecovered { group, .. } => group.as_deref(),
+		_ => client(event).and_then(|client| client.split_once('/')).map(|(group, _)| group),
+	}
+}
+
+/// Returns the destinations an alert is sent to: those of the first route it matches, none if it matches no route,
+/// or every destination when there are no routes.
+fn route(routes: &[AlertRoute], event: &MonitorEvent, severity: Severity) -> Vec<Sink>
+{
+	if routes.is_empty()
+	{
+		return Sink::ALL.to_vec();
+	}
+
+	let (group, event_type) = (group(event), event_type(event));
+	routes.iter().find(|route| route.matches(group, severity, event_type)).map(|route| route.sinks.clone()).unwrap_or_default()
+}
+
+/// Returns the client an event is about and the conditions it starts and ends, or `None` for events that do not
+/// change a condition.
+pub fn changes(event: &MonitorEvent) -> Option<(&str, Vec<Change>)>
+{
+	let (client, changes) = match event
+	{
+		MonitorEvent::HealthChanged { client, from, to, .. } =>
+		{
+			let mut changes = Vec::new();
+			match from
+			{
+				Health::Offline => changes.push(Change::End(Condition::Offline)),
+				Health::Degraded => changes.push(Change::End(Condition::Degraded)),
+				_ => {},
+			}
+			match to
+			{
+				Health::Offline => changes.push(Change::Start(Condition::Offline)),
+				Health::Degraded => changes.push(Change::Start(Condition::Degraded)),
+				_ => {},
+			}
+			(client, changes)
+		},
+		MonitorEvent::Flapping { client, .. } => (client, vec![Change::Start(Condition::Flapping)]),
+		MonitorEvent::FlappingStopped { client, .. } => (client, vec![Change::End(Condition::Flapping)]),
+		MonitorEvent::LagStarted { client, .. } => (client, vec![Change::Start(Condition::Lagging)]),
+		MonitorEvent::CaughtUp { client, .. } => (client, vec![Change::End(Condition::Lagging)]),
+		MonitorEvent::SyncStalled { client, .. } => (client, vec![Change::Start(Condition::Stalled)]),
+		MonitorEvent::SyncResumed { client, .. } => (client, vec![Change::End(Condition::Stalled)]),
+		MonitorEvent::TipFrozen { client, .. } => (client, vec![Change::Start(Condition::Frozen)]),
+		MonitorEvent::TipUnfrozen { client, .. } => (client, vec![Change::End(Condition::Frozen)]),
+		MonitorEvent::LowPeersStarted { client, .. } => (client, vec![Change::Start(Condition::LowPeers)]),
+		MonitorEvent::PeersRestored { client, .. } => (client, vec![Change::End(Condition::LowPeers)]),
+		_ => return None,
+	};
+
+	Some((client.as_str(), changes))
+}
+
+/// Records the conditions an event starts and ends, and returns the severity it is alerted with, or `None` if it is
+/// held back. An event is held back when every condition it starts was last alerted within the cooldown or is of a
+/// `dependent` client, one whose parent is down, or when it only ends conditions whose start was held back. Events that
+/// do not change a condition, such as summaries, are always alerted.
+fn record(conditions: &mut HashMap<(String, Condition), ConditionState>, event: &MonitorEvent, settings: &AlertSettings, dependent: bool, now: Instant) -> Option<Severity>
+{
+	// Reminders are alerted with the severity their condition has escalated to.
+	let severity = match event
+	{
+		MonitorEvent::StillOngoing { client, condition, .. } => conditions.get(&(client.clone(), *condition)).map_or_else(|| condition.severity(), |state| state.severity),
+		_ => classify(event, &settings.severity_rules),
+	};
+
+	// Lag and peer reports measure conditions that have already started, which can escalate them.
+	if let Some((client, condition, value)) = measurement(event)
+	{
+		if let Some(state) = conditions.get_mut(&(client.to_string(), condition)).filter(|state| state.since.is_some())
+		{
+			state.value = Some(value);
+		}
+	}
+
+	let (client, changes) = match changes(event)
+	{
+		Some((client, changes)) if !changes.is_empty() => (client, changes),
+		_ => return Some(severity),
+	};
+	// A condition that escalates is a warning until it does, unless rules set the severity of its events.
+	let ruled = settings.severity_rules.iter().any(|rule| event_type(event) == Some(rule.event.as_str()));
+	let escalates = !ruled && changes.iter().any(|change| matches!(change, Change::Start(condition) if settings.escalate_after.contains_key(condition)));
+	let severity = if escalates { severity.min(Severity::Warning) } else { severity };
+
+	// Changes in health are not reported while a client flaps, so its health conditions are forgotten until they are
+	// reported again.
+	if matches!(changes[..], [Change::Start(Condition::Flapping)])
+	{
+		for condition in [Condition::Offline, Condition::Degraded]
+		{
+			if let Some(state) = conditions.get_mut(&(client.to_string(), condition))
+			{
+				state.since = None;
+			}
+		}
+	}
+
+	let mut alert = false;
+	for change in changes
+	{
+		match change
+		{
+			Change::Start(condition) =>
+			{
+				let state = conditions.entry((client.to_string(), condition)).or_insert(ConditionState { event_type: "", value: None, severity, since: None, alerted: None, suppressed: false, dependent: false, notified: now, acked: false });
+				let suppressed = dependent || state.alerted.is_some_and(|alerted| now.duration_since(alerted) < settings.cooldown);
+				if !suppressed
+				{
+					state.alerted = Some(now);
+					alert = true;
+				}
+				state.event_type = event_type(event).unwrap_or_default();
+				state.value = value(event);
+				state.severity = severity;
+				state.since = Some(now);
+				state.suppressed = suppressed;
+				state.dependent = dependent;
+				state.notified = now;
+				state.acked = false;
+			},
+			Change::End(condition) => match conditions.get_mut(&(client.to_string(), condition))
+			{
+				Some(state) =>
+				{
+					alert |= !state.suppressed;
+					state.since = None;
+				},
+				// The condition started before the monitor did, or while its client was flapping.
+				None => alert = true,
+			},
+		}
+	}
+
+	if !alert && dependent
+	{
+		tracing::debug!("Not alerting a condition of client {} while something it depends on is down.", client);
+		return None;
+	}
+	if !alert
+	{
+		tracing::debug!("Not alerting a repeated condition of client {} within the cooldown.", client);
+		return None;
+	}
+	Some(severity)
+}
+
+/// Holds back the reminders about the ongoing conditions of clients whose parent has gone down, and drops the alerts
+/// about them that are waiting to be aggregated or held, since they are likely consequences of it. The conditions those
+/// alerts started are marked as held back, so the alerts that end them are too.
+fn suppress_dependents(conditions: &mut HashMap<(String, Condition), ConditionState>, pending: &mut HashMap<AggregationKey, (Instant, Vec<Alert>)>, held: &mut Vec<(Instant, Alert)>, clients: &[String])
+{
+	for ((client, _), state) in conditions.iter_mut()
+	{
+		if state.since.is_some() && clients.contains(client)
+		{
+			state.dependent = true;
+		}
+	}
+
+	for (_, collected) in pending.values_mut()
+	{
+		collected.retain(|alert| !suppress_dependent(conditions, alert, clients));
+	}
+	pending.retain(|_, (_, collected)| !collected.is_empty());
+	held.retain(|(_, alert)| !suppress_dependent(conditions, alert, clients));
+}
+
+/// Returns true if a waiting alert is about one of the dependent clients and starts a condition, marking the conditions
+/// it started as held back.
+fn suppress_dependent(conditions: &mut HashMap<(String, Condition), ConditionState>, alert: &Alert, clients: &[String]) -> bool
+{
+	let (client, changes) = match changes(&alert.event)
+	{
+		Some((client, changes)) if clients.iter().any(|dependent| dependent == client) => (client, changes),
+		_ => return false,
+	};
+	for change in changes.iter().filter(|change| matches!(change, Change::Start(_)))
+	{
+		if let Some(state) = conditions.get_mut(&(client.to_string(), change.condition()))
+		{
+			state.suppressed = true;
+		}
+	}
+	!alert.is_recovery()
+}
+
+/// Returns true if an alert starts a condition of a client that shares a host with other clients or whose group has
+/// reference nodes, so it may be a consequence of one of them going down.
+fn starts_for_dependent(alert: &Alert, fleet: &FleetSnapshot) -> bool
+{
+	!alert.is_recovery() && changes(&alert.event).is_some_and(|(client, changes)| changes.iter().any(|change| matches!(change, Change::Start(_))) && fleet.client(client).is_some_and(|client| client.has_parent))
+}
+
+/// Returns an escalation for each ongoing condition that has lasted past its threshold, and a reminder for each that
+/// has not been alerted within the renotify interval and is not acknowledged, along with an event for each that has
+/// just been acknowledged. Conditions of clients whose parent is down get neither. Conditions that have ended and are
+/// past their cooldown are forgotten.
+fn reminders(conditions: &mut HashMap<(String, Condition), ConditionState>, settings: &AlertSettings, acks: &AckWatcher, now: Instant) -> Vec<MonitorEvent>
+{
+	conditions.retain(|_, state| state.since.is_some() || state.alerted.is_some_and(|alerted| now.duration_since(alerted) < settings.cooldown));
+
+	let mut reminders = Vec::new();
+	for ((client, condition), state) in conditions.iter_mut()
+	{
+		let since = match state.since
+		{
+			Some(since) if !state.dependent => since,
+			_ => continue,
+		};
+		let lasted = now.duration_since(since);
+		let minutes = lasted.as_secs() / 60;
+
+		// A condition escalates to critical once it lasts past its threshold, and to the severity of any rule it comes
+		// to match, such as when a lagging client falls further behind.
+		let threshold = settings.escalate_after.get(condition).filter(|escalate_after| lasted >= **escalate_after).map(|_| Severity::Critical);
+		let rule = settings.severity_rules.iter().filter(|rule| rule.matches(state.event_type, state.value, lasted)).map(|rule| rule.severity).max();
+		if let Some(severity) = threshold.max(rule).filter(|severity| *severity > state.severity)
+		{
+			state.severity = severity;
+			state.notified = now;
+			reminders.push(MonitorEvent::Escalated { client: client.clone(), condition: *condition, minutes, severity });
+			continue;
+		}
+
+		// Escalations are still sent for an acknowledged condition, since it has become more urgent than when it was
+		// acknowledged, but reminders are not.
+		let started_at = Utc::now().timestamp() - lasted.as_secs() as i64;
+		let ack = acks.find(client, *condition, started_at);
+		if let Some(ack) = ack.filter(|_| !state.acked)
+		{
+			reminders.push(MonitorEvent::Acknowledged { client: client.clone(), condition: *condition, by: ack.by.clone(), acked_at: ack.acked_at, expires_at: ack.expires_at });
+		}
+		state.acked = ack.is_some();
+		if state.acked
+		{
+			continue;
+		}
+
+		if let Some(renotify_interval) = settings.renotify_interval
+		{
+			if now.duration_since(state.notified) >= renotify_interval
+			{
+				state.notified = now;
+				reminders.push(MonitorEvent::StillOngoing { client: client.clone(), condition: *condition, minutes });
+			}
+		}
+	}
+	reminders
+}
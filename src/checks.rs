@@ -0,0 +1,1047 @@
+use crate::client::{Client, PEER_CHURN_WINDOW};
+use crate::endpoint::{CallError, RpcEndpoint};
+use crate::events::MonitorEvent;
+use crate::health::{FlapChange, Health, Observation};
+use crate::history::RingBuffer;
+use crate::config::{ClientSettings, ExpectedScript};
+use crate::version::Version;
+use crate::rpc::{BlockView, CellsPage, FetchStatus, H256, HeaderView, LocalNodeInfo, Peer, ScriptStatus};
+use async_trait::async_trait;
+use chrono::{Local, TimeZone, Utc};
+use num_format::ToFormattedString;
+use crate::disk;
+use crate::latency::LatencyStats;
+use crate::otlp::CheckSpan;
+use crate::process;
+use std::collections::HashSet;
+use std::sync::Arc;
+use serde_json::json;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::time;
+
+/// The name of the RPC liveness check.
+pub const RPC_CHECK: &str = "rpc";
+
+/// The name of the peer count check.
+pub const PEERS_CHECK: &str = "peers";
+
+/// The name of the tip block number check.
+pub const TIP_CHECK: &str = "tip";
+
+/// The name of the filter script check.
+pub const SCRIPTS_CHECK: &str = "scripts";
+
+/// The name of the genesis block check.
+pub const GENESIS_CHECK: &str = "genesis";
+
+/// The name of the data retrieval check.
+pub const FETCH_CHECK: &str = "fetch";
+
+/// The name of the canary cells check.
+pub const CELLS_CHECK: &str = "cells";
+
+/// How long to wait between polls of a fetch that is still in progress.
+const FETCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The result of running a check on a client.
+#[derive(Clone, Debug)]
+pub enum CheckOutcome
+{
+	/// The client responded, so it is online.
+	Online(LocalNodeInfo),
+	/// The client did not respond or gave an error response, so it is offline.
+	Offline(String),
+	/// The peers the client is connected to.
+	Peers(Vec<Peer>),
+	/// The tip header of the client.
+	Tip(HeaderView),
+	/// The scripts the client is filtering for.
+	Scripts(Vec<ScriptStatus>),
+	/// The hash of the genesis block of the chain the client is on.
+	Genesis(H256),
+	/// How long the client took to fetch the probe data from the network.
+	Fetched(Duration),
+	/// The number of cells the client found for the canary script, up to the minimum.
+	Cells(usize),
+	/// The check could not be completed, but the client is not considered offline because of it.
+	Failed(String),
+	/// The client does not serve a method the check calls, such as when the RPC module it belongs to is disabled.
+	Unsupported(String),
+}
+
+/// Classifies a failed call, separating methods the client does not serve from calls it failed to complete.
+impl From<CallError> for CheckOutcome
+{
+	fn from(e: CallError) -> Self
+	{
+		match &e
+		{
+			CallError::Rpc(error) if error.is_unsupported() => Self::Unsupported(e.to_string()),
+			_ => Self::Failed(e.to_string()),
+		}
+	}
+}
+
+/// A health check that can be run against a client.
+#[async_trait]
+pub trait Check: Send + Sync
+{
+	/// Returns the name of the check, as used in the `checks` setting.
+	fn name(&self) -> &'static str;
+
+	/// Returns the RPC methods the check calls, so they can be sent together with those of other checks as one batch.
+	fn methods(&self) -> &'static [&'static str]
+	{
+		&[]
+	}
+
+	/// Returns true if the check only runs on clients that are online. Checks that can bring a client back online
+	/// return false, and are run before all other checks.
+	fn requires_online(&self) -> bool
+	{
+		true
+	}
+
+	/// Returns true if the check runs on every client unless the `checks` setting leaves it out. Checks that only make
+	/// sense for some clients return false, and run only when they are listed.
+	fn enabled_by_default(&self) -> bool
+	{
+		true
+	}
+
+	/// Runs the check against the endpoint of a client.
+	async fn run(&self, endpoint: &RpcEndpoint, settings: &ClientSettings) -> CheckOutcome;
+}
+
+/// Checks if the RPC server of a client is running using the `local_node_info` RPC call.
+pub struct RpcCheck;
+
+#[async_trait]
+impl Check for RpcCheck
+{
+	fn name(&self) -> &'static str
+	{
+		RPC_CHECK
+	}
+
+	fn methods(&self) -> &'static [&'static str]
+	{
+		&["local_node_info"]
+	}
+
+	fn requires_online(&self) -> bool
+	{
+		false
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, _settings: &ClientSettings) -> CheckOutcome
+	{
+		match endpoint.call::<LocalNodeInfo>("local_node_info").await
+		{
+			Ok(info) => CheckOutcome::Online(info),
+			Err(e) if e.is_offline() => CheckOutcome::Offline(e.to_string()),
+			Err(e) => e.into(),
+		}
+	}
+}
+
+/// Checks the number of peers a client is connected to using the `get_peers` RPC call.
+pub struct PeersCheck;
+
+#[async_trait]
+impl Check for PeersCheck
+{
+	fn name(&self) -> &'static str
+	{
+		PEERS_CHECK
+	}
+
+	fn methods(&self) -> &'static [&'static str]
+	{
+		&["get_peers"]
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, _settings: &ClientSettings) -> CheckOutcome
+	{
+		match endpoint.call::<Vec<Peer>>("get_peers").await
+		{
+			Ok(peers) => CheckOutcome::Peers(peers),
+			Err(e) => e.into(),
+		}
+	}
+}
+
+/// Retrieves the current block number of a client using the `get_tip_header` RPC call.
+pub struct TipCheck;
+
+#[async_trait]
+impl Check for TipCheck
+{
+	fn name(&self) -> &'static str
+	{
+		TIP_CHECK
+	}
+
+	fn methods(&self) -> &'static [&'static str]
+	{
+		&["get_tip_header"]
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, _settings: &ClientSettings) -> CheckOutcome
+	{
+		match endpoint.call::<HeaderView>("get_tip_header").await
+		{
+			Ok(header) => CheckOutcome::Tip(header),
+			Err(e) => e.into(),
+		}
+	}
+}
+
+/// Retrieves the scripts a client is filtering for using the `get_scripts` RPC call. A client without any scripts does
+/// not sync anything useful.
+pub struct ScriptsCheck;
+
+#[async_trait]
+impl Check for ScriptsCheck
+{
+	fn name(&self) -> &'static str
+	{
+		SCRIPTS_CHECK
+	}
+
+	fn methods(&self) -> &'static [&'static str]
+	{
+		&["get_scripts"]
+	}
+
+	fn enabled_by_default(&self) -> bool
+	{
+		false
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, _settings: &ClientSettings) -> CheckOutcome
+	{
+		match endpoint.call::<Vec<ScriptStatus>>("get_scripts").await
+		{
+			Ok(scripts) => CheckOutcome::Scripts(scripts),
+			Err(e) => e.into(),
+		}
+	}
+}
+
+/// Checks which chain a client is on using the hash of its genesis block from the `get_genesis_block` RPC call.
+pub struct GenesisCheck;
+
+#[async_trait]
+impl Check for GenesisCheck
+{
+	fn name(&self) -> &'static str
+	{
+		GENESIS_CHECK
+	}
+
+	fn methods(&self) -> &'static [&'static str]
+	{
+		&["get_genesis_block"]
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, _settings: &ClientSettings) -> CheckOutcome
+	{
+		match endpoint.call::<BlockView>("get_genesis_block").await
+		{
+			Ok(block) => CheckOutcome::Genesis(block.header.hash),
+			Err(e) => e.into(),
+		}
+	}
+}
+
+/// Checks that a client can retrieve data from the network, and not just follow headers, by fetching a header with
+/// `fetch_header` and waiting for the fetch to complete. The parent of its tip is fetched, which is recent enough that
+/// the client has not fetched it before, unless a known transaction or header to fetch is configured instead.
+pub struct FetchCheck;
+
+#[async_trait]
+impl Check for FetchCheck
+{
+	fn name(&self) -> &'static str
+	{
+		FETCH_CHECK
+	}
+
+	fn enabled_by_default(&self) -> bool
+	{
+		false
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, settings: &ClientSettings) -> CheckOutcome
+	{
+		let (method, hash) = match (settings.fetch_probe_transaction, settings.fetch_probe_header)
+		{
+			(Some(transaction), _) => ("fetch_transaction", transaction),
+			(None, Some(header)) => ("fetch_header", header),
+			(None, None) => match endpoint.call::<HeaderView>("get_tip_header").await
+			{
+				Ok(HeaderView { parent_hash: Some(parent_hash), .. }) => ("fetch_header", parent_hash),
+				Ok(_) => return CheckOutcome::Failed("the tip header has no parent hash to fetch".to_string()),
+				Err(e) if e.is_offline() => return CheckOutcome::Offline(e.to_string()),
+				Err(e) => return e.into(),
+			},
+		};
+
+		// The first call starts the fetch, and later calls report whether it has completed.
+		let started = Instant::now();
+		loop
+		{
+			let status = match endpoint.call_with_params::<FetchStatus>(method, json!([hash.to_string()])).await
+			{
+				Ok(status) => status,
+				Err(e) if e.is_offline() => return CheckOutcome::Offline(e.to_string()),
+				Err(e) => return e.into(),
+			};
+
+			match status.status.as_str()
+			{
+				"fetched" => return CheckOutcome::Fetched(started.elapsed()),
+				"not_found" => return CheckOutcome::Failed(format!("{} was not found", hash)),
+				_ if started.elapsed() >= settings.fetch_timeout => return CheckOutcome::Failed(format!("{} was not fetched within {} seconds", hash, settings.fetch_timeout.as_secs())),
+				_ => time::sleep(FETCH_POLL_INTERVAL).await,
+			}
+		}
+	}
+}
+
+/// Checks that the filter index of a client works by looking up the cells of a well known script with `get_cells`. A
+/// client can sync headers fine while its index is corrupted, which only shows when it is queried.
+pub struct CellsCheck;
+
+#[async_trait]
+impl Check for CellsCheck
+{
+	fn name(&self) -> &'static str
+	{
+		CELLS_CHECK
+	}
+
+	fn enabled_by_default(&self) -> bool
+	{
+		false
+	}
+
+	async fn run(&self, endpoint: &RpcEndpoint, settings: &ClientSettings) -> CheckOutcome
+	{
+		let canary_script = match &settings.canary_script
+		{
+			Some(canary_script) => canary_script,
+			None => return CheckOutcome::Failed("no canary script is configured".to_string()),
+		};
+
+		// Only as many cells as the minimum are needed, so that is all that is asked for.
+		let limit = format!("{:#x}", settings.min_canary_cells.max(1));
+		match endpoint.call_with_params::<CellsPage>("get_cells", json!([canary_script.search_key(), "asc", limit])).await
+		{
+			Ok(page) if page.objects.len() >= settings.min_canary_cells => CheckOutcome::Cells(page.objects.len()),
+			Ok(page) => CheckOutcome::Failed(format!("found {} cells for the canary script instead of at least {}", page.objects.len(), settings.min_canary_cells)),
+			Err(e) if e.is_offline() => CheckOutcome::Offline(e.to_string()),
+			Err(e) => e.into(),
+		}
+	}
+}
+
+/// Returns every available check, in the order they run.
+pub fn registry() -> Vec<Arc<dyn Check>>
+{
+	vec![Arc::new(RpcCheck), Arc::new(PeersCheck), Arc::new(TipCheck), Arc::new(ScriptsCheck), Arc::new(GenesisCheck), Arc::new(FetchCheck), Arc::new(CellsCheck)]
+}
+
+/// Returns the check with the specified name, if there is one.
+pub fn find(name: &str) -> Option<Arc<dyn Check>>
+{
+	registry().into_iter().find(|check| check.name() == name)
+}
+
+/// Returns the names of the checks that are enabled by default.
+pub fn default_checks() -> Vec<String>
+{
+	registry().iter().filter(|check| check.enabled_by_default()).map(|check| check.name().to_string()).collect()
+}
+
+/// Describes how the scripts a client is filtering for differ from what is expected, or returns `None` if they match.
+/// Without an expected set, the scripts only need to be present.
+fn script_problem(scripts: &[ScriptStatus], expected: Option<&[ExpectedScript]>) -> Option<String>
+{
+	let expected = match expected
+	{
+		Some(expected) => expected,
+		None if scripts.is_empty() => return Some("no filter scripts are set".to_string()),
+		None => return None,
+	};
+
+	let mut problems = Vec::new();
+	for expected_script in expected.iter().filter(|expected_script| !scripts.iter().any(|status| expected_script.matches(status)))
+	{
+		problems.push(format!("missing {} script {}", expected_script.script_type, expected_script.script()));
+	}
+	for status in scripts.iter().filter(|status| !expected.iter().any(|expected_script| expected_script.matches(status)))
+	{
+		problems.push(format!("unexpected {} script {}", status.script_type, status.script));
+	}
+
+	if problems.is_empty() { None } else { Some(problems.join(", ")) }
+}
+
+impl Client
+{
+	/// Runs the specified checks on the client, sending the RPC calls they need as a single JSON-RPC batch, then updates
+	/// the health of the client from the combined results.
+	///
+	/// Checks that require the client to be online are skipped for an offline client, unless a check that can bring it
+	/// back online is also being run. Returns how long each check that was run took, and why it failed if it did.
+	pub async fn run_checks(&mut self, checks: &[Arc<dyn Check>]) -> Vec<CheckSpan>
+	{
+		let mut checks = checks.to_vec();
+		let mut responding = self.health.health() != Health::Offline;
+		if checks.is_empty() || (!responding && checks.iter().all(|check| check.requires_online()))
+		{
+			return Vec::new();
+		}
+		checks.sort_by_key(|check| check.requires_online());
+
+		let methods = checks.iter().flat_map(|check| check.methods().iter().copied()).collect::<Vec<_>>();
+		self.endpoint.prefetch(&methods).await;
+
+		let mut observation = None;
+		let mut spans = Vec::new();
+		for check in checks.iter()
+		{
+			if check.requires_online() && !responding
+			{
+				continue;
+			}
+
+			let start = SystemTime::now();
+			let outcome = check.run(&self.endpoint, &self.settings).await;
+			let error = match &outcome
+			{
+				CheckOutcome::Offline(e) | CheckOutcome::Failed(e) | CheckOutcome::Unsupported(e) => Some(e.clone()),
+				_ => None,
+			};
+			spans.push(CheckSpan { name: check.name(), start, end: SystemTime::now(), error });
+			match &outcome
+			{
+				CheckOutcome::Online(_) => responding = true,
+				CheckOutcome::Offline(e) =>
+				{
+					responding = false;
+					observation = Some(Observation::Unresponsive(e.clone()));
+				},
+				CheckOutcome::Failed(e) if observation.is_none() =>
+				{
+					observation = Some(Observation::Failed(format!("the {} check failed: {}", check.name(), e)));
+				},
+				CheckOutcome::Unsupported(e) if observation.is_none() =>
+				{
+					observation = Some(Observation::Failed(format!("the {} check is not supported by the client: {}", check.name(), e)));
+				},
+				CheckOutcome::Scripts(scripts) if observation.is_none() =>
+				{
+					observation = script_problem(scripts, self.settings.expected_scripts.as_deref()).map(Observation::Failed);
+				},
+				_ => {},
+			}
+			self.apply_outcome(outcome);
+		}
+
+		self.endpoint.clear_prefetched();
+		if !responding
+		{
+			self.latency = None;
+		}
+		self.record_latency(self.endpoint.take_round_trips());
+		if let Some(certificate_expiry) = self.endpoint.take_certificate_expiry()
+		{
+			self.certificate_expiry = Some(certificate_expiry);
+		}
+		self.observe(observation.unwrap_or(Observation::Healthy));
+		self.check_script_sync();
+		self.check_tip_age();
+		self.check_epoch_age();
+		self.check_certificate_expiry();
+		self.sample_process().await;
+		self.measure_disk().await;
+		self.poll_log().await;
+		spans
+	}
+
+	/// Updates the state of the client from the outcome of a check.
+	fn apply_outcome(&mut self, outcome: CheckOutcome)
+	{
+		match outcome
+		{
+			CheckOutcome::Online(info) =>
+			{
+				tracing::debug!("Client {} is node {} running version {}.", self, info.node_id, info.version);
+				self.version = Some(info.version);
+			},
+			CheckOutcome::Peers(peers) => self.update_peers(&peers),
+			CheckOutcome::Tip(header) => self.update_tip(&header, self.endpoint.last_round_trip()),
+			CheckOutcome::Scripts(scripts) =>
+			{
+				tracing::debug!("Client {} is filtering for {} scripts.", self, scripts.len());
+				self.scripts = scripts;
+			},
+			CheckOutcome::Fetched(fetch_time) =>
+			{
+				tracing::debug!("Client {} fetched its probe in {} ms.", self, fetch_time.as_millis());
+				self.fetch_time = Some(fetch_time);
+			},
+			CheckOutcome::Cells(cells) =>
+			{
+				tracing::debug!("Client {} found {} cells for the canary script.", self, cells);
+			},
+			CheckOutcome::Genesis(genesis_hash) =>
+			{
+				tracing::debug!("Client {} has genesis block {}.", self, genesis_hash);
+				self.genesis_hash = Some(genesis_hash);
+			},
+			CheckOutcome::Offline(_) | CheckOutcome::Failed(_) => self.check_errors += 1,
+			CheckOutcome::Unsupported(_) => {},
+		}
+	}
+
+	/// Updates the health of the client from the result of a round of checks, emitting an event if it changed.
+	fn observe(&mut self, observation: Observation)
+	{
+		let transition = self.health.observe(&observation, &self.settings.health);
+		// The first result of a client is not a bounce, so it does not count towards flapping.
+		let bounced = transition.is_some_and(|transition| transition.from != Health::Unknown);
+		match self.flaps.record(bounced, &self.settings.health)
+		{
+			Some(FlapChange::Started(transitions)) => self.emit(MonitorEvent::Flapping
+			{
+				client: self.to_string(),
+				transitions,
+				window_seconds: self.settings.health.flap_window.as_secs(),
+			}),
+			Some(FlapChange::Stopped(suppressed)) => self.emit(MonitorEvent::FlappingStopped { client: self.to_string(), health: self.health.health(), suppressed }),
+			None => {},
+		}
+		let transition = match transition
+		{
+			Some(transition) => transition,
+			None => return,
+		};
+
+		if transition.to == Health::Offline
+		{
+			self.offline_transitions += 1;
+			self.peers = 0;
+			self.connected_peers.clear();
+			self.peers_known = false;
+			self.peer_churn = RingBuffer::new(PEER_CHURN_WINDOW);
+			self.block_number = 0;
+			self.tip_hash = None;
+			self.tip_time = None;
+			self.epoch = None;
+		}
+
+		let reason = match observation
+		{
+			Observation::Healthy => None,
+			Observation::Failed(reason) | Observation::Unresponsive(reason) => Some(reason),
+		};
+		if self.flaps.is_flapping()
+		{
+			tracing::debug!("Client {} is flapping and is now {}: {}", self, transition.to, reason.as_deref().unwrap_or_default());
+			return;
+		}
+		self.emit(MonitorEvent::HealthChanged
+		{
+			client: self.to_string(),
+			from: transition.from,
+			to: transition.to,
+			reason,
+			duration_seconds: Local::now().signed_duration_since(transition.since).num_seconds(),
+		});
+	}
+
+	/// Records the round trip times of the requests sent to the client, emitting an event when its latency crosses the
+	/// maximum latency in either direction.
+	fn record_latency(&mut self, round_trips: Vec<Duration>)
+	{
+		let latency = match round_trips.last()
+		{
+			Some(latency) => *latency,
+			None => return,
+		};
+		for round_trip in round_trips
+		{
+			self.latency_window.push(round_trip);
+		}
+		self.latency = Some(latency);
+
+		let max_latency = match self.settings.max_latency
+		{
+			Some(max_latency) => max_latency,
+			None => return,
+		};
+		let slow = latency > max_latency;
+		if slow == self.slow
+		{
+			return;
+		}
+		self.slow = slow;
+
+		let latency_ms = latency.as_millis() as u64;
+		if slow
+		{
+			let p95_ms = LatencyStats::new(&self.latency_window).map_or(latency_ms, |stats| stats.p95.as_millis() as u64);
+			self.emit(MonitorEvent::LatencyHigh { client: self.to_string(), latency_ms, max_latency_ms: max_latency.as_millis() as u64, p95_ms });
+		}
+		else
+		{
+			self.emit(MonitorEvent::LatencyNormal { client: self.to_string(), latency_ms });
+		}
+	}
+
+	/// Emits an event when the filtering of any script starts or stops trailing the tip of the client by more than the
+	/// maximum script lag.
+	fn check_script_sync(&mut self)
+	{
+		if self.block_number == 0 || !self.health.health().is_online()
+		{
+			return;
+		}
+
+		let lagging_scripts = self.scripts.iter()
+			.filter(|status| self.block_number > status.block_number.saturating_add(self.settings.max_script_lag))
+			.map(|status| (status.script.to_string(), status.block_number))
+			.collect::<Vec<_>>();
+		let lagging = !lagging_scripts.is_empty();
+		if lagging == self.scripts_lagging
+		{
+			return;
+		}
+		self.scripts_lagging = lagging;
+
+		if lagging
+		{
+			self.emit(MonitorEvent::ScriptsLagging { client: self.to_string(), block_number: self.block_number, max_script_lag: self.settings.max_script_lag, scripts: lagging_scripts });
+		}
+		else
+		{
+			self.emit(MonitorEvent::ScriptsCaughtUp { client: self.to_string() });
+		}
+	}
+
+	/// Updates the block number of the client from its tip header, whether it was polled or pushed by a subscription.
+	/// The round trip of the request that returned a polled header is left out of how long the client took to take it.
+	pub fn update_tip(&mut self, header: &HeaderView, round_trip: Option<Duration>)
+	{
+		tracing::debug!("Client {} is at block {} with hash {}.", self, header.number, header.hash);
+		if header.number < self.last_block_number
+		{
+			self.emit(MonitorEvent::TipRegressed { client: self.to_string(), previous: self.last_block_number, block_number: header.number });
+		}
+		self.last_block_number = header.number;
+		self.block_number = header.number;
+		let previous_hash = self.tip_hash.replace(header.hash);
+		self.tip_time = Local.timestamp_millis_opt(header.timestamp as i64).single();
+		self.check_tip_age();
+
+		// A host whose clock is behind only accepts a header once its clock reaches the timestamp of the header, so it
+		// takes new tips later than other hosts. The client answered somewhere within the round trip, so the middle of
+		// it is used rather than when the response arrived. The quickest of the recent tips is used, which leaves out
+		// most of the time the tip waited to be polled. The first tip seen may have been taken long before.
+		if let (Some(previous_hash), Some(tip_time)) = (previous_hash, self.tip_time)
+		{
+			if previous_hash != header.hash
+			{
+				let half_round_trip = chrono::Duration::from_std(round_trip.unwrap_or_default() / 2).unwrap_or_else(|_| chrono::Duration::zero());
+				self.tip_delays.push(Local::now() - half_round_trip - tip_time);
+				self.clock_offset = self.tip_delays.iter().min().map(|delay| -*delay);
+			}
+		}
+
+		let epoch = header.epoch();
+		if self.epoch.map(|previous| previous.number) != Some(epoch.number)
+		{
+			self.epoch_since = Some(Instant::now());
+		}
+		self.epoch = Some(epoch);
+		self.check_epoch_age();
+	}
+
+	/// Records the resource usage of the process of a locally run client, and emits an event when its memory rises
+	/// above or falls back below the maximum.
+	async fn sample_process(&mut self)
+	{
+		let source = match &self.settings.process
+		{
+			Some(source) => source.clone(),
+			None => return,
+		};
+
+		let stats = match process::sample(&source, self.cpu_sample).await
+		{
+			Ok((stats, cpu_sample)) =>
+			{
+				self.cpu_sample = Some(cpu_sample);
+				stats
+			},
+			Err(e) =>
+			{
+				tracing::debug!("Unable to sample the process of client {} ({}): {}", self, source, e);
+				self.process = None;
+				self.cpu_sample = None;
+				return;
+			}
+		};
+		self.process = Some(stats);
+
+		let max_memory = match self.settings.max_memory_mb
+		{
+			Some(max_memory) => max_memory,
+			None => return,
+		};
+		let memory_mb = stats.rss_bytes / (1024 * 1024);
+		let memory_high = memory_mb > max_memory;
+		if memory_high == self.memory_high
+		{
+			return;
+		}
+		self.memory_high = memory_high;
+
+		if memory_high
+		{
+			self.emit(MonitorEvent::MemoryHigh { client: self.to_string(), memory_mb, max_memory_mb: max_memory });
+		}
+		else
+		{
+			self.emit(MonitorEvent::MemoryNormal { client: self.to_string(), memory_mb });
+		}
+	}
+
+	/// Measures the data directory of a locally run client when it is due, and emits an event when the directory grows
+	/// past its maximum size or the free space on its filesystem drops below the minimum, and again when both recover.
+	async fn measure_disk(&mut self)
+	{
+		let data_dir = match &self.settings.data_dir
+		{
+			Some(data_dir) if self.disk_measured.is_none_or(|measured| measured.elapsed() >= self.settings.disk_interval) => data_dir.clone(),
+			_ => return,
+		};
+		self.disk_measured = Some(Instant::now());
+
+		let stats = match disk::measure(&data_dir).await
+		{
+			Ok(stats) => stats,
+			Err(e) =>
+			{
+				tracing::warn!("Unable to measure the disk usage of client {}: {}", self, e);
+				self.disk = None;
+				return;
+			}
+		};
+		self.disk = Some(stats);
+
+		let data_dir_mb = stats.data_dir_bytes / (1024 * 1024);
+		let problem = if stats.free_percent() < self.settings.min_disk_free_percent
+		{
+			Some(format!("only {:.1}% of the filesystem of {} is free, which is less than {:.1}%", stats.free_percent(), data_dir.display(), self.settings.min_disk_free_percent))
+		}
+		else
+		{
+			self.settings.max_data_dir_mb.filter(|max_data_dir_mb| data_dir_mb > *max_data_dir_mb)
+				.map(|max_data_dir_mb| format!("{} is {} MB, which is more than {} MB", data_dir.display(), data_dir_mb.to_formatted_string(&num_format::Locale::en), max_data_dir_mb.to_formatted_string(&num_format::Locale::en)))
+		};
+		if problem.is_some() == self.disk_full
+		{
+			return;
+		}
+		self.disk_full = problem.is_some();
+
+		match problem
+		{
+			Some(reason) => self.emit(MonitorEvent::DiskUsageHigh { client: self.to_string(), reason }),
+			None => self.emit(MonitorEvent::DiskUsageNormal { client: self.to_string(), data_dir_mb, free_percent: stats.free_percent() }),
+		}
+	}
+
+	/// Reads the new lines in the log file of the client, and emits an event for each that matches a pattern along with
+	/// the current health of the client, so errors in the log can be lined up with what the RPC showed.
+	async fn poll_log(&mut self)
+	{
+		let result = match self.log_watcher.as_mut()
+		{
+			Some(log_watcher) => log_watcher.poll().await,
+			None => return,
+		};
+
+		let (matches, skipped) = match result
+		{
+			Ok(result) => result,
+			Err(e) =>
+			{
+				tracing::debug!("Unable to read the log of client {}: {}", self, e);
+				return;
+			}
+		};
+		if skipped > 0
+		{
+			tracing::warn!("Client {} logged {} more matching lines that were not reported.", self, skipped);
+		}
+
+		for log_match in matches
+		{
+			self.emit(MonitorEvent::LogMatched { client: self.to_string(), pattern: log_match.pattern, line: log_match.line, health: self.health.health() });
+		}
+	}
+
+	/// Emits an event when the tip block of the client becomes older than the maximum tip age, or a newer one arrives.
+	/// A stale tip means the client has stopped hearing about new blocks, even if its block number looks plausible.
+	fn check_tip_age(&mut self)
+	{
+		let tip_time = match self.tip_time
+		{
+			Some(tip_time) if self.settings.max_tip_age > Duration::ZERO && self.health.health().is_online() => tip_time,
+			_ => return,
+		};
+		let age = Local::now().signed_duration_since(tip_time).to_std().unwrap_or_default();
+		let stale = age > self.settings.max_tip_age;
+		if stale == self.tip_stale
+		{
+			return;
+		}
+		self.tip_stale = stale;
+
+		if stale
+		{
+			self.emit(MonitorEvent::TipStale { client: self.to_string(), block_number: self.block_number, age_seconds: age.as_secs(), max_tip_age_seconds: self.settings.max_tip_age.as_secs() });
+		}
+		else
+		{
+			self.emit(MonitorEvent::TipFresh { client: self.to_string(), block_number: self.block_number });
+		}
+	}
+
+	/// Emits an event when the TLS certificate of the client expires sooner than the minimum certificate age, and again
+	/// once it has been renewed. The last certificate seen is kept while the client is offline, so an expired
+	/// certificate that keeps the monitor from connecting is still reported.
+	fn check_certificate_expiry(&mut self)
+	{
+		let certificate_expiry = match self.certificate_expiry
+		{
+			Some(certificate_expiry) if self.settings.min_certificate_age > Duration::ZERO => certificate_expiry,
+			_ => return,
+		};
+		let remaining = certificate_expiry.signed_duration_since(Utc::now());
+		let expiring = remaining.to_std().map_or(true, |remaining| remaining < self.settings.min_certificate_age);
+		if expiring == self.certificate_expiring
+		{
+			return;
+		}
+		self.certificate_expiring = expiring;
+
+		if expiring
+		{
+			self.emit(MonitorEvent::CertificateExpiring { client: self.to_string(), expires: certificate_expiry, days_left: remaining.num_days() });
+		}
+		else
+		{
+			self.emit(MonitorEvent::CertificateRenewed { client: self.to_string(), expires: certificate_expiry });
+		}
+	}
+
+	/// Emits an event when the client has been in the same epoch for longer than the maximum epoch age, which means it
+	/// has stalled even if its block number still changes, and again once it moves on to the next epoch.
+	fn check_epoch_age(&mut self)
+	{
+		let (epoch, since) = match (self.epoch, self.epoch_since)
+		{
+			(Some(epoch), Some(since)) if self.settings.max_epoch_age > Duration::ZERO && self.health.health().is_online() => (epoch, since),
+			_ => return,
+		};
+		let stalled = since.elapsed() > self.settings.max_epoch_age;
+		if stalled == self.epoch_stalled
+		{
+			return;
+		}
+		self.epoch_stalled = stalled;
+
+		if stalled
+		{
+			self.emit(MonitorEvent::EpochStalled { client: self.to_string(), epoch: epoch.number, seconds: since.elapsed().as_secs() });
+		}
+		else
+		{
+			self.emit(MonitorEvent::EpochAdvanced { client: self.to_string(), epoch: epoch.number });
+		}
+	}
+
+	/// Updates the number of peers the client is connected to.
+	fn update_peers(&mut self, peers: &[Peer])
+	{
+		let peers_count = peers.len() as u16;
+		if self.peers != peers_count
+		{
+			self.emit(MonitorEvent::PeerCountChanged { client: self.to_string(), previous: self.peers, peers: peers_count });
+		}
+		self.peers = peers_count;
+
+		// Peers that connected or disconnected since the last check, which are only known once there has been one.
+		if self.peers_known
+		{
+			let previous = self.connected_peers.iter().map(|peer| peer.node_id.as_str()).collect::<HashSet<_>>();
+			let current = peers.iter().map(|peer| peer.node_id.as_str()).collect::<HashSet<_>>();
+			self.peer_churn.push(previous.symmetric_difference(&current).count());
+		}
+		self.connected_peers = peers.to_vec();
+		self.peers_known = true;
+		self.check_min_peers();
+		self.check_peer_churn();
+		self.check_peer_age();
+		self.check_peer_versions();
+		self.check_expected_peers();
+	}
+
+	/// Emits events when a client with expected peers is connected to none of them or to any others, and again once
+	/// each has cleared.
+	fn check_expected_peers(&mut self)
+	{
+		let expected_peers = match &self.settings.expected_peers
+		{
+			Some(expected_peers) => expected_peers,
+			None => return,
+		};
+
+		let missing = !self.connected_peers.iter().any(|peer| expected_peers.iter().any(|expected| peer.matches(expected)));
+		let unexpected = self.connected_peers.iter()
+			.filter(|peer| !expected_peers.iter().any(|expected| peer.matches(expected)))
+			.map(|peer| peer.address().unwrap_or(&peer.node_id).to_string())
+			.collect::<Vec<_>>();
+
+		if missing != self.expected_peers_missing
+		{
+			self.expected_peers_missing = missing;
+			if missing
+			{
+				self.emit(MonitorEvent::ExpectedPeersMissing { client: self.to_string(), expected: expected_peers.clone() });
+			}
+			else
+			{
+				self.emit(MonitorEvent::ExpectedPeersConnected { client: self.to_string() });
+			}
+		}
+
+		let unexpected_present = !unexpected.is_empty();
+		if unexpected_present != self.unexpected_peers
+		{
+			self.unexpected_peers = unexpected_present;
+			if unexpected_present
+			{
+				self.emit(MonitorEvent::UnexpectedPeers { client: self.to_string(), peers: unexpected });
+			}
+			else
+			{
+				self.emit(MonitorEvent::UnexpectedPeersGone { client: self.to_string() });
+			}
+		}
+	}
+
+	/// Emits an event when every peer of the client has connected more recently than the minimum peer age, which
+	/// suggests the client or its peers keep restarting, and again once any of them has stayed connected longer.
+	fn check_peer_age(&mut self)
+	{
+		let min_peer_age = self.settings.min_peer_age.as_millis() as u64;
+		let longest = self.connected_peers.iter().map(|peer| peer.connected_duration).max();
+		let fresh = min_peer_age > 0 && longest.is_some_and(|longest| longest < min_peer_age);
+		if fresh == self.peers_fresh
+		{
+			return;
+		}
+		self.peers_fresh = fresh;
+
+		if fresh
+		{
+			self.emit(MonitorEvent::PeersFresh { client: self.to_string(), peers: self.connected_peers.len(), longest_seconds: longest.unwrap_or_default() / 1000 });
+		}
+		else
+		{
+			self.emit(MonitorEvent::PeersSettled { client: self.to_string() });
+		}
+	}
+
+	/// Emits an event when every peer of the client is running a version older than the minimum peer version, which
+	/// means they only speak outdated protocol versions, and again once any of them is not.
+	fn check_peer_versions(&mut self)
+	{
+		let min_peer_version = match self.settings.min_peer_version.as_deref().and_then(Version::parse)
+		{
+			Some(min_peer_version) => min_peer_version,
+			None => return,
+		};
+		let outdated = !self.connected_peers.is_empty() && self.connected_peers.iter().all(|peer| Version::parse(&peer.version).is_some_and(|version| version < min_peer_version));
+		if outdated == self.peers_outdated
+		{
+			return;
+		}
+		self.peers_outdated = outdated;
+
+		if outdated
+		{
+			let mut versions = self.connected_peers.iter().map(|peer| peer.version.clone()).collect::<Vec<_>>();
+			versions.sort();
+			versions.dedup();
+			self.emit(MonitorEvent::PeersOutdated { client: self.to_string(), peers: self.connected_peers.len(), versions, min_peer_version: min_peer_version.to_string() });
+		}
+		else
+		{
+			self.emit(MonitorEvent::PeersUpdated { client: self.to_string() });
+		}
+	}
+
+	/// Emits an event when the client falls below its minimum number of peers, and again once it has enough.
+	fn check_min_peers(&mut self)
+	{
+		let low = self.peers < self.settings.min_peers;
+		if low == self.peers_low
+		{
+			return;
+		}
+		self.peers_low = low;
+
+		if low
+		{
+			self.emit(MonitorEvent::LowPeersStarted { client: self.to_string(), peers: self.peers, min_peers: self.settings.min_peers });
+		}
+		else
+		{
+			self.emit(MonitorEvent::PeersRestored { client: self.to_string(), peers: self.peers });
+		}
+	}
+
+	/// Emits an event when the peer churn of the client rises above or falls back below its maximum.
+	fn check_peer_churn(&mut self)
+	{
+		let churn = match self.peer_churn_rate()
+		{
+			Some(churn) => churn,
+			None => return,
+		};
+		let churning = churn > self.settings.max_peer_churn;
+		if churning == self.churning
+		{
+			return;
+		}
+		self.churning = churning;
+
+		if churning
+		{
+			self.emit(MonitorEvent::PeerChurnHigh { client: self.to_string(), churn, max_peer_churn: self.settings.max_peer_churn });
+		}
+		else
+		{
+			self.emit(MonitorEvent::PeerChurnNormal { client: self.to_string(), churn });
+		}
+	}
+}
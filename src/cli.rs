@@ -0,0 +1,470 @@
+use ckb_light_client_monitor::alerts::Condition;
+use ckb_light_client_monitor::config::{Config, LagMode, DEFAULT_CONFIG_PATH};
+use ckb_light_client_monitor::filter::{ClientFilter, ClientSet};
+use ckb_light_client_monitor::rpc::H256;
+use ckb_light_client_monitor::scheduler::Schedule;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// A testing tool for monitoring multiple CKB light clients on a single host.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args
+{
+	/// The config file to load settings from.
+	#[arg(short, long, env = "CKB_MONITOR_CONFIG", default_value = DEFAULT_CONFIG_PATH)]
+	pub config: PathBuf,
+
+	/// Enable debug output.
+	#[arg(short, long)]
+	pub verbose: bool,
+
+	/// Validate the config and print the resolved fleet without performing any checks.
+	#[arg(long)]
+	pub dry_run: bool,
+
+	/// The host all light clients are running on.
+	#[arg(long)]
+	pub host: Option<String>,
+
+	/// The RPC port of the first client.
+	#[arg(long)]
+	pub starting_port: Option<u16>,
+
+	/// The number of clients to monitor.
+	#[arg(long)]
+	pub total_clients: Option<usize>,
+
+	/// How often to check clients, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub check_interval: Option<Schedule>,
+
+	/// How often to run RPC liveness checks, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub rpc_interval: Option<Schedule>,
+
+	/// How often to run peer count checks, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub peers_interval: Option<Schedule>,
+
+	/// How often to run tip block number checks, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub tip_interval: Option<Schedule>,
+
+	/// How often to check which chain each client is on, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub genesis_interval: Option<Schedule>,
+
+	/// The hash of the genesis block every client should be on.
+	#[arg(long)]
+	pub genesis_hash: Option<H256>,
+
+	/// How often to audit the clients of each group against each other, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub audit_interval: Option<Schedule>,
+
+	/// How often to summarize the outages of each client, as a number of seconds or a cron expression.
+	#[arg(long)]
+	pub summary_interval: Option<Schedule>,
+
+	/// The fraction of each interval, from 0.0 to 1.0, over which client checks are spread.
+	#[arg(long)]
+	pub check_spread: Option<f64>,
+
+	/// The number of seconds between checks of a client that is offline or lagging, until it recovers.
+	#[arg(long)]
+	pub degraded_interval: Option<u64>,
+
+	/// The number of consecutive rounds of checks a client must fail to respond to before it is considered offline.
+	#[arg(long)]
+	pub offline_after: Option<u32>,
+
+	/// The number of consecutive rounds of passing checks a degraded or offline client needs to be considered online.
+	#[arg(long)]
+	pub recover_after: Option<u32>,
+
+	/// The number of changes in health within the flap window at which a client is reported as flapping, or 0 to report every change.
+	#[arg(long)]
+	pub flap_threshold: Option<u32>,
+
+	/// The number of seconds over which changes in health are counted towards the flap threshold.
+	#[arg(long)]
+	pub flap_window: Option<u64>,
+
+	/// The number of recent results kept for each client.
+	#[arg(long)]
+	pub history_size: Option<usize>,
+
+	/// The number of blocks a client can trail the highest client before it is reported as lagging.
+	#[arg(long)]
+	pub max_block_diff: Option<u64>,
+
+	/// The number of consecutive cycles a lagging client can go without advancing before it is reported as stalled.
+	#[arg(long)]
+	pub stall_cycles: Option<usize>,
+
+	/// The number of consecutive cycles any client can report the same block number before its tip is reported as frozen, or 0 to disable the warning.
+	#[arg(long)]
+	pub frozen_cycles: Option<usize>,
+
+	/// How a client is decided to be lagging: fixed to use the maximum block difference, or outlier to compare it with the other clients in its group.
+	#[arg(long)]
+	pub lag_mode: Option<LagMode>,
+
+	/// In the outlier lag mode, the number of scaled median absolute deviations below the median tip that counts as lagging.
+	#[arg(long)]
+	pub outlier_threshold: Option<f64>,
+
+	/// The number of blocks the filtering of a script can trail the tip of its client before a warning.
+	#[arg(long)]
+	pub max_script_lag: Option<u64>,
+
+	/// The fraction of the clients in a group, from 0.0 to 1.0, that can share a single remote peer before a warning.
+	#[arg(long)]
+	pub shared_peer_fraction: Option<f64>,
+
+	/// The average number of peers that can connect or disconnect between peers checks of a client before a warning.
+	#[arg(long)]
+	pub max_peer_churn: Option<f64>,
+
+	/// The number of seconds the longest connected peer of a client must have been connected for before a warning. A value of 0 disables the warning.
+	#[arg(long)]
+	pub min_peer_age: Option<u64>,
+
+	/// The oldest version the peers of a client can run, such as 0.113.0, before a warning that every peer is outdated.
+	#[arg(long)]
+	pub min_peer_version: Option<String>,
+
+	/// The number of seconds old the tip block of a client can be before a warning. A value of 0 disables the warning.
+	#[arg(long)]
+	pub max_tip_age: Option<u64>,
+
+	/// The number of days before the TLS certificate of an HTTPS client expires that a warning is given. A value of 0 disables the warning.
+	#[arg(long)]
+	pub min_certificate_days: Option<u64>,
+
+	/// The number of seconds a client can stay in the same epoch before a warning. A value of 0 disables the warning.
+	#[arg(long)]
+	pub max_epoch_age: Option<u64>,
+
+	/// The number of seconds the clock of a client host can differ from that of the reference nodes before a warning. A value of 0 disables the warning.
+	#[arg(long)]
+	pub max_clock_skew: Option<u64>,
+
+	/// The oldest client version that does not cause a warning, such as 0.3.0.
+	#[arg(long)]
+	pub min_version: Option<String>,
+
+	/// The number of peers a client needs to avoid a warning. A value of 0 disables the warning.
+	#[arg(long)]
+	pub min_peers: Option<u16>,
+
+	/// The number of milliseconds a client can take to respond before a warning.
+	#[arg(long)]
+	pub max_latency_ms: Option<u64>,
+
+	/// The number of megabytes of memory the process of a locally run client can use before a warning.
+	#[arg(long)]
+	pub max_memory_mb: Option<u64>,
+
+	/// The hash of a header the fetch check asks each client to fetch.
+	#[arg(long)]
+	pub fetch_probe_header: Option<H256>,
+
+	/// The hash of a transaction the fetch check asks each client to fetch.
+	#[arg(long)]
+	pub fetch_probe_transaction: Option<H256>,
+
+	/// The number of seconds the fetch check waits for a fetch to complete.
+	#[arg(long)]
+	pub fetch_timeout: Option<u64>,
+
+	/// The number of cells the cells check must find for the canary script.
+	#[arg(long)]
+	pub min_canary_cells: Option<usize>,
+
+	/// The number of seconds between measurements of the data directory of each locally run client.
+	#[arg(long)]
+	pub disk_interval: Option<u64>,
+
+	/// The number of megabytes the data directory of a locally run client can grow to before a warning.
+	#[arg(long)]
+	pub max_data_dir_mb: Option<u64>,
+
+	/// The percentage of the filesystem holding a data directory that must stay free to avoid a warning.
+	#[arg(long)]
+	pub min_disk_free_percent: Option<f64>,
+
+	/// The number of seconds to wait for a connection to a client before it is considered offline.
+	#[arg(long)]
+	pub connect_timeout: Option<u64>,
+
+	/// The number of milliseconds to wait for the host of a client to resolve and accept a TCP connection before each round of checks, or 0 to skip the probe.
+	#[arg(long)]
+	pub probe_timeout_ms: Option<u64>,
+
+	/// Report RPC results with fields the monitor does not know about as schema drift.
+	#[arg(long)]
+	pub strict_schema: bool,
+
+	/// The number of seconds to wait for a complete RPC response before a client is considered offline.
+	#[arg(long)]
+	pub request_timeout: Option<u64>,
+
+	/// A MaxMind ASN database used to warn when all the peers of a client are in the same autonomous system.
+	#[arg(long)]
+	pub asn_database: Option<PathBuf>,
+
+	/// The maximum number of RPC requests per second sent to all clients combined.
+	#[arg(long)]
+	pub max_requests_per_second: Option<f64>,
+
+	/// Only monitor these clients, given as numbers, ranges, or labels. (e.g. 3,7,12-20)
+	#[arg(long)]
+	pub only: Option<ClientSet>,
+
+	/// Do not monitor these clients, given as numbers, ranges, or labels. (e.g. 50-60)
+	#[arg(long)]
+	pub skip: Option<ClientSet>,
+
+	/// A command to run instead of monitoring the clients.
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+/// Commands that act on the monitor instead of running it.
+#[derive(Debug, Subcommand)]
+pub enum Command
+{
+	/// Acknowledge an ongoing condition of a client, pausing its reminders until it ends or the acknowledgment expires.
+	Ack
+	{
+		/// The client, named as it is in alerts. (e.g. mainnet/relay-us-1)
+		client: String,
+
+		/// The condition: offline, degraded, lagging, stalled, frozen, flapping, or low_peers.
+		condition: Condition,
+
+		/// Who is acknowledging the condition. Defaults to the current user.
+		#[arg(long, env = "USER")]
+		by: String,
+
+		/// The number of seconds after which reminders resume if the condition has not ended.
+		#[arg(long)]
+		expires: Option<u64>,
+	},
+}
+
+impl Args
+{
+	/// Returns the filter that selects which clients are monitored.
+	pub fn filter(&self) -> ClientFilter
+	{
+		ClientFilter
+		{
+			only: self.only.clone(),
+			skip: self.skip.clone(),
+		}
+	}
+
+	/// Overrides settings in the config with any values given on the command line.
+	pub fn apply(&self, config: &mut Config)
+	{
+		if let Some(host) = &self.host
+		{
+			config.host = host.clone();
+		}
+		if let Some(starting_port) = self.starting_port
+		{
+			config.starting_port = starting_port;
+		}
+		if let Some(total_clients) = self.total_clients
+		{
+			config.total_clients = total_clients;
+		}
+		if let Some(check_interval) = &self.check_interval
+		{
+			config.check_interval = check_interval.clone();
+		}
+		if let Some(asn_database) = &self.asn_database
+		{
+			config.asn_database = Some(asn_database.clone());
+		}
+		if let Some(rpc_interval) = &self.rpc_interval
+		{
+			config.rpc_interval = Some(rpc_interval.clone());
+		}
+		if let Some(peers_interval) = &self.peers_interval
+		{
+			config.peers_interval = Some(peers_interval.clone());
+		}
+		if let Some(tip_interval) = &self.tip_interval
+		{
+			config.tip_interval = Some(tip_interval.clone());
+		}
+		if let Some(genesis_interval) = &self.genesis_interval
+		{
+			config.genesis_interval = genesis_interval.clone();
+		}
+		if let Some(genesis_hash) = self.genesis_hash
+		{
+			config.genesis_hash = Some(genesis_hash);
+		}
+		if let Some(audit_interval) = &self.audit_interval
+		{
+			config.audit_interval = audit_interval.clone();
+		}
+		if let Some(summary_interval) = &self.summary_interval
+		{
+			config.summary_interval = summary_interval.clone();
+		}
+		if let Some(check_spread) = self.check_spread
+		{
+			config.check_spread = check_spread;
+		}
+		if let Some(degraded_interval) = self.degraded_interval
+		{
+			config.degraded_interval = Some(degraded_interval);
+		}
+		if let Some(offline_after) = self.offline_after
+		{
+			config.offline_after = offline_after;
+		}
+		if let Some(recover_after) = self.recover_after
+		{
+			config.recover_after = recover_after;
+		}
+		if let Some(flap_threshold) = self.flap_threshold
+		{
+			config.flap_threshold = flap_threshold;
+		}
+		if let Some(flap_window) = self.flap_window
+		{
+			config.flap_window = flap_window;
+		}
+		if let Some(history_size) = self.history_size
+		{
+			config.history_size = history_size;
+		}
+		if let Some(max_block_diff) = self.max_block_diff
+		{
+			config.max_block_diff = max_block_diff;
+		}
+		if let Some(stall_cycles) = self.stall_cycles
+		{
+			config.stall_cycles = stall_cycles;
+		}
+		if let Some(frozen_cycles) = self.frozen_cycles
+		{
+			config.frozen_cycles = frozen_cycles;
+		}
+		if let Some(lag_mode) = self.lag_mode
+		{
+			config.lag_mode = lag_mode;
+		}
+		if let Some(outlier_threshold) = self.outlier_threshold
+		{
+			config.outlier_threshold = outlier_threshold;
+		}
+		if let Some(max_script_lag) = self.max_script_lag
+		{
+			config.max_script_lag = max_script_lag;
+		}
+		if let Some(shared_peer_fraction) = self.shared_peer_fraction
+		{
+			config.shared_peer_fraction = shared_peer_fraction;
+		}
+		if let Some(max_peer_churn) = self.max_peer_churn
+		{
+			config.max_peer_churn = max_peer_churn;
+		}
+		if let Some(min_peer_age) = self.min_peer_age
+		{
+			config.min_peer_age = min_peer_age;
+		}
+		if let Some(min_peer_version) = self.min_peer_version.clone()
+		{
+			config.min_peer_version = Some(min_peer_version);
+		}
+		if let Some(max_tip_age) = self.max_tip_age
+		{
+			config.max_tip_age = max_tip_age;
+		}
+		if let Some(min_certificate_days) = self.min_certificate_days
+		{
+			config.min_certificate_days = min_certificate_days;
+		}
+		if let Some(max_epoch_age) = self.max_epoch_age
+		{
+			config.max_epoch_age = max_epoch_age;
+		}
+		if let Some(max_clock_skew) = self.max_clock_skew
+		{
+			config.max_clock_skew = max_clock_skew;
+		}
+		if let Some(min_version) = self.min_version.clone()
+		{
+			config.min_version = Some(min_version);
+		}
+		if let Some(min_peers) = self.min_peers
+		{
+			config.min_peers = min_peers;
+		}
+		if let Some(max_latency_ms) = self.max_latency_ms
+		{
+			config.max_latency_ms = Some(max_latency_ms);
+		}
+		if let Some(max_memory_mb) = self.max_memory_mb
+		{
+			config.max_memory_mb = Some(max_memory_mb);
+		}
+		if let Some(fetch_probe_header) = self.fetch_probe_header
+		{
+			config.fetch_probe_header = Some(fetch_probe_header);
+		}
+		if let Some(fetch_probe_transaction) = self.fetch_probe_transaction
+		{
+			config.fetch_probe_transaction = Some(fetch_probe_transaction);
+		}
+		if let Some(fetch_timeout) = self.fetch_timeout
+		{
+			config.fetch_timeout = fetch_timeout;
+		}
+		if let Some(min_canary_cells) = self.min_canary_cells
+		{
+			config.min_canary_cells = min_canary_cells;
+		}
+		if let Some(disk_interval) = self.disk_interval
+		{
+			config.disk_interval = disk_interval;
+		}
+		if let Some(max_data_dir_mb) = self.max_data_dir_mb
+		{
+			config.max_data_dir_mb = Some(max_data_dir_mb);
+		}
+		if let Some(min_disk_free_percent) = self.min_disk_free_percent
+		{
+			config.min_disk_free_percent = min_disk_free_percent;
+		}
+		if let Some(connect_timeout) = self.connect_timeout
+		{
+			config.connect_timeout = connect_timeout;
+		}
+		if let Some(probe_timeout_ms) = self.probe_timeout_ms
+		{
+			config.probe_timeout_ms = probe_timeout_ms;
+		}
+		if self.strict_schema
+		{
+			config.strict_schema = true;
+		}
+		if let Some(request_timeout) = self.request_timeout
+		{
+			config.request_timeout = request_timeout;
+		}
+		if let Some(max_requests_per_second) = self.max_requests_per_second
+		{
+			config.max_requests_per_second = Some(max_requests_per_second);
+		}
+	}
+}
@@ -0,0 +1,315 @@
+use crate::config::ClientSettings;
+use crate::disk::DiskStats;
+use crate::endpoint::RpcEndpoint;
+use crate::events::MonitorEvent;
+use crate::health::{FlapDetector, Health, HealthTracker, OutageStats};
+use crate::history::RingBuffer;
+use crate::latency::{LatencyStats, LATENCY_WINDOW};
+use crate::logtail::LogWatcher;
+use crate::process::{CpuSample, ProcessStats};
+use crate::rpc::{Epoch, H256, Peer, ScriptStatus};
+use chrono::{Local, DateTime, Utc};
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// The number of recent peers checks that the peer churn of a client is averaged over.
+pub const PEER_CHURN_WINDOW: usize = 10;
+
+/// The number of recent new tips that the clock offset of a client is estimated from.
+pub const TIP_DELAY_WINDOW: usize = 10;
+
+/// The results of the most recent checks on a client.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientState
+{
+	pub health: Health,
+	pub block_number: u64,
+	/// The hash of the tip block, used to tell if clients at the same height are on the same chain.
+	pub tip_hash: Option<H256>,
+	/// When the tip block was mined.
+	pub tip_time: Option<DateTime<Local>>,
+	/// The epoch of the tip block.
+	pub epoch: Option<Epoch>,
+	/// The hash of the genesis block of the chain the client is on.
+	pub genesis_hash: Option<H256>,
+	pub peers: u16,
+	pub time_offline: Option<DateTime<Local>>,
+	/// The outages the client has recovered from.
+	pub outages: OutageStats,
+	/// How far ahead of the timestamps of new headers the client took them, which is compared between clients to find
+	/// a host whose clock is skewed.
+	pub clock_offset: Option<chrono::Duration>,
+	/// The average number of peers that connected or disconnected between recent peers checks.
+	pub peer_churn: Option<f64>,
+	/// The lowest block number any of the filter scripts of the client has been synced to.
+	pub script_block_number: Option<u64>,
+	/// How long the client took to respond to its last RPC request, or `None` if it did not respond.
+	pub latency: Option<Duration>,
+	/// Statistics of the round trip times of its recent RPC requests.
+	pub latency_stats: Option<LatencyStats>,
+	/// How long the client took to fetch the probe data at its last fetch check.
+	pub fetch_time: Option<Duration>,
+	/// The resource usage of the process of the client, if it is run locally.
+	pub process: Option<ProcessStats>,
+	/// The disk usage of the data directory of the client, if it is run locally.
+	pub disk: Option<DiskStats>,
+	/// The number of checks that have failed or found the client unresponsive since it started being checked.
+	pub check_errors: u64,
+	/// The number of times the client has gone offline since it started being checked.
+	pub offline_transitions: u64,
+}
+
+impl ClientState
+{
+	/// Returns true if the client is responding, even if it is degraded.
+	pub fn is_online(&self) -> bool
+	{
+		self.health.is_online()
+	}
+}
+
+/// Represents a CKB light client.
+pub struct Client 
+{
+	pub number: usize,
+	pub group: String,
+	pub settings: ClientSettings,
+	pub endpoint: RpcEndpoint,
+	pub health: HealthTracker,
+	/// Tells when the client keeps bouncing between healths.
+	pub flaps: FlapDetector,
+	pub block_number: u64,
+	pub tip_hash: Option<H256>,
+	/// When the tip block was mined.
+	pub tip_time: Option<DateTime<Local>>,
+	/// Whether the tip block is older than the maximum tip age.
+	pub tip_stale: bool,
+	/// The epoch of the tip block.
+	pub epoch: Option<Epoch>,
+	/// When the client was first seen in its current epoch.
+	pub epoch_since: Option<Instant>,
+	/// Whether the client has been in the same epoch for longer than the maximum epoch age.
+	pub epoch_stalled: bool,
+	/// The hash of the genesis block of the chain the client is on, once it has been checked.
+	pub genesis_hash: Option<H256>,
+	/// The last block number the client reported. Unlike `block_number`, this is kept while the client is offline, so
+	/// a client that restarts and resyncs from an earlier block is noticed.
+	pub last_block_number: u64,
+	/// The peers the client was connected to at its last peers check.
+	pub connected_peers: Vec<Peer>,
+	/// Whether `connected_peers` holds the result of a peers check since the client last came online.
+	pub peers_known: bool,
+	/// The number of peers that connected or disconnected between each of the recent peers checks.
+	pub peer_churn: RingBuffer<usize>,
+	/// Whether the peer churn of the client is above its maximum.
+	pub churning: bool,
+	/// Whether every peer of the client connected more recently than the minimum peer age.
+	pub peers_fresh: bool,
+	/// Whether every peer of the client is running a version older than the minimum peer version.
+	pub peers_outdated: bool,
+	/// Whether the client has fewer peers than its minimum.
+	pub peers_low: bool,
+	/// Whether the client is connected to none of its expected peers.
+	pub expected_peers_missing: bool,
+	/// Whether the client is connected to any peers it is not expected to be.
+	pub unexpected_peers: bool,
+	/// The version string the client reported in its last `local_node_info` response.
+	pub version: Option<String>,
+	/// The scripts the client is filtering for, along with the block number each has been synced to.
+	pub scripts: Vec<ScriptStatus>,
+	/// Whether the filtering of any script trails the tip by more than the maximum script lag.
+	pub scripts_lagging: bool,
+	pub peers: u16,
+	pub latency: Option<Duration>,
+	/// The round trip times of the most recent RPC requests.
+	pub latency_window: RingBuffer<Duration>,
+	/// Whether the latency of the client is above its maximum.
+	pub slow: bool,
+	/// How long after their timestamps the client took each of its recent new tips.
+	pub tip_delays: RingBuffer<chrono::Duration>,
+	/// How far ahead of the timestamps of new headers the clock of the client host was when it took them, from the
+	/// quickest of its recent new tips. This is negative, and further below zero when the clock of the host is behind.
+	pub clock_offset: Option<chrono::Duration>,
+	/// When the TLS certificate the client presented in its last HTTPS response expires.
+	pub certificate_expiry: Option<DateTime<Utc>>,
+	/// Whether the TLS certificate of the client expires sooner than the minimum certificate age.
+	pub certificate_expiring: bool,
+	/// How long the client took to fetch the probe data at its last fetch check.
+	pub fetch_time: Option<Duration>,
+	/// The resource usage of the process of the client, if it is run locally and was found at the last round of checks.
+	pub process: Option<ProcessStats>,
+	/// The CPU time of the process at the last sample, used to calculate its CPU usage at the next.
+	pub cpu_sample: Option<CpuSample>,
+	/// Whether the memory of the process is above its maximum.
+	pub memory_high: bool,
+	/// The disk usage of the data directory of the client, if it is run locally and was measured successfully.
+	pub disk: Option<DiskStats>,
+	/// When the data directory was last measured.
+	pub disk_measured: Option<Instant>,
+	/// Whether the data directory is over its maximum size or its filesystem is below the minimum free space.
+	pub disk_full: bool,
+	/// The number of checks that have failed or found the client unresponsive.
+	pub check_errors: u64,
+	/// The number of times the client has gone offline.
+	pub offline_transitions: u64,
+	/// Follows the log file of the client, if it has one.
+	pub log_watcher: Option<LogWatcher>,
+	events: broadcast::Sender<MonitorEvent>,
+}
+
+impl Client 
+{
+	/// Creates a new `Client` that broadcasts what it observes to `events`.
+	pub fn new(number: usize, group: String, settings: ClientSettings, events: broadcast::Sender<MonitorEvent>) -> Self 
+	{
+		Self 
+		{
+			number,
+			group,
+			endpoint: RpcEndpoint::new(&settings),
+			log_watcher: log_watcher(&settings),
+			settings,
+			health: HealthTracker::new(),
+			flaps: FlapDetector::default(),
+			block_number: 0,
+			tip_hash: None,
+			tip_time: None,
+			tip_stale: false,
+			epoch: None,
+			epoch_since: None,
+			epoch_stalled: false,
+			genesis_hash: None,
+			last_block_number: 0,
+			connected_peers: Vec::new(),
+			peers_known: false,
+			peer_churn: RingBuffer::new(PEER_CHURN_WINDOW),
+			churning: false,
+			peers_fresh: false,
+			peers_outdated: false,
+			peers_low: false,
+			expected_peers_missing: false,
+			unexpected_peers: false,
+			version: None,
+			scripts: Vec::new(),
+			scripts_lagging: false,
+			peers: 0,
+			latency: None,
+			latency_window: RingBuffer::new(LATENCY_WINDOW),
+			slow: false,
+			tip_delays: RingBuffer::new(TIP_DELAY_WINDOW),
+			clock_offset: None,
+			certificate_expiry: None,
+			certificate_expiring: false,
+			fetch_time: None,
+			process: None,
+			cpu_sample: None,
+			memory_high: false,
+			disk: None,
+			disk_measured: None,
+			disk_full: false,
+			check_errors: 0,
+			offline_transitions: 0,
+			events,
+		}
+	}
+
+	/// Returns the average number of peers that connected or disconnected between each of the recent peers checks, or
+	/// `None` until there have been enough checks to tell.
+	pub fn peer_churn_rate(&self) -> Option<f64>
+	{
+		if self.peer_churn.len() < self.peer_churn.capacity()
+		{
+			return None;
+		}
+
+		Some(self.peer_churn.iter().sum::<usize>() as f64 / self.peer_churn.len() as f64)
+	}
+
+	/// Broadcasts an event to all subscribers. Events are dropped if there are none.
+	pub fn emit(&self, event: MonitorEvent)
+	{
+		let _ = self.events.send(event);
+	}
+
+	/// Replaces the settings of the client, recreating its endpoint so the new connection options take effect.
+	pub fn update_settings(&mut self, settings: ClientSettings)
+	{
+		self.endpoint = RpcEndpoint::new(&settings);
+		if settings.log_file != self.settings.log_file || settings.log_patterns != self.settings.log_patterns
+		{
+			self.log_watcher = log_watcher(&settings);
+		}
+		self.settings = settings;
+	}
+
+	/// Returns the results of the most recent checks on the client.
+	pub fn state(&self) -> ClientState
+	{
+		ClientState
+		{
+			health: self.health.health(),
+			block_number: self.block_number,
+			tip_hash: self.tip_hash,
+			tip_time: self.tip_time,
+			epoch: self.epoch,
+			genesis_hash: self.genesis_hash,
+			peers: self.peers,
+			time_offline: self.time_offline(),
+			outages: self.health.outages(),
+			clock_offset: self.clock_offset,
+			peer_churn: self.peer_churn_rate(),
+			script_block_number: self.scripts.iter().map(|status| status.block_number).min(),
+			latency: self.latency,
+			latency_stats: LatencyStats::new(&self.latency_window),
+			fetch_time: self.fetch_time,
+			process: self.process,
+			disk: self.disk,
+			check_errors: self.check_errors,
+			offline_transitions: self.offline_transitions,
+		}
+	}
+
+	/// Returns when the client went offline, or `None` if it is not offline.
+	pub fn time_offline(&self) -> Option<DateTime<Local>>
+	{
+		match self.health.health()
+		{
+			Health::Offline => Some(self.health.since()),
+			_ => None,
+		}
+	}
+
+	/// Returns the label of the client, or its number if it has no label.
+	pub fn name(&self) -> String
+	{
+		match &self.settings.label
+		{
+			Some(label) => label.clone(),
+			None => self.number.to_string(),
+		}
+	}
+}
+
+impl fmt::Display for Client
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}/{}", self.group, self.name())
+	}
+}
+
+/// Creates the watcher for the log file of a client, if it has one.
+fn log_watcher(settings: &ClientSettings) -> Option<LogWatcher>
+{
+	let path = settings.log_file.clone()?;
+	match LogWatcher::new(path, &settings.log_patterns)
+	{
+		Ok(log_watcher) => Some(log_watcher),
+		Err(e) =>
+		{
+			tracing::warn!("Unable to watch the log of {}: {}", settings.url, e);
+			None
+		}
+	}
+}
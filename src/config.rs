@@ -0,0 +1,1514 @@
+use crate::alertmanager::AlertmanagerConfig;
+use crate::alerts::{AlertRoute, AlertSettings, Condition, RateLimit, SeverityRule};
+use crate::checks::{self, PEERS_CHECK, RPC_CHECK, TIP_CHECK};
+use crate::desktop::DesktopConfig;
+use crate::discord::DiscordConfig;
+use crate::endpoint::{AuthSettings, TlsSettings, TlsVersion};
+use crate::exec::ExecConfig;
+use crate::fleet::FleetCondition;
+use crate::gotify::GotifyConfig;
+use crate::health::HealthRules;
+use crate::heartbeat::HeartbeatConfig;
+use crate::logtail::DEFAULT_LOG_PATTERNS;
+use crate::matrix::MatrixConfig;
+use crate::mqtt::MqttConfig;
+use crate::ntfy::NtfyConfig;
+use crate::otlp::OtlpConfig;
+use crate::pushover::PushoverConfig;
+use crate::process::ProcessSource;
+use crate::quiet::QuietHours;
+use crate::retry::RetryPolicy;
+use crate::rpc::{H256, Script, ScriptStatus};
+use crate::scheduler::Schedule;
+use crate::slack::SlackConfig;
+use crate::targets::{self, TargetGroup};
+use crate::telegram::TelegramConfig;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{env, error::Error, fs, path::{Path, PathBuf}, str::FromStr};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// The config file that is loaded when no other path is given.
+pub const DEFAULT_CONFIG_PATH: &str = "monitor.toml";
+
+/// The number of seconds in a day, for settings that are given in days.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+const DEFAULT_HOST: &str = "http://127.0.0.1";
+const DEFAULT_STARTING_PORT: u16 = 19000;
+const DEFAULT_TOTAL_CLIENTS: usize = 100;
+const DEFAULT_CHECK_INTERVAL: u64 = 60;
+const DEFAULT_MAX_BLOCK_DIFF: u64 = 30;
+const DEFAULT_MIN_PEERS: u16 = 0;
+const DEFAULT_TARGETS_REFRESH_INTERVAL: u64 = 30;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 5;
+const DEFAULT_PROBE_TIMEOUT_MS: u64 = 1000;
+const DEFAULT_REQUEST_TIMEOUT: u64 = 10;
+const DEFAULT_RETRY_ATTEMPTS: u32 = 2;
+const DEFAULT_RETRY_INITIAL_BACKOFF_MS: u64 = 500;
+const DEFAULT_RETRY_MAX_BACKOFF_MS: u64 = 5000;
+const DEFAULT_RETRY_JITTER: f64 = 0.2;
+const DEFAULT_WEBHOOK_RETRY_ATTEMPTS: u32 = 5;
+const DEFAULT_ALERT_COOLDOWN: u64 = 300;
+const DEFAULT_ALERT_RATE_WINDOW: u64 = 3600;
+const DEFAULT_DEPENDENT_ALERT_GRACE: u64 = 60;
+const DEFAULT_ACKS_FILE: &str = "acks.json";
+const DEFAULT_CHECK_SPREAD: f64 = 0.0;
+const DEFAULT_OFFLINE_AFTER: u32 = 1;
+const DEFAULT_RECOVER_AFTER: u32 = 1;
+const DEFAULT_FLAP_THRESHOLD: u32 = 6;
+const DEFAULT_FLAP_WINDOW: u64 = 600;
+const DEFAULT_HISTORY_SIZE: usize = 1440;
+const DEFAULT_STALL_CYCLES: usize = 5;
+const DEFAULT_FROZEN_CYCLES: usize = 30;
+const DEFAULT_OUTLIER_THRESHOLD: f64 = 3.5;
+const DEFAULT_MAX_SCRIPT_LAG: u64 = 1000;
+const DEFAULT_SHARED_PEER_FRACTION: f64 = 0.5;
+const DEFAULT_MAX_PEER_CHURN: f64 = 4.0;
+const DEFAULT_MIN_PEER_AGE: u64 = 300;
+const DEFAULT_MAX_TIP_AGE: u64 = 1800;
+const DEFAULT_MIN_CERTIFICATE_DAYS: u64 = 14;
+const DEFAULT_MAX_EPOCH_AGE: u64 = 28800;
+const DEFAULT_MAX_CLOCK_SKEW: u64 = 30;
+const DEFAULT_CHAIN_HALT_AFTER: u64 = 900;
+const DEFAULT_GENESIS_INTERVAL: u64 = 3600;
+const DEFAULT_AUDIT_INTERVAL: u64 = 600;
+const DEFAULT_SUMMARY_INTERVAL: u64 = 3600;
+const DEFAULT_DISK_INTERVAL: u64 = 300;
+const DEFAULT_MIN_DISK_FREE_PERCENT: f64 = 10.0;
+const DEFAULT_FETCH_TIMEOUT: u64 = 30;
+const DEFAULT_MIN_CANARY_CELLS: usize = 1;
+
+/// The name of the group that holds the clients when no groups are configured.
+const DEFAULT_GROUP_NAME: &str = "default";
+
+/// The prefix of all environment variables that override config settings.
+const ENV_PREFIX: &str = "CKB_MONITOR_";
+
+/// Settings for the monitor, loaded from a TOML, YAML, or JSON config file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config
+{
+	/// The host all light clients are running on.
+	pub host: String,
+	/// The RPC port of the first client. Each following client uses the next port.
+	pub starting_port: u16,
+	/// The number of clients to monitor.
+	pub total_clients: usize,
+	/// How often to check clients, as a number of seconds or cron expressions.
+	pub check_interval: Schedule,
+	/// How often to run RPC liveness checks. Defaults to `check_interval`.
+	pub rpc_interval: Option<Schedule>,
+	/// How often to run peer count checks. Defaults to `check_interval`.
+	pub peers_interval: Option<Schedule>,
+	/// How often to run tip block number checks. Defaults to `check_interval`.
+	pub tip_interval: Option<Schedule>,
+	/// How often to check which chain each client is on. Clients are also checked when they are first started.
+	pub genesis_interval: Schedule,
+	/// The hash of the genesis block every client should be on. Defaults to that of the majority of each group.
+	pub genesis_hash: Option<H256>,
+	/// How often to audit the clients of each group against each other at a random recent height.
+	pub audit_interval: Schedule,
+	/// How often to summarize the outages each client has recovered from.
+	pub summary_interval: Schedule,
+	/// The names of the checks to run on each client. All checks are run by default.
+	pub checks: Vec<String>,
+	/// The fraction of each interval, from 0.0 to 1.0, over which the checks of the clients in a group are spread.
+	pub check_spread: f64,
+	/// The number of seconds between the rpc and tip checks of a client that is offline or lagging, until it recovers.
+	pub degraded_interval: Option<u64>,
+	/// The number of consecutive rounds of checks a client must fail to respond to before it is considered offline.
+	pub offline_after: u32,
+	/// The number of consecutive rounds of passing checks a degraded or offline client needs to be considered online.
+	pub recover_after: u32,
+	/// The number of changes in health within the flap window at which a client is reported as flapping, or 0 to
+	/// report every change.
+	pub flap_threshold: u32,
+	/// The number of seconds over which changes in health are counted towards the flap threshold.
+	pub flap_window: u64,
+	/// The number of recent results kept for each client. The oldest are dropped once it is full.
+	pub history_size: usize,
+	/// The number of blocks a client can trail the highest client before it is reported as lagging.
+	pub max_block_diff: u64,
+	/// How a client is decided to be lagging, either `fixed` to use `max_block_diff`, or `outlier` to compare it with
+	/// the spread of the tips of the other clients in its group.
+	pub lag_mode: LagMode,
+	/// In the outlier lag mode, the number of scaled median absolute deviations a client can be below the median tip of
+	/// its group before it is reported as lagging.
+	pub outlier_threshold: f64,
+	/// The number of consecutive cycles a lagging client can go without advancing before it is reported as stalled.
+	pub stall_cycles: usize,
+	/// The number of consecutive cycles any client can report the same block number before its tip is reported as
+	/// frozen, whether or not it is lagging. A value of 0 disables the warning.
+	pub frozen_cycles: usize,
+	/// The number of blocks the filtering of a script can trail the tip of its client before a warning.
+	pub max_script_lag: u64,
+	/// The fraction of the clients in a group, from 0.0 to 1.0, that can share a single remote peer before a warning.
+	pub shared_peer_fraction: f64,
+	/// The average number of peers that can connect or disconnect between peers checks of a client before a warning.
+	pub max_peer_churn: f64,
+	/// The number of seconds the longest connected peer of a client must have been connected for, or a warning is
+	/// printed because every peer is new. A value of 0 disables the warning.
+	pub min_peer_age: u64,
+	/// The oldest version the peers of a client can run, such as `0.113.0`, before a warning that every peer is
+	/// outdated. Disabled when omitted.
+	pub min_peer_version: Option<String>,
+	/// The number of seconds old the tip block of a client can be before a warning. A value of 0 disables the warning.
+	pub max_tip_age: u64,
+	/// The number of days before the TLS certificate of an HTTPS client expires that a warning is given. A value of 0
+	/// disables the warning.
+	pub min_certificate_days: u64,
+	/// The number of seconds a client can stay in the same epoch before a warning. A value of 0 disables the warning.
+	pub max_epoch_age: u64,
+	/// The number of seconds the clock of a client host can differ from that of the reference nodes before a warning. A
+	/// value of 0 disables the warning.
+	pub max_clock_skew: u64,
+	/// The number of seconds the highest tip of a group can go without advancing before the chain is reported as halted.
+	/// A value of 0 disables the alert.
+	pub chain_halt_after: u64,
+	/// The oldest client version that does not cause a warning, such as `0.3.0`. Disabled when omitted.
+	pub min_version: Option<String>,
+	/// The RPC endpoints of trusted nodes, such as a full node or a public API. When any are online, the highest of
+	/// their tips is used as the height that clients are measured against, instead of the highest client.
+	pub reference_endpoints: Vec<String>,
+	/// The number of peers a client needs to avoid a warning. A value of 0 disables the warning.
+	pub min_peers: u16,
+	/// The number of milliseconds a client can take to respond before a warning. Disabled when omitted.
+	pub max_latency_ms: Option<u64>,
+	/// The number of megabytes of memory the process of a locally run client can use before a warning. Disabled when
+	/// omitted.
+	pub max_memory_mb: Option<u64>,
+	/// The number of seconds between measurements of the data directory of each locally run client.
+	pub disk_interval: u64,
+	/// The number of megabytes the data directory of a locally run client can grow to before a warning. Disabled when
+	/// omitted.
+	pub max_data_dir_mb: Option<u64>,
+	/// The percentage of the filesystem holding a data directory that must stay free to avoid a warning.
+	pub min_disk_free_percent: f64,
+	/// The regular expressions that lines in the log file of a client are matched against.
+	pub log_patterns: Vec<String>,
+	/// The hash of a header the fetch check asks each client to fetch, instead of the parent of its tip.
+	pub fetch_probe_header: Option<H256>,
+	/// The hash of a transaction the fetch check asks each client to fetch. Takes precedence over `fetch_probe_header`.
+	pub fetch_probe_transaction: Option<H256>,
+	/// The number of seconds the fetch check waits for a fetch to complete before the client is considered degraded.
+	pub fetch_timeout: u64,
+	/// A well known script that every client filters for, whose cells the cells check looks up.
+	pub canary_script: Option<ExpectedScript>,
+	/// The number of cells the cells check must find for the canary script.
+	pub min_canary_cells: usize,
+	/// The number of seconds to wait for a connection to a client before it is considered offline.
+	pub connect_timeout: u64,
+	/// The number of milliseconds to wait for the host of an HTTP client to resolve and accept a TCP connection before
+	/// each round of checks. A value of 0 disables the probe.
+	pub probe_timeout_ms: u64,
+	/// Set to true to check every RPC result for fields the monitor does not know about, and report them as schema
+	/// drift instead of ignoring them.
+	pub strict_schema: bool,
+	/// The number of seconds to wait for a complete RPC response before a client is considered offline.
+	pub request_timeout: u64,
+	/// The number of times a failed RPC request is retried before the failure is reported.
+	pub retry_attempts: u32,
+	/// The number of milliseconds to wait before the first retry. The delay doubles with each retry.
+	pub retry_initial_backoff_ms: u64,
+	/// The maximum number of milliseconds to wait between retries.
+	pub retry_max_backoff_ms: u64,
+	/// The fraction of each retry delay that is randomized, from 0.0 to 1.0.
+	pub retry_jitter: f64,
+	/// A MaxMind ASN database, such as GeoLite2-ASN, used to warn when all the peers of a client are in the same
+	/// autonomous system. Only subnets are compared when omitted.
+	pub asn_database: Option<PathBuf>,
+	/// The maximum number of RPC requests per second sent to all clients combined. Unlimited when omitted.
+	pub max_requests_per_second: Option<f64>,
+	/// URLs that a JSON payload is posted to whenever a client goes offline, comes back online, starts lagging, or
+	/// catches up.
+	pub webhook_urls: Vec<String>,
+	/// The number of times a failed webhook delivery is retried, using the same backoff as RPC requests.
+	pub webhook_retry_attempts: u32,
+	/// Whether payloads that a client has recovered, such as coming back online, are not posted to webhooks.
+	pub webhook_mute_recoveries: bool,
+	/// A shared secret that each webhook payload is signed with, in an `X-Signature-256` header, so receivers can check
+	/// that it came from the monitor. Payloads are not signed when omitted.
+	pub webhook_secret: Option<String>,
+	/// The hours of the day during which only the most severe payloads are posted to webhooks straight away, and the
+	/// rest are held until the next digest. Every payload is posted straight away when omitted.
+	pub webhook_quiet_hours: Option<QuietHours>,
+	/// How many seconds after a condition of a client is alerted, such as it going offline, that the same condition
+	/// starting again is not alerted, nor is it ending. 0 alerts every change.
+	pub alert_cooldown: u64,
+	/// How many seconds apart reminders are sent while an alerted condition lasts. 0 disables reminders.
+	pub alert_renotify_interval: u64,
+	/// How many seconds each condition lasts before it escalates from a warning to critical, such as `offline = 900`.
+	/// Conditions that are not listed never escalate.
+	pub escalate_after: HashMap<Condition, u64>,
+	/// Routes that send alerts to destinations by group, severity, and event type. The first route an alert matches
+	/// decides where it is sent. Every alert is sent everywhere when there are none.
+	pub alert_routes: Vec<AlertRoute>,
+	/// Handlebars templates that replace the text of alerts, keyed by event type, such as
+	/// `offline = "{{label}} ({{endpoint}}) is down: {{reason}}"`.
+	pub alert_templates: HashMap<String, String>,
+	/// Conditions over the clients of the whole fleet, or of a group, such as too many of them being offline, that are
+	/// each alerted once rather than for every client.
+	pub fleet_conditions: Vec<FleetCondition>,
+	/// How many seconds alerts about clients are held, so alerts of the same type about clients on the same host are
+	/// sent as one. 0 sends each alert at once.
+	pub alert_aggregation_window: u64,
+	/// Whether alerts about clients are suppressed while something they depend on is down, such as every client on
+	/// their host or every reference node of their group being offline, leaving a single alert about it.
+	pub suppress_dependent_alerts: bool,
+	/// How many seconds alerts that a condition of a client with a parent started are held while alerts about dependent
+	/// clients are suppressed, so they can be dropped if the parent turns out to be down. 0 sends them at once.
+	pub dependent_alert_grace: u64,
+	/// Rules that set the severity of events past thresholds, such as lag above 500 blocks being critical. The most
+	/// severe rule an event matches is used, and events of a type with rules that match none of them are info.
+	pub severity_rules: Vec<SeverityRule>,
+	/// The most alerts each destination sends within the rate window. The alerts held back once it is reached are
+	/// counted in a summary sent when the window ends. 0 sends every alert.
+	pub alert_rate_limit: u32,
+	/// How many seconds the windows that `alert_rate_limit` counts alerts in last.
+	pub alert_rate_window: u64,
+	/// The file that the `ack` command writes acknowledgments of ongoing conditions to, and the monitor reads them from.
+	pub acks_file: PathBuf,
+	/// When to send a digest of the incidents, uptime, and lag of every client to the alert destinations, such as
+	/// `"0 0 9 * * *"` for 09:00 each day. No digest is sent when omitted.
+	pub digest_schedule: Option<Schedule>,
+	/// Where Slack notifications are sent. Nothing is sent to Slack when omitted.
+	pub slack: Option<SlackConfig>,
+	/// Where Discord notifications are sent. Nothing is sent to Discord when omitted.
+	pub discord: Option<DiscordConfig>,
+	/// Where Telegram notifications are sent. Nothing is sent to Telegram when omitted.
+	pub telegram: Option<TelegramConfig>,
+	/// Where Matrix notifications are sent. Nothing is sent to Matrix when omitted.
+	pub matrix: Option<MatrixConfig>,
+	/// A command that is run for each alert. No command is run when omitted.
+	pub exec: Option<ExecConfig>,
+	/// Where ntfy notifications are published. Nothing is published to ntfy when omitted.
+	pub ntfy: Option<NtfyConfig>,
+	/// Where Pushover notifications are sent. Nothing is sent to Pushover when omitted.
+	pub pushover: Option<PushoverConfig>,
+	/// Where Gotify notifications are sent. Nothing is sent to Gotify when omitted.
+	pub gotify: Option<GotifyConfig>,
+	/// The Prometheus Alertmanager that alerts are forwarded to. Nothing is forwarded when omitted.
+	pub alertmanager: Option<AlertmanagerConfig>,
+	/// How native desktop notifications are shown. None are shown when omitted.
+	pub desktop: Option<DesktopConfig>,
+	/// Where every event and the status of each client are published over MQTT. Nothing is published when omitted.
+	pub mqtt: Option<MqttConfig>,
+	/// Prometheus metrics are served at `/metrics` on this address, such as `127.0.0.1:9100`. No metrics are served when
+	/// omitted.
+	pub metrics_address: Option<SocketAddr>,
+	/// The OpenTelemetry collector that traces of the checks and metrics of each client are exported to. Nothing is
+	/// exported when omitted.
+	pub otlp: Option<OtlpConfig>,
+	/// Where the monitor pings to show that it is running. Nothing is pinged when omitted.
+	pub heartbeat: Option<HeartbeatConfig>,
+	/// An explicit list of clients to monitor. When present, `host`, `starting_port`, and `total_clients` are ignored.
+	pub clients: Vec<ClientConfig>,
+	/// Named groups of clients. When present, the top level settings act as defaults for each group.
+	pub groups: Vec<GroupConfig>,
+	/// A Prometheus `file_sd` style targets file listing additional clients. It is reloaded whenever it changes.
+	pub targets_file: Option<PathBuf>,
+	/// The number of seconds between checks of the targets file for changes.
+	pub targets_refresh_interval: u64,
+	/// The target groups loaded from the targets file.
+	#[serde(skip)]
+	pub targets: Vec<TargetGroup>,
+}
+
+/// Settings for a named group of clients. Any setting that is omitted is inherited from the top level of the config.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig
+{
+	/// The name of the group, which is included in all output for its clients.
+	pub name: String,
+	pub host: Option<String>,
+	pub starting_port: Option<u16>,
+	pub total_clients: Option<usize>,
+	pub check_interval: Option<Schedule>,
+	pub rpc_interval: Option<Schedule>,
+	pub peers_interval: Option<Schedule>,
+	pub tip_interval: Option<Schedule>,
+	pub genesis_interval: Option<Schedule>,
+	pub genesis_hash: Option<H256>,
+	pub audit_interval: Option<Schedule>,
+	pub summary_interval: Option<Schedule>,
+	pub check_spread: Option<f64>,
+	pub degraded_interval: Option<u64>,
+	pub offline_after: Option<u32>,
+	pub recover_after: Option<u32>,
+	pub flap_threshold: Option<u32>,
+	pub flap_window: Option<u64>,
+	pub history_size: Option<usize>,
+	pub max_block_diff: Option<u64>,
+	pub stall_cycles: Option<usize>,
+	pub frozen_cycles: Option<usize>,
+	pub lag_mode: Option<LagMode>,
+	pub outlier_threshold: Option<f64>,
+	pub max_script_lag: Option<u64>,
+	pub shared_peer_fraction: Option<f64>,
+	pub max_peer_churn: Option<f64>,
+	pub min_peer_age: Option<u64>,
+	pub min_peer_version: Option<String>,
+	pub max_tip_age: Option<u64>,
+	pub min_certificate_days: Option<u64>,
+	pub max_epoch_age: Option<u64>,
+	pub max_clock_skew: Option<u64>,
+	pub chain_halt_after: Option<u64>,
+	pub min_version: Option<String>,
+	pub reference_endpoints: Option<Vec<String>>,
+	pub min_peers: Option<u16>,
+	pub max_latency_ms: Option<u64>,
+	pub max_memory_mb: Option<u64>,
+	pub disk_interval: Option<u64>,
+	pub max_data_dir_mb: Option<u64>,
+	pub min_disk_free_percent: Option<f64>,
+	pub log_patterns: Option<Vec<String>>,
+	pub fetch_probe_header: Option<H256>,
+	pub fetch_probe_transaction: Option<H256>,
+	pub fetch_timeout: Option<u64>,
+	pub canary_script: Option<ExpectedScript>,
+	pub min_canary_cells: Option<usize>,
+	pub connect_timeout: Option<u64>,
+	pub probe_timeout_ms: Option<u64>,
+	pub strict_schema: Option<bool>,
+	pub request_timeout: Option<u64>,
+	pub checks: Option<Vec<String>>,
+	pub check_rpc: Option<bool>,
+	pub check_peers: Option<bool>,
+	pub check_block_number: Option<bool>,
+	pub tls_ca_file: Option<PathBuf>,
+	pub tls_verify_hostname: Option<bool>,
+	pub tls_min_version: Option<TlsVersion>,
+	pub basic_auth_username: Option<String>,
+	pub basic_auth_password: Option<String>,
+	pub bearer_token: Option<String>,
+	#[serde(default)]
+	pub headers: HashMap<String, String>,
+	#[serde(default)]
+	pub clients: Vec<ClientConfig>,
+}
+
+/// The resolved settings of a client group, with all inherited values filled in.
+#[derive(Clone, Debug)]
+pub struct GroupSettings
+{
+	pub name: String,
+	/// How often to run checks that have no schedule of their own.
+	pub check_interval: Schedule,
+	/// How often to run RPC liveness checks.
+	pub rpc_interval: Schedule,
+	/// How often to run peer count checks.
+	pub peers_interval: Schedule,
+	/// How often to run tip block number checks.
+	pub tip_interval: Schedule,
+	/// How often to check which chain each client is on.
+	pub genesis_interval: Schedule,
+	/// The hash of the genesis block every client should be on, or `None` to compare clients with each other.
+	pub genesis_hash: Option<H256>,
+	/// How often to audit the clients against each other at a random recent height.
+	pub audit_interval: Schedule,
+	/// How often to summarize the outages each client has recovered from.
+	pub summary_interval: Schedule,
+	/// The fraction of each interval over which the checks of the clients are spread.
+	pub check_spread: f64,
+	/// How often to check a client that is offline or lagging, if it should be checked more often than usual.
+	pub degraded_interval: Option<Duration>,
+	/// The number of recent results kept for each client.
+	pub history_size: usize,
+	/// The number of consecutive cycles a lagging client can go without advancing before it is reported as stalled.
+	pub stall_cycles: usize,
+	/// The number of consecutive cycles any client can report the same block number before its tip is reported as frozen.
+	pub frozen_cycles: usize,
+	/// How a client is decided to be lagging.
+	pub lag_mode: LagMode,
+	/// In the outlier lag mode, the number of scaled median absolute deviations below the median that counts as lagging.
+	pub outlier_threshold: f64,
+	/// The fraction of the clients that can share a single remote peer before a warning.
+	pub shared_peer_fraction: f64,
+	/// How far the clock of a client host can differ from that of the reference nodes before a warning, or zero to
+	/// disable the warning.
+	pub max_clock_skew: Duration,
+	/// How long the highest tip of the group can go without advancing before the chain is reported as halted, or zero to
+	/// disable the alert.
+	pub chain_halt_after: Duration,
+	/// The oldest client version that does not cause a warning.
+	pub min_version: Option<String>,
+	pub clients: Vec<ClientSettings>,
+	/// The trusted nodes whose tip is the height that clients are measured against.
+	pub references: Vec<ClientSettings>,
+}
+
+/// Settings for a single client in an explicit client list. Any setting that is omitted is inherited from its group.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig
+{
+	/// The RPC endpoint of the client, either as `host:port` or a full URL.
+	pub endpoint: String,
+	/// A human-readable name used in place of the client number in all output.
+	pub label: Option<String>,
+	pub max_block_diff: Option<u64>,
+	pub max_script_lag: Option<u64>,
+	pub min_peers: Option<u16>,
+	pub max_latency_ms: Option<u64>,
+	pub max_memory_mb: Option<u64>,
+	pub max_data_dir_mb: Option<u64>,
+	pub min_disk_free_percent: Option<f64>,
+	pub connect_timeout: Option<u64>,
+	pub probe_timeout_ms: Option<u64>,
+	pub strict_schema: Option<bool>,
+	pub request_timeout: Option<u64>,
+	/// The data directory of the client, if it runs on the same machine as the monitor.
+	pub data_dir: Option<PathBuf>,
+	/// The log file of the client, if it runs on the same machine as the monitor.
+	pub log_file: Option<PathBuf>,
+	/// The regular expressions that lines in the log file are matched against.
+	pub log_patterns: Option<Vec<String>>,
+	/// The process id of the client, if it runs on the same machine as the monitor.
+	pub pid: Option<u32>,
+	/// The systemd unit the client runs as, if it runs on the same machine as the monitor. Takes precedence over `pid`.
+	pub systemd_unit: Option<String>,
+	/// The names of the checks to run on this client.
+	pub checks: Option<Vec<String>>,
+	/// Set to false to skip the RPC liveness check for this client.
+	pub check_rpc: Option<bool>,
+	/// Set to false to skip the peer count check for this client.
+	pub check_peers: Option<bool>,
+	/// Set to false to skip the tip block number check for this client.
+	pub check_block_number: Option<bool>,
+	/// The scripts the client is expected to be filtering for. When omitted, the scripts check only warns if there are none.
+	pub expected_scripts: Option<Vec<ExpectedScript>>,
+	/// The peers the client is expected to be connected to, such as the bootnodes it is started with `--connect` to, as
+	/// node ids or multiaddrs. When set, a warning is printed if it is connected to none of them or to any others.
+	pub expected_peers: Option<Vec<String>>,
+	/// The `tcp://` or `ws://` subscription RPC of the client. When set, tips are pushed by the client instead of polled.
+	pub subscription_endpoint: Option<String>,
+	/// A PEM encoded CA certificate used to verify an HTTPS endpoint.
+	pub tls_ca_file: Option<PathBuf>,
+	/// Set to false to accept HTTPS certificates that do not match the hostname.
+	pub tls_verify_hostname: Option<bool>,
+	/// The minimum TLS version accepted from an HTTPS endpoint: "1.0", "1.1", "1.2", or "1.3".
+	pub tls_min_version: Option<TlsVersion>,
+	/// The username for HTTP basic auth.
+	pub basic_auth_username: Option<String>,
+	/// The password for HTTP basic auth.
+	pub basic_auth_password: Option<String>,
+	/// A token sent as an `Authorization: Bearer` header.
+	pub bearer_token: Option<String>,
+	/// Additional headers sent with every request. These are merged with any headers set on the group.
+	#[serde(default)]
+	pub headers: HashMap<String, String>,
+}
+
+/// How a client is decided to be lagging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LagMode
+{
+	/// A client is lagging when it trails the tip of its group by more than its maximum block difference.
+	Fixed,
+	/// A client is lagging when it is a statistical outlier below the median tip of its group, which adapts to network
+	/// conditions and the size of the group.
+	Outlier,
+}
+
+impl FromStr for LagMode
+{
+	type Err = String;
+
+	fn from_str(mode: &str) -> Result<Self, Self::Err>
+	{
+		match mode
+		{
+			"fixed" => Ok(Self::Fixed),
+			"outlier" => Ok(Self::Outlier),
+			_ => Err(format!("unknown lag mode: {}", mode)),
+		}
+	}
+}
+
+/// A script a client is expected to be filtering for.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedScript
+{
+	pub code_hash: H256,
+	pub hash_type: String,
+	pub args: String,
+	/// Whether the script is used as a `lock` or `type` script. Defaults to `lock`.
+	#[serde(default = "default_script_type")]
+	pub script_type: String,
+}
+
+impl ExpectedScript
+{
+	/// Returns the script without its type.
+	pub fn script(&self) -> Script
+	{
+		Script
+		{
+			code_hash: self.code_hash,
+			hash_type: self.hash_type.clone(),
+			args: self.args.clone(),
+		}
+	}
+
+	/// Returns the search key that finds the cells of this script with the `get_cells` RPC call.
+	pub fn search_key(&self) -> Value
+	{
+		json!(
+		{
+			"script":
+			{
+				"code_hash": self.code_hash.to_string(),
+				"hash_type": self.hash_type,
+				"args": self.args,
+			},
+			"script_type": self.script_type,
+		})
+	}
+
+	/// Returns true if the client is filtering for this script.
+	pub fn matches(&self, status: &ScriptStatus) -> bool
+	{
+		status.script.code_hash == self.code_hash
+			&& status.script.hash_type.eq_ignore_ascii_case(&self.hash_type)
+			&& status.script.args.eq_ignore_ascii_case(&self.args)
+			&& status.script_type.eq_ignore_ascii_case(&self.script_type)
+	}
+}
+
+/// Returns the script type of an expected script that does not set one.
+fn default_script_type() -> String
+{
+	"lock".to_string()
+}
+
+impl ClientSettings
+{
+	/// Returns true if the check with the specified name is enabled for the client.
+	pub fn has_check(&self, name: &str) -> bool
+	{
+		self.checks.iter().any(|check| check == name)
+	}
+}
+
+impl ClientConfig
+{
+	/// Resolves the settings of the client, using the specified defaults for any setting that is omitted.
+	fn resolve(&self, defaults: &ClientSettings) -> ClientSettings
+	{
+		ClientSettings
+		{
+			url: normalize_endpoint(&self.endpoint),
+			label: self.label.clone(),
+			subscription: self.subscription_endpoint.clone(),
+			expected_scripts: self.expected_scripts.clone(),
+			expected_peers: self.expected_peers.clone(),
+			max_block_diff: self.max_block_diff.unwrap_or(defaults.max_block_diff),
+			max_script_lag: self.max_script_lag.unwrap_or(defaults.max_script_lag),
+			max_peer_churn: defaults.max_peer_churn,
+			min_peer_age: defaults.min_peer_age,
+			min_peer_version: defaults.min_peer_version.clone(),
+			max_tip_age: defaults.max_tip_age,
+			min_certificate_age: defaults.min_certificate_age,
+			max_epoch_age: defaults.max_epoch_age,
+			min_peers: self.min_peers.unwrap_or(defaults.min_peers),
+			max_latency: self.max_latency_ms.map(Duration::from_millis).or(defaults.max_latency),
+			process: self.systemd_unit.clone().map(ProcessSource::SystemdUnit).or(self.pid.map(ProcessSource::Pid)),
+			max_memory_mb: self.max_memory_mb.or(defaults.max_memory_mb),
+			data_dir: self.data_dir.clone(),
+			disk_interval: defaults.disk_interval,
+			max_data_dir_mb: self.max_data_dir_mb.or(defaults.max_data_dir_mb),
+			min_disk_free_percent: self.min_disk_free_percent.unwrap_or(defaults.min_disk_free_percent),
+			log_file: self.log_file.clone(),
+			log_patterns: self.log_patterns.clone().unwrap_or_else(|| defaults.log_patterns.clone()),
+			fetch_probe_header: defaults.fetch_probe_header,
+			fetch_probe_transaction: defaults.fetch_probe_transaction,
+			fetch_timeout: defaults.fetch_timeout,
+			canary_script: defaults.canary_script.clone(),
+			min_canary_cells: defaults.min_canary_cells,
+			connect_timeout: self.connect_timeout.map(Duration::from_secs).unwrap_or(defaults.connect_timeout),
+			probe_timeout: self.probe_timeout_ms.map(Duration::from_millis).unwrap_or(defaults.probe_timeout),
+			strict_schema: self.strict_schema.unwrap_or(defaults.strict_schema),
+			request_timeout: self.request_timeout.map(Duration::from_secs).unwrap_or(defaults.request_timeout),
+			retry: defaults.retry.clone(),
+			health: defaults.health,
+			checks: apply_check_switches(self.checks.clone().unwrap_or_else(|| defaults.checks.clone()), self.check_rpc, self.check_peers, self.check_block_number),
+			tls: TlsSettings
+			{
+				ca_file: self.tls_ca_file.clone().or_else(|| defaults.tls.ca_file.clone()),
+				verify_hostname: self.tls_verify_hostname.unwrap_or(defaults.tls.verify_hostname),
+				min_version: self.tls_min_version.or(defaults.tls.min_version),
+			},
+			auth: AuthSettings
+			{
+				username: self.basic_auth_username.clone().or_else(|| defaults.auth.username.clone()),
+				password: self.basic_auth_password.clone().or_else(|| defaults.auth.password.clone()),
+				bearer_token: self.bearer_token.clone().or_else(|| defaults.auth.bearer_token.clone()),
+				headers: defaults.auth.headers.clone().into_iter().chain(self.headers.clone()).collect(),
+			},
+		}
+	}
+}
+
+/// The resolved settings of a single client, with all inherited values filled in.
+#[derive(Clone, Debug)]
+pub struct ClientSettings
+{
+	/// The RPC URL of the client.
+	pub url: String,
+	pub label: Option<String>,
+	/// The subscription RPC URL of the client, which pushes new tips when it is connected.
+	pub subscription: Option<String>,
+	/// The scripts the client is expected to be filtering for.
+	pub expected_scripts: Option<Vec<ExpectedScript>>,
+	/// The peers the client is expected to be connected to, as node ids or multiaddrs.
+	pub expected_peers: Option<Vec<String>>,
+	pub max_block_diff: u64,
+	/// The number of blocks the filtering of a script can trail the tip of the client before a warning.
+	pub max_script_lag: u64,
+	/// The average number of peers that can connect or disconnect between peers checks before a warning.
+	pub max_peer_churn: f64,
+	/// How long the longest connected peer must have been connected before a warning. Disabled when zero.
+	pub min_peer_age: Duration,
+	/// The oldest version the peers can run before a warning that every peer is outdated.
+	pub min_peer_version: Option<String>,
+	/// How old the tip block can be before a warning. Disabled when zero.
+	pub max_tip_age: Duration,
+	/// How long before the TLS certificate of the client expires that a warning is given. Disabled when zero.
+	pub min_certificate_age: Duration,
+	/// How long the client can stay in the same epoch before a warning. Disabled when zero.
+	pub max_epoch_age: Duration,
+	pub min_peers: u16,
+	/// How long the client can take to respond before a warning.
+	pub max_latency: Option<Duration>,
+	/// The process of the client, if it runs on the same machine as the monitor.
+	pub process: Option<ProcessSource>,
+	/// The number of megabytes of memory the process can use before a warning.
+	pub max_memory_mb: Option<u64>,
+	/// The data directory of the client, if it runs on the same machine as the monitor.
+	pub data_dir: Option<PathBuf>,
+	/// How often to measure the data directory.
+	pub disk_interval: Duration,
+	/// The number of megabytes the data directory can grow to before a warning.
+	pub max_data_dir_mb: Option<u64>,
+	/// The percentage of the filesystem holding the data directory that must stay free to avoid a warning.
+	pub min_disk_free_percent: f64,
+	/// The log file of the client, if it runs on the same machine as the monitor.
+	pub log_file: Option<PathBuf>,
+	/// The regular expressions that lines in the log file are matched against.
+	pub log_patterns: Vec<String>,
+	/// The hash of a header the fetch check asks the client to fetch, instead of the parent of its tip.
+	pub fetch_probe_header: Option<H256>,
+	/// The hash of a transaction the fetch check asks the client to fetch, instead of the parent of its tip.
+	pub fetch_probe_transaction: Option<H256>,
+	/// How long the fetch check waits for a fetch to complete.
+	pub fetch_timeout: Duration,
+	/// A well known script whose cells the cells check looks up.
+	pub canary_script: Option<ExpectedScript>,
+	/// The number of cells the cells check must find for the canary script.
+	pub min_canary_cells: usize,
+	pub connect_timeout: Duration,
+	/// How long to wait for the host to resolve and accept a TCP connection before each round of checks, or zero to skip
+	/// the probe.
+	pub probe_timeout: Duration,
+	/// Whether RPC results with fields the monitor does not know about are reported as schema drift.
+	pub strict_schema: bool,
+	pub request_timeout: Duration,
+	pub retry: RetryPolicy,
+	/// The rules that decide when the client changes health.
+	pub health: HealthRules,
+	/// The names of the checks to run on the client.
+	pub checks: Vec<String>,
+	pub tls: TlsSettings,
+	pub auth: AuthSettings,
+}
+
+impl Default for Config
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			host: DEFAULT_HOST.to_string(),
+			starting_port: DEFAULT_STARTING_PORT,
+			total_clients: DEFAULT_TOTAL_CLIENTS,
+			check_interval: Schedule::from_secs(DEFAULT_CHECK_INTERVAL),
+			rpc_interval: None,
+			peers_interval: None,
+			tip_interval: None,
+			genesis_interval: Schedule::from_secs(DEFAULT_GENESIS_INTERVAL),
+			genesis_hash: None,
+			audit_interval: Schedule::from_secs(DEFAULT_AUDIT_INTERVAL),
+			summary_interval: Schedule::from_secs(DEFAULT_SUMMARY_INTERVAL),
+			checks: checks::default_checks(),
+			check_spread: DEFAULT_CHECK_SPREAD,
+			degraded_interval: None,
+			offline_after: DEFAULT_OFFLINE_AFTER,
+			recover_after: DEFAULT_RECOVER_AFTER,
+			flap_threshold: DEFAULT_FLAP_THRESHOLD,
+			flap_window: DEFAULT_FLAP_WINDOW,
+			history_size: DEFAULT_HISTORY_SIZE,
+			max_block_diff: DEFAULT_MAX_BLOCK_DIFF,
+			stall_cycles: DEFAULT_STALL_CYCLES,
+			frozen_cycles: DEFAULT_FROZEN_CYCLES,
+			lag_mode: LagMode::Fixed,
+			outlier_threshold: DEFAULT_OUTLIER_THRESHOLD,
+			max_script_lag: DEFAULT_MAX_SCRIPT_LAG,
+			shared_peer_fraction: DEFAULT_SHARED_PEER_FRACTION,
+			max_peer_churn: DEFAULT_MAX_PEER_CHURN,
+			min_peer_age: DEFAULT_MIN_PEER_AGE,
+			min_peer_version: None,
+			max_tip_age: DEFAULT_MAX_TIP_AGE,
+			min_certificate_days: DEFAULT_MIN_CERTIFICATE_DAYS,
+			max_epoch_age: DEFAULT_MAX_EPOCH_AGE,
+			max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+			chain_halt_after: DEFAULT_CHAIN_HALT_AFTER,
+			min_version: None,
+			reference_endpoints: Vec::new(),
+			min_peers: DEFAULT_MIN_PEERS,
+			max_latency_ms: None,
+			max_memory_mb: None,
+			disk_interval: DEFAULT_DISK_INTERVAL,
+			max_data_dir_mb: None,
+			min_disk_free_percent: DEFAULT_MIN_DISK_FREE_PERCENT,
+			log_patterns: DEFAULT_LOG_PATTERNS.iter().map(|pattern| pattern.to_string()).collect(),
+			fetch_probe_header: None,
+			fetch_probe_transaction: None,
+			fetch_timeout: DEFAULT_FETCH_TIMEOUT,
+			canary_script: None,
+			min_canary_cells: DEFAULT_MIN_CANARY_CELLS,
+			connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+			probe_timeout_ms: DEFAULT_PROBE_TIMEOUT_MS,
+			strict_schema: false,
+			request_timeout: DEFAULT_REQUEST_TIMEOUT,
+			retry_attempts: DEFAULT_RETRY_ATTEMPTS,
+			retry_initial_backoff_ms: DEFAULT_RETRY_INITIAL_BACKOFF_MS,
+			retry_max_backoff_ms: DEFAULT_RETRY_MAX_BACKOFF_MS,
+			retry_jitter: DEFAULT_RETRY_JITTER,
+			max_requests_per_second: None,
+			webhook_urls: Vec::new(),
+			webhook_retry_attempts: DEFAULT_WEBHOOK_RETRY_ATTEMPTS,
+			webhook_mute_recoveries: false,
+			webhook_quiet_hours: None,
+			webhook_secret: None,
+			alert_cooldown: DEFAULT_ALERT_COOLDOWN,
+			alert_renotify_interval: 0,
+			escalate_after: HashMap::new(),
+			alert_routes: Vec::new(),
+			alert_templates: HashMap::new(),
+			fleet_conditions: Vec::new(),
+			alert_aggregation_window: 0,
+			suppress_dependent_alerts: true,
+			dependent_alert_grace: DEFAULT_DEPENDENT_ALERT_GRACE,
+			severity_rules: Vec::new(),
+			alert_rate_limit: 0,
+			alert_rate_window: DEFAULT_ALERT_RATE_WINDOW,
+			acks_file: PathBuf::from(DEFAULT_ACKS_FILE),
+			digest_schedule: None,
+			slack: None,
+			discord: None,
+			telegram: None,
+			matrix: None,
+			exec: None,
+			ntfy: None,
+			pushover: None,
+			gotify: None,
+			alertmanager: None,
+			desktop: None,
+			mqtt: None,
+			metrics_address: None,
+			otlp: None,
+			heartbeat: None,
+			clients: Vec::new(),
+			groups: Vec::new(),
+			targets_file: None,
+			asn_database: None,
+			targets_refresh_interval: DEFAULT_TARGETS_REFRESH_INTERVAL,
+			targets: Vec::new(),
+		}
+	}
+}
+
+impl Config
+{
+	/// Loads the config from the specified file, or returns the defaults if the file does not exist.
+	/// Files ending in `.yaml`, `.yml`, or `.json` are parsed as YAML or JSON, and all others as TOML.
+	pub fn load(path: &Path) -> Result<Self, Box<dyn Error>>
+	{
+		if !path.exists()
+		{
+			tracing::debug!("Config file {} not found. Using defaults.", path.display());
+			return Ok(Self::default());
+		}
+
+		let contents = fs::read_to_string(path)?;
+		let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+		let config: Result<Self, String> = match extension.as_str()
+		{
+			"yaml" | "yml" => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+			"json" => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+			_ => toml::from_str(&contents).map_err(|e| e.to_string()),
+		};
+
+		config.map_err(|e| format!("Failed to parse config file {}: {}", path.display(), e).into())
+	}
+
+	/// Overrides settings with any `CKB_MONITOR_*` environment variables that are set.
+	pub fn apply_env(&mut self) -> Result<(), Box<dyn Error>>
+	{
+		if let Some(host) = env_var("HOST")?
+		{
+			self.host = host;
+		}
+		if let Some(starting_port) = env_var("STARTING_PORT")?
+		{
+			self.starting_port = starting_port;
+		}
+		if let Some(total_clients) = env_var("TOTAL_CLIENTS")?
+		{
+			self.total_clients = total_clients;
+		}
+		if let Some(check_interval) = env_var("CHECK_INTERVAL")?
+		{
+			self.check_interval = check_interval;
+		}
+		if let Some(rpc_interval) = env_var("RPC_INTERVAL")?
+		{
+			self.rpc_interval = Some(rpc_interval);
+		}
+		if let Some(peers_interval) = env_var("PEERS_INTERVAL")?
+		{
+			self.peers_interval = Some(peers_interval);
+		}
+		if let Some(tip_interval) = env_var("TIP_INTERVAL")?
+		{
+			self.tip_interval = Some(tip_interval);
+		}
+		if let Some(genesis_interval) = env_var("GENESIS_INTERVAL")?
+		{
+			self.genesis_interval = genesis_interval;
+		}
+		if let Some(genesis_hash) = env_var("GENESIS_HASH")?
+		{
+			self.genesis_hash = Some(genesis_hash);
+		}
+		if let Some(audit_interval) = env_var("AUDIT_INTERVAL")?
+		{
+			self.audit_interval = audit_interval;
+		}
+		if let Some(summary_interval) = env_var("SUMMARY_INTERVAL")?
+		{
+			self.summary_interval = summary_interval;
+		}
+		if let Some(check_spread) = env_var("CHECK_SPREAD")?
+		{
+			self.check_spread = check_spread;
+		}
+		if let Some(degraded_interval) = env_var("DEGRADED_INTERVAL")?
+		{
+			self.degraded_interval = Some(degraded_interval);
+		}
+		if let Some(offline_after) = env_var("OFFLINE_AFTER")?
+		{
+			self.offline_after = offline_after;
+		}
+		if let Some(recover_after) = env_var("RECOVER_AFTER")?
+		{
+			self.recover_after = recover_after;
+		}
+		if let Some(flap_threshold) = env_var("FLAP_THRESHOLD")?
+		{
+			self.flap_threshold = flap_threshold;
+		}
+		if let Some(flap_window) = env_var("FLAP_WINDOW")?
+		{
+			self.flap_window = flap_window;
+		}
+		if let Some(history_size) = env_var("HISTORY_SIZE")?
+		{
+			self.history_size = history_size;
+		}
+		if let Some(max_block_diff) = env_var("MAX_BLOCK_DIFF")?
+		{
+			self.max_block_diff = max_block_diff;
+		}
+		if let Some(stall_cycles) = env_var("STALL_CYCLES")?
+		{
+			self.stall_cycles = stall_cycles;
+		}
+		if let Some(frozen_cycles) = env_var("FROZEN_CYCLES")?
+		{
+			self.frozen_cycles = frozen_cycles;
+		}
+		if let Some(lag_mode) = env_var("LAG_MODE")?
+		{
+			self.lag_mode = lag_mode;
+		}
+		if let Some(outlier_threshold) = env_var("OUTLIER_THRESHOLD")?
+		{
+			self.outlier_threshold = outlier_threshold;
+		}
+		if let Some(asn_database) = env_var("ASN_DATABASE")?
+		{
+			self.asn_database = Some(asn_database);
+		}
+		if let Some(max_script_lag) = env_var("MAX_SCRIPT_LAG")?
+		{
+			self.max_script_lag = max_script_lag;
+		}
+		if let Some(shared_peer_fraction) = env_var("SHARED_PEER_FRACTION")?
+		{
+			self.shared_peer_fraction = shared_peer_fraction;
+		}
+		if let Some(max_peer_churn) = env_var("MAX_PEER_CHURN")?
+		{
+			self.max_peer_churn = max_peer_churn;
+		}
+		if let Some(min_peer_age) = env_var("MIN_PEER_AGE")?
+		{
+			self.min_peer_age = min_peer_age;
+		}
+		if let Some(min_peer_version) = env_var("MIN_PEER_VERSION")?
+		{
+			self.min_peer_version = Some(min_peer_version);
+		}
+		if let Some(max_tip_age) = env_var("MAX_TIP_AGE")?
+		{
+			self.max_tip_age = max_tip_age;
+		}
+		if let Some(min_certificate_days) = env_var("MIN_CERTIFICATE_DAYS")?
+		{
+			self.min_certificate_days = min_certificate_days;
+		}
+		if let Some(max_epoch_age) = env_var("MAX_EPOCH_AGE")?
+		{
+			self.max_epoch_age = max_epoch_age;
+		}
+		if let Some(max_clock_skew) = env_var("MAX_CLOCK_SKEW")?
+		{
+			self.max_clock_skew = max_clock_skew;
+		}
+		if let Some(chain_halt_after) = env_var("CHAIN_HALT_AFTER")?
+		{
+			self.chain_halt_after = chain_halt_after;
+		}
+		if let Some(min_version) = env_var("MIN_VERSION")?
+		{
+			self.min_version = Some(min_version);
+		}
+		if let Some(min_peers) = env_var("MIN_PEERS")?
+		{
+			self.min_peers = min_peers;
+		}
+		if let Some(max_latency_ms) = env_var("MAX_LATENCY_MS")?
+		{
+			self.max_latency_ms = Some(max_latency_ms);
+		}
+		if let Some(max_memory_mb) = env_var("MAX_MEMORY_MB")?
+		{
+			self.max_memory_mb = Some(max_memory_mb);
+		}
+		if let Some(fetch_probe_header) = env_var("FETCH_PROBE_HEADER")?
+		{
+			self.fetch_probe_header = Some(fetch_probe_header);
+		}
+		if let Some(fetch_probe_transaction) = env_var("FETCH_PROBE_TRANSACTION")?
+		{
+			self.fetch_probe_transaction = Some(fetch_probe_transaction);
+		}
+		if let Some(fetch_timeout) = env_var("FETCH_TIMEOUT")?
+		{
+			self.fetch_timeout = fetch_timeout;
+		}
+		if let Some(min_canary_cells) = env_var("MIN_CANARY_CELLS")?
+		{
+			self.min_canary_cells = min_canary_cells;
+		}
+		if let Some(disk_interval) = env_var("DISK_INTERVAL")?
+		{
+			self.disk_interval = disk_interval;
+		}
+		if let Some(max_data_dir_mb) = env_var("MAX_DATA_DIR_MB")?
+		{
+			self.max_data_dir_mb = Some(max_data_dir_mb);
+		}
+		if let Some(min_disk_free_percent) = env_var("MIN_DISK_FREE_PERCENT")?
+		{
+			self.min_disk_free_percent = min_disk_free_percent;
+		}
+		if let Some(connect_timeout) = env_var("CONNECT_TIMEOUT")?
+		{
+			self.connect_timeout = connect_timeout;
+		}
+		if let Some(probe_timeout_ms) = env_var("PROBE_TIMEOUT_MS")?
+		{
+			self.probe_timeout_ms = probe_timeout_ms;
+		}
+		if let Some(strict_schema) = env_var("STRICT_SCHEMA")?
+		{
+			self.strict_schema = strict_schema;
+		}
+		if let Some(request_timeout) = env_var("REQUEST_TIMEOUT")?
+		{
+			self.request_timeout = request_timeout;
+		}
+		if let Some(retry_attempts) = env_var("RETRY_ATTEMPTS")?
+		{
+			self.retry_attempts = retry_attempts;
+		}
+		if let Some(retry_initial_backoff_ms) = env_var("RETRY_INITIAL_BACKOFF_MS")?
+		{
+			self.retry_initial_backoff_ms = retry_initial_backoff_ms;
+		}
+		if let Some(retry_max_backoff_ms) = env_var("RETRY_MAX_BACKOFF_MS")?
+		{
+			self.retry_max_backoff_ms = retry_max_backoff_ms;
+		}
+		if let Some(retry_jitter) = env_var("RETRY_JITTER")?
+		{
+			self.retry_jitter = retry_jitter;
+		}
+		if let Some(max_requests_per_second) = env_var("MAX_REQUESTS_PER_SECOND")?
+		{
+			self.max_requests_per_second = Some(max_requests_per_second);
+		}
+		if let Some(webhook_retry_attempts) = env_var("WEBHOOK_RETRY_ATTEMPTS")?
+		{
+			self.webhook_retry_attempts = webhook_retry_attempts;
+		}
+		if let Some(webhook_mute_recoveries) = env_var("WEBHOOK_MUTE_RECOVERIES")?
+		{
+			self.webhook_mute_recoveries = webhook_mute_recoveries;
+		}
+		if let Some(webhook_secret) = env_var("WEBHOOK_SECRET")?
+		{
+			self.webhook_secret = Some(webhook_secret);
+		}
+		if let Some(alert_cooldown) = env_var("ALERT_COOLDOWN")?
+		{
+			self.alert_cooldown = alert_cooldown;
+		}
+		if let Some(alert_renotify_interval) = env_var("ALERT_RENOTIFY_INTERVAL")?
+		{
+			self.alert_renotify_interval = alert_renotify_interval;
+		}
+		if let Some(alert_aggregation_window) = env_var("ALERT_AGGREGATION_WINDOW")?
+		{
+			self.alert_aggregation_window = alert_aggregation_window;
+		}
+		if let Some(suppress_dependent_alerts) = env_var("SUPPRESS_DEPENDENT_ALERTS")?
+		{
+			self.suppress_dependent_alerts = suppress_dependent_alerts;
+		}
+		if let Some(dependent_alert_grace) = env_var("DEPENDENT_ALERT_GRACE")?
+		{
+			self.dependent_alert_grace = dependent_alert_grace;
+		}
+		if let Some(alert_rate_limit) = env_var("ALERT_RATE_LIMIT")?
+		{
+			self.alert_rate_limit = alert_rate_limit;
+		}
+		if let Some(alert_rate_window) = env_var("ALERT_RATE_WINDOW")?
+		{
+			self.alert_rate_window = alert_rate_window;
+		}
+		if let Some(digest_schedule) = env_var("DIGEST_SCHEDULE")?
+		{
+			self.digest_schedule = Some(digest_schedule);
+		}
+		if let Some(webhook_url) = env_var("SLACK_WEBHOOK_URL")?
+		{
+			self.slack.get_or_insert_with(SlackConfig::default).webhook_url = Some(webhook_url);
+		}
+		if let Some(bot_token) = env_var("SLACK_BOT_TOKEN")?
+		{
+			self.slack.get_or_insert_with(SlackConfig::default).bot_token = Some(bot_token);
+		}
+		if let Some(channel) = env_var("SLACK_CHANNEL")?
+		{
+			self.slack.get_or_insert_with(SlackConfig::default).channel = Some(channel);
+		}
+		if let Some(webhook_url) = env_var("DISCORD_WEBHOOK_URL")?
+		{
+			self.discord.get_or_insert_with(DiscordConfig::default).webhook_url = Some(webhook_url);
+		}
+		if let Some(bot_token) = env_var("TELEGRAM_BOT_TOKEN")?
+		{
+			self.telegram.get_or_insert_with(TelegramConfig::default).bot_token = Some(bot_token);
+		}
+		if let Some(access_token) = env_var("MATRIX_ACCESS_TOKEN")?
+		{
+			self.matrix.get_or_insert_with(MatrixConfig::default).access_token = Some(access_token);
+		}
+		if let Some(topic) = env_var("NTFY_TOPIC")?
+		{
+			self.ntfy.get_or_insert_with(NtfyConfig::default).topic = Some(topic);
+		}
+		if let Some(token) = env_var("NTFY_TOKEN")?
+		{
+			self.ntfy.get_or_insert_with(NtfyConfig::default).token = Some(token);
+		}
+		if let Some(app_token) = env_var("PUSHOVER_APP_TOKEN")?
+		{
+			self.pushover.get_or_insert_with(PushoverConfig::default).app_token = Some(app_token);
+		}
+		if let Some(user_key) = env_var("PUSHOVER_USER_KEY")?
+		{
+			self.pushover.get_or_insert_with(PushoverConfig::default).user_key = Some(user_key);
+		}
+		if let Some(app_token) = env_var("GOTIFY_APP_TOKEN")?
+		{
+			self.gotify.get_or_insert_with(GotifyConfig::default).app_token = Some(app_token);
+		}
+		if let Some(password) = env_var("ALERTMANAGER_PASSWORD")?
+		{
+			self.alertmanager.get_or_insert_with(AlertmanagerConfig::default).password = Some(password);
+		}
+		if let Some(password) = env_var("MQTT_PASSWORD")?
+		{
+			self.mqtt.get_or_insert_with(MqttConfig::default).password = Some(password);
+		}
+		if let Some(address) = env_var("METRICS_ADDRESS")?
+		{
+			self.metrics_address = Some(address);
+		}
+		if let Some(endpoint) = env_var("OTLP_ENDPOINT")?
+		{
+			self.otlp.get_or_insert_with(OtlpConfig::default).endpoint = Some(endpoint);
+		}
+		if let Some(url) = env_var("HEARTBEAT_URL")?
+		{
+			self.heartbeat.get_or_insert_with(HeartbeatConfig::default).url = Some(url);
+		}
+
+		Ok(())
+	}
+
+	/// Loads the target groups from the targets file, if one is configured.
+	pub fn load_targets(&mut self) -> Result<(), Box<dyn Error>>
+	{
+		self.targets = match &self.targets_file
+		{
+			Some(path) => targets::load_targets(path)?,
+			None => Vec::new(),
+		};
+
+		Ok(())
+	}
+
+	/// Returns the resolved settings of every client group. If no groups are configured, a single default group is returned.
+	/// Clients from the targets file are added to the group named by their `group` label, or to the default group.
+	pub fn groups(&self) -> Vec<GroupSettings>
+	{
+		let mut groups = self.groups.clone();
+		if groups.is_empty()
+		{
+			// When a targets file is used, the sequential port range is only generated if it is given explicitly in a group.
+			groups.push(GroupConfig
+			{
+				name: DEFAULT_GROUP_NAME.to_string(),
+				total_clients: if self.targets_file.is_some() { Some(0) } else { None },
+				clients: self.clients.clone(),
+				..Default::default()
+			});
+		}
+
+		let mut target_clients: Vec<Vec<ClientConfig>> = vec![Vec::new(); groups.len()];
+		for target_group in self.targets.iter()
+		{
+			let name = target_group.group().unwrap_or(DEFAULT_GROUP_NAME);
+			let index = match groups.iter().position(|group| group.name == name)
+			{
+				Some(index) => index,
+				None =>
+				{
+					groups.push(GroupConfig
+					{
+						name: name.to_string(),
+						total_clients: Some(0),
+						..Default::default()
+					});
+					target_clients.push(Vec::new());
+					groups.len() - 1
+				}
+			};
+			target_clients[index].extend(target_group.clients());
+		}
+
+		groups.iter().zip(target_clients.iter()).map(|(group, extra_clients)| self.resolve_group(group, extra_clients)).collect()
+	}
+
+	/// Returns the name of every group with a sequential port range that runs past the highest port. The clients past
+	/// the highest port are left out of the group.
+	pub fn overflowing_port_ranges(&self) -> Vec<String>
+	{
+		// Without groups, the default group only has a port range if no clients are listed.
+		let ranges = if !self.groups.is_empty()
+		{
+			self.groups.iter()
+				.filter(|group| group.clients.is_empty())
+				.map(|group| (group.name.as_str(), group.starting_port.unwrap_or(self.starting_port), group.total_clients.unwrap_or(self.total_clients)))
+				.collect()
+		}
+		else if self.clients.is_empty() && self.targets_file.is_none()
+		{
+			vec![(DEFAULT_GROUP_NAME, self.starting_port, self.total_clients)]
+		}
+		else
+		{
+			Vec::new()
+		};
+
+		ranges.into_iter()
+			.filter(|(_, starting_port, total_clients)| *total_clients > 0 && client_port(*starting_port, total_clients - 1).is_none())
+			.map(|(name, _, _)| name.to_string())
+			.collect()
+	}
+
+	/// Returns how failed webhook deliveries are retried.
+	pub fn webhook_retry(&self) -> RetryPolicy
+	{
+		RetryPolicy
+		{
+			attempts: self.webhook_retry_attempts,
+			initial_backoff: Duration::from_millis(self.retry_initial_backoff_ms),
+			max_backoff: Duration::from_millis(self.retry_max_backoff_ms),
+			jitter: self.retry_jitter,
+		}
+	}
+
+	/// Returns how many alerts each destination sends within a window, or `None` if there is no limit.
+	pub fn alert_rate_limit(&self) -> Option<RateLimit>
+	{
+		Some(RateLimit { max_alerts: self.alert_rate_limit, window: Duration::from_secs(self.alert_rate_window) }).filter(|limit| limit.max_alerts > 0)
+	}
+
+	/// Returns how repeated alerts are held back, how often ongoing conditions are reminded, when they escalate, and
+	/// where each is sent.
+	pub fn alert_settings(&self) -> AlertSettings
+	{
+		AlertSettings
+		{
+			cooldown: Duration::from_secs(self.alert_cooldown),
+			renotify_interval: Some(Duration::from_secs(self.alert_renotify_interval)).filter(|interval| !interval.is_zero()),
+			escalate_after: self.escalate_after.iter().map(|(condition, seconds)| (*condition, Duration::from_secs(*seconds))).collect(),
+			routes: self.alert_routes.clone(),
+			templates: self.alert_templates.clone(),
+			aggregation_window: Some(Duration::from_secs(self.alert_aggregation_window)).filter(|window| !window.is_zero()),
+			dependent_grace: Some(Duration::from_secs(self.dependent_alert_grace)).filter(|grace| self.suppress_dependent_alerts && !grace.is_zero()),
+			severity_rules: self.severity_rules.clone(),
+			acks_file: self.acks_file.clone(),
+		}
+	}
+
+	/// Returns the resolved settings of a group with no clients, inheriting every setting from the top level.
+	pub fn empty_group(&self, name: &str) -> GroupSettings
+	{
+		let group = GroupConfig
+		{
+			name: name.to_string(),
+			total_clients: Some(0),
+			..Default::default()
+		};
+
+		self.resolve_group(&group, &[])
+	}
+
+	/// Resolves the settings of a group, inheriting any omitted setting from the top level, and adding the extra clients.
+	fn resolve_group(&self, group: &GroupConfig, extra_clients: &[ClientConfig]) -> GroupSettings
+	{
+		let host = group.host.as_deref().unwrap_or(&self.host);
+		let starting_port = group.starting_port.unwrap_or(self.starting_port);
+		let total_clients = group.total_clients.unwrap_or(self.total_clients);
+		let check_interval = group.check_interval.as_ref().unwrap_or(&self.check_interval);
+		let defaults = ClientSettings
+		{
+			url: String::new(),
+			label: None,
+			subscription: None,
+			expected_scripts: None,
+			expected_peers: None,
+			max_block_diff: group.max_block_diff.unwrap_or(self.max_block_diff),
+			max_script_lag: group.max_script_lag.unwrap_or(self.max_script_lag),
+			max_peer_churn: group.max_peer_churn.unwrap_or(self.max_peer_churn),
+			min_peer_age: Duration::from_secs(group.min_peer_age.unwrap_or(self.min_peer_age)),
+			min_peer_version: group.min_peer_version.clone().or_else(|| self.min_peer_version.clone()),
+			max_tip_age: Duration::from_secs(group.max_tip_age.unwrap_or(self.max_tip_age)),
+			min_certificate_age: Duration::from_secs(group.min_certificate_days.unwrap_or(self.min_certificate_days) * SECONDS_PER_DAY),
+			max_epoch_age: Duration::from_secs(group.max_epoch_age.unwrap_or(self.max_epoch_age)),
+			min_peers: group.min_peers.unwrap_or(self.min_peers),
+			max_latency: group.max_latency_ms.or(self.max_latency_ms).map(Duration::from_millis),
+			process: None,
+			max_memory_mb: group.max_memory_mb.or(self.max_memory_mb),
+			data_dir: None,
+			disk_interval: Duration::from_secs(group.disk_interval.unwrap_or(self.disk_interval)),
+			max_data_dir_mb: group.max_data_dir_mb.or(self.max_data_dir_mb),
+			min_disk_free_percent: group.min_disk_free_percent.unwrap_or(self.min_disk_free_percent),
+			log_file: None,
+			log_patterns: group.log_patterns.clone().unwrap_or_else(|| self.log_patterns.clone()),
+			fetch_probe_header: group.fetch_probe_header.or(self.fetch_probe_header),
+			fetch_probe_transaction: group.fetch_probe_transaction.or(self.fetch_probe_transaction),
+			fetch_timeout: Duration::from_secs(group.fetch_timeout.unwrap_or(self.fetch_timeout)),
+			canary_script: group.canary_script.clone().or_else(|| self.canary_script.clone()),
+			min_canary_cells: group.min_canary_cells.unwrap_or(self.min_canary_cells),
+			connect_timeout: Duration::from_secs(group.connect_timeout.unwrap_or(self.connect_timeout)),
+			probe_timeout: Duration::from_millis(group.probe_timeout_ms.unwrap_or(self.probe_timeout_ms)),
+			strict_schema: group.strict_schema.unwrap_or(self.strict_schema),
+			request_timeout: Duration::from_secs(group.request_timeout.unwrap_or(self.request_timeout)),
+			retry: RetryPolicy
+			{
+				attempts: self.retry_attempts,
+				initial_backoff: Duration::from_millis(self.retry_initial_backoff_ms),
+				max_backoff: Duration::from_millis(self.retry_max_backoff_ms),
+				jitter: self.retry_jitter,
+			},
+			health: HealthRules
+			{
+				offline_after: group.offline_after.unwrap_or(self.offline_after),
+				recover_after: group.recover_after.unwrap_or(self.recover_after),
+				flap_threshold: group.flap_threshold.unwrap_or(self.flap_threshold),
+				flap_window: Duration::from_secs(group.flap_window.unwrap_or(self.flap_window)),
+			},
+			checks: apply_check_switches(group.checks.clone().unwrap_or_else(|| self.checks.clone()), group.check_rpc, group.check_peers, group.check_block_number),
+			tls: TlsSettings
+			{
+				ca_file: group.tls_ca_file.clone(),
+				verify_hostname: group.tls_verify_hostname.unwrap_or(true),
+				min_version: group.tls_min_version,
+			},
+			auth: AuthSettings
+			{
+				username: group.basic_auth_username.clone(),
+				password: group.basic_auth_password.clone(),
+				bearer_token: group.bearer_token.clone(),
+				headers: group.headers.clone(),
+			},
+		};
+
+		let mut clients = resolve_clients(host, starting_port, total_clients, &group.clients, &defaults);
+		clients.extend(extra_clients.iter().map(|client| client.resolve(&defaults)));
+
+		// Reference nodes only have their tip checked, and do not use the authentication of the group.
+		let references = group.reference_endpoints.as_ref().unwrap_or(&self.reference_endpoints).iter().enumerate()
+			.map(|(index, endpoint)| ClientSettings
+			{
+				url: normalize_endpoint(endpoint),
+				label: Some(format!("reference-{}", index)),
+				subscription: None,
+				expected_scripts: None,
+				expected_peers: None,
+				max_latency: None,
+				checks: vec![TIP_CHECK.to_string()],
+				tls: TlsSettings::default(),
+				auth: AuthSettings::default(),
+				..defaults.clone()
+			})
+			.collect();
+
+		GroupSettings
+		{
+			name: group.name.clone(),
+			check_interval: check_interval.clone(),
+			rpc_interval: group.rpc_interval.as_ref().or(self.rpc_interval.as_ref()).unwrap_or(check_interval).clone(),
+			peers_interval: group.peers_interval.as_ref().or(self.peers_interval.as_ref()).unwrap_or(check_interval).clone(),
+			tip_interval: group.tip_interval.as_ref().or(self.tip_interval.as_ref()).unwrap_or(check_interval).clone(),
+			genesis_interval: group.genesis_interval.as_ref().unwrap_or(&self.genesis_interval).clone(),
+			genesis_hash: group.genesis_hash.or(self.genesis_hash),
+			audit_interval: group.audit_interval.as_ref().unwrap_or(&self.audit_interval).clone(),
+			summary_interval: group.summary_interval.as_ref().unwrap_or(&self.summary_interval).clone(),
+			check_spread: group.check_spread.unwrap_or(self.check_spread),
+			degraded_interval: group.degraded_interval.or(self.degraded_interval).map(Duration::from_secs),
+			history_size: group.history_size.unwrap_or(self.history_size),
+			stall_cycles: group.stall_cycles.unwrap_or(self.stall_cycles),
+			frozen_cycles: group.frozen_cycles.unwrap_or(self.frozen_cycles),
+			lag_mode: group.lag_mode.unwrap_or(self.lag_mode),
+			outlier_threshold: group.outlier_threshold.unwrap_or(self.outlier_threshold),
+			shared_peer_fraction: group.shared_peer_fraction.unwrap_or(self.shared_peer_fraction),
+			max_clock_skew: Duration::from_secs(group.max_clock_skew.unwrap_or(self.max_clock_skew)),
+			chain_halt_after: Duration::from_secs(group.chain_halt_after.unwrap_or(self.chain_halt_after)),
+			min_version: group.min_version.clone().or_else(|| self.min_version.clone()),
+			clients,
+			references,
+		}
+	}
+}
+
+/// Enables or disables the built in checks according to the `check_rpc`, `check_peers`, and `check_block_number`
+/// switches, which take precedence over a list of checks.
+fn apply_check_switches(mut checks: Vec<String>, check_rpc: Option<bool>, check_peers: Option<bool>, check_block_number: Option<bool>) -> Vec<String>
+{
+	for (name, enabled) in [(RPC_CHECK, check_rpc), (PEERS_CHECK, check_peers), (TIP_CHECK, check_block_number)]
+	{
+		match enabled
+		{
+			Some(true) if !checks.iter().any(|check| check == name) => checks.push(name.to_string()),
+			Some(false) => checks.retain(|check| check != name),
+			_ => {},
+		}
+	}
+
+	checks
+}
+
+/// Resolves the settings of every client, either from an explicit client list or a sequential port range.
+fn resolve_clients(host: &str, starting_port: u16, total_clients: usize, clients: &[ClientConfig], defaults: &ClientSettings) -> Vec<ClientSettings>
+{
+	if !clients.is_empty()
+	{
+		return clients.iter().map(|client| client.resolve(defaults)).collect();
+	}
+
+	(0..total_clients)
+		.map_while(|number| client_port(starting_port, number))
+		.map(|port| ClientSettings
+		{
+			url: format!("{}:{}/", host, port),
+			..defaults.clone()
+		})
+		.collect()
+}
+
+/// Returns the port of the client with the specified number in a sequential port range, or `None` if it is past the
+/// highest port.
+fn client_port(starting_port: u16, number: usize) -> Option<u16>
+{
+	u16::try_from(number).ok().and_then(|number| starting_port.checked_add(number))
+}
+
+/// Reads and parses the `CKB_MONITOR_` environment variable with the specified name, if it is set.
+fn env_var<T>(name: &str) -> Result<Option<T>, Box<dyn Error>>
+where
+	T: FromStr,
+	T::Err: std::fmt::Display,
+{
+	let key = format!("{}{}", ENV_PREFIX, name);
+	match env::var(&key)
+	{
+		Ok(value) =>
+		{
+			let parsed = value.parse::<T>().map_err(|e| format!("Invalid value for {}: {}", key, e))?;
+			Ok(Some(parsed))
+		},
+		Err(env::VarError::NotPresent) => Ok(None),
+		Err(e) => Err(format!("Invalid value for {}: {}", key, e).into()),
+	}
+}
+
+/// Converts a `host:port` endpoint into a full URL. Endpoints that already have a scheme are left as is.
+fn normalize_endpoint(endpoint: &str) -> String
+{
+	if endpoint.contains("://")
+	{
+		endpoint.to_string()
+	}
+	else
+	{
+		format!("http://{}/", endpoint)
+	}
+}
@@ -0,0 +1,192 @@
+use crate::events::MonitorEvent;
+use crate::group::Group;
+use crate::health::Health;
+use crate::worker::ClientHandle;
+use chrono::{DateTime, Local};
+use num_format::ToFormattedString;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Something that clients depend on, whose failure makes the alerts about them consequences rather than causes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum Parent
+{
+	/// A host that more than one client runs on, which is down when all of them are offline.
+	Host(String),
+	/// The reference nodes of a group, which are down when all of them are offline.
+	References(String),
+}
+
+impl Parent
+{
+	/// Returns the name of the incident of the parent being down, such as `host/10.0.0.5` or `mainnet/references`.
+	pub fn incident(&self) -> String
+	{
+		match self
+		{
+			Parent::Host(host) => format!("host/{}", host),
+			Parent::References(group) => format!("{}/references", group),
+		}
+	}
+
+	/// Returns the group the parent belongs to, if it belongs to one.
+	pub fn group(&self) -> Option<&str>
+	{
+		match self
+		{
+			Parent::Host(_) => None,
+			Parent::References(group) => Some(group),
+		}
+	}
+}
+
+impl fmt::Display for Parent
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Parent::Host(host) => write!(f, "host {}", host),
+			Parent::References(group) => write!(f, "the reference nodes of group {}", group),
+		}
+	}
+}
+
+/// Tells when the parents of clients go down and come back, from the state of the groups.
+#[derive(Debug)]
+pub struct Dependencies
+{
+	/// Whether parents are watched. Every parent is treated as up when they are not.
+	enabled: bool,
+	/// When each parent that is down went down.
+	down: HashMap<Parent, DateTime<Local>>,
+}
+
+impl Dependencies
+{
+	/// Creates a watch of the parents of clients, none of which are down yet.
+	pub fn new(enabled: bool) -> Self
+	{
+		Self { enabled, down: HashMap::new() }
+	}
+
+	/// Turns watching parents on or off, such as when the config is reloaded. Parents that are down are reported as
+	/// recovered at the next evaluation once it is turned off.
+	pub fn set_enabled(&mut self, enabled: bool)
+	{
+		self.enabled = enabled;
+	}
+
+	/// Evaluates the parents of the clients in the groups, and returns an event for each that goes down or comes back.
+	pub fn evaluate(&mut self, groups: &[Group]) -> Vec<MonitorEvent>
+	{
+		let now = Local::now();
+		let failed = if self.enabled { failed_parents(groups) } else { Vec::new() };
+
+		let mut events = Vec::new();
+		let recovered = self.down.keys().filter(|parent| !failed.iter().any(|(failed, _)| failed == *parent)).cloned().collect::<Vec<_>>();
+		for parent in recovered
+		{
+			if let Some(since) = self.down.remove(&parent)
+			{
+				events.push(MonitorEvent::ParentRecovered { parent, duration_seconds: (now - since).num_seconds() });
+			}
+		}
+		for (parent, clients) in failed
+		{
+			if !self.down.contains_key(&parent)
+			{
+				self.down.insert(parent.clone(), now);
+				events.push(MonitorEvent::ParentDown { parent, clients });
+			}
+		}
+		events
+	}
+}
+
+/// Returns each parent that is down along with the clients that depend on it: hosts that more than one client runs on
+/// whose clients are all offline, and groups whose reference nodes are all offline.
+fn failed_parents(groups: &[Group]) -> Vec<(Parent, Vec<String>)>
+{
+	let mut failed = Vec::new();
+	let mut hosts: BTreeMap<String, Vec<&ClientHandle>> = BTreeMap::new();
+	for group in groups.iter()
+	{
+		for client in group.clients.iter()
+		{
+			if let Some(host) = Url::parse(&client.settings.url).ok().and_then(|url| url.host_str().map(str::to_string))
+			{
+				hosts.entry(host).or_default().push(client);
+			}
+		}
+		if !group.references.is_empty() && group.references.iter().all(is_offline)
+		{
+			failed.push((Parent::References(group.settings.name.clone()), group.clients.iter().map(ToString::to_string).collect()));
+		}
+	}
+	for (host, clients) in hosts
+	{
+		if clients.len() > 1 && clients.iter().all(|client| is_offline(client))
+		{
+			failed.push((Parent::Host(host), clients.iter().map(ToString::to_string).collect()));
+		}
+	}
+	failed
+}
+
+/// Returns the name of every client that has a parent: those on a host that more than one client runs on, and those in
+/// a group with reference nodes.
+pub fn dependent_clients(groups: &[Group]) -> HashSet<String>
+{
+	let mut dependents = HashSet::new();
+	let mut hosts: BTreeMap<String, Vec<&ClientHandle>> = BTreeMap::new();
+	for group in groups.iter()
+	{
+		for client in group.clients.iter()
+		{
+			if let Some(host) = Url::parse(&client.settings.url).ok().and_then(|url| url.host_str().map(str::to_string))
+			{
+				hosts.entry(host).or_default().push(client);
+			}
+		}
+		if !group.references.is_empty()
+		{
+			dependents.extend(group.clients.iter().map(ToString::to_string));
+		}
+	}
+	dependents.extend(hosts.into_values().filter(|clients| clients.len() > 1).flatten().map(ToString::to_string));
+	dependents
+}
+
+/// Returns whether a client has reported and is offline.
+fn is_offline(client: &ClientHandle) -> bool
+{
+	client.state.is_some_and(|state| state.health == Health::Offline)
+}
+
+/// Returns the text of a parent going down or coming back, or `None` for other events.
+pub fn text(event: &MonitorEvent) -> Option<String>
+{
+	match event
+	{
+		MonitorEvent::ParentDown { parent: parent @ Parent::Host(_), clients } => Some(format!("{} is down: all {} of its clients are offline. Alerts about them are suppressed until it recovers.", capitalize(parent), clients.len())),
+		MonitorEvent::ParentDown { parent, clients } => Some(format!("{} are all offline. Alerts about the {} clients that depend on them are suppressed until one recovers.", capitalize(parent), clients.len())),
+		MonitorEvent::ParentRecovered { parent, duration_seconds } => Some(format!("{} {} back after {} seconds.", capitalize(parent), if matches!(parent, Parent::Host(_)) { "is" } else { "are" }, duration_seconds.to_formatted_string(&num_format::Locale::en))),
+		_ => None,
+	}
+}
+
+/// Returns the name of a parent with its first letter in upper case, to start a sentence with.
+fn capitalize(parent: &Parent) -> String
+{
+	let name = parent.to_string();
+	let mut chars = name.chars();
+	match chars.next()
+	{
+		Some(first) => first.to_uppercase().chain(chars).collect(),
+		None => name,
+	}
+}
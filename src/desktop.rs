@@ -0,0 +1,144 @@
+use crate::alerts::{Alert, AlertLimiter, RateLimit, Sink};
+use crate::events::Severity;
+use crate::matrix;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use notify_rust::{Notification, Timeout};
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::{self, JoinHandle};
+
+/// The name desktop notifications are shown as coming from.
+const DESKTOP_APP_NAME: &str = "CKB Light Client Monitor";
+
+/// How native desktop notifications are shown, for when the monitor runs on a workstation.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesktopConfig
+{
+	/// The least severe notifications that are shown. `critical` when omitted.
+	pub min_severity: Severity,
+	/// Whether notifications that a client has recovered, such as coming back online, are left out.
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe notifications are sent straight away, and the rest are held
+	/// until the next digest. Every notification is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for DesktopConfig
+{
+	fn default() -> Self
+	{
+		Self { min_severity: Severity::Critical, mute_recoveries: false, quiet_hours: None }
+	}
+}
+
+/// Shows a desktop notification when clients change state and when summaries are made. The task is stopped when this
+/// is dropped.
+pub struct DesktopSink
+{
+	task: JoinHandle<()>,
+}
+
+impl DesktopSink
+{
+	/// Starts showing notifications for the alerts received from `alerts`. Once the rate limit is reached, the alerts
+	/// held back are summarized when its window ends.
+	pub fn spawn(config: DesktopConfig, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Self
+	{
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Desktop, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Desktop, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						show("Alerts suppressed".to_string(), limiter.summary_text(suppressed), Severity::Warning);
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not shown as desktop notifications because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Desktop) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let (title, message) = match message(&alert)
+				{
+					Some(message) => message,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				show(title, message, alert.severity);
+			}
+		});
+
+		Self { task }
+	}
+}
+
+impl Drop for DesktopSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the title and text of the notification shown for an alert, or `None` for alerts that are not shown. The
+/// same events are shown as are sent to Matrix, titled with the client or group they are about and the type of event.
+fn message(alert: &Alert) -> Option<(String, String)>
+{
+	let message = match &alert.text
+	{
+		Some(text) => text.clone(),
+		None => matrix::message(&alert.event).map(|(body, _)| body)?,
+	};
+
+	Some((alert.title()?, message))
+}
+
+/// Shows a notification on a blocking thread, since talking to the notification service can block. Critical
+/// notifications stay on screen until they are dismissed.
+fn show(title: String, message: String, severity: Severity)
+{
+	task::spawn_blocking(move ||
+	{
+		let mut notification = Notification::new();
+		notification.appname(DESKTOP_APP_NAME).summary(&title).body(&message);
+		if severity == Severity::Critical
+		{
+			notification.timeout(Timeout::Never);
+		}
+		#[cfg(all(unix, not(target_os = "macos")))]
+		notification.urgency(match severity
+		{
+			Severity::Info => notify_rust::Urgency::Low,
+			Severity::Warning => notify_rust::Urgency::Normal,
+			Severity::Critical => notify_rust::Urgency::Critical,
+		});
+
+		if let Err(e) = notification.show()
+		{
+			tracing::error!("Failed to show a desktop notification: {}", e);
+		}
+	});
+}
@@ -0,0 +1,111 @@
+use crate::alerts;
+use crate::events::MonitorEvent;
+use crate::health::Health;
+use chrono::{DateTime, Local};
+use num_format::ToFormattedString;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// The number of clients listed as the worst laggards in a digest.
+const DIGEST_LAGGARDS: usize = 5;
+
+/// What a client reported over the period of a digest.
+#[derive(Clone, Copy, Debug, Default)]
+struct ClientDigest
+{
+	/// The number of rounds of checks the client reported.
+	reports: u32,
+	/// The number of those rounds the client was online for.
+	online: u32,
+	/// The most blocks the client was behind the tip of its group while online.
+	max_lag: u64,
+}
+
+/// Records the incidents and results of every client between digests.
+#[derive(Debug)]
+pub struct DigestRecorder
+{
+	since: DateTime<Local>,
+	incidents: usize,
+	clients: BTreeMap<String, ClientDigest>,
+}
+
+impl Default for DigestRecorder
+{
+	fn default() -> Self
+	{
+		Self { since: Local::now(), incidents: 0, clients: BTreeMap::new() }
+	}
+}
+
+impl DigestRecorder
+{
+	/// Records a round of checks of a client, and how many blocks it was behind the tip of its group if it is online.
+	pub fn record_report(&mut self, client: String, lag: Option<u64>)
+	{
+		let digest = self.clients.entry(client).or_default();
+		digest.reports += 1;
+		if let Some(lag) = lag
+		{
+			digest.online += 1;
+			digest.max_lag = digest.max_lag.max(lag);
+		}
+	}
+
+	/// Counts each condition an event starts, such as a client going offline or lagging, as an incident.
+	pub fn record_event(&mut self, event: &MonitorEvent)
+	{
+		self.incidents += alerts::incidents(event);
+	}
+
+	/// Returns the digest of the period since the last one, with the clients that are degraded or offline now, and starts
+	/// a new period.
+	pub fn take(&mut self, degraded: Vec<(String, Health)>) -> MonitorEvent
+	{
+		let recorder = std::mem::take(self);
+
+		let mut uptime = recorder.clients.iter()
+			.filter(|(_, digest)| digest.reports > 0)
+			.map(|(client, digest)| (client.clone(), digest.online as f64 * 100.0 / digest.reports as f64))
+			.collect::<Vec<_>>();
+		uptime.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+		let mut laggards = recorder.clients.iter().filter(|(_, digest)| digest.max_lag > 0).map(|(client, digest)| (client.clone(), digest.max_lag)).collect::<Vec<_>>();
+		laggards.sort_by_key(|laggard| Reverse(laggard.1));
+		laggards.truncate(DIGEST_LAGGARDS);
+
+		MonitorEvent::Digest { since: recorder.since, incidents: recorder.incidents, uptime, laggards, degraded }
+	}
+}
+
+/// Returns the lines of the text of a digest, starting with its title, or `None` for other events.
+pub fn lines(event: &MonitorEvent) -> Option<Vec<String>>
+{
+	let (since, incidents, uptime, laggards, degraded) = match event
+	{
+		MonitorEvent::Digest { since, incidents, uptime, laggards, degraded } => (since, incidents, uptime, laggards, degraded),
+		_ => return None,
+	};
+
+	let mut lines = vec![format!("Digest since {}: {} incidents.", since.format("%Y-%m-%d %H:%M"), incidents.to_formatted_string(&num_format::Locale::en))];
+	if !uptime.is_empty()
+	{
+		let uptime = uptime.iter().map(|(client, percent)| format!("{} {:.2}%", client, percent)).collect::<Vec<_>>();
+		lines.push(format!("Uptime: {}", uptime.join(", ")));
+	}
+	if !laggards.is_empty()
+	{
+		let laggards = laggards.iter().map(|(client, lag)| format!("{} {} blocks", client, lag.to_formatted_string(&num_format::Locale::en))).collect::<Vec<_>>();
+		lines.push(format!("Worst lag: {}", laggards.join(", ")));
+	}
+	if degraded.is_empty()
+	{
+		lines.push("No clients are degraded or offline.".to_string());
+	}
+	else
+	{
+		let degraded = degraded.iter().map(|(client, health)| format!("{} {}", client, health)).collect::<Vec<_>>();
+		lines.push(format!("Degraded now: {}", degraded.join(", ")));
+	}
+	Some(lines)
+}
@@ -0,0 +1,253 @@
+use crate::alerts::{self, Alert, AlertLimiter, RateLimit, Sink};
+use crate::dependency;
+use crate::digest;
+use crate::events::{MonitorEvent, Severity};
+use crate::fleet;
+use crate::halt;
+use crate::health::Health;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use crate::webhook;
+use chrono::Local;
+use num_format::ToFormattedString;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+/// How long Discord can take to accept a message before the attempt fails.
+const DISCORD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How Discord notifications are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig
+{
+	/// The Discord webhook URL messages are posted to.
+	pub webhook_url: Option<String>,
+	/// The least severe messages that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether messages that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe messages are sent straight away, and the rest are held
+	/// until the next digest. Every message is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+/// Posts an embed to a Discord webhook when a client changes state. The task is stopped when this is dropped.
+pub struct DiscordSink
+{
+	task: JoinHandle<()>,
+}
+
+impl DiscordSink
+{
+	/// Starts posting embeds for the alerts received from `alerts`, retrying failed deliveries according to the retry
+	/// policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: DiscordConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, reqwest::Error>
+	{
+		let client = reqwest::Client::builder().timeout(DISCORD_TIMEOUT).build()?;
+		let task = tokio::spawn(async move
+		{
+			let webhook_url = match config.webhook_url
+			{
+				Some(webhook_url) => webhook_url,
+				None => return,
+			};
+
+			let mut limiter = AlertLimiter::new(Sink::Discord, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Discord, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let embed = json!({ "title": "alerts held back", "description": limiter.summary_text(suppressed), "color": color(Severity::Warning), "timestamp": Local::now().to_rfc3339() });
+						tokio::spawn(webhook::deliver(client.clone(), webhook_url.clone(), json!({ "embeds": [embed] }), None, retry.clone()));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to Discord because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Discord) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let embed = match embed(&alert)
+				{
+					Some(embed) => embed,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				tokio::spawn(webhook::deliver(client.clone(), webhook_url.clone(), json!({ "embeds": [embed] }), None, retry.clone()));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for DiscordSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the color of the side bar of embeds of a severity.
+fn color(severity: Severity) -> u32
+{
+	match severity
+	{
+		Severity::Info => 0x2ecc71,
+		Severity::Warning => 0xf1c40f,
+		Severity::Critical => 0xe74c3c,
+	}
+}
+
+/// Returns the Discord embed of an alert, showing the client, the event, and how far behind or how long offline the
+/// client was. Returns `None` for events that are not sent to Discord, which are those that are neither about a change
+/// in the state of a client nor a digest.
+pub fn embed(alert: &Alert) -> Option<Value>
+{
+	let blocks = |blocks: &u64| format!("{} blocks", blocks.to_formatted_string(&num_format::Locale::en));
+	let seconds = |seconds: i64| format!("{} seconds", seconds.to_formatted_string(&num_format::Locale::en));
+
+	let (client, title, description, detail) = match &alert.event
+	{
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } =>
+		{
+			let reason = reason.clone().unwrap_or_default();
+			match (from, to)
+			{
+				(_, Health::Offline) => (client, "offline", reason, None),
+				(Health::Offline, Health::Online) => (client, "online", String::new(), Some(("Offline for", seconds(*duration_seconds)))),
+				(Health::Offline, Health::Degraded) => (client, "degraded", reason, Some(("Offline for", seconds(*duration_seconds)))),
+				(_, Health::Degraded) => (client, "degraded", reason, None),
+				(Health::Degraded, Health::Online) => (client, "recovered", String::new(), Some(("Degraded for", seconds(*duration_seconds)))),
+				_ => return None,
+			}
+		},
+		MonitorEvent::Flapping { client, transitions, window_seconds } => (client, "flapping", format!("Changed health {} times in {} seconds.", transitions, window_seconds), None),
+		MonitorEvent::FlappingStopped { client, health, .. } => (client, "stopped flapping", format!("The client is {}.", health), None),
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } => (client, "lagging", format!("At block {}.", block_number.to_formatted_string(&num_format::Locale::en)), Some(("Behind by", blocks(blocks_behind)))),
+		MonitorEvent::CaughtUp { client, block_number } => (client, "caught up", format!("At block {}.", block_number.to_formatted_string(&num_format::Locale::en)), None),
+		MonitorEvent::SyncStalled { client, block_number, blocks_behind, cycles } => (client, "stalled", format!("Stuck at block {} for {} cycles.", block_number.to_formatted_string(&num_format::Locale::en), cycles), Some(("Behind by", blocks(blocks_behind)))),
+		MonitorEvent::SyncResumed { client, blocks_per_minute } => (client, "syncing again", format!("Syncing at {:.1} blocks per minute.", blocks_per_minute), None),
+		MonitorEvent::TipFrozen { client, block_number, cycles } => (client, "tip frozen", format!("Reported block {} for {} consecutive cycles.", block_number.to_formatted_string(&num_format::Locale::en), cycles), None),
+		MonitorEvent::TipUnfrozen { client, block_number } => (client, "tip moving", format!("Moved on to block {}.", block_number.to_formatted_string(&num_format::Locale::en)), None),
+		MonitorEvent::StillOngoing { client, condition, minutes } => (client, "still ongoing", format!("Still {}.", condition), Some(("Ongoing for", format!("{} minutes", minutes.to_formatted_string(&num_format::Locale::en))))),
+		MonitorEvent::LowPeersStarted { client, peers, min_peers } => (client, "low on peers", format!("Below the minimum of {} peers.", min_peers), Some(("Peers", peers.to_string()))),
+		MonitorEvent::PeersRestored { client, peers } => (client, "peers restored", format!("Back to {} peers.", peers), None),
+		MonitorEvent::Escalated { client, condition, minutes, severity } => (client, "escalated", format!("Still {}, now {}.", condition, severity), Some(("Ongoing for", format!("{} minutes", minutes.to_formatted_string(&num_format::Locale::en))))),
+		MonitorEvent::AlertsAggregated { event_type, host, clients } =>
+		{
+			// Collapsed alerts are about many clients, so they have a layout of their own.
+			return Some(json!(
+			{
+				"title": format!("{} clients: {}", clients.len(), event_type.replace('_', " ")),
+				"description": alert.text.clone().unwrap_or_else(|| alerts::aggregated_text(event_type, host.as_deref(), clients)),
+				"color": color(alert.severity),
+				"fields": [{ "name": "Host", "value": host.as_deref().unwrap_or("unknown"), "inline": true }],
+				"timestamp": Local::now().to_rfc3339(),
+			}));
+		},
+		MonitorEvent::Digest { .. } =>
+		{
+			let mut lines = digest::lines(&alert.event)?;
+			let title = lines.remove(0);
+			return Some(json!(
+			{
+				"title": title,
+				"description": alert.text.clone().unwrap_or_else(|| lines.join("\n")),
+				"color": color(alert.severity),
+				"timestamp": Local::now().to_rfc3339(),
+			}));
+		},
+		MonitorEvent::FleetDegraded { condition, group, .. } | MonitorEvent::FleetRecovered { condition, group, .. } =>
+		{
+			let title = if alert.is_recovery() { "cleared" } else { "holds" };
+			return Some(json!(
+			{
+				"title": format!("Fleet condition {}: {}", condition, title),
+				"description": alert.text.clone().or_else(|| fleet::text(&alert.event)).unwrap_or_default(),
+				"color": color(alert.severity),
+				"fields": [{ "name": "Group", "value": group.as_deref().unwrap_or("all"), "inline": true }],
+				"timestamp": Local::now().to_rfc3339(),
+			}));
+		},
+		MonitorEvent::ChainHalted { group, block_number, .. } | MonitorEvent::ChainResumed { group, block_number, .. } =>
+		{
+			let title = if alert.is_recovery() { "resumed" } else { "halted" };
+			return Some(json!(
+			{
+				"title": format!("{}: chain {}", group, title),
+				"description": alert.text.clone().or_else(|| halt::text(&alert.event)).unwrap_or_default(),
+				"color": color(alert.severity),
+				"fields": [{ "name": "Block", "value": block_number.to_formatted_string(&num_format::Locale::en), "inline": true }],
+				"timestamp": Local::now().to_rfc3339(),
+			}));
+		},
+		MonitorEvent::ParentDown { parent, .. } | MonitorEvent::ParentRecovered { parent, .. } =>
+		{
+			let mut embed = json!(
+			{
+				"title": alert.title()?,
+				"description": alert.text.clone().or_else(|| dependency::text(&alert.event)).unwrap_or_default(),
+				"color": color(alert.severity),
+				"timestamp": Local::now().to_rfc3339(),
+			});
+			if let MonitorEvent::ParentDown { clients, .. } = &alert.event
+			{
+				embed["fields"] = json!([{ "name": "Suppressed clients", "value": clients.len().to_string(), "inline": true }, { "name": "Parent", "value": parent.to_string(), "inline": true }]);
+			}
+			return Some(embed);
+		},
+		_ => return None,
+	};
+
+	let mut fields = vec![json!({ "name": "Client", "value": client, "inline": true }), json!({ "name": "Event", "value": title, "inline": true })];
+	if let Some((name, value)) = detail
+	{
+		fields.push(json!({ "name": name, "value": value, "inline": true }));
+	}
+
+	let mut embed = json!(
+	{
+		"title": format!("{}: {}", client, title),
+		"color": color(alert.severity),
+		"fields": fields,
+		"timestamp": Local::now().to_rfc3339(),
+	});
+	if let Some(text) = &alert.text
+	{
+		embed["description"] = Value::String(text.clone());
+	}
+	else if !description.is_empty()
+	{
+		embed["description"] = Value::String(description);
+	}
+
+	Some(embed)
+}
@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// The disk usage of the data directory of a locally run client.
+#[derive(Clone, Copy, Debug)]
+pub struct DiskStats
+{
+	/// The total size of the files in the data directory, in bytes.
+	pub data_dir_bytes: u64,
+	/// The space available on the filesystem the data directory is on, in bytes.
+	pub free_bytes: u64,
+	/// The size of the filesystem the data directory is on, in bytes.
+	pub total_bytes: u64,
+}
+
+impl DiskStats
+{
+	/// Returns the percentage of the filesystem that is available.
+	pub fn free_percent(&self) -> f64
+	{
+		if self.total_bytes == 0
+		{
+			return 0.0;
+		}
+
+		self.free_bytes as f64 / self.total_bytes as f64 * 100.0
+	}
+}
+
+/// Measures the size of a data directory and the free space on its filesystem. The directory is walked on a blocking
+/// thread, since a large store can take a while.
+pub async fn measure(data_dir: &Path) -> Result<DiskStats, Box<dyn Error>>
+{
+	let path = data_dir.to_path_buf();
+	let data_dir_bytes = tokio::task::spawn_blocking(move || directory_size(&path)).await?
+		.map_err(|e| format!("Unable to measure data directory {}: {}", data_dir.display(), e))?;
+	let (free_bytes, total_bytes) = filesystem_space(data_dir).await?;
+
+	Ok(DiskStats { data_dir_bytes, free_bytes, total_bytes })
+}
+
+/// Returns the total size of the files in a directory and all of its subdirectories. Symbolic links are not followed.
+fn directory_size(path: &Path) -> io::Result<u64>
+{
+	let mut size = 0;
+	let mut directories = vec![PathBuf::from(path)];
+	while let Some(directory) = directories.pop()
+	{
+		for entry in fs::read_dir(&directory)?
+		{
+			let entry = entry?;
+			let metadata = entry.metadata()?;
+			if metadata.is_dir()
+			{
+				directories.push(entry.path());
+			}
+			else
+			{
+				size += metadata.len();
+			}
+		}
+	}
+
+	Ok(size)
+}
+
+/// Returns the available and total space of the filesystem a path is on, in bytes, using the POSIX output of `df`.
+async fn filesystem_space(path: &Path) -> Result<(u64, u64), Box<dyn Error>>
+{
+	let output = Command::new("df").arg("-Pk").arg(path).output().await?;
+	if !output.status.success()
+	{
+		return Err(format!("Unable to read the free space of {}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim()).into());
+	}
+
+	// The second line holds the filesystem, total, used, and available 1024 byte blocks.
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let fields = stdout.lines().nth(1).map(|line| line.split_whitespace().collect::<Vec<_>>()).unwrap_or_default();
+	match (fields.get(1).and_then(|total| total.parse::<u64>().ok()), fields.get(3).and_then(|available| available.parse::<u64>().ok()))
+	{
+		(Some(total), Some(available)) => Ok((available * 1024, total * 1024)),
+		_ => Err(format!("Unable to parse the free space of {}.", path.display()).into()),
+	}
+}
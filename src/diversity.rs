@@ -0,0 +1,99 @@
+use maxminddb::{geoip2, Reader};
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// The network a peer address falls in, used to tell if the peers of a client are all run from the same place.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Network
+{
+	/// The /24 of an IPv4 address or the /48 of an IPv6 address.
+	Subnet(IpAddr, u8),
+	/// The autonomous system that announces the address.
+	Asn(u32),
+}
+
+impl fmt::Display for Network
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Network::Subnet(ip, prefix) => write!(f, "subnet {}/{}", ip, prefix),
+			Network::Asn(asn) => write!(f, "AS{}", asn),
+		}
+	}
+}
+
+/// A MaxMind ASN database, such as GeoLite2-ASN, used to find the autonomous system of a peer.
+pub struct AsnDatabase
+{
+	reader: Reader<Vec<u8>>,
+}
+
+impl AsnDatabase
+{
+	/// Loads the database from a `.mmdb` file.
+	pub fn open(path: &Path) -> Result<Self, Box<dyn Error>>
+	{
+		let reader = Reader::open_readfile(path).map_err(|e| format!("Unable to read ASN database {}: {}", path.display(), e))?;
+		Ok(Self { reader })
+	}
+
+	/// Returns the autonomous system of an IP address, or `None` if it is not in the database.
+	pub fn lookup(&self, ip: IpAddr) -> Option<Network>
+	{
+		let asn: geoip2::Asn = self.reader.lookup(ip).ok()?;
+		asn.autonomous_system_number.map(Network::Asn)
+	}
+}
+
+/// Extracts the IP address from a peer multiaddr, such as `/ip4/1.2.3.4/tcp/8115/p2p/Qm...`. Addresses that use a DNS
+/// name have no IP address.
+pub fn peer_ip(address: &str) -> Option<IpAddr>
+{
+	let mut parts = address.trim_start_matches('/').split('/');
+	match (parts.next()?, parts.next()?)
+	{
+		("ip4" | "ip6", ip) => ip.parse().ok(),
+		_ => None,
+	}
+}
+
+/// Returns the subnet an IP address falls in, which is the /24 of an IPv4 address or the /48 of an IPv6 address.
+pub fn subnet(ip: IpAddr) -> Network
+{
+	match ip
+	{
+		IpAddr::V4(ip) =>
+		{
+			let [a, b, c, _] = ip.octets();
+			Network::Subnet(IpAddr::from([a, b, c, 0]), 24)
+		},
+		IpAddr::V6(ip) =>
+		{
+			let segments = ip.segments();
+			Network::Subnet(IpAddr::from([segments[0], segments[1], segments[2], 0, 0, 0, 0, 0]), 48)
+		},
+	}
+}
+
+/// Returns the single network every IP address falls in, or `None` if there are fewer than two addresses or they span
+/// more than one network. The subnet is checked first, then the autonomous system if a database is available.
+pub fn shared_network(ips: &[IpAddr], asn_database: Option<&AsnDatabase>) -> Option<Network>
+{
+	if ips.len() < 2
+	{
+		return None;
+	}
+
+	let same = |networks: Vec<Option<Network>>| -> Option<Network>
+	{
+		let first = networks.first()?.clone()?;
+		networks.iter().all(|network| network.as_ref() == Some(&first)).then_some(first)
+	};
+
+	same(ips.iter().map(|ip| Some(subnet(*ip))).collect())
+		.or_else(|| same(ips.iter().map(|ip| asn_database?.lookup(*ip)).collect()))
+}
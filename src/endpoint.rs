@@ -0,0 +1,640 @@
+use crate::config::ClientSettings;
+use crate::ratelimit;
+use crate::retry::RetryPolicy;
+use crate::rpc::{self, ResponseError, RpcError, Schema};
+use chrono::{DateTime, TimeZone, Utc};
+use hyperlocal::{UnixClientExt, UnixConnector};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::{self, TcpStream};
+use tokio::time;
+use tokio_rustls::TlsConnector;
+
+/// The URL scheme used for JSON-RPC servers listening on a unix domain socket.
+const UNIX_SCHEME: &str = "unix://";
+
+/// How long an idle pooled connection is kept open. This is longer than the default check interval so connections
+/// survive between checks.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The interval of TCP keepalive probes on pooled connections.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// How long after the certificate chain of a server is inspected that it is inspected again.
+const CERTIFICATE_INSPECTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The minimum TLS version accepted from an HTTPS endpoint.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum TlsVersion
+{
+	#[serde(rename = "1.0")]
+	Tls10,
+	#[serde(rename = "1.1")]
+	Tls11,
+	#[serde(rename = "1.2")]
+	Tls12,
+	#[serde(rename = "1.3")]
+	Tls13,
+}
+
+impl From<TlsVersion> for reqwest::tls::Version
+{
+	fn from(version: TlsVersion) -> Self
+	{
+		match version
+		{
+			TlsVersion::Tls10 => reqwest::tls::Version::TLS_1_0,
+			TlsVersion::Tls11 => reqwest::tls::Version::TLS_1_1,
+			TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+			TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+		}
+	}
+}
+
+/// TLS options for HTTPS endpoints.
+#[derive(Clone, Debug)]
+pub struct TlsSettings
+{
+	/// A PEM encoded CA certificate to trust in addition to the system roots.
+	pub ca_file: Option<PathBuf>,
+	/// Set to false to accept certificates that do not match the hostname.
+	pub verify_hostname: bool,
+	pub min_version: Option<TlsVersion>,
+}
+
+impl Default for TlsSettings
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			ca_file: None,
+			verify_hostname: true,
+			min_version: None,
+		}
+	}
+}
+
+/// Authentication applied to every request sent to an HTTP endpoint.
+#[derive(Clone, Debug, Default)]
+pub struct AuthSettings
+{
+	/// The username for HTTP basic auth. Basic auth is only used when this is set.
+	pub username: Option<String>,
+	pub password: Option<String>,
+	/// A token sent as an `Authorization: Bearer` header.
+	pub bearer_token: Option<String>,
+	/// Additional headers sent with every request.
+	pub headers: HashMap<String, String>,
+}
+
+/// The transport used to reach the JSON-RPC server of a client.
+#[derive(Clone, Debug)]
+pub enum Transport
+{
+	/// An HTTP or HTTPS URL, such as `http://127.0.0.1:19000/`.
+	Http(String),
+	/// The path of a unix domain socket, given as `unix:///path/to.sock`.
+	Unix(PathBuf),
+}
+
+impl Transport
+{
+	/// Creates a transport from a URL. URLs starting with `unix://` are treated as unix domain socket paths.
+	pub fn new(url: &str) -> Self
+	{
+		match url.strip_prefix(UNIX_SCHEME)
+		{
+			Some(path) => Self::Unix(PathBuf::from(path)),
+			None => Self::Http(url.to_string()),
+		}
+	}
+}
+
+impl fmt::Display for Transport
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Self::Http(url) => write!(f, "{}", url),
+			Self::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+		}
+	}
+}
+
+/// The HTTP client used to send requests over a transport. It is created once per endpoint so connections are pooled
+/// and kept alive between checks.
+#[derive(Clone, Debug)]
+enum Connection
+{
+	Http(reqwest::Client),
+	Unix(hyper::Client<UnixConnector>),
+}
+
+/// The JSON-RPC server of a client, along with the options used to connect to it.
+#[derive(Clone, Debug)]
+pub struct RpcEndpoint
+{
+	pub transport: Transport,
+	auth: AuthSettings,
+	request_timeout: Duration,
+	probe_timeout: Duration,
+	strict_schema: bool,
+	retry: RetryPolicy,
+	/// The connection to the server, or the reason it could not be created.
+	connection: Result<Connection, String>,
+	/// Responses from the last batch sent by `prefetch`, keyed by method, that have not been used by `call` yet.
+	prefetched: Arc<Mutex<HashMap<String, Result<Value, CallError>>>>,
+	/// The round trip times of the requests that received a response since `take_round_trips` was last called.
+	round_trips: Arc<Mutex<Vec<Duration>>>,
+	/// Whether the certificate chain of an HTTPS server is inspected for certificates that are about to expire.
+	certificate_warnings: bool,
+	/// When the certificate chain of the server was last inspected.
+	certificates_inspected: Arc<Mutex<Option<Instant>>>,
+	/// When the earliest expiring certificate in the chain the server presented at its last inspection expires.
+	certificate_expiry: Arc<Mutex<Option<DateTime<Utc>>>>,
+}
+
+/// Why a JSON-RPC call failed.
+#[derive(Clone, Debug)]
+pub enum CallError
+{
+	/// The server could not be reached or did not respond.
+	Unreachable(String),
+	/// The host of the server could not be resolved or did not answer the connection probe.
+	HostUnreachable(String),
+	/// The host of the server answered the connection probe, but nothing is listening on the port.
+	PortClosed(String),
+	/// The server responded with an HTTP error status.
+	Status(u16),
+	/// The server handled the call, but returned a JSON-RPC error.
+	Rpc(RpcError),
+	/// The server responded, but with a result that could not be parsed.
+	Invalid(String),
+	/// The server responded with a result that has fields the monitor does not know about, in strict schema mode.
+	SchemaDrift(Vec<String>),
+	/// The TLS handshake failed because a certificate the server presented has expired.
+	CertificateExpired(String),
+}
+
+impl CallError
+{
+	/// Returns true if the error means the server is not serving requests. Errors returned by the node itself mean it
+	/// is serving requests, even if it cannot complete this one.
+	pub fn is_offline(&self) -> bool
+	{
+		matches!(self, Self::Unreachable(_) | Self::HostUnreachable(_) | Self::PortClosed(_) | Self::Status(_) | Self::CertificateExpired(_))
+	}
+}
+
+impl From<ResponseError> for CallError
+{
+	fn from(e: ResponseError) -> Self
+	{
+		match e
+		{
+			ResponseError::Rpc(e) => Self::Rpc(e),
+			ResponseError::Invalid(e) => Self::Invalid(e),
+			ResponseError::SchemaDrift(fields) => Self::SchemaDrift(fields),
+		}
+	}
+}
+
+impl fmt::Display for CallError
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Self::Unreachable(e) => write!(f, "unreachable: {}", e),
+			Self::HostUnreachable(e) => write!(f, "host unreachable: {}", e),
+			Self::PortClosed(e) => write!(f, "port closed: {}", e),
+			Self::Status(status) => write!(f, "HTTP status {}", status),
+			Self::Rpc(e) => write!(f, "{}", e),
+			Self::Invalid(e) => write!(f, "invalid response: {}", e),
+			Self::SchemaDrift(fields) => write!(f, "schema drift: unexpected fields {}", fields.join(", ")),
+			Self::CertificateExpired(e) => write!(f, "certificate expired: {}", e),
+		}
+	}
+}
+
+/// The status and body of a response from a JSON-RPC server.
+pub struct RpcResponse
+{
+	pub status: u16,
+	pub body: Vec<u8>,
+}
+
+impl RpcResponse
+{
+	/// Returns true if the HTTP status code indicates success.
+	pub fn is_success(&self) -> bool
+	{
+		(200..300).contains(&self.status)
+	}
+
+	/// Parses the body of the response as JSON.
+	pub fn json(&self) -> Result<Value, serde_json::Error>
+	{
+		serde_json::from_slice(&self.body)
+	}
+}
+
+impl RpcEndpoint
+{
+	/// Creates the endpoint of a client.
+	pub fn new(settings: &ClientSettings) -> Self
+	{
+		let transport = Transport::new(&settings.url);
+		let connection = match &transport
+		{
+			Transport::Http(_) => http_client(settings).map(Connection::Http).map_err(|e| e.to_string()),
+			Transport::Unix(_) => Ok(Connection::Unix(hyper::Client::unix())),
+		};
+		if let Err(e) = &connection
+		{
+			tracing::error!("Failed to create an HTTP client for {}: {}", transport, e);
+		}
+
+		Self
+		{
+			transport,
+			auth: settings.auth.clone(),
+			request_timeout: settings.request_timeout,
+			probe_timeout: settings.probe_timeout,
+			strict_schema: settings.strict_schema,
+			retry: settings.retry.clone(),
+			connection,
+			prefetched: Arc::new(Mutex::new(HashMap::new())),
+			round_trips: Arc::new(Mutex::new(Vec::new())),
+			certificate_warnings: !settings.min_certificate_age.is_zero(),
+			certificates_inspected: Arc::new(Mutex::new(None)),
+			certificate_expiry: Arc::new(Mutex::new(None)),
+		}
+	}
+
+	/// Sends calls to the specified methods as a single JSON-RPC batch, and holds each response for the next `call` of
+	/// its method. This lets several checks share one request. The host is probed first, and if the probe fails the
+	/// batch is not sent and every call fails with the reason.
+	pub async fn prefetch(&self, methods: &[&str])
+	{
+		if methods.is_empty()
+		{
+			return;
+		}
+
+		let batch = methods.iter().enumerate().map(|(id, method)| rpc::request(id as u64, method)).collect();
+		let responses = match self.probe().await
+		{
+			Err(e) => Err(e),
+			Ok(()) =>
+			{
+				self.inspect_certificates().await;
+				match self.post(&Value::Array(batch)).await
+				{
+					Ok(response) if response.is_success() => response.json().map_err(|e| e.to_string()).and_then(rpc::batch_responses).map_err(CallError::Invalid),
+					Ok(response) => Err(CallError::Status(response.status)),
+					Err(e) => Err(request_error(e.as_ref())),
+				}
+			},
+		};
+
+		let mut prefetched = self.prefetched.lock().unwrap();
+		for (id, method) in methods.iter().enumerate()
+		{
+			let response = match &responses
+			{
+				Ok(responses) => responses.get(&(id as u64)).cloned().ok_or_else(|| CallError::Invalid("no response".to_string())),
+				Err(e) => Err(e.clone()),
+			};
+			prefetched.insert(method.to_string(), response);
+		}
+	}
+
+	/// Resolves the host of an HTTP endpoint and opens a TCP connection to it, so a host that cannot be reached can be
+	/// told apart from a closed port or a server that returns an error. Unix sockets are not probed.
+	async fn probe(&self) -> Result<(), CallError>
+	{
+		let url = match &self.transport
+		{
+			Transport::Http(url) if !self.probe_timeout.is_zero() => url,
+			_ => return Ok(()),
+		};
+		let url = reqwest::Url::parse(url).map_err(|e| CallError::Unreachable(e.to_string()))?;
+		let (host, port) = match (url.host_str(), url.port_or_known_default())
+		{
+			(Some(host), Some(port)) => (host.trim_start_matches('[').trim_end_matches(']').to_string(), port),
+			_ => return Ok(()),
+		};
+
+		let addresses = match time::timeout(self.probe_timeout, net::lookup_host((host.as_str(), port))).await
+		{
+			Ok(Ok(addresses)) => addresses.collect::<Vec<_>>(),
+			Ok(Err(e)) => return Err(CallError::HostUnreachable(format!("unable to resolve {}: {}", host, e))),
+			Err(_) => return Err(CallError::HostUnreachable(format!("resolving {} timed out", host))),
+		};
+
+		let mut error = CallError::HostUnreachable(format!("{} did not resolve to any address", host));
+		for address in addresses
+		{
+			error = match time::timeout(self.probe_timeout, TcpStream::connect(address)).await
+			{
+				Ok(Ok(_)) => return Ok(()),
+				Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => CallError::PortClosed(format!("{} refused the connection", address)),
+				Ok(Err(e)) => CallError::HostUnreachable(format!("unable to connect to {}: {}", address, e)),
+				Err(_) => CallError::HostUnreachable(format!("connecting to {} timed out after {} ms", address, self.probe_timeout.as_millis())),
+			};
+		}
+		Err(error)
+	}
+
+	/// Inspects the certificate chain of an HTTPS server and records when the earliest expiring certificate in it
+	/// expires. The chain is inspected again only once an hour has passed since it last was.
+	async fn inspect_certificates(&self)
+	{
+		let url = match &self.transport
+		{
+			Transport::Http(url) if self.certificate_warnings && url.starts_with("https://") => url,
+			_ => return,
+		};
+		if self.certificates_inspected.lock().unwrap().is_some_and(|inspected| inspected.elapsed() < CERTIFICATE_INSPECTION_INTERVAL)
+		{
+			return;
+		}
+
+		match certificate_chain_expiry(url, self.request_timeout).await
+		{
+			Ok(Some(expiry)) =>
+			{
+				*self.certificates_inspected.lock().unwrap() = Some(Instant::now());
+				*self.certificate_expiry.lock().unwrap() = Some(expiry);
+			},
+			Ok(None) => {},
+			Err(e) => tracing::debug!("Failed to inspect the certificates of {}: {}", self.transport, e),
+		}
+	}
+
+	/// Discards any prefetched responses that were not used.
+	pub fn clear_prefetched(&self)
+	{
+		self.prefetched.lock().unwrap().clear();
+	}
+
+	/// Returns the round trip times of the requests that received a response since this was last called.
+	pub fn take_round_trips(&self) -> Vec<Duration>
+	{
+		std::mem::take(&mut *self.round_trips.lock().unwrap())
+	}
+
+	/// Returns the round trip time of the last request that received a response, without taking it.
+	pub fn last_round_trip(&self) -> Option<Duration>
+	{
+		self.round_trips.lock().unwrap().last().copied()
+	}
+
+	/// Returns when the earliest expiring certificate in the chain of the server expires, if the chain was inspected
+	/// since this was last called, or `None` if it was not.
+	pub fn take_certificate_expiry(&self) -> Option<DateTime<Utc>>
+	{
+		self.certificate_expiry.lock().unwrap().take()
+	}
+
+	/// Calls a method that takes no parameters and parses its result. A response held by `prefetch` is used instead of
+	/// sending a new request if there is one.
+	pub async fn call<T>(&self, method: &str) -> Result<T, CallError>
+	where
+		T: DeserializeOwned + Schema,
+	{
+		let prefetched = self.prefetched.lock().unwrap().remove(method);
+		let response = match prefetched
+		{
+			Some(response) => response?,
+			None => match self.post(&rpc::request(1, method)).await
+			{
+				Ok(response) if response.is_success() => response.json().map_err(|e| CallError::Invalid(e.to_string()))?,
+				Ok(response) => return Err(CallError::Status(response.status)),
+				Err(e) => return Err(request_error(e.as_ref())),
+			},
+		};
+
+		self.parse_result(response)
+	}
+
+	/// Calls a method with the specified parameters and parses its result. These calls are never prefetched.
+	pub async fn call_with_params<T>(&self, method: &str, params: Value) -> Result<T, CallError>
+	where
+		T: DeserializeOwned + Schema,
+	{
+		let response = match self.post(&rpc::request_with_params(1, method, params)).await
+		{
+			Ok(response) if response.is_success() => response.json().map_err(|e| CallError::Invalid(e.to_string()))?,
+			Ok(response) => return Err(CallError::Status(response.status)),
+			Err(e) => return Err(request_error(e.as_ref())),
+		};
+
+		self.parse_result(response)
+	}
+
+	/// Parses the result of a response, checking it against the known shape of its type first in strict schema mode.
+	fn parse_result<T>(&self, response: Value) -> Result<T, CallError>
+	where
+		T: DeserializeOwned + Schema,
+	{
+		let result = if self.strict_schema { rpc::parse_result_strict(Some(response)) } else { rpc::parse_result(Some(response)) };
+		result.map_err(CallError::from)
+	}
+
+	/// Sends a JSON-RPC payload to the endpoint and returns the response. Transport failures and gateway errors are
+	/// retried according to the retry policy of the endpoint.
+	pub async fn post(&self, payload: &Value) -> Result<RpcResponse, Box<dyn Error + Send + Sync>>
+	{
+		let mut retry = 0;
+		loop
+		{
+			let result = self.post_once(payload).await;
+			let is_transient = match &result
+			{
+				Ok(response) => matches!(response.status, 502..=504),
+				Err(_) => true,
+			};
+			if !is_transient || retry >= self.retry.attempts
+			{
+				return result;
+			}
+
+			let delay = self.retry.delay(retry);
+			tracing::debug!("Request to {} failed. Retrying in {} ms.", self.transport, delay.as_millis());
+			time::sleep(delay).await;
+			retry += 1;
+		}
+	}
+
+	/// Sends a JSON-RPC payload to the endpoint once and returns the response, recording its round trip time. Time
+	/// spent waiting for the rate limit is not included.
+	async fn post_once(&self, payload: &Value) -> Result<RpcResponse, Box<dyn Error + Send + Sync>>
+	{
+		let connection = self.connection.as_ref().map_err(|e| e.clone())?;
+		ratelimit::acquire().await;
+
+		let started = Instant::now();
+		let result = self.send(connection, payload).await;
+		if result.is_ok()
+		{
+			self.round_trips.lock().unwrap().push(started.elapsed());
+		}
+
+		result
+	}
+
+	/// Sends a JSON-RPC payload over a connection and returns the response.
+	async fn send(&self, connection: &Connection, payload: &Value) -> Result<RpcResponse, Box<dyn Error + Send + Sync>>
+	{
+		match (&self.transport, connection)
+		{
+			(Transport::Http(url), Connection::Http(client)) =>
+			{
+				let mut request = client.post(url).json(payload);
+				if let Some(username) = &self.auth.username
+				{
+					request = request.basic_auth(username, self.auth.password.as_ref());
+				}
+				if let Some(bearer_token) = &self.auth.bearer_token
+				{
+					request = request.bearer_auth(bearer_token);
+				}
+				for (name, value) in self.auth.headers.iter()
+				{
+					request = request.header(name, value);
+				}
+
+				let response = request.send().await?;
+				let status = response.status().as_u16();
+				let body = response.bytes().await?.to_vec();
+
+				Ok(RpcResponse { status, body })
+			},
+			(Transport::Unix(path), Connection::Unix(client)) =>
+			{
+				let uri: hyper::Uri = hyperlocal::Uri::new(path, "/").into();
+				let request = hyper::Request::post(uri)
+					.header(hyper::header::CONTENT_TYPE, "application/json")
+					.body(hyper::Body::from(serde_json::to_vec(payload)?))?;
+
+				// The unix socket client has no built in timeouts, so the whole exchange is bounded by the request timeout.
+				let exchange = async
+				{
+					let response = client.request(request).await?;
+					let status = response.status().as_u16();
+					let body = hyper::body::to_bytes(response.into_body()).await?.to_vec();
+
+					Ok::<_, hyper::Error>(RpcResponse { status, body })
+				};
+				let response = time::timeout(self.request_timeout, exchange).await
+					.map_err(|_| format!("Request timed out after {} seconds.", self.request_timeout.as_secs()))??;
+
+				Ok(response)
+			},
+			_ => Err(format!("The connection for {} does not match its transport.", self.transport).into()),
+		}
+	}
+}
+
+/// Returns when a DER encoded X.509 certificate expires.
+fn parse_certificate_expiry(der: &[u8]) -> Option<DateTime<Utc>>
+{
+	let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+	Utc.timestamp_opt(certificate.validity().not_after.timestamp(), 0).single()
+}
+
+/// Records the certificate chain a server presents without verifying it, so the chain can still be inspected when a
+/// certificate in it has expired. Requests are never sent over a connection that uses this.
+struct ChainRecorder
+{
+	chain: Mutex<Vec<Vec<u8>>>,
+}
+
+impl ServerCertVerifier for ChainRecorder
+{
+	fn verify_server_cert(&self, end_entity: &rustls::Certificate, intermediates: &[rustls::Certificate], _server_name: &rustls::ServerName, _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: SystemTime) -> Result<ServerCertVerified, rustls::Error>
+	{
+		*self.chain.lock().unwrap() = std::iter::once(end_entity).chain(intermediates).map(|certificate| certificate.0.clone()).collect();
+		Ok(ServerCertVerified::assertion())
+	}
+}
+
+/// Completes a TLS handshake with an HTTPS server and returns when the earliest expiring certificate in the chain it
+/// presents expires, or `None` if the URL is not HTTPS.
+async fn certificate_chain_expiry(url: &str, timeout: Duration) -> Result<Option<DateTime<Utc>>, Box<dyn Error + Send + Sync>>
+{
+	let url = reqwest::Url::parse(url)?;
+	let (host, port) = match (url.scheme(), url.host_str(), url.port_or_known_default())
+	{
+		("https", Some(host), Some(port)) => (host.trim_start_matches('[').trim_end_matches(']').to_string(), port),
+		_ => return Ok(None),
+	};
+
+	let recorder = Arc::new(ChainRecorder { chain: Mutex::new(Vec::new()) });
+	let config = rustls::ClientConfig::builder()
+		.with_safe_defaults()
+		.with_custom_certificate_verifier(recorder.clone())
+		.with_no_client_auth();
+	let server_name = rustls::ServerName::try_from(host.as_str())?;
+	let stream = time::timeout(timeout, TcpStream::connect((host.as_str(), port))).await??;
+	time::timeout(timeout, TlsConnector::from(Arc::new(config)).connect(server_name, stream)).await??;
+
+	let chain = recorder.chain.lock().unwrap();
+	Ok(chain.iter().filter_map(|der| parse_certificate_expiry(der)).min())
+}
+
+/// Classifies a request that failed without a response, telling a TLS handshake that failed because a certificate
+/// has expired apart from a server that could not be reached.
+fn request_error(e: &(dyn Error + 'static)) -> CallError
+{
+	let mut source = Some(e);
+	while let Some(error) = source
+	{
+		let text = error.to_string().to_lowercase();
+		if text.contains("certificate has expired") || text.contains("certificate expired") || text.contains("certificateexpired")
+		{
+			return CallError::CertificateExpired(error.to_string());
+		}
+		source = error.source();
+	}
+	CallError::Unreachable(e.to_string())
+}
+
+/// Builds an HTTP client that applies the TLS options and timeouts of a client. Idle connections are kept alive so
+/// they can be reused by the next check.
+pub fn http_client(settings: &ClientSettings) -> Result<reqwest::Client, Box<dyn Error + Send + Sync>>
+{
+	let tls = &settings.tls;
+	let mut builder = reqwest::Client::builder()
+		.connect_timeout(settings.connect_timeout)
+		.timeout(settings.request_timeout)
+		.pool_idle_timeout(POOL_IDLE_TIMEOUT)
+		.tcp_keepalive(TCP_KEEPALIVE)
+		.danger_accept_invalid_hostnames(!tls.verify_hostname);
+
+	if let Some(ca_file) = &tls.ca_file
+	{
+		let pem = fs::read(ca_file).map_err(|e| format!("Failed to read CA file {}: {}", ca_file.display(), e))?;
+		builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+	}
+	if let Some(min_version) = tls.min_version
+	{
+		builder = builder.min_tls_version(min_version.into());
+	}
+
+	Ok(builder.build()?)
+}
@@ -0,0 +1,902 @@
+use crate::alerts::Condition;
+use crate::dependency::{self, Parent};
+use crate::digest;
+use crate::fleet;
+use crate::halt;
+use crate::health::{Health, OutageStats};
+use crate::propagation::PropagationStats;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use num_format::{ToFormattedString};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How urgent an event is, as used by alert destinations to decide where, and whether, to send it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity
+{
+	#[default]
+	Info,
+	Warning,
+	Critical,
+}
+
+impl fmt::Display for Severity
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Severity::Info => write!(f, "info"),
+			Severity::Warning => write!(f, "warning"),
+			Severity::Critical => write!(f, "critical"),
+		}
+	}
+}
+
+/// Something the monitor observed about a client or group. Events are broadcast to every subscriber, such as the log
+/// output, so checks and reports do not need to know how their results are presented. Events are serialized as objects
+/// of their fields, with their variant in snake case as `event`, such as `{"event": "lag_started", ...}`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MonitorEvent
+{
+	/// The health of a client changed.
+	HealthChanged
+	{
+		client: String,
+		from: Health,
+		to: Health,
+		/// Why the client is no longer online, if it is not.
+		reason: Option<String>,
+		/// How long the client had the health it has just left.
+		duration_seconds: i64,
+	},
+	/// A client changed health so often within the flap window that it is flapping. Its changes in health are not
+	/// reported until it stops.
+	Flapping
+	{
+		client: String,
+		/// The number of changes in health within the flap window.
+		transitions: usize,
+		window_seconds: u64,
+	},
+	/// A client that was flapping has settled down.
+	FlappingStopped
+	{
+		client: String,
+		health: Health,
+		/// The number of changes in health that were not reported while the client was flapping.
+		suppressed: usize,
+	},
+	/// The number of peers a client is connected to changed.
+	PeerCountChanged
+	{
+		client: String,
+		previous: u16,
+		peers: u16,
+	},
+	/// The latency of a client rose above its maximum.
+	LatencyHigh
+	{
+		client: String,
+		latency_ms: u64,
+		max_latency_ms: u64,
+		/// The 95th percentile latency of its recent requests.
+		p95_ms: u64,
+	},
+	/// The latency of a client fell back below its maximum.
+	LatencyNormal
+	{
+		client: String,
+		latency_ms: u64,
+	},
+	/// The block number of a client went backwards, which means it rolled back, its data was corrupted, or it
+	/// restarted and is resyncing.
+	TipRegressed
+	{
+		client: String,
+		previous: u64,
+		block_number: u64,
+	},
+	/// A client trails the highest client in its group by more than its maximum block difference.
+	Lagging
+	{
+		client: String,
+		block_number: u64,
+		blocks_behind: u64,
+		/// The estimated number of minutes until the client catches up at its current rate, or `None` if it is not
+		/// catching up.
+		eta_minutes: Option<f64>,
+	},
+	/// A client has started lagging. It is reported as `Lagging` at every lag report until it catches up.
+	LagStarted
+	{
+		client: String,
+		block_number: u64,
+		blocks_behind: u64,
+	},
+	/// A client that was lagging has caught up with its group.
+	CaughtUp
+	{
+		client: String,
+		block_number: u64,
+	},
+	/// The estimated time for a lagging client to catch up has grown since it was last reported, or it has stopped
+	/// catching up.
+	EtaGrowing
+	{
+		client: String,
+		blocks_behind: u64,
+		eta_minutes: Option<f64>,
+		previous_eta_minutes: f64,
+	},
+	/// The filtering of some scripts on a client trails its tip by more than the maximum script lag.
+	ScriptsLagging
+	{
+		client: String,
+		/// The tip of the client.
+		block_number: u64,
+		max_script_lag: u64,
+		/// Each script that is behind, along with the block number it has been synced to.
+		scripts: Vec<(String, u64)>,
+	},
+	/// The filtering of every script on a client is within the maximum script lag of its tip again.
+	ScriptsCaughtUp
+	{
+		client: String,
+	},
+	/// A client is on a different chain than the rest of its group, according to the hash of its genesis block.
+	WrongNetwork
+	{
+		client: String,
+		genesis_hash: String,
+		expected: String,
+	},
+	/// A client is running a version older than the minimum version of its group.
+	OutdatedVersion
+	{
+		client: String,
+		version: String,
+		min_version: String,
+	},
+	/// A client is running a different version than the majority of its group.
+	VersionMismatch
+	{
+		client: String,
+		version: String,
+		majority: String,
+	},
+	/// The process of a locally run client is using more memory than its maximum.
+	MemoryHigh
+	{
+		client: String,
+		memory_mb: u64,
+		max_memory_mb: u64,
+	},
+	/// The process of a locally run client is using no more memory than its maximum again.
+	MemoryNormal
+	{
+		client: String,
+		memory_mb: u64,
+	},
+	/// The data directory of a locally run client is over its maximum size, or its filesystem is nearly full.
+	DiskUsageHigh
+	{
+		client: String,
+		reason: String,
+	},
+	/// The disk usage of a locally run client is within its limits again.
+	DiskUsageNormal
+	{
+		client: String,
+		data_dir_mb: u64,
+		free_percent: f64,
+	},
+	/// A line in the log file of a client matched one of the log patterns.
+	LogMatched
+	{
+		client: String,
+		pattern: String,
+		line: String,
+		/// The health of the client when the line was read.
+		health: Health,
+	},
+	/// The tip block of a client is older than its maximum tip age.
+	TipStale
+	{
+		client: String,
+		block_number: u64,
+		age_seconds: u64,
+		max_tip_age_seconds: u64,
+	},
+	/// A client that had a stale tip has a recent one again.
+	TipFresh
+	{
+		client: String,
+		block_number: u64,
+	},
+	/// The TLS certificate of a client expires sooner than the minimum certificate age, or already has.
+	CertificateExpiring
+	{
+		client: String,
+		expires: DateTime<Utc>,
+		days_left: i64,
+	},
+	/// A client whose TLS certificate was expiring has presented one that expires later.
+	CertificateRenewed
+	{
+		client: String,
+		expires: DateTime<Utc>,
+	},
+	/// Clients in a group at the same height have different tip hashes, so they are on different chains.
+	Fork
+	{
+		group: String,
+		block_number: u64,
+		/// Each tip hash seen at the height, along with the clients that reported it.
+		branches: Vec<(String, Vec<String>)>,
+	},
+	/// A header audit found clients that reported a different hash at a past height than the rest of their group.
+	HeaderMismatch
+	{
+		group: String,
+		block_number: u64,
+		/// The hash reported by the reference nodes, or by the majority of clients.
+		expected: String,
+		/// Each client that disagreed, along with the hash it reported.
+		outliers: Vec<(String, String)>,
+	},
+	/// Clients in a group disagree about where an epoch starts or how long it is, so they are on different chains.
+	EpochMismatch
+	{
+		group: String,
+		epoch: u64,
+		/// Each view of the epoch, along with the clients that hold it.
+		views: Vec<(String, Vec<String>)>,
+	},
+	/// A client has been in the same epoch for longer than the maximum epoch age.
+	EpochStalled
+	{
+		client: String,
+		epoch: u64,
+		seconds: u64,
+	},
+	/// A client whose epoch had stalled has moved on to the next epoch.
+	EpochAdvanced
+	{
+		client: String,
+		epoch: u64,
+	},
+	/// A client that is behind has not advanced at all for the specified number of cycles.
+	SyncStalled
+	{
+		client: String,
+		block_number: u64,
+		blocks_behind: u64,
+		cycles: usize,
+	},
+	/// A client that had stalled is advancing again.
+	SyncResumed
+	{
+		client: String,
+		blocks_per_minute: f64,
+	},
+	/// A client has reported the same block number for the specified number of cycles, whether or not it is lagging.
+	TipFrozen
+	{
+		client: String,
+		block_number: u64,
+		cycles: usize,
+	},
+	/// A client whose tip had frozen has reported a new block number.
+	TipUnfrozen
+	{
+		client: String,
+		block_number: u64,
+	},
+	/// A condition of a client that was alerted has lasted another renotify interval without being resolved.
+	StillOngoing
+	{
+		client: String,
+		condition: Condition,
+		/// How long the condition has lasted.
+		minutes: u64,
+	},
+	/// Alerts of the same type about several clients on the same host, collapsed into one within the aggregation
+	/// window. This is only sent to alert destinations, since each alert it collapses has already been logged.
+	AlertsAggregated
+	{
+		/// The type of the alerts, such as `offline`.
+		event_type: String,
+		/// The host of the endpoints of the clients, if they are known.
+		host: Option<String>,
+		clients: Vec<String>,
+	},
+	/// A condition of a client has lasted past its escalation threshold, or come to match a more severe severity rule,
+	/// so it is now more urgent.
+	Escalated
+	{
+		client: String,
+		condition: Condition,
+		/// How long the condition has lasted.
+		minutes: u64,
+		/// The severity the condition has escalated to.
+		severity: Severity,
+	},
+	/// Someone acknowledged an ongoing condition of a client, so its reminders are paused until it ends or the
+	/// acknowledgment expires.
+	Acknowledged
+	{
+		client: String,
+		condition: Condition,
+		/// Who acknowledged the condition.
+		by: String,
+		/// When the condition was acknowledged, as a Unix timestamp.
+		acked_at: i64,
+		/// When the acknowledgment expires, as a Unix timestamp, if it does.
+		expires_at: Option<i64>,
+	},
+	/// A client has fewer peers than its minimum. It is reported at every peer report until it has enough again.
+	LowPeers
+	{
+		client: String,
+		peers: u16,
+		min_peers: u16,
+	},
+	/// A client has fallen below its minimum number of peers.
+	LowPeersStarted
+	{
+		client: String,
+		peers: u16,
+		min_peers: u16,
+	},
+	/// A client that had too few peers is back at its minimum or above.
+	PeersRestored
+	{
+		client: String,
+		peers: u16,
+	},
+	/// The clients in a group that have no peers or only one peer.
+	PeerSummary
+	{
+		group: String,
+		no_peers: Vec<String>,
+		one_peer: Vec<String>,
+	},
+	/// The peers of a client are connecting and disconnecting more often than its maximum peer churn.
+	PeerChurnHigh
+	{
+		client: String,
+		/// The average number of peers that connected or disconnected between recent peers checks.
+		churn: f64,
+		max_peer_churn: f64,
+	},
+	/// The peer churn of a client has fallen back to its maximum or below.
+	PeerChurnNormal
+	{
+		client: String,
+		churn: f64,
+	},
+	/// Every peer of a client connected more recently than the minimum peer age, which suggests restarts.
+	PeersFresh
+	{
+		client: String,
+		peers: usize,
+		/// How long the longest connected peer has been connected.
+		longest_seconds: u64,
+	},
+	/// A client whose peers were all freshly connected has a peer that has stayed connected longer.
+	PeersSettled
+	{
+		client: String,
+	},
+	/// Every peer of a client is running a version older than the minimum peer version.
+	PeersOutdated
+	{
+		client: String,
+		peers: usize,
+		/// Each version the peers are running.
+		versions: Vec<String>,
+		min_peer_version: String,
+	},
+	/// A client whose peers were all outdated has a peer running a recent version.
+	PeersUpdated
+	{
+		client: String,
+	},
+	/// A client with expected peers is connected to none of them.
+	ExpectedPeersMissing
+	{
+		client: String,
+		expected: Vec<String>,
+	},
+	/// A client that was connected to none of its expected peers is connected to one again.
+	ExpectedPeersConnected
+	{
+		client: String,
+	},
+	/// A client with expected peers is connected to peers it is not expected to be, which can mean its peer settings
+	/// are wrong or have been tampered with.
+	UnexpectedPeers
+	{
+		client: String,
+		/// The address of each unexpected peer, or its node id if it has none.
+		peers: Vec<String>,
+	},
+	/// A client that was connected to unexpected peers is only connected to expected peers again.
+	UnexpectedPeersGone
+	{
+		client: String,
+	},
+	/// A single remote peer is shared by a large fraction of the clients in a group.
+	SharedPeer
+	{
+		group: String,
+		node_id: String,
+		address: Option<String>,
+		/// The clients connected to the peer.
+		clients: Vec<String>,
+		/// The number of online clients in the group whose peers are checked.
+		total: usize,
+	},
+	/// All the peers of a client are in the same subnet or autonomous system.
+	LowPeerDiversity
+	{
+		client: String,
+		peers: usize,
+		/// The subnet or autonomous system the peers share.
+		network: String,
+	},
+	/// The clients in a group that are offline.
+	OfflineSummary
+	{
+		group: String,
+		clients: Vec<String>,
+	},
+	/// The clock of the host of a client is skewed by more than the maximum clock skew, which breaks header verification.
+	ClockSkew
+	{
+		client: String,
+		/// How many seconds ahead the clock of the client is, or behind if negative.
+		skew_seconds: f64,
+		/// What the clock was compared with, either the reference nodes or the other clients in the group.
+		baseline: String,
+	},
+	/// How long after the first client in a group each client reported recent new blocks.
+	PropagationSummary
+	{
+		group: String,
+		/// Each client that has reported new blocks, starting with the slowest at the 90th percentile.
+		clients: Vec<(String, PropagationStats)>,
+	},
+	/// The outages the clients in a group have recovered from, for the clients that have had any.
+	OutageSummary
+	{
+		group: String,
+		/// Each client that has had an outage, starting with the one that was down the longest.
+		clients: Vec<(String, OutageStats)>,
+	},
+	/// The state of a group when the monitor stops.
+	GroupSummary
+	{
+		group: String,
+		online: usize,
+		total: usize,
+		highest_block_number: u64,
+		offline_since: Vec<(String, DateTime<Local>)>,
+		/// Each version string reported by the clients in the group, along with the number of clients running it.
+		versions: Vec<(String, usize)>,
+	},
+	/// A scheduled summary of the whole fleet over the period since the last digest.
+	Digest
+	{
+		/// When the period started.
+		since: DateTime<Local>,
+		/// The number of conditions, such as a client going offline or lagging, that started over the period.
+		incidents: usize,
+		/// The percentage of its rounds of checks each client was online for, starting with the lowest.
+		uptime: Vec<(String, f64)>,
+		/// The clients that fell furthest behind the tip of their group, along with the most blocks they were behind.
+		laggards: Vec<(String, u64)>,
+		/// The clients that are degraded or offline when the digest is sent.
+		degraded: Vec<(String, Health)>,
+	},
+	/// A condition over the clients of the fleet, or of a group, has started to hold.
+	FleetDegraded
+	{
+		condition: String,
+		/// The group the condition is over, or `None` for the whole fleet.
+		group: Option<String>,
+		reason: String,
+		severity: Severity,
+	},
+	/// A condition over the clients of the fleet, or of a group, no longer holds.
+	FleetRecovered
+	{
+		condition: String,
+		group: Option<String>,
+		/// How long the condition held.
+		duration_seconds: i64,
+	},
+	/// No client or reference node in a group has advanced its tip for longer than the chain halt threshold.
+	ChainHalted
+	{
+		group: String,
+		/// The highest block reported in the group, which it has not advanced past.
+		block_number: u64,
+		/// How long the highest tip has not advanced for.
+		seconds: u64,
+	},
+	/// The highest tip of a group has advanced again after the chain was reported as halted.
+	ChainResumed
+	{
+		group: String,
+		block_number: u64,
+		halted_seconds: u64,
+	},
+	/// Something that clients depend on, such as the host they run on, has gone down, so alerts about those clients are
+	/// suppressed until it comes back.
+	ParentDown
+	{
+		parent: Parent,
+		/// The clients whose alerts are suppressed.
+		clients: Vec<String>,
+	},
+	/// Something that clients depend on has come back after going down.
+	ParentRecovered
+	{
+		parent: Parent,
+		duration_seconds: i64,
+	},
+}
+
+/// Writes an event to the log. This is the output the monitor has always printed.
+pub fn log_event(event: &MonitorEvent)
+{
+	match event
+	{
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } =>
+		{
+			let reason = reason.as_deref().unwrap_or_default();
+			match (from, to)
+			{
+				(_, Health::Offline) => tracing::error!("Client {} did not respond: {}", client, reason),
+				(Health::Offline, Health::Online) => tracing::info!("Client {} is now online. (Offline {} seconds.)", client, duration_seconds.to_formatted_string(&num_format::Locale::en)),
+				(Health::Offline, Health::Degraded) => tracing::warn!("Client {} is responding again but is degraded: {}", client, reason),
+				(_, Health::Degraded) => tracing::warn!("Client {} is degraded: {}", client, reason),
+				(Health::Unknown, Health::Online) => tracing::debug!("Client {} is online.", client),
+				(_, Health::Online) => tracing::info!("Client {} has recovered. (Degraded {} seconds.)", client, duration_seconds.to_formatted_string(&num_format::Locale::en)),
+				(_, Health::Unknown) => {},
+			}
+		},
+		MonitorEvent::Flapping { client, transitions, window_seconds } =>
+		{
+			tracing::error!("Client {} is flapping after changing health {} times in {} seconds. Changes in health will not be reported until it settles.", client, transitions, window_seconds.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::FlappingStopped { client, health, suppressed } =>
+		{
+			tracing::info!("Client {} has stopped flapping and is {}. ({} changes in health were not reported.)", client, health, suppressed.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::PeerCountChanged { client, peers, .. } =>
+		{
+			if *peers <= 1
+			{
+				let plural = if *peers == 1 { "" } else { "s" };
+				tracing::debug!("Client {} has {} peer{}.", client, peers, plural);
+			}
+		},
+		MonitorEvent::LatencyHigh { client, latency_ms, max_latency_ms, p95_ms } =>
+		{
+			tracing::warn!("Client {} took {} ms to respond, which is above the maximum of {} ms. (p95 {} ms.)", client, latency_ms, max_latency_ms, p95_ms);
+		},
+		MonitorEvent::LatencyNormal { client, latency_ms } =>
+		{
+			tracing::info!("Client {} is responding in {} ms again.", client, latency_ms);
+		},
+		MonitorEvent::TipRegressed { client, previous, block_number } =>
+		{
+			let regressed = (previous - block_number).to_formatted_string(&num_format::Locale::en);
+			tracing::warn!("Client {} went back {} blocks from {} to {}.", client, regressed, previous.to_formatted_string(&num_format::Locale::en), block_number.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::Lagging { client, block_number, blocks_behind, eta_minutes } =>
+		{
+			let block_difference = blocks_behind.to_formatted_string(&num_format::Locale::en);
+			let client_block_tip = block_number.to_formatted_string(&num_format::Locale::en);
+			match eta_minutes
+			{
+				Some(eta_minutes) => tracing::warn!("Client {} is lagging by {} blocks: {} (ETA ~{:.0} min at current rate.)", client, block_difference, client_block_tip, eta_minutes.ceil()),
+				None => tracing::warn!("Client {} is lagging by {} blocks: {}", client, block_difference, client_block_tip),
+			}
+		},
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } =>
+		{
+			tracing::debug!("Client {} started lagging by {} blocks at block {}.", client, blocks_behind.to_formatted_string(&num_format::Locale::en), block_number.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::CaughtUp { client, block_number } =>
+		{
+			tracing::info!("Client {} has caught up at block {}.", client, block_number.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::EtaGrowing { client, blocks_behind, eta_minutes, previous_eta_minutes } =>
+		{
+			let block_difference = blocks_behind.to_formatted_string(&num_format::Locale::en);
+			match eta_minutes
+			{
+				Some(eta_minutes) => tracing::warn!("Client {} is falling further behind at {} blocks. ETA grew from ~{:.0} to ~{:.0} min.", client, block_difference, previous_eta_minutes.ceil(), eta_minutes.ceil()),
+				None => tracing::warn!("Client {} is no longer catching up at {} blocks behind. (ETA was ~{:.0} min.)", client, block_difference, previous_eta_minutes.ceil()),
+			}
+		},
+		MonitorEvent::ScriptsLagging { client, block_number, max_script_lag, scripts } =>
+		{
+			let scripts = scripts.iter().map(|(script, script_block_number)| format!("{} at {}", script, script_block_number.to_formatted_string(&num_format::Locale::en))).collect::<Vec<_>>();
+			tracing::warn!("Client {} has scripts more than {} blocks behind its tip of {}: {}", client, max_script_lag, block_number.to_formatted_string(&num_format::Locale::en), scripts.join(", "));
+		},
+		MonitorEvent::ScriptsCaughtUp { client } =>
+		{
+			tracing::info!("Client {} has caught up on filtering its scripts.", client);
+		},
+		MonitorEvent::WrongNetwork { client, genesis_hash, expected } =>
+		{
+			tracing::error!("Client {} is on a different network: its genesis block is {} instead of {}.", client, genesis_hash, expected);
+		},
+		MonitorEvent::OutdatedVersion { client, version, min_version } =>
+		{
+			tracing::warn!("Client {} is running version {}, which is older than the minimum of {}.", client, version, min_version);
+		},
+		MonitorEvent::VersionMismatch { client, version, majority } =>
+		{
+			tracing::warn!("Client {} is running version {} while most of its group is running {}.", client, version, majority);
+		},
+		MonitorEvent::MemoryHigh { client, memory_mb, max_memory_mb } =>
+		{
+			tracing::warn!("Client {} is using {} MB of memory, which is more than {} MB.", client, memory_mb.to_formatted_string(&num_format::Locale::en), max_memory_mb.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::MemoryNormal { client, memory_mb } =>
+		{
+			tracing::info!("Client {} is back to using {} MB of memory.", client, memory_mb.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::DiskUsageHigh { client, reason } =>
+		{
+			tracing::warn!("Client {} is running out of disk: {}", client, reason);
+		},
+		MonitorEvent::DiskUsageNormal { client, data_dir_mb, free_percent } =>
+		{
+			tracing::info!("Client {} is within its disk limits again. ({} MB used, {:.1}% free.)", client, data_dir_mb.to_formatted_string(&num_format::Locale::en), free_percent);
+		},
+		MonitorEvent::LogMatched { client, pattern, line, health } =>
+		{
+			tracing::warn!("Client {} logged a line matching \"{}\" while {}: {}", client, pattern, health, line);
+		},
+		MonitorEvent::TipStale { client, block_number, age_seconds, max_tip_age_seconds } =>
+		{
+			tracing::warn!("Client {} has a stale tip: block {} was mined {} seconds ago, which is more than {}.", client, block_number.to_formatted_string(&num_format::Locale::en), age_seconds.to_formatted_string(&num_format::Locale::en), max_tip_age_seconds.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::TipFresh { client, block_number } =>
+		{
+			tracing::info!("Client {} has a recent tip again at block {}.", client, block_number.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::CertificateExpiring { client, expires, .. } if *expires <= Utc::now() =>
+		{
+			tracing::error!("Client {} has a TLS certificate that expired on {}.", client, expires.format("%Y-%m-%d %H:%M:%S UTC"));
+		},
+		MonitorEvent::CertificateExpiring { client, expires, days_left } =>
+		{
+			tracing::warn!("Client {} has a TLS certificate that expires in {} days, on {}.", client, days_left, expires.format("%Y-%m-%d %H:%M:%S UTC"));
+		},
+		MonitorEvent::CertificateRenewed { client, expires } =>
+		{
+			tracing::info!("Client {} has a renewed TLS certificate that expires on {}.", client, expires.format("%Y-%m-%d %H:%M:%S UTC"));
+		},
+		MonitorEvent::Fork { group, block_number, branches } =>
+		{
+			let branches = branches.iter().map(|(hash, clients)| format!("{} ({})", hash, clients.join(", "))).collect::<Vec<_>>();
+			tracing::warn!("Clients in group {} disagree on block {}: {}", group, block_number.to_formatted_string(&num_format::Locale::en), branches.join(" vs "));
+		},
+		MonitorEvent::HeaderMismatch { group, block_number, expected, outliers } =>
+		{
+			for (client, tip_hash) in outliers
+			{
+				tracing::error!("Client {} in group {} reported block {} with hash {} instead of {}.", client, group, block_number.to_formatted_string(&num_format::Locale::en), tip_hash, expected);
+			}
+		},
+		MonitorEvent::EpochMismatch { group, epoch, views } =>
+		{
+			let views = views.iter().map(|(view, clients)| format!("{} ({})", view, clients.join(", "))).collect::<Vec<_>>();
+			tracing::warn!("Clients in group {} disagree on epoch {}: {}", group, epoch.to_formatted_string(&num_format::Locale::en), views.join(" vs "));
+		},
+		MonitorEvent::EpochStalled { client, epoch, seconds } =>
+		{
+			tracing::warn!("Client {} has been in epoch {} for {} seconds.", client, epoch.to_formatted_string(&num_format::Locale::en), seconds.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::EpochAdvanced { client, epoch } =>
+		{
+			tracing::info!("Client {} has advanced to epoch {}.", client, epoch.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::SyncStalled { client, block_number, blocks_behind, cycles } =>
+		{
+			tracing::warn!("Client {} has stalled at block {} for {} cycles while {} blocks behind.", client, block_number.to_formatted_string(&num_format::Locale::en), cycles, blocks_behind.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::SyncResumed { client, blocks_per_minute } =>
+		{
+			tracing::info!("Client {} is syncing again at {:.1} blocks per minute.", client, blocks_per_minute);
+		},
+		MonitorEvent::TipFrozen { client, block_number, cycles } =>
+		{
+			tracing::warn!("Client {} has reported block {} for {} consecutive cycles.", client, block_number.to_formatted_string(&num_format::Locale::en), cycles);
+		},
+		MonitorEvent::TipUnfrozen { client, block_number } =>
+		{
+			tracing::info!("Client {} has moved on to block {}.", client, block_number.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::StillOngoing { client, condition, minutes } =>
+		{
+			tracing::info!("Client {} is still {} after {} minutes.", client, condition, minutes.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::AlertsAggregated { event_type, host, clients } =>
+		{
+			tracing::debug!("Collapsed {} {} alerts on host {} into one: {}", clients.len(), event_type, host.as_deref().unwrap_or("unknown"), clients.join(", "));
+		},
+		MonitorEvent::Escalated { client, condition, minutes, severity } =>
+		{
+			let minutes = minutes.to_formatted_string(&num_format::Locale::en);
+			match severity
+			{
+				Severity::Critical => tracing::error!("Client {} has been {} for {} minutes, so it has escalated to critical.", client, condition, minutes),
+				_ => tracing::warn!("Client {} has been {} for {} minutes, so it has escalated to {}.", client, condition, minutes, severity),
+			}
+		},
+		MonitorEvent::Acknowledged { client, condition, by, acked_at, expires_at } =>
+		{
+			let time = |timestamp: i64| Local.timestamp_opt(timestamp, 0).single().map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default();
+			match expires_at
+			{
+				Some(expires_at) => tracing::info!("Client {} being {} was acknowledged by {} at {}. Reminders are paused until it ends or {}.", client, condition, by, time(*acked_at), time(*expires_at)),
+				None => tracing::info!("Client {} being {} was acknowledged by {} at {}. Reminders are paused until it ends.", client, condition, by, time(*acked_at)),
+			}
+		},
+		MonitorEvent::LowPeers { client, peers, min_peers } =>
+		{
+			tracing::warn!("Client {} has {} peers, which is below the minimum of {}.", client, peers, min_peers);
+		},
+		MonitorEvent::LowPeersStarted { client, peers, min_peers } =>
+		{
+			tracing::debug!("Client {} fell to {} peers, below the minimum of {}.", client, peers, min_peers);
+		},
+		MonitorEvent::PeersRestored { client, peers } =>
+		{
+			tracing::info!("Client {} is back to {} peers.", client, peers);
+		},
+		MonitorEvent::PeerChurnHigh { client, churn, max_peer_churn } =>
+		{
+			tracing::warn!("Client {} is churning through {:.1} peers per check, which is more than {:.1}.", client, churn, max_peer_churn);
+		},
+		MonitorEvent::PeersFresh { client, peers, longest_seconds } =>
+		{
+			tracing::warn!("All {} peers of client {} connected in the last {} seconds, which suggests it or its peers are restarting.", peers, client, longest_seconds.to_formatted_string(&num_format::Locale::en));
+		},
+		MonitorEvent::PeersSettled { client } =>
+		{
+			tracing::info!("Client {} has a peer that has stayed connected again.", client);
+		},
+		MonitorEvent::PeersOutdated { client, peers, versions, min_peer_version } =>
+		{
+			tracing::warn!("All {} peers of client {} are running versions older than {}: {}", peers, client, min_peer_version, versions.join(", "));
+		},
+		MonitorEvent::PeersUpdated { client } =>
+		{
+			tracing::info!("Client {} has a peer running a recent version again.", client);
+		},
+		MonitorEvent::ExpectedPeersMissing { client, expected } =>
+		{
+			tracing::warn!("Client {} is not connected to any of its expected peers: {}", client, expected.join(", "));
+		},
+		MonitorEvent::ExpectedPeersConnected { client } =>
+		{
+			tracing::info!("Client {} is connected to an expected peer again.", client);
+		},
+		MonitorEvent::UnexpectedPeers { client, peers } =>
+		{
+			tracing::warn!("Client {} is connected to {} unexpected peers: {}", client, peers.len(), peers.join(", "));
+		},
+		MonitorEvent::UnexpectedPeersGone { client } =>
+		{
+			tracing::info!("Client {} is only connected to expected peers again.", client);
+		},
+		MonitorEvent::PeerChurnNormal { client, churn } =>
+		{
+			tracing::info!("Client {} has settled down to {:.1} peers changing per check.", client, churn);
+		},
+		MonitorEvent::SharedPeer { group, node_id, address, clients, total } =>
+		{
+			let peer = match address
+			{
+				Some(address) => format!("{} ({})", node_id, address),
+				None => node_id.clone(),
+			};
+			tracing::warn!("Peer {} is shared by {} of {} clients in group {}: {}", peer, clients.len(), total, group, clients.join(", "));
+		},
+		MonitorEvent::LowPeerDiversity { client, peers, network } =>
+		{
+			tracing::warn!("All {} peers of client {} are in {}.", peers, client, network);
+		},
+		MonitorEvent::PeerSummary { group, no_peers, one_peer } =>
+		{
+			if !no_peers.is_empty()
+			{
+				tracing::info!("There are {} clients in group {} with 0 peers: {}", no_peers.len(), group, no_peers.join(", "));
+			}
+			if !one_peer.is_empty()
+			{
+				tracing::info!("There are {} clients in group {} with 1 peer: {}", one_peer.len(), group, one_peer.join(", "));
+			}
+		},
+		MonitorEvent::OfflineSummary { group, clients } =>
+		{
+			tracing::info!("There are {} clients in group {} that are offline: {}", clients.len(), group, clients.join(", "));
+		},
+		MonitorEvent::ClockSkew { client, skew_seconds, baseline } =>
+		{
+			let direction = if *skew_seconds < 0.0 { "behind" } else { "ahead of" };
+			tracing::warn!("The clock of client {} is {:.0} seconds {} {}.", client, skew_seconds.abs(), direction, baseline);
+		},
+		MonitorEvent::PropagationSummary { group, clients } =>
+		{
+			for (client, stats) in clients.iter()
+			{
+				tracing::info!("Client {} in group {} reported new blocks {:.1} seconds after the first client at the median, {:.1} seconds at the 90th percentile, and {:.1} seconds at worst. ({} blocks.)",
+					client,
+					group,
+					stats.p50.as_secs_f64(),
+					stats.p90.as_secs_f64(),
+					stats.max.as_secs_f64(),
+					stats.blocks);
+			}
+		},
+		MonitorEvent::OutageSummary { group, clients } =>
+		{
+			for (client, outages) in clients.iter()
+			{
+				tracing::info!("Client {} in group {} has recovered from {} outages, offline {} seconds in total. (Mean time to recover {} seconds, longest {} seconds.)",
+					client,
+					group,
+					outages.outages.to_formatted_string(&num_format::Locale::en),
+					outages.total_downtime.as_secs().to_formatted_string(&num_format::Locale::en),
+					outages.mean_recovery().unwrap_or_default().as_secs().to_formatted_string(&num_format::Locale::en),
+					outages.max_recovery.as_secs().to_formatted_string(&num_format::Locale::en));
+			}
+		},
+		MonitorEvent::GroupSummary { group, online, total, highest_block_number, offline_since, versions } =>
+		{
+			tracing::info!("Group {} has {} of {} clients online at block {}.", group, online, total, highest_block_number.to_formatted_string(&num_format::Locale::en));
+			for (version, clients) in versions.iter()
+			{
+				tracing::info!("Group {} has {} clients running version {}.", group, clients, version);
+			}
+			for (client, time_offline) in offline_since.iter()
+			{
+				tracing::info!("Client {} has been offline since {}.", client, time_offline.format("%Y-%m-%d %H:%M:%S"));
+			}
+		},
+		MonitorEvent::Digest { .. } =>
+		{
+			for line in digest::lines(event).unwrap_or_default()
+			{
+				tracing::info!("{}", line);
+			}
+		},
+		MonitorEvent::FleetDegraded { severity, .. } =>
+		{
+			let text = fleet::text(event).unwrap_or_default();
+			match severity
+			{
+				Severity::Critical => tracing::error!("{}", text),
+				_ => tracing::warn!("{}", text),
+			}
+		},
+		MonitorEvent::FleetRecovered { .. } => tracing::info!("{}", fleet::text(event).unwrap_or_default()),
+		MonitorEvent::ChainHalted { .. } => tracing::error!("{}", halt::text(event).unwrap_or_default()),
+		MonitorEvent::ChainResumed { .. } => tracing::info!("{}", halt::text(event).unwrap_or_default()),
+		MonitorEvent::ParentDown { .. } => tracing::error!("{}", dependency::text(event).unwrap_or_default()),
+		MonitorEvent::ParentRecovered { .. } => tracing::info!("{}", dependency::text(event).unwrap_or_default()),
+	}
+}
@@ -0,0 +1,238 @@
+use crate::alerts::{self, Alert, AlertLimiter, RateLimit, Sink};
+use crate::events::{MonitorEvent, Severity};
+use crate::matrix;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::json;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// How long a command can run when the config does not say.
+const DEFAULT_EXEC_TIMEOUT: u64 = 30;
+
+/// How a command is run for each alert.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecConfig
+{
+	/// The program and its arguments, such as `["/usr/local/bin/page-oncall", "--team", "infra"]`. It is run directly
+	/// rather than through a shell.
+	pub command: Vec<String>,
+	/// Whether the alert is also written to the standard input of the command as JSON.
+	#[serde(default)]
+	pub stdin: bool,
+	/// How many seconds the command can run before it is killed. 30 when omitted.
+	pub timeout: Option<u64>,
+	/// The least severe alerts the command is run for.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether alerts that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe alerts are sent straight away, and the rest are held
+	/// until the next digest. Every alert is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+/// Runs a command for each alert, passing the alert in environment variables and optionally as JSON on standard input.
+/// The task is stopped when this is dropped, but commands that are already running are left to finish.
+pub struct ExecSink
+{
+	task: JoinHandle<()>,
+}
+
+impl ExecSink
+{
+	/// Starts running the command for the alerts received from `alerts`. Once the rate limit is reached, the alerts held
+	/// back are summarized when its window ends, with an `ALERT_EVENT` of `suppressed`.
+	pub fn spawn(config: ExecConfig, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, String>
+	{
+		if config.command.is_empty()
+		{
+			return Err("The exec command is empty.".to_string());
+		}
+		let timeout = Duration::from_secs(config.timeout.unwrap_or(DEFAULT_EXEC_TIMEOUT));
+
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Exec, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Exec, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let variables = vec!
+						[
+							("ALERT_EVENT", "suppressed".to_string()),
+							("ALERT_SEVERITY", Severity::Warning.to_string()),
+							("ALERT_MESSAGE", limiter.summary_text(suppressed)),
+							("ALERT_RESOLVED", false.to_string()),
+							("ALERT_TIMESTAMP", Local::now().to_rfc3339()),
+							("ALERT_COUNT", suppressed.to_string()),
+						];
+						let stdin = config.stdin.then(|| input(&variables).to_string());
+						tokio::spawn(run(config.command.clone(), variables, stdin, timeout));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not passed to the exec command because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Exec) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let variables = match variables(&alert)
+				{
+					Some(variables) => variables,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				// Each command runs on its own task, so a slow one does not hold up the alerts after it.
+				let stdin = config.stdin.then(|| input(&variables).to_string());
+				tokio::spawn(run(config.command.clone(), variables, stdin, timeout));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for ExecSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the environment variables a command is run with for an alert, or `None` for alerts that it is not run for.
+/// Only alerts that other destinations can be sent, such as changes in the state of a client, run it.
+pub fn variables(alert: &Alert) -> Option<Vec<(&'static str, String)>>
+{
+	let event_type = alerts::event_type(&alert.event)?;
+	let message = match &alert.text
+	{
+		Some(text) => text.clone(),
+		None => matrix::message(&alert.event).map(|(body, _)| body)?,
+	};
+
+	let mut variables = vec!
+	[
+		("ALERT_EVENT", event_type.to_string()),
+		("ALERT_SEVERITY", alert.severity.to_string()),
+		("ALERT_MESSAGE", message),
+		("ALERT_RESOLVED", alert.is_recovery().to_string()),
+		("ALERT_TIMESTAMP", Local::now().to_rfc3339()),
+	];
+	if let Some(client) = alerts::client(&alert.event)
+	{
+		variables.push(("ALERT_CLIENT", client.to_string()));
+		if let Some(condition) = alerts::condition(&alert.event)
+		{
+			variables.push(("ALERT_INCIDENT", format!("{}/{}", client, condition.name())));
+		}
+	}
+	if let Some(group) = alerts::group(&alert.event)
+	{
+		variables.push(("ALERT_GROUP", group.to_string()));
+	}
+	if let MonitorEvent::AlertsAggregated { clients, .. } = &alert.event
+	{
+		variables.push(("ALERT_CLIENTS", clients.join(",")));
+	}
+	match &alert.event
+	{
+		MonitorEvent::FleetDegraded { condition, .. } | MonitorEvent::FleetRecovered { condition, .. } => variables.push(("ALERT_INCIDENT", format!("fleet/{}", condition))),
+		MonitorEvent::ChainHalted { group, .. } | MonitorEvent::ChainResumed { group, .. } => variables.push(("ALERT_INCIDENT", format!("{}/chain_halted", group))),
+		MonitorEvent::ParentDown { parent, .. } | MonitorEvent::ParentRecovered { parent, .. } => variables.push(("ALERT_INCIDENT", parent.incident())),
+		_ => {},
+	}
+	Some(variables)
+}
+
+/// Returns the JSON written to the standard input of a command: the same values as its environment variables, keyed by
+/// their names in lower case without the `ALERT_` prefix, except that `resolved` is a boolean, `count` is a number,
+/// and `clients` is a list.
+fn input(variables: &[(&'static str, String)]) -> serde_json::Value
+{
+	let mut input = serde_json::Map::new();
+	for (name, value) in variables.iter()
+	{
+		let key = name.trim_start_matches("ALERT_").to_lowercase();
+		let value = match *name
+		{
+			"ALERT_RESOLVED" => json!(value == "true"),
+			"ALERT_COUNT" => json!(value.parse::<usize>().unwrap_or_default()),
+			"ALERT_CLIENTS" => json!(value.split(',').collect::<Vec<_>>()),
+			_ => json!(value),
+		};
+		input.insert(key, value);
+	}
+	serde_json::Value::Object(input)
+}
+
+/// Runs a command with the variables of an alert, writing `input` to its standard input if there is any, and kills it
+/// if it is still running after the timeout.
+async fn run(command: Vec<String>, variables: Vec<(&'static str, String)>, input: Option<String>, timeout: Duration)
+{
+	let program = &command[0];
+	let mut child = match Command::new(program)
+		.args(&command[1..])
+		.envs(variables)
+		.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+		.stdout(Stdio::null())
+		.stderr(Stdio::piped())
+		.kill_on_drop(true)
+		.spawn()
+	{
+		Ok(child) => child,
+		Err(e) =>
+		{
+			tracing::error!("Failed to run exec command {}: {}", program, e);
+			return;
+		},
+	};
+
+	// The child is dropped if the timeout passes first, which kills it.
+	let result = time::timeout(timeout, async move
+	{
+		if let (Some(input), Some(mut stdin)) = (input, child.stdin.take())
+		{
+			// A command that does not read its input closes the pipe early, which is not an error.
+			let _ = stdin.write_all(input.as_bytes()).await;
+		}
+		child.wait_with_output().await
+	}).await;
+
+	match result
+	{
+		Ok(Ok(output)) if output.status.success() => {},
+		Ok(Ok(output)) => tracing::error!("Exec command {} failed with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()),
+		Ok(Err(e)) => tracing::error!("Failed to wait for exec command {}: {}", program, e),
+		Err(_) => tracing::error!("Exec command {} did not finish within {} seconds and was killed.", program, timeout.as_secs()),
+	}
+}
@@ -0,0 +1,100 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// A set of clients selected on the command line, such as `3,7,12-20,relay-eu-1`.
+#[derive(Clone, Debug, Default)]
+pub struct ClientSet
+{
+	ranges: Vec<RangeInclusive<usize>>,
+	labels: Vec<String>,
+}
+
+impl ClientSet
+{
+	/// Returns true if the set contains the client with the specified number or label.
+	pub fn contains(&self, number: usize, label: Option<&str>) -> bool
+	{
+		if self.ranges.iter().any(|range| range.contains(&number))
+		{
+			return true;
+		}
+
+		match label
+		{
+			Some(label) => self.labels.iter().any(|l| l == label),
+			None => false,
+		}
+	}
+}
+
+impl FromStr for ClientSet
+{
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err>
+	{
+		let mut set = Self::default();
+
+		for item in s.split(',').map(str::trim).filter(|item| !item.is_empty())
+		{
+			// Items that are not numbers or number ranges are treated as labels.
+			let range = item.split_once('-').map(|(start, end)| (start.parse::<usize>(), end.parse::<usize>()));
+			match range
+			{
+				Some((Ok(start), Ok(end))) =>
+				{
+					if start > end
+					{
+						return Err(format!("Invalid client range: {}", item));
+					}
+					set.ranges.push(start..=end);
+				},
+				_ =>
+				{
+					match item.parse::<usize>()
+					{
+						Ok(number) => set.ranges.push(number..=number),
+						Err(_) => set.labels.push(item.to_string()),
+					}
+				}
+			}
+		}
+
+		Ok(set)
+	}
+}
+
+/// Decides which of the configured clients are monitored.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFilter
+{
+	/// When present, only clients in this set are monitored.
+	pub only: Option<ClientSet>,
+	/// Clients in this set are never monitored.
+	pub skip: Option<ClientSet>,
+}
+
+impl ClientFilter
+{
+	/// Returns true if the client with the specified number and label should be monitored.
+	pub fn includes(&self, number: usize, label: Option<&str>) -> bool
+	{
+		if let Some(only) = &self.only
+		{
+			if !only.contains(number, label)
+			{
+				return false;
+			}
+		}
+
+		if let Some(skip) = &self.skip
+		{
+			if skip.contains(number, label)
+			{
+				return false;
+			}
+		}
+
+		true
+	}
+}
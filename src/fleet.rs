@@ -0,0 +1,132 @@
+use crate::events::{MonitorEvent, Severity};
+use crate::monitor::{ClientSnapshot, FleetSnapshot};
+use chrono::{DateTime, Local};
+use num_format::ToFormattedString;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A condition over the clients of the whole fleet, or of one group, that is alerted once rather than for each client,
+/// such as more than a fifth of them being offline. It holds when any of its thresholds is crossed.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetCondition
+{
+	/// The name of the condition, such as `mass_outage`, which its alerts are about.
+	pub name: String,
+	/// The group whose clients the condition is over. Every client in the fleet when omitted.
+	pub group: Option<String>,
+	/// Holds when more than this percentage of the clients are offline.
+	pub max_offline_percent: Option<f64>,
+	/// Holds when fewer than this many clients are online with at least `min_peers` peers.
+	pub min_clients_with_peers: Option<usize>,
+	/// The peers a client needs to count towards `min_clients_with_peers`. 1 when omitted.
+	pub min_peers: Option<u16>,
+	/// The severity of the alert sent when the condition starts to hold.
+	#[serde(default = "default_fleet_severity")]
+	pub severity: Severity,
+}
+
+fn default_fleet_severity() -> Severity
+{
+	Severity::Critical
+}
+
+impl FleetCondition
+{
+	/// Returns why the condition holds over the clients, or `None` if it does not.
+	pub fn check(&self, clients: &[&ClientSnapshot]) -> Option<String>
+	{
+		let total = clients.len();
+		if let Some(max_offline_percent) = self.max_offline_percent
+		{
+			let offline = clients.iter().filter(|client| !client.is_online).count();
+			let percent = offline as f64 * 100.0 / total as f64;
+			if percent > max_offline_percent
+			{
+				return Some(format!("{} of {} clients are offline ({:.0}%), more than {}%.", offline, total, percent, max_offline_percent));
+			}
+		}
+		if let Some(min_clients) = self.min_clients_with_peers
+		{
+			let min_peers = self.min_peers.unwrap_or(1);
+			let connected = clients.iter().filter(|client| client.is_online && client.peers >= min_peers).count();
+			if connected < min_clients
+			{
+				return Some(format!("{} of {} clients have at least {} peers, fewer than {}.", connected, total, min_peers, min_clients));
+			}
+		}
+		None
+	}
+}
+
+/// Evaluates the fleet conditions over each fleet snapshot, and tells when each starts and stops holding.
+#[derive(Debug, Default)]
+pub struct FleetConditions
+{
+	conditions: Vec<FleetCondition>,
+	/// When each condition that holds started to, by name.
+	started: HashMap<String, DateTime<Local>>,
+}
+
+impl FleetConditions
+{
+	/// Creates an evaluator of the conditions, none of which hold yet.
+	pub fn new(conditions: Vec<FleetCondition>) -> Self
+	{
+		Self { conditions, started: HashMap::new() }
+	}
+
+	/// Replaces the conditions, such as when the config is reloaded. Those that are no longer configured are forgotten
+	/// without being alerted as ended.
+	pub fn update(&mut self, conditions: Vec<FleetCondition>)
+	{
+		self.started.retain(|name, _| conditions.iter().any(|condition| &condition.name == name));
+		self.conditions = conditions;
+	}
+
+	/// Evaluates every condition over a snapshot of the fleet, and returns an event for each that starts or stops
+	/// holding. Only clients that have reported are counted, and a condition over no such clients is left as it was.
+	pub fn evaluate(&mut self, fleet: &FleetSnapshot) -> Vec<MonitorEvent>
+	{
+		let mut events = Vec::new();
+		for condition in self.conditions.iter()
+		{
+			let clients = fleet.clients.iter()
+				.filter(|client| client.reported && condition.group.as_ref().is_none_or(|group| &client.group == group))
+				.collect::<Vec<_>>();
+			if clients.is_empty()
+			{
+				continue;
+			}
+
+			match (condition.check(&clients), self.started.get(&condition.name).copied())
+			{
+				(Some(reason), None) =>
+				{
+					self.started.insert(condition.name.clone(), fleet.time);
+					events.push(MonitorEvent::FleetDegraded { condition: condition.name.clone(), group: condition.group.clone(), reason, severity: condition.severity });
+				},
+				(None, Some(started)) =>
+				{
+					let duration_seconds = (fleet.time - started).num_seconds();
+					self.started.remove(&condition.name);
+					events.push(MonitorEvent::FleetRecovered { condition: condition.name.clone(), group: condition.group.clone(), duration_seconds });
+				},
+				_ => {},
+			}
+		}
+		events
+	}
+}
+
+/// Returns the text of a fleet condition starting or ending, or `None` for other events.
+pub fn text(event: &MonitorEvent) -> Option<String>
+{
+	let scope = |group: &Option<String>| group.as_ref().map(|group| format!(" in group {}", group)).unwrap_or_default();
+	match event
+	{
+		MonitorEvent::FleetDegraded { condition, group, reason, .. } => Some(format!("Fleet condition {}{} holds: {}", condition, scope(group), reason)),
+		MonitorEvent::FleetRecovered { condition, group, duration_seconds } => Some(format!("Fleet condition {}{} has cleared after {} seconds.", condition, scope(group), duration_seconds.to_formatted_string(&num_format::Locale::en))),
+		_ => None,
+	}
+}
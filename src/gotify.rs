@@ -0,0 +1,189 @@
+use crate::alerts::{Alert, AlertLimiter, RateLimit, Sink};
+use crate::events::Severity;
+use crate::matrix;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// How long the server can take to accept a message before the attempt fails.
+const GOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How Gotify notifications are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GotifyConfig
+{
+	/// The URL of the Gotify server, such as `https://gotify.example.com`.
+	pub server_url: Option<String>,
+	/// The token of the Gotify application that the messages are sent from.
+	pub app_token: Option<String>,
+	/// The Gotify priority of messages of each severity, from 0 to 10, such as `critical = 10`. Severities that are not
+	/// listed use their default priority: 2 for info, 5 for warning, and 8 for critical.
+	#[serde(default)]
+	pub priorities: HashMap<Severity, u8>,
+	/// The least severe messages that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether messages that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe messages are sent straight away, and the rest are held
+	/// until the next digest. Every message is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+impl GotifyConfig
+{
+	/// Returns the priority of messages of a severity.
+	pub fn priority(&self, severity: Severity) -> u8
+	{
+		match self.priorities.get(&severity)
+		{
+			Some(priority) => *priority,
+			None => match severity
+			{
+				Severity::Info => 2,
+				Severity::Warning => 5,
+				Severity::Critical => 8,
+			},
+		}
+	}
+}
+
+/// Sends messages to a Gotify server when clients change state and when summaries are made. The task is stopped when
+/// this is dropped.
+pub struct GotifySink
+{
+	task: JoinHandle<()>,
+}
+
+impl GotifySink
+{
+	/// Starts sending messages for the alerts received from `alerts`, retrying failed deliveries according to the retry
+	/// policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: GotifyConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		let (server_url, app_token) = match (&config.server_url, &config.app_token)
+		{
+			(Some(server_url), Some(app_token)) => (server_url, app_token.clone()),
+			_ => return Err("Gotify needs a server URL and an app token.".into()),
+		};
+		let message_url = message_url(server_url)?;
+		let client = reqwest::Client::builder().timeout(GOTIFY_TIMEOUT).build()?;
+
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Gotify, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Gotify, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let content = json!({ "title": "Alerts suppressed", "message": limiter.summary_text(suppressed), "priority": config.priority(Severity::Warning) });
+						tokio::spawn(deliver(client.clone(), message_url.clone(), app_token.clone(), content, retry.clone()));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to Gotify because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Gotify) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let (title, message) = match message(&alert)
+				{
+					Some(message) => message,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				let content = json!({ "title": title, "message": message, "priority": config.priority(alert.severity) });
+				tokio::spawn(deliver(client.clone(), message_url.clone(), app_token.clone(), content, retry.clone()));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for GotifySink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the URL that messages are sent to on a Gotify server.
+pub fn message_url(server_url: &str) -> Result<Url, String>
+{
+	let mut url = Url::parse(server_url).map_err(|e| format!("Invalid Gotify server URL {}: {}", server_url, e))?;
+	url.path_segments_mut()
+		.map_err(|_| format!("Invalid Gotify server URL {}.", server_url))?
+		.pop_if_empty()
+		.push("message");
+	Ok(url)
+}
+
+/// Returns the title and text of the message sent for an alert, or `None` for alerts that are not sent to Gotify. The
+/// same events are sent as to Matrix, titled with the client or group they are about and the type of event.
+fn message(alert: &Alert) -> Option<(String, String)>
+{
+	let message = match &alert.text
+	{
+		Some(text) => text.clone(),
+		None => matrix::message(&alert.event).map(|(body, _)| body)?,
+	};
+
+	Some((alert.title()?, message))
+}
+
+/// Sends a message to a Gotify server, retrying transport failures and error statuses according to the retry policy.
+/// The token is sent in a header rather than the URL, so it does not end up in the logs of proxies.
+async fn deliver(client: reqwest::Client, message_url: Url, app_token: String, content: Value, retry: RetryPolicy)
+{
+	let mut attempt = 0;
+	loop
+	{
+		let error = match client.post(message_url.clone()).header("X-Gotify-Key", &app_token).json(&content).send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send a message to Gotify: {}", error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending a message to Gotify failed: {}. Retrying in {} ms.", error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
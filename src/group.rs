@@ -0,0 +1,513 @@
+use crate::checks::TIP_CHECK;
+use crate::client::Client;
+use crate::config::{ClientSettings, GroupSettings, LagMode};
+use crate::diversity::AsnDatabase;
+use crate::events::MonitorEvent;
+use crate::filter::ClientFilter;
+use crate::halt::ChainWatch;
+use crate::history::{RingBuffer, Sample};
+use crate::report;
+use crate::rpc::H256;
+use crate::scheduler::Timer;
+use crate::worker::{CheckSchedules, ClientHandle, ClientReport};
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc};
+
+/// The number of lag reports the growth rate of the chain is calculated from.
+const TIP_RATE_SAMPLES: usize = 10;
+
+/// The number of recent heights whose tip hashes are kept for header audits.
+const AUDIT_HEIGHTS: usize = 1000;
+
+/// The number of recent new blocks whose first sighting is kept to measure how long they take to reach each client.
+const PROPAGATION_HEIGHTS: usize = 100;
+
+/// The tip hashes that were reported at a single height, kept so the clients can later be audited against each other.
+#[derive(Clone, Debug, Default)]
+pub struct RecordedTips
+{
+	/// The hash a reference node reported at this height, which is trusted over the clients.
+	pub reference: Option<H256>,
+	/// The hash each client reported at this height, keyed by client name.
+	pub clients: BTreeMap<String, H256>,
+}
+
+/// The minimum number of online clients the outlier lag mode needs for the spread of their tips to mean anything. Smaller
+/// groups fall back to the maximum block difference.
+const MIN_OUTLIER_CLIENTS: usize = 3;
+
+/// The factor that scales a median absolute deviation to be comparable with a standard deviation.
+const MAD_SCALE: f64 = 1.4826;
+
+/// Decides whether a client is lagging, worked out from the group once and then applied to each client.
+#[derive(Clone, Copy, Debug)]
+pub enum LagThreshold
+{
+	/// A client is lagging when it trails the tip height by more than its maximum block difference.
+	Fixed { tip_height: u64 },
+	/// A client is lagging when it is more than the limit below the median tip of the group.
+	Outlier { median: f64, limit: f64 },
+}
+
+impl LagThreshold
+{
+	/// Returns true if a client at the specified block number, with the specified maximum block difference, is lagging.
+	pub fn is_lagging(&self, block_number: u64, max_block_diff: u64) -> bool
+	{
+		match *self
+		{
+			LagThreshold::Fixed { tip_height } => tip_height > block_number.saturating_add(max_block_diff),
+			LagThreshold::Outlier { median, limit } => median - block_number as f64 > limit,
+		}
+	}
+}
+
+/// A named group of clients that are reported on together. Each client is checked by its own task, which reports its
+/// results back to the group.
+pub struct Group
+{
+	pub settings: GroupSettings,
+	pub clients: Vec<ClientHandle>,
+	pub highest_block_number: u64,
+	/// The reference nodes whose tip is the authoritative height of the group. They are not reported on themselves.
+	pub references: Vec<ClientHandle>,
+	/// The tip height of the group each time lag was reported, used to tell how fast the chain is growing.
+	tip_heights: RingBuffer<(DateTime<Local>, u64)>,
+	/// The tip hashes reported at each recent height, oldest first.
+	pub recorded_tips: BTreeMap<u64, RecordedTips>,
+	/// When each recent new block was first reported by any client in the group.
+	first_seen: BTreeMap<u64, Instant>,
+	/// Whether the highest tip of the group is still advancing.
+	chain_watch: ChainWatch,
+	reports: mpsc::Sender<ClientReport>,
+	events: broadcast::Sender<MonitorEvent>,
+	rpc_timer: Timer,
+	peers_timer: Timer,
+	tip_timer: Timer,
+	audit_timer: Timer,
+	summary_timer: Timer,
+}
+
+impl Group
+{
+	/// Creates a new `Group` whose clients are numbered starting from `first_number`, and starts a task for each client
+	/// that sends its results to `reports`. Clients excluded by the filter are left out. Everything the group and its
+	/// clients observe is broadcast to `events`.
+	pub fn new(settings: GroupSettings, first_number: usize, filter: &ClientFilter, reports: mpsc::Sender<ClientReport>, events: broadcast::Sender<MonitorEvent>) -> Self
+	{
+		let clients = settings.clients.iter().enumerate()
+			.filter(|(index, client_settings)| filter.includes(first_number + index, client_settings.label.as_deref()))
+			.map(|(index, client_settings)| ClientHandle::spawn(Client::new(first_number + index, settings.name.clone(), client_settings.clone(), events.clone()), check_schedules(&settings, index), reports.clone(), settings.history_size))
+			.collect();
+		let references = settings.references.iter().enumerate()
+			.map(|(index, reference_settings)| ClientHandle::spawn(Client::new(index, settings.name.clone(), reference_settings.clone(), events.clone()), reference_schedules(&settings), reports.clone(), 0))
+			.collect();
+
+		Self
+		{
+			rpc_timer: Timer::new(settings.rpc_interval.clone()),
+			peers_timer: Timer::new(settings.peers_interval.clone()),
+			tip_timer: Timer::new(settings.tip_interval.clone()),
+			audit_timer: Timer::new(settings.audit_interval.clone()),
+			summary_timer: Timer::new(settings.summary_interval.clone()),
+			settings,
+			clients,
+			highest_block_number: 0,
+			references,
+			tip_heights: RingBuffer::new(TIP_RATE_SAMPLES),
+			recorded_tips: BTreeMap::new(),
+			first_seen: BTreeMap::new(),
+			chain_watch: ChainWatch::default(),
+			reports,
+			events,
+		}
+	}
+
+	/// Updates the group to match the specified settings, preserving the state of any client whose endpoint is unchanged.
+	pub fn reconcile(&mut self, settings: GroupSettings, first_number: usize, filter: &ClientFilter)
+	{
+		let mut existing_clients: HashMap<String, ClientHandle> = self.clients.drain(..).map(|client| (client.settings.url.clone(), client)).collect();
+
+		for (index, client_settings) in settings.clients.iter().enumerate()
+		{
+			let number = first_number + index;
+			if !filter.includes(number, client_settings.label.as_deref())
+			{
+				continue;
+			}
+
+			let schedules = check_schedules(&settings, index);
+			match existing_clients.remove(&client_settings.url)
+			{
+				Some(mut client) =>
+				{
+					client.update(number, client_settings.clone(), schedules);
+					client.history.set_capacity(settings.history_size);
+					self.clients.push(client);
+				},
+				None =>
+				{
+					let client = ClientHandle::spawn(Client::new(number, settings.name.clone(), client_settings.clone(), self.events.clone()), schedules, self.reports.clone(), settings.history_size);
+					tracing::info!("Client {} added at {}.", client, client.settings.url);
+					self.clients.push(client);
+				}
+			}
+		}
+
+		for client in existing_clients.values()
+		{
+			tracing::info!("Client {} at {} removed.", client, client.settings.url);
+		}
+
+		let mut existing_references: HashMap<String, ClientHandle> = self.references.drain(..).map(|reference| (reference.settings.url.clone(), reference)).collect();
+		for (index, reference_settings) in settings.references.iter().enumerate()
+		{
+			let reference = match existing_references.remove(&reference_settings.url)
+			{
+				Some(mut reference) =>
+				{
+					reference.update(index, reference_settings.clone(), reference_schedules(&settings));
+					reference
+				},
+				None => ClientHandle::spawn(Client::new(index, settings.name.clone(), reference_settings.clone(), self.events.clone()), reference_schedules(&settings), self.reports.clone(), 0),
+			};
+			self.references.push(reference);
+		}
+
+		self.rpc_timer.set_schedule(settings.rpc_interval.clone(), 0.0);
+		self.peers_timer.set_schedule(settings.peers_interval.clone(), 0.0);
+		self.tip_timer.set_schedule(settings.tip_interval.clone(), 0.0);
+		self.audit_timer.set_schedule(settings.audit_interval.clone(), 0.0);
+		self.summary_timer.set_schedule(settings.summary_interval.clone(), 0.0);
+		self.settings = settings;
+	}
+
+	/// Adds a client to the group with the specified number and starts its task.
+	pub fn add_client(&mut self, number: usize, settings: ClientSettings)
+	{
+		self.settings.clients.push(settings.clone());
+		let schedules = check_schedules(&self.settings, self.settings.clients.len() - 1);
+		let client = ClientHandle::spawn(Client::new(number, self.settings.name.clone(), settings, self.events.clone()), schedules, self.reports.clone(), self.settings.history_size);
+		tracing::info!("Client {} added at {}.", client, client.settings.url);
+		self.clients.push(client);
+	}
+
+	/// Returns the height that clients in the group are measured against. This is the highest tip of the reference
+	/// nodes if any of them are online, and otherwise the highest tip of the clients.
+	pub fn tip_height(&self) -> u64
+	{
+		self.references.iter()
+			.filter_map(|reference| reference.state)
+			.filter(|state| state.is_online() && state.block_number > 0)
+			.map(|state| state.block_number)
+			.max()
+			.unwrap_or(self.highest_block_number)
+	}
+
+	/// Returns how to decide whether a client in the group is lagging. In the outlier lag mode this compares clients with
+	/// the median and median absolute deviation of the tips of the online clients, which never goes below one block so
+	/// a group whose clients are all at the same height does not flag a client one block behind.
+	pub fn lag_threshold(&self) -> LagThreshold
+	{
+		let mut heights = self.clients.iter()
+			.filter(|client| client.settings.has_check(TIP_CHECK))
+			.filter_map(|client| client.state)
+			.filter(|state| state.is_online() && state.block_number > 0)
+			.map(|state| state.block_number as f64)
+			.collect::<Vec<_>>();
+		if self.settings.lag_mode == LagMode::Fixed || heights.len() < MIN_OUTLIER_CLIENTS
+		{
+			return LagThreshold::Fixed { tip_height: self.tip_height() };
+		}
+
+		let median = median_of(&mut heights);
+		let mut deviations = heights.iter().map(|height| (height - median).abs()).collect::<Vec<_>>();
+		let deviation = (median_of(&mut deviations) * MAD_SCALE).max(1.0);
+		LagThreshold::Outlier { median, limit: self.settings.outlier_threshold * deviation }
+	}
+
+	/// Returns the number of blocks per minute the tip height of the group grew over its recent lag reports, or `None` if
+	/// there have not been enough reports.
+	pub fn tip_rate(&self) -> Option<f64>
+	{
+		let (first_time, first_height) = self.tip_heights.iter().next()?;
+		let (last_time, last_height) = self.tip_heights.latest()?;
+		let minutes = last_time.signed_duration_since(*first_time).num_milliseconds() as f64 / 60_000.0;
+		if minutes <= 0.0
+		{
+			return None;
+		}
+
+		Some((*last_height as f64 - *first_height as f64) / minutes)
+	}
+
+	/// Records the results of a round of checks reported by the task of one of the clients or reference nodes in the
+	/// group.
+	pub fn apply_report(&mut self, report: ClientReport)
+	{
+		if let Some(reference) = self.references.iter_mut().find(|reference| reference.settings.url == report.url)
+		{
+			reference.state = Some(report.state);
+			if let Some(tip_hash) = report.state.tip_hash.filter(|_| report.state.is_online())
+			{
+				self.recorded_tips.entry(report.state.block_number).or_default().reference = Some(tip_hash);
+				self.trim_recorded_tips();
+			}
+			if report.state.is_online()
+			{
+				self.watch_chain();
+			}
+			return;
+		}
+
+		// Reports from clients that have since been removed are ignored.
+		let index = match self.clients.iter().position(|client| client.settings.url == report.url)
+		{
+			Some(index) => index,
+			None => return,
+		};
+
+		let state = report.state;
+		let has_tip_check = self.clients[index].settings.has_check(TIP_CHECK);
+		if state.is_online() && has_tip_check
+		{
+			self.record_propagation(index, state.block_number);
+		}
+		if state.is_online() && has_tip_check && state.block_number > self.highest_block_number
+		{
+			self.highest_block_number = state.block_number;
+		}
+		if state.is_online() && has_tip_check
+		{
+			self.watch_chain();
+		}
+		let tip_height = self.tip_height();
+		let lag_threshold = self.lag_threshold();
+
+		if let Some(tip_hash) = state.tip_hash.filter(|_| state.is_online() && has_tip_check)
+		{
+			self.recorded_tips.entry(state.block_number).or_default().clients.insert(self.clients[index].name(), tip_hash);
+			self.trim_recorded_tips();
+		}
+
+		let client = &mut self.clients[index];
+		client.state = Some(state);
+		if report.version.is_some()
+		{
+			client.version = report.version;
+		}
+		client.connected_peers = report.connected_peers;
+		client.history.push(Sample::new(&state, report.tip_checked));
+
+		let lagging = state.is_online() && has_tip_check && lag_threshold.is_lagging(state.block_number, client.settings.max_block_diff);
+		let was_lagging = client.is_lagging();
+		client.set_lagging(lagging);
+
+		// A client that stops lagging because it went offline has not caught up.
+		if lagging && !was_lagging
+		{
+			let event = MonitorEvent::LagStarted { client: client.to_string(), block_number: state.block_number, blocks_behind: tip_height.saturating_sub(state.block_number) };
+			self.emit(event);
+		}
+		else if !lagging && was_lagging && state.is_online()
+		{
+			let event = MonitorEvent::CaughtUp { client: client.to_string(), block_number: state.block_number };
+			self.emit(event);
+		}
+		let client = &mut self.clients[index];
+
+		// A client that is behind is stalled if it has not advanced at all, rather than just catching up slowly.
+		let stalled = lagging && client.history.is_stalled(self.settings.stall_cycles);
+		let advanced = !stalled && state.is_online();
+		if stalled && !client.stalled
+		{
+			client.stalled = true;
+			let event = MonitorEvent::SyncStalled
+			{
+				client: client.to_string(),
+				block_number: state.block_number,
+				blocks_behind: tip_height.saturating_sub(state.block_number),
+				cycles: self.settings.stall_cycles,
+			};
+			self.emit(event);
+		}
+		else if advanced && client.stalled
+		{
+			client.stalled = false;
+			let event = MonitorEvent::SyncResumed { client: client.to_string(), blocks_per_minute: client.history.sync_rate().unwrap_or_default() };
+			self.emit(event);
+		}
+
+		// A client can sit just inside the lag threshold forever, so a tip that never moves is reported on its own. Only
+		// rounds that fetched the tip count, since the other checks can run much more often than the tip check.
+		if !(state.is_online() && has_tip_check && report.tip_checked)
+		{
+			return;
+		}
+		let client = &mut self.clients[index];
+		let unchanged = state.block_number == client.unchanged_block;
+		client.unchanged_cycles = if unchanged { client.unchanged_cycles + 1 } else { 0 };
+		client.unchanged_block = state.block_number;
+		let frozen_cycles = self.settings.frozen_cycles;
+		if frozen_cycles > 0 && client.unchanged_cycles >= frozen_cycles && !client.frozen
+		{
+			client.frozen = true;
+			let event = MonitorEvent::TipFrozen { client: client.to_string(), block_number: state.block_number, cycles: client.unchanged_cycles };
+			self.emit(event);
+		}
+		else if !unchanged && client.frozen
+		{
+			client.frozen = false;
+			let event = MonitorEvent::TipUnfrozen { client: client.to_string(), block_number: state.block_number };
+			self.emit(event);
+		}
+	}
+
+	/// Records how long after the first client in the group a client reported a new block. Only blocks at the tip of
+	/// the group count, so a client that is catching up is not mistaken for one that hears about blocks late, and the
+	/// first report of a client is skipped since it is not known when the client first had that block.
+	fn record_propagation(&mut self, index: usize, block_number: u64)
+	{
+		let now = Instant::now();
+		let at_tip = block_number >= self.highest_block_number;
+		if block_number > self.highest_block_number
+		{
+			self.first_seen.insert(block_number, now);
+			while self.first_seen.len() > PROPAGATION_HEIGHTS
+			{
+				self.first_seen.pop_first();
+			}
+		}
+
+		let client = &mut self.clients[index];
+		if at_tip && block_number > client.highest_reported && client.highest_reported > 0
+		{
+			if let Some(first_seen) = self.first_seen.get(&block_number)
+			{
+				client.propagation_delays.push(now.duration_since(*first_seen));
+			}
+		}
+		client.highest_reported = client.highest_reported.max(block_number);
+	}
+
+	/// Drops the tip hashes of the oldest heights once more than the audit keeps have been recorded.
+	fn trim_recorded_tips(&mut self)
+	{
+		while self.recorded_tips.len() > AUDIT_HEIGHTS
+		{
+			self.recorded_tips.pop_first();
+		}
+	}
+
+	/// Runs every type of report that is due using the most recent results from the clients in the group.
+	pub fn report_due(&mut self, asn_database: Option<&AsnDatabase>)
+	{
+		let now = Instant::now();
+		if self.tip_timer.is_due(now)
+		{
+			self.tip_heights.push((Local::now(), self.tip_height()));
+			report::lag(self);
+			report::forks(self);
+			report::epochs(self);
+			self.tip_timer.schedule_next(now);
+		}
+		if self.audit_timer.is_due(now)
+		{
+			report::audit(self);
+			self.audit_timer.schedule_next(now);
+		}
+		if self.summary_timer.is_due(now)
+		{
+			report::outages(self);
+			report::propagation(self);
+			self.summary_timer.schedule_next(now);
+		}
+		if self.peers_timer.is_due(now)
+		{
+			report::peers(self);
+			report::shared_peers(self);
+			report::peer_diversity(self, asn_database);
+			self.peers_timer.schedule_next(now);
+		}
+		if self.rpc_timer.is_due(now)
+		{
+			report::offline(self);
+			report::clock_skew(self);
+			report::versions(self);
+			report::networks(self);
+			self.rpc_timer.schedule_next(now);
+		}
+	}
+
+	/// Records the highest tip of the group after an online report, and reports the chain halting or resuming.
+	fn watch_chain(&mut self)
+	{
+		let height = self.tip_height().max(self.highest_block_number);
+		if let Some(event) = self.chain_watch.record(&self.settings.name, height, self.settings.chain_halt_after, Instant::now())
+		{
+			self.emit(event);
+		}
+	}
+
+	/// Broadcasts an event to all subscribers. Events are dropped if there are none.
+	pub fn emit(&self, event: MonitorEvent)
+	{
+		let _ = self.events.send(event);
+	}
+
+	/// Stops the tasks of all clients in the group, cancelling any checks that are in progress.
+	pub fn stop(&self)
+	{
+		for client in self.clients.iter().chain(self.references.iter())
+		{
+			client.stop();
+		}
+	}
+
+}
+
+/// Returns the schedules the reference nodes of a group are checked on. Their tips are fetched on the tip interval of
+/// the group, without any offset, so they are current when the clients are compared against them.
+fn reference_schedules(settings: &GroupSettings) -> CheckSchedules
+{
+	CheckSchedules
+	{
+		default: settings.tip_interval.clone(),
+		rpc: settings.tip_interval.clone(),
+		peers: settings.tip_interval.clone(),
+		tip: settings.tip_interval.clone(),
+		genesis: settings.genesis_interval.clone(),
+		offset: 0.0,
+		degraded_interval: settings.degraded_interval,
+	}
+}
+
+/// Returns the schedules the client at the specified index in a group is checked on. The offsets of the clients are
+/// spaced evenly across the check spread of the group.
+fn check_schedules(settings: &GroupSettings, index: usize) -> CheckSchedules
+{
+	CheckSchedules
+	{
+		default: settings.check_interval.clone(),
+		rpc: settings.rpc_interval.clone(),
+		peers: settings.peers_interval.clone(),
+		tip: settings.tip_interval.clone(),
+		genesis: settings.genesis_interval.clone(),
+		offset: settings.check_spread * index as f64 / settings.clients.len() as f64,
+		degraded_interval: settings.degraded_interval,
+	}
+}
+
+/// Returns the median of a list of numbers, sorting it in place.
+fn median_of(values: &mut [f64]) -> f64
+{
+	values.sort_by(|a, b| a.total_cmp(b));
+	let middle = values.len() / 2;
+	match values.len() % 2
+	{
+		0 => (values[middle - 1] + values[middle]) / 2.0,
+		_ => values[middle],
+	}
+}
@@ -0,0 +1,63 @@
+use crate::events::MonitorEvent;
+use num_format::ToFormattedString;
+use std::time::{Duration, Instant};
+
+/// Watches the highest tip reported by the clients and reference nodes of a group, to tell when the whole chain appears
+/// to have stopped. Lag is measured between clients, so it cannot see every client stopping at the same height.
+#[derive(Clone, Copy, Debug)]
+pub struct ChainWatch
+{
+	/// The highest tip reported so far.
+	height: u64,
+	/// When the highest tip last advanced, or when watching started if no tip has been reported.
+	advanced: Instant,
+	/// Whether the chain has been reported as halted and has not advanced since.
+	halted: bool,
+}
+
+impl Default for ChainWatch
+{
+	fn default() -> Self
+	{
+		Self { height: 0, advanced: Instant::now(), halted: false }
+	}
+}
+
+impl ChainWatch
+{
+	/// Records the highest tip of a group after a client or reference node reports while online, and returns an event
+	/// if the chain has gone `halt_after` without advancing, or has advanced again after that. A `halt_after` of zero
+	/// never reports a halt.
+	pub fn record(&mut self, group: &str, height: u64, halt_after: Duration, now: Instant) -> Option<MonitorEvent>
+	{
+		let stopped = now.duration_since(self.advanced);
+		if height > self.height
+		{
+			self.height = height;
+			self.advanced = now;
+			if std::mem::take(&mut self.halted)
+			{
+				return Some(MonitorEvent::ChainResumed { group: group.to_string(), block_number: height, halted_seconds: stopped.as_secs() });
+			}
+			return None;
+		}
+
+		if self.halted || self.height == 0 || halt_after.is_zero() || stopped < halt_after
+		{
+			return None;
+		}
+		self.halted = true;
+		Some(MonitorEvent::ChainHalted { group: group.to_string(), block_number: self.height, seconds: stopped.as_secs() })
+	}
+}
+
+/// Returns the text of the chain of a group halting or resuming, or `None` for other events.
+pub fn text(event: &MonitorEvent) -> Option<String>
+{
+	match event
+	{
+		MonitorEvent::ChainHalted { group, block_number, seconds } => Some(format!("The chain of group {} appears halted: no client or reference node has advanced past block {} in {} seconds.", group, block_number.to_formatted_string(&num_format::Locale::en), seconds.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::ChainResumed { group, block_number, halted_seconds } => Some(format!("The chain of group {} has advanced to block {} after {} seconds halted.", group, block_number.to_formatted_string(&num_format::Locale::en), halted_seconds.to_formatted_string(&num_format::Locale::en))),
+		_ => None,
+	}
+}
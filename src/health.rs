@@ -0,0 +1,293 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// The health of a client, as decided from the results of its checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health
+{
+	/// The client has not been checked yet.
+	Unknown,
+	/// The client responded and every check passed.
+	Online,
+	/// The client responded but a check failed, or it stopped responding too recently to be considered offline.
+	Degraded,
+	/// The client has not responded for the configured number of rounds of checks.
+	Offline,
+}
+
+impl Health
+{
+	/// Returns true if the client is responding, even if it is degraded.
+	pub fn is_online(&self) -> bool
+	{
+		matches!(self, Health::Online | Health::Degraded)
+	}
+}
+
+impl fmt::Display for Health
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Health::Unknown => write!(f, "unknown"),
+			Health::Online => write!(f, "online"),
+			Health::Degraded => write!(f, "degraded"),
+			Health::Offline => write!(f, "offline"),
+		}
+	}
+}
+
+/// The rules that decide when a client changes health.
+#[derive(Clone, Copy, Debug)]
+pub struct HealthRules
+{
+	/// The number of consecutive rounds of checks a client must fail to respond to before it is considered offline.
+	pub offline_after: u32,
+	/// The number of consecutive rounds of passing checks a degraded or offline client needs to be considered online.
+	pub recover_after: u32,
+	/// The number of changes in health within the flap window at which a client is considered to be flapping, or 0 to
+	/// never consider a client flapping.
+	pub flap_threshold: u32,
+	/// How far back changes in health are counted towards the flap threshold.
+	pub flap_window: Duration,
+}
+
+/// The combined result of a round of checks on a client.
+#[derive(Clone, Debug)]
+pub enum Observation
+{
+	/// The client responded and every check passed.
+	Healthy,
+	/// The client responded but a check failed.
+	Failed(String),
+	/// The client did not respond.
+	Unresponsive(String),
+}
+
+/// A change in the health of a client.
+#[derive(Clone, Copy, Debug)]
+pub struct Transition
+{
+	pub from: Health,
+	pub to: Health,
+	/// When the client entered the health it has just left.
+	pub since: DateTime<Local>,
+}
+
+/// The outages a client has recovered from, used to spot clients that are chronically unreliable.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct OutageStats
+{
+	/// The number of times the client went offline and came back.
+	pub outages: u32,
+	/// The total time the client spent offline across those outages.
+	pub total_downtime: Duration,
+	/// The longest time the client took to recover from an outage.
+	pub max_recovery: Duration,
+}
+
+impl OutageStats
+{
+	/// Returns the mean time the client took to recover from an outage, or `None` if it has not had any.
+	pub fn mean_recovery(&self) -> Option<Duration>
+	{
+		(self.outages > 0).then(|| self.total_downtime / self.outages)
+	}
+
+	/// Records an outage the client has recovered from.
+	fn record(&mut self, downtime: Duration)
+	{
+		self.outages += 1;
+		self.total_downtime += downtime;
+		self.max_recovery = self.max_recovery.max(downtime);
+	}
+}
+
+/// Tracks the health of a client across rounds of checks, changing it according to the health rules.
+#[derive(Clone, Copy, Debug)]
+pub struct HealthTracker
+{
+	health: Health,
+	since: DateTime<Local>,
+	failures: u32,
+	successes: u32,
+	outages: OutageStats,
+}
+
+impl HealthTracker
+{
+	/// Creates a new `HealthTracker` for a client that has not been checked yet.
+	pub fn new() -> Self
+	{
+		Self
+		{
+			health: Health::Unknown,
+			since: Local::now(),
+			failures: 0,
+			successes: 0,
+			outages: OutageStats::default(),
+		}
+	}
+
+	/// Returns the current health of the client.
+	pub fn health(&self) -> Health
+	{
+		self.health
+	}
+
+	/// Returns when the client entered its current health.
+	pub fn since(&self) -> DateTime<Local>
+	{
+		self.since
+	}
+
+	/// Returns the outages the client has recovered from.
+	pub fn outages(&self) -> OutageStats
+	{
+		self.outages
+	}
+
+	/// Records the result of a round of checks, returning the transition if the health of the client changed.
+	pub fn observe(&mut self, observation: &Observation, rules: &HealthRules) -> Option<Transition>
+	{
+		let health = match observation
+		{
+			Observation::Healthy =>
+			{
+				self.failures = 0;
+				self.successes += 1;
+				if self.health == Health::Unknown || self.successes >= rules.recover_after
+				{
+					Health::Online
+				}
+				else
+				{
+					self.health
+				}
+			},
+			Observation::Failed(_) =>
+			{
+				self.failures = 0;
+				self.successes = 0;
+				Health::Degraded
+			},
+			Observation::Unresponsive(_) =>
+			{
+				self.failures += 1;
+				self.successes = 0;
+				if self.failures >= rules.offline_after
+				{
+					Health::Offline
+				}
+				else if self.health == Health::Online
+				{
+					Health::Degraded
+				}
+				else
+				{
+					self.health
+				}
+			},
+		};
+
+		if health == self.health
+		{
+			return None;
+		}
+
+		let now = Local::now();
+		if self.health == Health::Offline
+		{
+			self.outages.record(now.signed_duration_since(self.since).to_std().unwrap_or_default());
+		}
+
+		let transition = Transition { from: self.health, to: health, since: self.since };
+		self.health = health;
+		self.since = now;
+		Some(transition)
+	}
+}
+
+impl Default for HealthTracker
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+/// A change in whether a client is flapping.
+#[derive(Clone, Copy, Debug)]
+pub enum FlapChange
+{
+	/// The client started flapping after changing health the specified number of times within the flap window.
+	Started(usize),
+	/// The client stopped flapping. Holds the number of changes in health that were not reported while it was.
+	Stopped(usize),
+}
+
+/// Tells when a client keeps bouncing between healths, so the changes can be reported once instead of every time. A
+/// client starts flapping when it reaches the flap threshold, and only stops once the changes in the flap window fall
+/// to half of it, so a client near the threshold does not flap in and out of flapping.
+#[derive(Clone, Debug, Default)]
+pub struct FlapDetector
+{
+	/// When each change in health within the flap window happened, oldest first.
+	transitions: VecDeque<DateTime<Local>>,
+	flapping: bool,
+	/// The number of changes in health since the client started flapping.
+	suppressed: usize,
+}
+
+impl FlapDetector
+{
+	/// Returns true if the client is flapping, in which case its changes in health are not reported.
+	pub fn is_flapping(&self) -> bool
+	{
+		self.flapping
+	}
+
+	/// Records the result of a round of checks, returning the change if the client started or stopped flapping.
+	pub fn record(&mut self, transitioned: bool, rules: &HealthRules) -> Option<FlapChange>
+	{
+		let now = Local::now();
+		if transitioned
+		{
+			self.transitions.push_back(now);
+			if self.flapping
+			{
+				self.suppressed += 1;
+			}
+		}
+		while let Some(time) = self.transitions.front()
+		{
+			if now.signed_duration_since(*time).to_std().unwrap_or_default() <= rules.flap_window
+			{
+				break;
+			}
+			self.transitions.pop_front();
+		}
+
+		let threshold = rules.flap_threshold as usize;
+		if !self.flapping && threshold > 0 && self.transitions.len() >= threshold
+		{
+			self.flapping = true;
+			self.suppressed = 0;
+			Some(FlapChange::Started(self.transitions.len()))
+		}
+		else if self.flapping && (threshold == 0 || self.transitions.len() <= threshold / 2)
+		{
+			self.flapping = false;
+			Some(FlapChange::Stopped(self.suppressed))
+		}
+		else
+		{
+			None
+		}
+	}
+}
@@ -0,0 +1,97 @@
+use crate::scheduler::{Schedule, Timer};
+use reqwest::Url;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// How many seconds apart the heartbeat is sent when the config does not say.
+const DEFAULT_HEARTBEAT_INTERVAL: u64 = 60;
+
+/// How long the heartbeat URL can take to respond before the ping fails.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the monitor pings to show that it is running, so a service such as healthchecks.io can alert when it stops.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeartbeatConfig
+{
+	/// The URL that is pinged while the monitor is healthy, such as `https://hc-ping.com/<uuid>`.
+	pub url: Option<String>,
+	/// The URL that is pinged instead while the monitor is not healthy, such as `https://hc-ping.com/<uuid>/fail`. Nothing
+	/// is pinged then when omitted, so the service alerts once the heartbeat is overdue.
+	pub fail_url: Option<String>,
+	/// How many seconds apart the heartbeat is sent. 60 when omitted.
+	pub interval: Option<u64>,
+}
+
+/// Pings a URL on an interval from the loop of the monitor, so the pings stop if the process dies or the loop stalls.
+pub struct Heartbeat
+{
+	client: reqwest::Client,
+	url: Url,
+	fail_url: Option<Url>,
+	timer: Timer,
+}
+
+impl Heartbeat
+{
+	/// Creates a heartbeat that pings the URLs in the config, the first time as soon as it is due.
+	pub fn new(config: &HeartbeatConfig) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		let url = match &config.url
+		{
+			Some(url) => parse_url(url)?,
+			None => return Err("The heartbeat needs a URL.".into()),
+		};
+		let fail_url = config.fail_url.as_deref().map(parse_url).transpose()?;
+		let client = reqwest::Client::builder().timeout(HEARTBEAT_TIMEOUT).build()?;
+		let timer = Timer::new(Schedule::from_secs(config.interval.unwrap_or(DEFAULT_HEARTBEAT_INTERVAL)));
+
+		Ok(Self { client, url, fail_url, timer })
+	}
+
+	/// Returns whether the next ping is due.
+	pub fn is_due(&self, now: Instant) -> bool
+	{
+		self.timer.is_due(now)
+	}
+
+	/// Pings the URL in the background if the monitor is healthy, or else the fail URL if there is one, and schedules
+	/// the next ping. Failed pings are not retried, since the next one is never far off.
+	pub fn beat(&mut self, healthy: bool, now: Instant)
+	{
+		self.timer.schedule_next(now);
+		let url = match (healthy, &self.fail_url)
+		{
+			(true, _) => self.url.clone(),
+			(false, Some(fail_url)) => fail_url.clone(),
+			(false, None) =>
+			{
+				tracing::warn!("Skipped the heartbeat because no client has reported recently.");
+				return;
+			},
+		};
+
+		let client = self.client.clone();
+		tokio::spawn(async move
+		{
+			let error = match client.get(url).send().await
+			{
+				Ok(response) if response.status().is_success() => return,
+				Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+				Err(e) => e.without_url().to_string(),
+			};
+			tracing::warn!("Failed to send the heartbeat: {}", error);
+		});
+	}
+}
+
+/// Parses a heartbeat URL, which must be HTTP or HTTPS.
+pub fn parse_url(url: &str) -> Result<Url, String>
+{
+	match Url::parse(url)
+	{
+		Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(parsed),
+		Ok(_) => Err(format!("Heartbeat URL {} is not an HTTP or HTTPS URL.", url)),
+		Err(e) => Err(format!("Invalid heartbeat URL {}: {}", url, e)),
+	}
+}
@@ -0,0 +1,179 @@
+use crate::client::ClientState;
+use chrono::{DateTime, Local};
+use std::time::Duration;
+
+/// A fixed capacity buffer that overwrites its oldest item once it is full, so it never grows past its capacity.
+#[derive(Clone, Debug)]
+pub struct RingBuffer<T>
+{
+	items: Vec<T>,
+	/// The index of the oldest item once the buffer is full.
+	start: usize,
+	capacity: usize,
+}
+
+impl<T> RingBuffer<T>
+{
+	/// Creates an empty `RingBuffer` that holds up to `capacity` items. A capacity of 0 holds nothing.
+	pub fn new(capacity: usize) -> Self
+	{
+		Self
+		{
+			items: Vec::with_capacity(capacity),
+			start: 0,
+			capacity,
+		}
+	}
+
+	/// Adds an item, replacing the oldest item if the buffer is full.
+	pub fn push(&mut self, item: T)
+	{
+		if self.capacity == 0
+		{
+			return;
+		}
+
+		if self.items.len() < self.capacity
+		{
+			self.items.push(item);
+		}
+		else
+		{
+			self.items[self.start] = item;
+			self.start = (self.start + 1) % self.capacity;
+		}
+	}
+
+	/// Returns the number of items in the buffer.
+	pub fn len(&self) -> usize
+	{
+		self.items.len()
+	}
+
+	/// Returns true if the buffer holds no items.
+	pub fn is_empty(&self) -> bool
+	{
+		self.items.is_empty()
+	}
+
+	/// Returns the maximum number of items the buffer holds.
+	pub fn capacity(&self) -> usize
+	{
+		self.capacity
+	}
+
+	/// Returns the most recently added item.
+	pub fn latest(&self) -> Option<&T>
+	{
+		match self.start
+		{
+			0 => self.items.last(),
+			start => self.items.get(start - 1),
+		}
+	}
+
+	/// Returns the items from oldest to newest.
+	pub fn iter(&self) -> impl Iterator<Item = &T> + '_
+	{
+		self.items[self.start..].iter().chain(self.items[..self.start].iter())
+	}
+
+	/// Returns up to `count` of the newest items, from oldest to newest.
+	pub fn recent(&self, count: usize) -> impl Iterator<Item = &T> + '_
+	{
+		self.iter().skip(self.len().saturating_sub(count))
+	}
+
+	/// Changes the capacity of the buffer, dropping the oldest items if it shrinks.
+	pub fn set_capacity(&mut self, capacity: usize)
+	{
+		if capacity == self.capacity
+		{
+			return;
+		}
+
+		let mut items = std::mem::take(&mut self.items);
+		items.rotate_left(self.start);
+		let excess = items.len().saturating_sub(capacity);
+		items.drain(..excess);
+
+		self.items = items;
+		self.items.reserve(capacity.saturating_sub(self.items.len()));
+		self.start = 0;
+		self.capacity = capacity;
+	}
+}
+
+/// The results of a client at a point in time.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample
+{
+	pub time: DateTime<Local>,
+	pub is_online: bool,
+	pub block_number: u64,
+	pub peers: u16,
+	/// How long the client took to respond to its last batch of RPC calls.
+	pub latency: Option<Duration>,
+	/// Whether the tip was fetched for this sample, rather than carried over from an earlier round.
+	pub tip_checked: bool,
+}
+
+impl Sample
+{
+	/// Creates a sample of the specified client state, taken now.
+	pub fn new(state: &ClientState, tip_checked: bool) -> Self
+	{
+		Self
+		{
+			time: Local::now(),
+			is_online: state.is_online(),
+			block_number: state.block_number,
+			peers: state.peers,
+			latency: state.latency,
+			tip_checked,
+		}
+	}
+}
+
+/// The recent results of a client, oldest first.
+pub type History = RingBuffer<Sample>;
+
+/// The number of recent samples the sync rate is calculated from.
+const SYNC_RATE_SAMPLES: usize = 10;
+
+impl History
+{
+	/// Returns the number of blocks per minute the client advanced over its recent samples, or `None` if there are not
+	/// enough samples from while it was online.
+	pub fn sync_rate(&self) -> Option<f64>
+	{
+		let samples = self.recent(SYNC_RATE_SAMPLES).filter(|sample| sample.is_online && sample.tip_checked).collect::<Vec<_>>();
+		let (first, last) = (samples.first()?, samples.last()?);
+		let minutes = last.time.signed_duration_since(first.time).num_milliseconds() as f64 / 60_000.0;
+		if minutes <= 0.0
+		{
+			return None;
+		}
+
+		Some((last.block_number as f64 - first.block_number as f64) / minutes)
+	}
+
+	/// Returns true if the client was online with the same block number for each of its last `cycles` results that
+	/// checked the tip, and the one before them.
+	pub fn is_stalled(&self, cycles: usize) -> bool
+	{
+		let samples = self.iter().filter(|sample| sample.tip_checked).collect::<Vec<_>>();
+		if cycles == 0 || samples.len() <= cycles
+		{
+			return false;
+		}
+
+		let mut samples = samples[samples.len() - cycles - 1..].iter();
+		let first = match samples.next()
+		{
+			Some(first) => *first,
+			None => return false,
+		};
+		first.is_online && samples.all(|sample| sample.is_online && sample.block_number == first.block_number)
+	}
+}
@@ -0,0 +1,39 @@
+use crate::history::RingBuffer;
+use std::time::Duration;
+
+/// The number of recent round trips that latency statistics are calculated from.
+pub const LATENCY_WINDOW: usize = 100;
+
+/// Rolling statistics of the round trip times of the RPC requests sent to a client.
+#[derive(Clone, Copy, Debug)]
+pub struct LatencyStats
+{
+	pub min: Duration,
+	pub avg: Duration,
+	/// The 95th percentile, which only the slowest 5% of round trips exceed.
+	pub p95: Duration,
+}
+
+impl LatencyStats
+{
+	/// Calculates the statistics of the round trips in a window, or returns `None` if it is empty.
+	pub fn new(window: &RingBuffer<Duration>) -> Option<Self>
+	{
+		let mut round_trips = window.iter().copied().collect::<Vec<_>>();
+		if round_trips.is_empty()
+		{
+			return None;
+		}
+		round_trips.sort();
+
+		let total: Duration = round_trips.iter().sum();
+		let p95_index = (round_trips.len() * 95).div_ceil(100) - 1;
+
+		Some(Self
+		{
+			min: round_trips[0],
+			avg: total / round_trips.len() as u32,
+			p95: round_trips[p95_index],
+		})
+	}
+}
@@ -0,0 +1,55 @@
+//! Monitoring of CKB light clients. A [`Monitor`] checks a fleet of clients, organized into groups, and reports on
+//! their health, so other tools can embed the same logic as the `ckb-light-client-monitor` binary.
+
+pub mod ack;
+pub mod alertmanager;
+pub mod alerts;
+pub mod checks;
+pub mod client;
+pub mod config;
+pub mod dependency;
+pub mod desktop;
+pub mod digest;
+pub mod discord;
+pub mod disk;
+pub mod diversity;
+pub mod endpoint;
+pub mod events;
+pub mod exec;
+pub mod filter;
+pub mod fleet;
+pub mod gotify;
+pub mod group;
+pub mod halt;
+pub mod health;
+pub mod heartbeat;
+pub mod history;
+pub mod latency;
+pub mod logtail;
+pub mod matrix;
+pub mod metrics;
+pub mod monitor;
+pub mod mqtt;
+pub mod ntfy;
+pub mod otlp;
+pub mod process;
+pub mod propagation;
+pub mod pushover;
+pub mod quiet;
+pub mod ratelimit;
+pub mod report;
+pub mod retry;
+pub mod rpc;
+pub mod scheduler;
+pub mod slack;
+pub mod subscription;
+pub mod targets;
+pub mod telegram;
+pub mod template;
+pub mod validate;
+pub mod version;
+pub mod webhook;
+pub mod worker;
+
+pub use events::MonitorEvent;
+pub use monitor::{ClientSnapshot, FleetSnapshot, Monitor};
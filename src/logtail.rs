@@ -0,0 +1,86 @@
+use regex::Regex;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// The patterns that are matched against client logs when none are configured.
+pub const DEFAULT_LOG_PATTERNS: [&str; 3] = ["panicked at", "(?i)database.*corrupt", "(?i)disconnected from .* peers"];
+
+/// The most lines that are reported from a log per round of checks, so a client spamming errors cannot flood the output.
+const MAX_MATCHES_PER_POLL: usize = 20;
+
+/// A line in a client log that matched one of the patterns.
+#[derive(Clone, Debug)]
+pub struct LogMatch
+{
+	pub pattern: String,
+	pub line: String,
+}
+
+/// Follows the log file of a client, like `tail -F`, and picks out the new lines that match any of the patterns. Only
+/// lines written after the first poll are read, and the file is reread from the start whenever it shrinks, such as when
+/// it is truncated or rotated.
+pub struct LogWatcher
+{
+	pub path: PathBuf,
+	patterns: Vec<Regex>,
+	/// The position in the file up to which it has been read, or `None` before the file has been opened.
+	offset: Option<u64>,
+	/// The end of a line that has only been partly written.
+	partial: String,
+}
+
+impl LogWatcher
+{
+	/// Creates a new `LogWatcher` for the file at the specified path, compiling the patterns it matches lines against.
+	pub fn new(path: PathBuf, patterns: &[String]) -> Result<Self, regex::Error>
+	{
+		let patterns = patterns.iter().map(|pattern| Regex::new(pattern)).collect::<Result<Vec<_>, _>>()?;
+
+		Ok(Self
+		{
+			path,
+			patterns,
+			offset: None,
+			partial: String::new(),
+		})
+	}
+
+	/// Reads the lines written since the last poll and returns those that match a pattern, along with the number of
+	/// matching lines that were left out because there were too many.
+	pub async fn poll(&mut self) -> std::io::Result<(Vec<LogMatch>, usize)>
+	{
+		let mut file = File::open(&self.path).await?;
+		let length = file.metadata().await?.len();
+
+		// Start from the end the first time, so old errors are not reported again on every restart of the monitor.
+		let offset = match self.offset
+		{
+			Some(offset) if offset <= length => offset,
+			Some(_) =>
+			{
+				self.partial.clear();
+				0
+			},
+			None => length,
+		};
+		file.seek(SeekFrom::Start(offset)).await?;
+		let mut bytes = Vec::new();
+		file.read_to_end(&mut bytes).await?;
+		self.offset = Some(offset + bytes.len() as u64);
+
+		self.partial.push_str(&String::from_utf8_lossy(&bytes));
+		let complete = match self.partial.rfind('\n')
+		{
+			Some(index) => self.partial.drain(..=index).collect::<String>(),
+			None => return Ok((Vec::new(), 0)),
+		};
+
+		let matches = complete.lines()
+			.filter_map(|line| self.patterns.iter().find(|pattern| pattern.is_match(line)).map(|pattern| LogMatch { pattern: pattern.to_string(), line: line.trim().to_string() }))
+			.collect::<Vec<_>>();
+		let skipped = matches.len().saturating_sub(MAX_MATCHES_PER_POLL);
+		Ok((matches.into_iter().take(MAX_MATCHES_PER_POLL).collect(), skipped))
+	}
+}
@@ -1,325 +1,125 @@
-use chrono::{Local, DateTime};
-use env_logger::{Builder, Env};
-use num_format::{ToFormattedString};
-use reqwest;
-use serde_json::json;
-use std::{env, error::Error, thread, time};
-use std::io::Write;
-
-const HOST: &str = "http://127.0.0.1";
-const STARTING_PORT: u16 = 19000;
-const TOTAL_CLIENTS: usize = 100;
-const CHECK_INTERVAL: u64 = 60;
-const MAX_BLOCK_DIFF: u64 = 30;
-
-/// Represents a CKB light client.
-struct Client 
+mod cli;
+
+use ckb_light_client_monitor::ack::{self, Ack};
+use ckb_light_client_monitor::config::Config;
+use ckb_light_client_monitor::{validate, Monitor};
+use clap::Parser;
+use cli::{Args, Command};
+use std::error::Error;
+use std::future::Future;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tracing_subscriber::fmt::time::ChronoLocal;
+use tracing_subscriber::EnvFilter;
+
+/// Loads the config file, applies any environment variable and command line overrides, and loads the targets file.
+fn load_config(args: &Args) -> Result<Config, Box<dyn Error>>
 {
-	number: usize,
-	port: u16,
-	is_online: bool,
-	block_number: u64,
-	peers: u16,
-	time_offline: Option<DateTime<Local>>,
+	let mut config = Config::load(&args.config)?;
+	config.apply_env()?;
+	args.apply(&mut config);
+	config.load_targets()?;
+
+	Ok(config)
 }
 
-impl Client 
+/// Listens for SIGHUP and flags that the config should be reloaded.
+#[cfg(unix)]
+fn listen_for_reload(reload_requested: Arc<AtomicBool>) -> Result<(), Box<dyn Error>>
 {
-	/// Creates a new `Client`.
-	fn new(number: usize) -> Self 
+	let mut hangup = signal(SignalKind::hangup())?;
+	tokio::spawn(async move
 	{
-		Self 
+		while hangup.recv().await.is_some()
 		{
-			number,
-			port: STARTING_PORT + number as u16,
-			is_online: true,
-			block_number: 0,
-			peers: 0,
-			time_offline: None,
+			tracing::info!("Received SIGHUP. Reloading the config.");
+			reload_requested.store(true, Ordering::SeqCst);
 		}
-	}
+	});
 
-	/// Checks if the RPC server of the client is running using the `local_node_info` RPC call.
-	async fn check_rpc(&mut self) -> Result<(), Box<dyn Error>>
-	{
-		let rpc_payload = json!(
-		{
-			"id": 1,
-			"jsonrpc": "2.0",
-			"method": "local_node_info",
-			"params": []
-		});
-
-		let client = reqwest::Client::new();
-		let response_result = client.post(format!("{}:{}/", HOST, self.port))
-			.json(&rpc_payload)
-			.send().await;
-
-		match response_result
-		{
-			Ok(res) =>
-			{
-				if res.status().is_success()
-				{
-					if !self.is_online
-					{
-						let duration_offline = Local::now().signed_duration_since(self.time_offline.unwrap()).num_seconds();
-						log::info!("Client {} is now online. (Offline {} seconds.)", self.number, duration_offline.to_formatted_string(&num_format::Locale::en));
-
-						self.is_online = true;
-						self.time_offline = None;
-					}
-				}
-				else
-				{
-					if self.is_online
-					{
-						log::error!("Client {} gave an error response.", self.number);
-						self.is_online = false;
-						self.time_offline = Some(Local::now());
-						self.peers = 0;
-						self.block_number = 0;
-					}
-				}
-			}
-			Err(e) =>
-			{
-				if self.is_online
-				{
-					// Handle the specific case where the client does not respond.
-					log::error!("Client {} did not respond: {}", self.number, e);
-					self.is_online = false;
-					self.time_offline = Some(Local::now());
-					self.peers = 0;
-					self.block_number = 0;
-				}
-			}
-		}
+	Ok(())
+}
 
-		Ok(())
-	}
+/// Registers for Ctrl-C and SIGTERM, returning a future that completes when either is received.
+#[cfg(unix)]
+fn shutdown_signal() -> Result<impl Future<Output = ()>, Box<dyn Error>>
+{
+	let mut terminate = signal(SignalKind::terminate())?;
 
-	/// Checks the number of peers the client is connected to using the `get_peers` RPC call.
-	async fn check_peers(&mut self) -> Result<(), Box<dyn Error>>
+	Ok(async move
 	{
-		if !self.is_online
+		tokio::select!
 		{
-			return Ok(());
+			_ = tokio::signal::ctrl_c() => tracing::info!("Received Ctrl-C. Shutting down."),
+			_ = terminate.recv() => tracing::info!("Received SIGTERM. Shutting down."),
 		}
+	})
+}
 
-		let rpc_payload = json!(
-		{
-			"id": 1,
-			"jsonrpc": "2.0",
-			"method": "get_peers",
-			"params": []
-		});
-
-		let client = reqwest::Client::new();
-		let response_result = client.post(format!("{}:{}/", HOST, self.port))
-			.json(&rpc_payload)
-			.send().await;
-
-		match response_result
-		{
-			Ok(res) =>
-			{
-				let json_result: Result<serde_json::Value, _> = res.json().await;
-				match json_result
-				{
-					Ok(json) =>
-					{
-						let peers_option = json["result"].as_array();
-
-						match peers_option
-						{
-							Some(peers) =>
-							{
-								let peers_count = peers.len();
-
-								// Print a warning if the client peer cound has changed and has 0 or 1 peers.
-								if self.peers != peers_count as u16 && (peers_count == 0 || peers_count == 1)
-								{
-									let plural = if peers_count == 1 { "" } else { "s" };
-									log::debug!("Client {} has {} peer{}.", self.number, peers_count, plural);
-								}
-								self.peers = peers_count as u16;
-							},
-							None =>
-							{
-								log::error!("Client {} failed to parse JSON response: 'result' field is not an array or missing", self.number);
-							}
-						}
-					},
-					Err(e) =>
-					{
-						log::error!("Client {} failed to parse JSON response: {}", self.number, e);
-					}
-				}
-			},
-			Err(_) =>
-			{
-				log::error!("Client {} did not respond to the peer request.", self.number);
-			}
-		}
-
-		Ok(())
-	}
-
-	/// Retrieves and updates the current block number of the client using the `get_tip_header` RPC call.
-	async fn check_block_number(&mut self) -> Result<(), Box<dyn Error>>
+/// Returns a future that completes when Ctrl-C is received.
+#[cfg(not(unix))]
+fn shutdown_signal() -> Result<impl Future<Output = ()>, Box<dyn Error>>
+{
+	Ok(async
 	{
-		if !self.is_online
-		{
-			return Ok(());
-		}
-
-		let rpc_payload = json!(
-		{
-			"id": 1,
-			"jsonrpc": "2.0",
-			"method": "get_tip_header",
-			"params": []
-		});
-
-		let client = reqwest::Client::new();
-		let response_result = client.post(format!("{}:{}/", HOST, self.port))
-			.json(&rpc_payload)
-			.send().await;
-
-		if let Err(_) = response_result
-		{
-			log::error!("Client {} did not respond to the tip request.", self.number);
-			return Ok(());
-		}
-
-		let response = response_result.unwrap();
-		let json_result = response.json::<serde_json::Value>().await;
-
-		if let Err(e) = json_result
-		{
-			log::error!("Client {} failed to parse JSON response: {}", self.number, e);
-			return Ok(());
-		}
-
-		let json = json_result.unwrap();
-		if let Some(header) = json["result"].get("number")
-		{
-			match header.as_str()
-			{
-				Some(block_num_str) =>
-				{
-					let block_num = u64::from_str_radix(block_num_str.trim_start_matches("0x"), 16);
-					
-					match block_num
-					{
-						Ok(num) => { self.block_number = num; },
-						Err(e) =>
-						{
-							log::error!("Client {} failed to parse block number: {}", self.number, e);
-						}
-					};
-				},
-				None =>
-				{
-					log::error!("Client {} returned a block number in an unexpected format.", self.number);
-				}
-			}
-		}
-		else
-		{
-			log::error!("Client {} returned an unexpected JSON object.", self.number);
-		}
-
-		Ok(())
-	}
+		let _ = tokio::signal::ctrl_c().await;
+		tracing::info!("Received Ctrl-C. Shutting down.");
+	})
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> 
 {
-	// Initialize the logger with a default log level.
-	let is_verbose = env::args().any(|arg| arg == "-v");
-	let logger_level = if is_verbose { "debug" } else { "info" };
-	Builder::from_env(Env::default().default_filter_or(logger_level))
-		.format(|buf, rec| writeln!(buf, "{} [{}] {}", Local::now().format("%Y%m%d %H:%M:%S"), rec.level(), rec.args()))
+	let args = Args::parse();
+
+	// Initialize the subscriber with a default log level. RUST_LOG can filter by target, span, and field.
+	let logger_level = if args.verbose { "debug" } else { "info" };
+	tracing_subscriber::fmt()
+		.with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(logger_level)))
+		.with_timer(ChronoLocal::new("%Y%m%d %H:%M:%S".to_string()))
+		.with_target(false)
 		.init();
 
-	let mut clients = (0..TOTAL_CLIENTS).map(Client::new).collect::<Vec<_>>();
-	let mut highest_block_number = 0;
+	let config = load_config(&args)?;
+	let filter = args.filter();
 
-	loop
+	if let Some(Command::Ack { client, condition, by, expires }) = &args.command
 	{
-		// Check all clients online status, peer count, and tip block number.
-		for client in clients.iter_mut() 
-		{
-			log::debug!("Checking client {}.", client.number);
-
-			client.check_rpc().await?;
-			if client.is_online
-			{
-				client.check_peers().await?;
-				client.check_block_number().await?;
+		ack::acknowledge(&config.acks_file, Ack::new(client.clone(), *condition, by.clone(), *expires))?;
+		println!("Acknowledged client {} being {} on behalf of {}.", client, condition, by);
+		return Ok(());
+	}
 
-				if client.block_number > highest_block_number 
-				{
-					highest_block_number = client.block_number;
-				}
-			}
-		}
+	if args.dry_run
+	{
+		validate::print_fleet(&config.groups(), &filter);
 
-		// Print warnings for all lagging clients.
-		for client in clients.iter()
+		let errors = validate::validate(&config).await;
+		if errors.is_empty()
 		{
-			if client.is_online && highest_block_number > client.block_number + MAX_BLOCK_DIFF 
-			{
-				let block_difference = (highest_block_number - client.block_number).to_formatted_string(&num_format::Locale::en);
-				let client_block_tip = client.block_number.to_formatted_string(&num_format::Locale::en);
-				log::warn!("Client {} is lagging by {} blocks: {}", client.number, block_difference, client_block_tip);
-			}
+			println!("The config is valid.");
+			return Ok(());
 		}
 
-		// Count offline clients from a collection and print a warning if any are found.
-		let mut peer_0_clients = Vec::new();
-		let mut peer_1_clients = Vec::new();
-		let mut offline_clients = Vec::new();
-		for client in clients.iter()
-		{
-			if client.is_online
-			{
-				if client.peers == 0
-				{
-					peer_0_clients.push(client.number);
-				}
-				else if client.peers == 1
-				{
-					peer_1_clients.push(client.number);
-				}
-			}
-			else
-			{
-				offline_clients.push(client.number);
-			}
-		}
-		if !peer_0_clients.is_empty()
-		{
-			let peer_0_client_count = peer_0_clients.len();
-			let peer_0_client_string: String = peer_0_clients.iter().map(|x|x.to_string()).collect::<Vec<String>>().join(", ");
-			log::info!("There are {} clients with 0 peers: {}", peer_0_client_count, peer_0_client_string);
-		}
-		if !peer_1_clients.is_empty()
-		{
-			let peer_1_client_count = peer_1_clients.len();
-			let peer_1_client_string = peer_1_clients.iter().map(|x|x.to_string()).collect::<Vec<String>>().join(", ");
-			log::info!("There are {} clients with 1 peer: {}", peer_1_client_count, peer_1_client_string);
-		}
-		if !offline_clients.is_empty()
+		for error in errors.iter()
 		{
-			let offline_client_count = offline_clients.len();
-			let offline_client_string = offline_clients.iter().map(|x|x.to_string()).collect::<Vec<String>>().join(", ");
-			log::info!("There are {} clients that are offline: {}", offline_client_count, offline_client_string);
+			tracing::error!("{}", error);
 		}
-
-		thread::sleep(time::Duration::from_secs(CHECK_INTERVAL));
+		tracing::error!("Found {} problems in the config.", errors.len());
+		process::exit(1);
 	}
+
+	let shutdown = shutdown_signal()?;
+
+	let mut monitor = Monitor::new(config, filter);
+	#[cfg(unix)]
+	listen_for_reload(monitor.reload_flag())?;
+	monitor.set_config_loader(move || load_config(&args));
+
+	monitor.run(shutdown).await;
+
+	Ok(())
 }
@@ -0,0 +1,258 @@
+use crate::alerts::{self, Alert, AlertLimiter, RateLimit, Sink};
+use crate::dependency;
+use crate::digest;
+use crate::events::{MonitorEvent, Severity};
+use crate::fleet;
+use crate::halt;
+use crate::health::Health;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use num_format::ToFormattedString;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// How long the homeserver can take to accept a message before the attempt fails.
+const MATRIX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Counts the messages sent, so each has its own transaction id even within the same millisecond.
+static TRANSACTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// How Matrix notifications are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixConfig
+{
+	/// The URL of the homeserver, such as `https://matrix.example.com`.
+	pub homeserver_url: Option<String>,
+	/// The access token of the account that posts the messages.
+	pub access_token: Option<String>,
+	/// The id of the room messages are posted to, such as `!abcdef:example.com`. The account must have joined it.
+	pub room_id: Option<String>,
+	/// The least severe messages that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether messages that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe messages are sent straight away, and the rest are held
+	/// until the next digest. Every message is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+/// Posts messages to a Matrix room when clients change state and when group summaries are made. The task is stopped
+/// when this is dropped.
+pub struct MatrixSink
+{
+	task: JoinHandle<()>,
+}
+
+impl MatrixSink
+{
+	/// Starts posting messages for the alerts received from `alerts`, retrying failed deliveries according to the
+	/// retry policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: MatrixConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		let (homeserver_url, access_token, room_id) = match (&config.homeserver_url, &config.access_token, &config.room_id)
+		{
+			(Some(homeserver_url), Some(access_token), Some(room_id)) => (homeserver_url, access_token.clone(), room_id),
+			_ => return Err("Matrix needs a homeserver URL, an access token, and a room id.".into()),
+		};
+		let room_url = room_url(homeserver_url, room_id)?;
+		let client = reqwest::Client::builder().timeout(MATRIX_TIMEOUT).build()?;
+
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Matrix, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Matrix, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let body = limiter.summary_text(suppressed);
+						let content = json!({ "msgtype": "m.text", "body": body, "format": "org.matrix.custom.html", "formatted_body": escape_html(&body) });
+						tokio::spawn(deliver(client.clone(), room_url.clone(), access_token.clone(), content, retry.clone()));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to Matrix because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Matrix) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let (body, formatted_body) = match message(&alert.event)
+				{
+					Some(message) => match &alert.text
+					{
+						Some(text) => (text.clone(), escape_html(text)),
+						None => message,
+					},
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				let content = json!({ "msgtype": "m.text", "body": body, "format": "org.matrix.custom.html", "formatted_body": formatted_body });
+				tokio::spawn(deliver(client.clone(), room_url.clone(), access_token.clone(), content, retry.clone()));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for MatrixSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the URL that `m.room.message` events are sent to in a room, without the transaction id. The room id is
+/// escaped, since it contains `!` and `:`.
+pub fn room_url(homeserver_url: &str, room_id: &str) -> Result<Url, String>
+{
+	let mut url = Url::parse(homeserver_url).map_err(|e| format!("Invalid Matrix homeserver URL {}: {}", homeserver_url, e))?;
+	url.path_segments_mut()
+		.map_err(|_| format!("Invalid Matrix homeserver URL {}.", homeserver_url))?
+		.pop_if_empty()
+		.extend(["_matrix", "client", "v3", "rooms", room_id, "send", "m.room.message"]);
+	Ok(url)
+}
+
+/// Returns the plain text and HTML of an event, or `None` for events that are not sent to Matrix. Changes in the state
+/// of a client, group summaries, digests, fleet conditions, chain halts, and parents going down are sent, but not
+/// reports that repeat at every check.
+pub fn message(event: &MonitorEvent) -> Option<(String, String)>
+{
+	let (subject, text) = match event
+	{
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } =>
+		{
+			let reason = reason.as_deref().unwrap_or_default();
+			let duration = duration_seconds.to_formatted_string(&num_format::Locale::en);
+			match (from, to)
+			{
+				(_, Health::Offline) => (client, format!("is offline: {}", reason)),
+				(Health::Offline, Health::Online) => (client, format!("is back online after {} seconds offline.", duration)),
+				(Health::Offline, Health::Degraded) => (client, format!("is responding again but is degraded: {}", reason)),
+				(_, Health::Degraded) => (client, format!("is degraded: {}", reason)),
+				(Health::Degraded, Health::Online) => (client, format!("has recovered after {} seconds degraded.", duration)),
+				_ => return None,
+			}
+		},
+		MonitorEvent::Flapping { client, transitions, window_seconds } => (client, format!("is flapping after changing health {} times in {} seconds.", transitions, window_seconds)),
+		MonitorEvent::FlappingStopped { client, health, .. } => (client, format!("has stopped flapping and is {}.", health)),
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } => (client, format!("is lagging {} blocks behind at block {}.", blocks_behind.to_formatted_string(&num_format::Locale::en), block_number.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::CaughtUp { client, block_number } => (client, format!("has caught up at block {}.", block_number.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::SyncStalled { client, block_number, blocks_behind, cycles } => (client, format!("has stalled at block {} for {} cycles while {} blocks behind.", block_number.to_formatted_string(&num_format::Locale::en), cycles, blocks_behind.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::SyncResumed { client, blocks_per_minute } => (client, format!("is syncing again at {:.1} blocks per minute.", blocks_per_minute)),
+		MonitorEvent::TipFrozen { client, block_number, cycles } => (client, format!("has reported block {} for {} consecutive cycles.", block_number.to_formatted_string(&num_format::Locale::en), cycles)),
+		MonitorEvent::TipUnfrozen { client, block_number } => (client, format!("has moved on to block {}.", block_number.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::StillOngoing { client, condition, minutes } => (client, format!("is still {} after {} minutes.", condition, minutes.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::LowPeersStarted { client, peers, min_peers } => (client, format!("has {} peers, below its minimum of {}.", peers, min_peers)),
+		MonitorEvent::PeersRestored { client, peers } => (client, format!("is back to {} peers.", peers)),
+		MonitorEvent::Escalated { client, condition, minutes, severity } => (client, format!("has been {} for {} minutes and has escalated to {}.", condition, minutes.to_formatted_string(&num_format::Locale::en), severity)),
+		MonitorEvent::AlertsAggregated { event_type, host, clients } =>
+		{
+			let text = alerts::aggregated_text(event_type, host.as_deref(), clients);
+			return Some((text.clone(), escape_html(&text)));
+		},
+		MonitorEvent::OutageSummary { group, clients } =>
+		{
+			let clients = clients.iter()
+				.map(|(client, outages)| format!("{} recovered from {} outages in {} seconds on average", client, outages.outages, outages.mean_recovery().unwrap_or_default().as_secs().to_formatted_string(&num_format::Locale::en)))
+				.collect::<Vec<_>>();
+			(group, format!("outages: {}.", clients.join("; ")))
+		},
+		MonitorEvent::GroupSummary { group, online, total, highest_block_number, .. } => (group, format!("has {} of {} clients online at block {}.", online, total, highest_block_number.to_formatted_string(&num_format::Locale::en))),
+		MonitorEvent::Digest { .. } =>
+		{
+			let text = digest::lines(event)?.join("\n");
+			return Some((text.clone(), escape_html(&text).replace('\n', "<br>")));
+		},
+		MonitorEvent::FleetDegraded { .. } | MonitorEvent::FleetRecovered { .. } =>
+		{
+			let text = fleet::text(event)?;
+			return Some((text.clone(), escape_html(&text)));
+		},
+		MonitorEvent::ChainHalted { .. } | MonitorEvent::ChainResumed { .. } =>
+		{
+			let text = halt::text(event)?;
+			return Some((text.clone(), escape_html(&text)));
+		},
+		MonitorEvent::ParentDown { .. } | MonitorEvent::ParentRecovered { .. } =>
+		{
+			let text = dependency::text(event)?;
+			return Some((text.clone(), escape_html(&text)));
+		},
+		_ => return None,
+	};
+
+	let body = format!("{} {}", subject, text);
+	let formatted_body = format!("<b>{}</b> {}", escape_html(subject), escape_html(&text));
+	Some((body, formatted_body))
+}
+
+/// Escapes the characters that have a meaning in HTML.
+fn escape_html(text: &str) -> String
+{
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Sends a message to a room, retrying transport failures and error statuses according to the retry policy. The same
+/// transaction id is used for every attempt, so the homeserver does not post a message twice if a retry follows an
+/// attempt that actually succeeded.
+async fn deliver(client: reqwest::Client, room_url: Url, access_token: String, content: serde_json::Value, retry: RetryPolicy)
+{
+	let transaction = format!("ckb-monitor-{}-{}", chrono::Utc::now().timestamp_millis(), TRANSACTIONS.fetch_add(1, Ordering::Relaxed));
+	let mut url = room_url;
+	if let Ok(mut segments) = url.path_segments_mut()
+	{
+		segments.push(&transaction);
+	}
+
+	let mut attempt = 0;
+	loop
+	{
+		let error = match client.put(url.clone()).bearer_auth(&access_token).json(&content).send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send a message to Matrix: {}", error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending a message to Matrix failed: {}. Retrying in {} ms.", error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
@@ -0,0 +1,112 @@
+use crate::monitor::{ClientSnapshot, FleetSnapshot};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// The prefix of the name of every metric.
+const METRIC_PREFIX: &str = "ckb_light_client_";
+
+/// The content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Serves the latest results of each client as Prometheus metrics at `/metrics`. The server is stopped, which frees the
+/// address, when this is dropped.
+pub struct MetricsServer
+{
+	task: JoinHandle<()>,
+}
+
+impl MetricsServer
+{
+	/// Starts serving the metrics of the clients in the latest snapshot of `fleet` on an address.
+	pub fn spawn(address: SocketAddr, fleet: watch::Receiver<Arc<FleetSnapshot>>) -> Result<Self, hyper::Error>
+	{
+		let make_service = make_service_fn(move |_|
+		{
+			let fleet = fleet.clone();
+			async move
+			{
+				Ok::<_, Infallible>(service_fn(move |request: Request<Body>|
+				{
+					let snapshot = fleet.borrow().clone();
+					async move { Ok::<_, Infallible>(respond(&request, &snapshot)) }
+				}))
+			}
+		});
+		let server = Server::try_bind(&address)?.serve(make_service);
+		tracing::info!("Serving metrics on http://{}/metrics", address);
+
+		let task = tokio::spawn(async move
+		{
+			if let Err(e) = server.await
+			{
+				tracing::error!("Metrics server failed: {}", e);
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for MetricsServer
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the metrics for a request to `/metrics`, or a 404 for anything else.
+fn respond(request: &Request<Body>, snapshot: &FleetSnapshot) -> Response<Body>
+{
+	if request.method() != Method::GET || request.uri().path() != "/metrics"
+	{
+		let mut response = Response::new(Body::from("Not Found\n"));
+		*response.status_mut() = StatusCode::NOT_FOUND;
+		return response;
+	}
+
+	let mut response = Response::new(Body::from(render(snapshot)));
+	response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static(CONTENT_TYPE));
+	response
+}
+
+/// Renders the metrics of every client that has reported in the text exposition format.
+pub fn render(snapshot: &FleetSnapshot) -> String
+{
+	let clients = snapshot.clients.iter().filter(|client| client.reported).collect::<Vec<_>>();
+	let mut text = String::new();
+	write_metric(&mut text, "online", "gauge", "Whether the client is online.", &clients, |client| Some(if client.is_online { 1.0 } else { 0.0 }));
+	write_metric(&mut text, "peer_count", "gauge", "The number of peers the client is connected to.", &clients, |client| Some(client.peers as f64));
+	write_metric(&mut text, "tip_block_number", "gauge", "The block number of the tip of the client.", &clients, |client| Some(client.block_number as f64));
+	write_metric(&mut text, "lag_blocks", "gauge", "How many blocks the client is behind the tip of its group.", &clients, |client| client.lag.map(|lag| lag as f64));
+	write_metric(&mut text, "rpc_latency_seconds", "gauge", "How long the client took to respond to its last RPC request.", &clients, |client| client.latency.map(|latency| latency.as_secs_f64()));
+	write_metric(&mut text, "offline_transitions_total", "counter", "The number of times the client has gone offline.", &clients, |client| Some(client.offline_transitions as f64));
+	write_metric(&mut text, "check_errors_total", "counter", "The number of checks that have failed or found the client unresponsive.", &clients, |client| Some(client.check_errors as f64));
+	text
+}
+
+/// Writes a metric with a sample for each client that has a value for it.
+fn write_metric(text: &mut String, name: &str, kind: &str, help: &str, clients: &[&ClientSnapshot], value: impl Fn(&ClientSnapshot) -> Option<f64>)
+{
+	let _ = writeln!(text, "# HELP {}{} {}", METRIC_PREFIX, name, help);
+	let _ = writeln!(text, "# TYPE {}{} {}", METRIC_PREFIX, name, kind);
+	for client in clients
+	{
+		if let Some(value) = value(client)
+		{
+			let _ = writeln!(text, "{}{}{{client=\"{}\",group=\"{}\"}} {}", METRIC_PREFIX, name, escape(&client.name()), escape(&client.group), value);
+		}
+	}
+}
+
+/// Escapes a label value for the text exposition format.
+fn escape(value: &str) -> String
+{
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
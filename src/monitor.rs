@@ -0,0 +1,933 @@
+use crate::alertmanager::{AlertmanagerSink, FiringAlerts};
+use crate::alerts::{Alert, AlertFilter, Sink};
+use crate::checks::TIP_CHECK;
+use crate::config::{ClientSettings, Config};
+use crate::dependency::{self, Dependencies};
+use crate::desktop::DesktopSink;
+use crate::digest::DigestRecorder;
+use crate::discord::DiscordSink;
+use crate::disk::DiskStats;
+use crate::diversity::AsnDatabase;
+use crate::events::{self, MonitorEvent};
+use crate::exec::ExecSink;
+use crate::filter::ClientFilter;
+use crate::fleet::FleetConditions;
+use crate::gotify::GotifySink;
+use crate::group::Group;
+use crate::health::{Health, OutageStats};
+use crate::heartbeat::Heartbeat;
+use crate::history::Sample;
+use crate::latency::LatencyStats;
+use crate::matrix::MatrixSink;
+use crate::metrics::MetricsServer;
+use crate::mqtt::MqttPublisher;
+use crate::ntfy::NtfySink;
+use crate::otlp::OtlpExporter;
+use crate::pushover::PushoverSink;
+use crate::process::ProcessStats;
+use crate::propagation::PropagationStats;
+use crate::quiet::{HeldAlerts, QuietQueue};
+use crate::ratelimit;
+use crate::report;
+use crate::rpc::{Epoch, H256, Peer};
+use crate::scheduler::Timer;
+use crate::slack::SlackSink;
+use crate::targets::TargetsWatcher;
+use crate::telegram::TelegramSink;
+use crate::webhook::WebhookSink;
+use crate::worker::ClientReport;
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{self, MissedTickBehavior};
+
+/// How often the monitor wakes to run any reports that are due.
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+
+/// The number of client reports that can be queued before client tasks wait for the monitor to catch up.
+const REPORT_CHANNEL_CAPACITY: usize = 1024;
+
+/// The number of events a subscriber can fall behind by before it misses some.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Loads a fresh copy of the config when a reload is requested.
+pub type ConfigLoader = Box<dyn Fn() -> Result<Config, Box<dyn Error>>>;
+
+/// The most recent results of a single client.
+#[derive(Clone, Debug)]
+pub struct ClientSnapshot
+{
+	pub number: usize,
+	pub group: String,
+	pub label: Option<String>,
+	pub url: String,
+	/// Whether the client has reported the results of its first checks.
+	pub reported: bool,
+	pub health: Health,
+	pub is_online: bool,
+	pub block_number: u64,
+	pub tip_hash: Option<H256>,
+	/// When the tip block was mined.
+	pub tip_time: Option<DateTime<Local>>,
+	/// The epoch of the tip block.
+	pub epoch: Option<Epoch>,
+	pub peers: u16,
+	/// The peers the client was connected to at its last peers check.
+	pub connected_peers: Vec<Peer>,
+	/// The average number of peers that connected or disconnected between recent peers checks.
+	pub peer_churn: Option<f64>,
+	/// The version string the client last reported.
+	pub version: Option<String>,
+	/// The lowest block number any of the filter scripts of the client has been synced to.
+	pub script_block_number: Option<u64>,
+	pub time_offline: Option<DateTime<Local>>,
+	/// The outages the client has recovered from.
+	pub outages: OutageStats,
+	/// How far ahead of the timestamps of new headers the client took them, which is further below zero when the clock
+	/// of its host is behind.
+	pub clock_offset: Option<chrono::Duration>,
+	/// How long after the first client in its group the client reported recent new blocks.
+	pub propagation: Option<PropagationStats>,
+	/// How long the client took to respond to its last RPC request.
+	pub latency: Option<Duration>,
+	pub latency_stats: Option<LatencyStats>,
+	/// How long the client took to fetch the probe data at its last fetch check.
+	pub fetch_time: Option<Duration>,
+	/// The resource usage of the process of the client, if it is run locally.
+	pub process: Option<ProcessStats>,
+	/// The disk usage of the data directory of the client, if it is run locally.
+	pub disk: Option<DiskStats>,
+	/// The number of blocks per minute the client advanced over its recent results.
+	pub sync_rate: Option<f64>,
+	/// Whether the client is behind and has stopped advancing.
+	pub stalled: bool,
+	/// How many blocks the client is behind the tip of its group, or `None` if it is offline or its tip is not checked.
+	pub lag: Option<u64>,
+	/// The number of checks that have failed or found the client unresponsive.
+	pub check_errors: u64,
+	/// The number of times the client has gone offline.
+	pub offline_transitions: u64,
+	/// Whether the client shares a host with other clients or its group has reference nodes, so it can be affected by
+	/// one of them going down.
+	pub has_parent: bool,
+}
+
+impl ClientSnapshot
+{
+	/// Returns the name of the client, as used in events, such as `mainnet/3`.
+	pub fn name(&self) -> String
+	{
+		format!("{}/{}", self.group, self.label.clone().unwrap_or_else(|| self.number.to_string()))
+	}
+}
+
+/// The most recent results of every monitored client, published as a whole so readers always see a consistent view.
+#[derive(Clone, Debug)]
+pub struct FleetSnapshot
+{
+	/// When the snapshot was taken.
+	pub time: DateTime<Local>,
+	pub clients: Vec<ClientSnapshot>,
+}
+
+impl FleetSnapshot
+{
+	/// Returns the client with a name, as used in events, such as `mainnet/3`.
+	pub fn client(&self, name: &str) -> Option<&ClientSnapshot>
+	{
+		self.clients.iter().find(|client| client.name() == name)
+	}
+}
+
+/// Monitors a fleet of light clients, organized into groups, and reports on their health.
+pub struct Monitor
+{
+	config: Config,
+	filter: ClientFilter,
+	groups: Vec<Group>,
+	reports: mpsc::Sender<ClientReport>,
+	report_receiver: mpsc::Receiver<ClientReport>,
+	events: broadcast::Sender<MonitorEvent>,
+	/// The subscription that writes events to the log.
+	log_events: broadcast::Receiver<MonitorEvent>,
+	/// The events that are passed on to alert destinations, without repeats held back by the alert filter.
+	alerts: broadcast::Sender<Alert>,
+	/// Holds back repeated alerts and sends reminders about conditions that last.
+	alert_filter: AlertFilter,
+	/// The alerts each destination has held during its quiet hours, which outlive the destinations across reloads.
+	held_alerts: HeldAlerts,
+	fleet: watch::Sender<Arc<FleetSnapshot>>,
+	/// Whether a client has reported since the fleet snapshot was last published.
+	fleet_changed: bool,
+	/// The conditions over the whole fleet, which are evaluated each time its snapshot is published.
+	fleet_conditions: FleetConditions,
+	/// The hosts and reference nodes that clients depend on, which are evaluated along with the fleet conditions.
+	dependencies: Dependencies,
+	reload_requested: Arc<AtomicBool>,
+	config_loader: Option<ConfigLoader>,
+	targets_watcher: Option<TargetsWatcher>,
+	asn_database: Option<AsnDatabase>,
+	/// The subscription that posts events to the webhooks in the config, if there are any.
+	webhooks: Option<WebhookSink>,
+	/// The subscription that posts messages to Slack, if it is configured.
+	slack: Option<SlackSink>,
+	/// The subscription that posts embeds to Discord, if it is configured.
+	discord: Option<DiscordSink>,
+	/// The subscription that sends messages to Telegram, if it is configured.
+	telegram: Option<TelegramSink>,
+	/// The subscription that posts messages to a Matrix room, if it is configured.
+	matrix: Option<MatrixSink>,
+	/// The subscription that runs a command for each alert, if it is configured.
+	exec: Option<ExecSink>,
+	/// The subscription that publishes notifications to an ntfy topic, if it is configured.
+	ntfy: Option<NtfySink>,
+	/// The subscription that sends Pushover messages, if it is configured.
+	pushover: Option<PushoverSink>,
+	/// The subscription that sends messages to a Gotify server, if it is configured.
+	gotify: Option<GotifySink>,
+	/// The subscription that forwards alerts to Prometheus Alertmanager, if it is configured.
+	alertmanager: Option<AlertmanagerSink>,
+	/// The alerts that are firing on Alertmanager, which outlive the sink across reloads.
+	firing_alerts: FiringAlerts,
+	/// The subscription that shows native desktop notifications, if they are configured.
+	desktop: Option<DesktopSink>,
+	/// The subscription that publishes every event and the status of each client over MQTT, if it is configured.
+	mqtt: Option<MqttPublisher>,
+	/// The server that exposes Prometheus metrics for each client, if it is configured.
+	metrics: Option<MetricsServer>,
+	/// The exporter that sends traces of the checks and metrics of each client to OpenTelemetry, if it is configured.
+	otlp: Option<OtlpExporter>,
+	/// When the monitor started, which the counters exported to OpenTelemetry are totals since.
+	started: SystemTime,
+	/// The incidents and results of each client since the last digest.
+	digest: DigestRecorder,
+	/// When the next digest is sent, if digests are configured.
+	digest_timer: Option<Timer>,
+	/// The heartbeat that shows the monitor is running, if it is configured.
+	heartbeat: Option<Heartbeat>,
+	/// When a client last reported, which tells whether the monitor is still checking its clients.
+	last_report: Instant,
+}
+
+impl Monitor
+{
+	/// Creates a new `Monitor` for the clients in the config that are included by the filter, and starts checking them.
+	/// This must be called from within a tokio runtime.
+	pub fn new(config: Config, filter: ClientFilter) -> Self
+	{
+		// Each client is checked by its own task, which sends its results back to the monitor.
+		let (reports, report_receiver) = mpsc::channel(REPORT_CHANNEL_CAPACITY);
+		let (events, log_events) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+		ratelimit::set_rate(config.max_requests_per_second);
+		let groups = reconcile_groups(Vec::new(), &config, &filter, &reports, &events);
+		let targets_watcher = config.targets_file.clone().map(|path| TargetsWatcher::new(path, config.targets_refresh_interval));
+		let asn_database = load_asn_database(&config);
+		let (fleet, _) = watch::channel(Arc::new(FleetSnapshot { time: Local::now(), clients: Vec::new() }));
+		let (alerts, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+		let alert_filter = AlertFilter::spawn(config.alert_settings(), &events, alerts.clone(), fleet.subscribe());
+		let held_alerts = HeldAlerts::default();
+		let webhooks = start_webhooks(&config, &alerts, &held_alerts);
+		let slack = start_slack(&config, &alerts, &held_alerts);
+		let discord = start_discord(&config, &alerts, &held_alerts);
+		let telegram = start_telegram(&config, &alerts, &held_alerts);
+		let matrix = start_matrix(&config, &alerts, &held_alerts);
+		let exec = start_exec(&config, &alerts, &held_alerts);
+		let ntfy = start_ntfy(&config, &alerts, &held_alerts);
+		let pushover = start_pushover(&config, &alerts, &held_alerts);
+		let gotify = start_gotify(&config, &alerts, &held_alerts);
+		let firing_alerts = FiringAlerts::default();
+		let alertmanager = start_alertmanager(&config, &alerts, &firing_alerts);
+		let desktop = start_desktop(&config, &alerts, &held_alerts);
+		let mqtt = start_mqtt(&config, &events, &fleet);
+		let metrics = start_metrics(&config, &fleet);
+		let started = SystemTime::now();
+		let otlp = start_otlp(&config, started, &fleet);
+		let digest_timer = config.digest_schedule.clone().map(Timer::new);
+		let heartbeat = start_heartbeat(&config);
+		let fleet_conditions = FleetConditions::new(config.fleet_conditions.clone());
+		let dependencies = Dependencies::new(config.suppress_dependent_alerts);
+
+		let mut monitor = Self
+		{
+			config,
+			filter,
+			groups,
+			reports,
+			report_receiver,
+			events,
+			log_events,
+			alerts,
+			alert_filter,
+			held_alerts,
+			fleet,
+			fleet_changed: false,
+			fleet_conditions,
+			dependencies,
+			reload_requested: Arc::new(AtomicBool::new(false)),
+			config_loader: None,
+			targets_watcher,
+			asn_database,
+			webhooks,
+			slack,
+			discord,
+			telegram,
+			matrix,
+			exec,
+			ntfy,
+			pushover,
+			gotify,
+			alertmanager,
+			firing_alerts,
+			desktop,
+			mqtt,
+			metrics,
+			otlp,
+			started,
+			digest: DigestRecorder::default(),
+			digest_timer,
+			heartbeat,
+			last_report: Instant::now(),
+		};
+		monitor.publish_fleet();
+		monitor
+	}
+
+	/// Sets the function used to load the config when a reload is requested. Without one, reloads are ignored.
+	pub fn set_config_loader<F>(&mut self, loader: F)
+	where
+		F: Fn() -> Result<Config, Box<dyn Error>> + 'static,
+	{
+		self.config_loader = Some(Box::new(loader));
+	}
+
+	/// Returns a flag that requests a config reload when set, such as from a signal handler.
+	pub fn reload_flag(&self) -> Arc<AtomicBool>
+	{
+		self.reload_requested.clone()
+	}
+
+	/// Returns a new subscription to the events the monitor observes, such as clients going offline or lagging.
+	pub fn subscribe(&self) -> broadcast::Receiver<MonitorEvent>
+	{
+		self.events.subscribe()
+	}
+
+	/// Returns a receiver of the fleet snapshot, which is republished at most once per second while clients report.
+	/// Readers never block the monitor, and always see the results of every client from the same moment.
+	pub fn watch(&self) -> watch::Receiver<Arc<FleetSnapshot>>
+	{
+		self.fleet.subscribe()
+	}
+
+	/// Adds a client to the named group, creating the group with the top level settings if it does not exist. Clients
+	/// added this way are replaced by the clients in the config when it is reloaded.
+	pub fn add_client(&mut self, group: &str, settings: ClientSettings)
+	{
+		let number = self.groups.iter().flat_map(|group| group.clients.iter()).map(|client| client.number + 1).max().unwrap_or(0);
+
+		let index = match self.groups.iter().position(|existing| existing.settings.name == group)
+		{
+			Some(index) => index,
+			None =>
+			{
+				self.groups.push(Group::new(self.config.empty_group(group), number, &self.filter, self.reports.clone(), self.events.clone()));
+				tracing::info!("Group {} added.", group);
+				self.groups.len() - 1
+			}
+		};
+
+		self.groups[index].add_client(number, settings);
+		self.publish_fleet();
+	}
+
+	/// Returns the most recent results of every monitored client.
+	pub fn snapshot(&self) -> Vec<ClientSnapshot>
+	{
+		let dependents = dependency::dependent_clients(&self.groups);
+		self.groups.iter().flat_map(|group| group.clients.iter().map(move |client| (group, client))).map(|(group, client)|
+		{
+			ClientSnapshot
+			{
+				number: client.number,
+				group: client.group.clone(),
+				label: client.settings.label.clone(),
+				url: client.settings.url.clone(),
+				reported: client.state.is_some(),
+				health: client.state.map_or(Health::Unknown, |state| state.health),
+				is_online: client.state.is_some_and(|state| state.is_online()),
+				block_number: client.state.map_or(0, |state| state.block_number),
+				tip_hash: client.state.and_then(|state| state.tip_hash),
+				tip_time: client.state.and_then(|state| state.tip_time),
+				epoch: client.state.and_then(|state| state.epoch),
+				peers: client.state.map_or(0, |state| state.peers),
+				connected_peers: client.connected_peers.clone(),
+				peer_churn: client.state.and_then(|state| state.peer_churn),
+				version: client.version.clone(),
+				script_block_number: client.state.and_then(|state| state.script_block_number),
+				time_offline: client.state.and_then(|state| state.time_offline),
+				outages: client.state.map(|state| state.outages).unwrap_or_default(),
+				clock_offset: client.state.and_then(|state| state.clock_offset),
+				propagation: PropagationStats::new(&client.propagation_delays),
+				latency: client.state.and_then(|state| state.latency),
+				latency_stats: client.state.and_then(|state| state.latency_stats),
+				fetch_time: client.state.and_then(|state| state.fetch_time),
+				process: client.state.and_then(|state| state.process),
+				disk: client.state.and_then(|state| state.disk),
+				sync_rate: client.history.sync_rate(),
+				stalled: client.stalled,
+				lag: client.state.filter(|state| state.is_online() && client.settings.has_check(TIP_CHECK)).map(|state| group.tip_height().saturating_sub(state.block_number)),
+				check_errors: client.state.map_or(0, |state| state.check_errors),
+				offline_transitions: client.state.map_or(0, |state| state.offline_transitions),
+				has_parent: dependents.contains(&client.to_string()),
+			}
+		}).collect()
+	}
+
+	/// Returns the recent results of the client with the specified number, oldest first.
+	pub fn history(&self, number: usize) -> Vec<Sample>
+	{
+		self.groups.iter().flat_map(|group| group.clients.iter()).filter(|client| client.number == number).flat_map(|client| client.history.iter().copied()).collect()
+	}
+
+	/// Runs the monitor until the shutdown future completes, then cancels any checks in progress and prints a final
+	/// summary of each group.
+	pub async fn run<F>(&mut self, shutdown: F)
+	where
+		F: Future<Output = ()>,
+	{
+		tokio::pin!(shutdown);
+
+		// Wake on a fixed cadence to run whatever reports are due, and record client results as they arrive in between.
+		let mut ticker = time::interval(SCHEDULER_TICK);
+		ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+		loop
+		{
+			tokio::select!
+			{
+				_ = ticker.tick() => {},
+				Some(report) = self.report_receiver.recv() =>
+				{
+					self.apply_report(report);
+					continue;
+				},
+				result = self.log_events.recv() =>
+				{
+					if let Ok(event) = &result
+					{
+						self.digest.record_event(event);
+					}
+					log_received_event(result);
+					continue;
+				},
+				_ = &mut shutdown => break,
+			}
+
+			// The rounds of checks reported since the last tick are exported as the trace of this cycle.
+			if let Some(otlp) = self.otlp.as_mut()
+			{
+				otlp.end_cycle();
+			}
+
+			// Request a reload if the targets file has changed.
+			if let Some(watcher) = self.targets_watcher.as_mut()
+			{
+				if watcher.poll()
+				{
+					tracing::info!("The targets file has changed. Reloading the config.");
+					self.reload_requested.store(true, Ordering::SeqCst);
+				}
+			}
+
+			if self.reload_requested.swap(false, Ordering::SeqCst)
+			{
+				self.reload();
+			}
+
+			// Run every report that is due in each group.
+			for group in self.groups.iter_mut()
+			{
+				group.report_due(self.asn_database.as_ref());
+			}
+
+			let now = Instant::now();
+			if self.digest_timer.as_ref().is_some_and(|timer| timer.is_due(now))
+			{
+				self.send_digest();
+				if let Some(timer) = self.digest_timer.as_mut()
+				{
+					timer.schedule_next(now);
+				}
+			}
+
+			if self.heartbeat.as_ref().is_some_and(|heartbeat| heartbeat.is_due(now))
+			{
+				let healthy = self.is_healthy(now);
+				if let Some(heartbeat) = self.heartbeat.as_mut()
+				{
+					heartbeat.beat(healthy, now);
+				}
+			}
+
+			if self.fleet_changed
+			{
+				self.publish_fleet();
+				let fleet = self.fleet.borrow().clone();
+				for event in self.fleet_conditions.evaluate(&fleet).into_iter().chain(self.dependencies.evaluate(&self.groups))
+				{
+					let _ = self.events.send(event);
+				}
+			}
+		}
+
+		self.shutdown();
+	}
+
+	/// Reloads the config and updates the groups to match, preserving the state of any client that is unchanged.
+	fn reload(&mut self)
+	{
+		let loader = match &self.config_loader
+		{
+			Some(loader) => loader,
+			None => return,
+		};
+
+		match loader()
+		{
+			Ok(config) =>
+			{
+				ratelimit::set_rate(config.max_requests_per_second);
+				let groups = std::mem::take(&mut self.groups);
+				self.groups = reconcile_groups(groups, &config, &self.filter, &self.reports, &self.events);
+				self.targets_watcher = config.targets_file.clone().map(|path| TargetsWatcher::new(path, config.targets_refresh_interval));
+				self.asn_database = load_asn_database(&config);
+				// The old webhooks are stopped first, so no event is posted twice.
+				drop(self.webhooks.take());
+				drop(self.slack.take());
+				drop(self.discord.take());
+				drop(self.telegram.take());
+				drop(self.matrix.take());
+				drop(self.exec.take());
+				drop(self.ntfy.take());
+				drop(self.pushover.take());
+				drop(self.gotify.take());
+				drop(self.alertmanager.take());
+				drop(self.desktop.take());
+				drop(self.mqtt.take());
+				drop(self.otlp.take());
+				self.alert_filter.update(config.alert_settings());
+				self.fleet_conditions.update(config.fleet_conditions.clone());
+				self.dependencies.set_enabled(config.suppress_dependent_alerts);
+				self.webhooks = start_webhooks(&config, &self.alerts, &self.held_alerts);
+				self.slack = start_slack(&config, &self.alerts, &self.held_alerts);
+				self.discord = start_discord(&config, &self.alerts, &self.held_alerts);
+				self.telegram = start_telegram(&config, &self.alerts, &self.held_alerts);
+				self.matrix = start_matrix(&config, &self.alerts, &self.held_alerts);
+				self.exec = start_exec(&config, &self.alerts, &self.held_alerts);
+				self.ntfy = start_ntfy(&config, &self.alerts, &self.held_alerts);
+				self.pushover = start_pushover(&config, &self.alerts, &self.held_alerts);
+				self.gotify = start_gotify(&config, &self.alerts, &self.held_alerts);
+				self.alertmanager = start_alertmanager(&config, &self.alerts, &self.firing_alerts);
+				self.desktop = start_desktop(&config, &self.alerts, &self.held_alerts);
+				self.mqtt = start_mqtt(&config, &self.events, &self.fleet);
+				self.otlp = start_otlp(&config, self.started, &self.fleet);
+				// The server keeps running while its address is unchanged, since the address could not be bound again until it is freed.
+				if config.metrics_address != self.config.metrics_address || self.metrics.is_none()
+				{
+					drop(self.metrics.take());
+					self.metrics = start_metrics(&config, &self.fleet);
+				}
+				self.digest_timer = match (self.digest_timer.take(), config.digest_schedule.clone())
+				{
+					(Some(mut timer), Some(schedule)) =>
+					{
+						timer.set_schedule(schedule, 0.0);
+						Some(timer)
+					},
+					(_, schedule) => schedule.map(Timer::new),
+				};
+				self.heartbeat = start_heartbeat(&config);
+				self.config = config;
+
+				self.publish_fleet();
+				let client_count: usize = self.groups.iter().map(|group| group.clients.len()).sum();
+				tracing::info!("Config reloaded. Monitoring {} clients in {} groups.", client_count, self.groups.len());
+			},
+			Err(e) =>
+			{
+				tracing::error!("Failed to reload config: {}", e);
+			}
+		}
+	}
+
+	/// Cancels any checks that are in progress, records the results that already arrived, and prints a final summary.
+	fn shutdown(&mut self)
+	{
+		for group in self.groups.iter()
+		{
+			group.stop();
+		}
+		while let Ok(report) = self.report_receiver.try_recv()
+		{
+			self.apply_report(report);
+		}
+		self.publish_fleet();
+		for group in self.groups.iter()
+		{
+			report::summary(group);
+		}
+
+		// Write out every event that has not been logged yet, including the summaries.
+		loop
+		{
+			match self.log_events.try_recv()
+			{
+				Ok(event) => events::log_event(&event),
+				Err(broadcast::error::TryRecvError::Lagged(count)) => tracing::warn!("{} events were not logged because the log fell behind.", count),
+				Err(_) => break,
+			}
+		}
+	}
+
+	/// Records a client report in the group the client belongs to, and in the digest. Reports from removed groups are
+	/// ignored.
+	fn apply_report(&mut self, mut report: ClientReport)
+	{
+		if let Some(group) = self.groups.iter_mut().find(|group| group.settings.name == report.group)
+		{
+			let (url, state, trace) = (report.url.clone(), report.state, report.trace.take());
+			group.apply_report(report);
+			self.fleet_changed = true;
+			self.last_report = Instant::now();
+
+			// Reports from reference nodes are not about a client.
+			if let Some(client) = group.clients.iter().find(|client| client.settings.url == url)
+			{
+				// Only clients with a tip check report the block they are at.
+				let lag = Some(group.tip_height().saturating_sub(state.block_number)).filter(|_| client.settings.has_check(TIP_CHECK)).unwrap_or_default();
+				self.digest.record_report(client.to_string(), Some(lag).filter(|_| state.is_online()));
+				if let (Some(otlp), Some(trace)) = (self.otlp.as_mut(), trace)
+				{
+					otlp.record(&client.to_string(), &client.group, state.health, trace);
+				}
+			}
+		}
+	}
+
+	/// Sends a digest of the period since the last one, listing the clients that are degraded or offline now.
+	fn send_digest(&mut self)
+	{
+		let degraded = self.groups.iter()
+			.flat_map(|group| group.clients.iter())
+			.filter_map(|client| client.state.map(|state| (client.to_string(), state.health)))
+			.filter(|(_, health)| matches!(health, Health::Degraded | Health::Offline))
+			.collect();
+		let _ = self.events.send(self.digest.take(degraded));
+	}
+
+	/// Returns whether the monitor is still checking its clients: it has none, or one has reported within twice the
+	/// longest interval between the checks of any group.
+	fn is_healthy(&self, now: Instant) -> bool
+	{
+		let longest = self.groups.iter().filter(|group| !group.clients.is_empty()).map(|group| group.settings.rpc_interval.period()).max();
+		longest.is_none_or(|longest| now.duration_since(self.last_report) <= longest * 2)
+	}
+
+	/// Publishes a new fleet snapshot to every receiver.
+	fn publish_fleet(&mut self)
+	{
+		self.fleet.send_replace(Arc::new(FleetSnapshot { time: Local::now(), clients: self.snapshot() }));
+		self.fleet_changed = false;
+	}
+}
+
+/// Starts posting events to the webhooks in the config, if there are any.
+fn start_webhooks(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<WebhookSink>
+{
+	if config.webhook_urls.is_empty()
+	{
+		return None;
+	}
+
+	match WebhookSink::spawn(config.webhook_urls.clone(), config.webhook_retry(), config.webhook_mute_recoveries, config.webhook_secret.clone(), QuietQueue::new(config.webhook_quiet_hours.clone(), Sink::Webhooks, held.clone()), config.alert_rate_limit(), alerts.subscribe())
+	{
+		Ok(webhooks) => Some(webhooks),
+		Err(e) =>
+		{
+			tracing::error!("Failed to create an HTTP client for webhooks: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts posting messages to Slack, if it is configured.
+fn start_slack(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<SlackSink>
+{
+	let slack = config.slack.clone()?;
+	match SlackSink::spawn(slack, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(slack) => Some(slack),
+		Err(e) =>
+		{
+			tracing::error!("Failed to create an HTTP client for Slack: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts posting embeds to Discord, if it is configured.
+fn start_discord(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<DiscordSink>
+{
+	let discord = config.discord.clone()?;
+	match DiscordSink::spawn(discord, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(discord) => Some(discord),
+		Err(e) =>
+		{
+			tracing::error!("Failed to create an HTTP client for Discord: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts sending messages to Telegram, if it is configured.
+fn start_telegram(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<TelegramSink>
+{
+	let telegram = config.telegram.clone()?;
+	match TelegramSink::spawn(telegram, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(telegram) => Some(telegram),
+		Err(e) =>
+		{
+			tracing::error!("Failed to create an HTTP client for Telegram: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts posting messages to a Matrix room, if it is configured.
+fn start_matrix(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<MatrixSink>
+{
+	let matrix = config.matrix.clone()?;
+	match MatrixSink::spawn(matrix, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(matrix) => Some(matrix),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start Matrix notifications: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts running a command for each alert, if it is configured.
+fn start_exec(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<ExecSink>
+{
+	let exec = config.exec.clone()?;
+	match ExecSink::spawn(exec, config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(exec) => Some(exec),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start the exec command: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts publishing notifications to an ntfy topic, if it is configured.
+fn start_ntfy(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<NtfySink>
+{
+	let ntfy = config.ntfy.clone()?;
+	match NtfySink::spawn(ntfy, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(ntfy) => Some(ntfy),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start ntfy notifications: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts sending Pushover messages, if it is configured.
+fn start_pushover(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<PushoverSink>
+{
+	let pushover = config.pushover.clone()?;
+	match PushoverSink::spawn(pushover, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(pushover) => Some(pushover),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start Pushover notifications: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts sending messages to a Gotify server, if it is configured.
+fn start_gotify(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<GotifySink>
+{
+	let gotify = config.gotify.clone()?;
+	match GotifySink::spawn(gotify, config.webhook_retry(), config.alert_rate_limit(), held.clone(), alerts.subscribe())
+	{
+		Ok(gotify) => Some(gotify),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start Gotify notifications: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts forwarding alerts to Prometheus Alertmanager, if it is configured.
+fn start_alertmanager(config: &Config, alerts: &broadcast::Sender<Alert>, firing: &FiringAlerts) -> Option<AlertmanagerSink>
+{
+	let alertmanager = config.alertmanager.clone()?;
+	match AlertmanagerSink::spawn(alertmanager, config.webhook_retry(), firing.clone(), alerts.subscribe())
+	{
+		Ok(alertmanager) => Some(alertmanager),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start Alertmanager forwarding: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts publishing events and the status of each client over MQTT, if it is configured.
+fn start_mqtt(config: &Config, events: &broadcast::Sender<MonitorEvent>, fleet: &watch::Sender<Arc<FleetSnapshot>>) -> Option<MqttPublisher>
+{
+	let mqtt = config.mqtt.clone()?;
+	match MqttPublisher::spawn(mqtt, events.subscribe(), fleet.subscribe())
+	{
+		Ok(mqtt) => Some(mqtt),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start publishing to MQTT: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts serving Prometheus metrics for each client, if an address is configured.
+fn start_metrics(config: &Config, fleet: &watch::Sender<Arc<FleetSnapshot>>) -> Option<MetricsServer>
+{
+	let address = config.metrics_address?;
+	match MetricsServer::spawn(address, fleet.subscribe())
+	{
+		Ok(metrics) => Some(metrics),
+		Err(e) =>
+		{
+			tracing::error!("Failed to serve metrics on {}: {}", address, e);
+			None
+		}
+	}
+}
+
+/// Starts exporting traces and metrics to an OpenTelemetry collector, if one is configured.
+fn start_otlp(config: &Config, started: SystemTime, fleet: &watch::Sender<Arc<FleetSnapshot>>) -> Option<OtlpExporter>
+{
+	let otlp = config.otlp.clone()?;
+	match OtlpExporter::spawn(otlp, started, fleet.subscribe())
+	{
+		Ok(otlp) => Some(otlp),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start exporting to OpenTelemetry: {}", e);
+			None
+		}
+	}
+}
+
+/// Starts showing desktop notifications, if they are configured.
+fn start_desktop(config: &Config, alerts: &broadcast::Sender<Alert>, held: &HeldAlerts) -> Option<DesktopSink>
+{
+	let desktop = config.desktop.clone()?;
+	Some(DesktopSink::spawn(desktop, config.alert_rate_limit(), held.clone(), alerts.subscribe()))
+}
+
+/// Starts the heartbeat, if it is configured.
+fn start_heartbeat(config: &Config) -> Option<Heartbeat>
+{
+	let heartbeat = config.heartbeat.as_ref()?;
+	match Heartbeat::new(heartbeat)
+	{
+		Ok(heartbeat) => Some(heartbeat),
+		Err(e) =>
+		{
+			tracing::error!("Failed to start the heartbeat: {}", e);
+			None
+		}
+	}
+}
+
+/// Loads the ASN database named in the config, if any. Peers are only compared by subnet if it cannot be loaded.
+fn load_asn_database(config: &Config) -> Option<AsnDatabase>
+{
+	let path = config.asn_database.as_ref()?;
+	match AsnDatabase::open(path)
+	{
+		Ok(asn_database) => Some(asn_database),
+		Err(e) =>
+		{
+			tracing::warn!("{} Peers will only be compared by subnet.", e);
+			None
+		}
+	}
+}
+
+/// Writes an event received by the log subscription to the log.
+fn log_received_event(result: Result<MonitorEvent, RecvError>)
+{
+	match result
+	{
+		Ok(event) => events::log_event(&event),
+		Err(RecvError::Lagged(count)) => tracing::warn!("{} events were not logged because the log fell behind.", count),
+		Err(RecvError::Closed) => {},
+	}
+}
+
+/// Updates the groups to match the config, preserving the state of any client whose group and endpoint are unchanged.
+fn reconcile_groups(groups: Vec<Group>, config: &Config, filter: &ClientFilter, reports: &mpsc::Sender<ClientReport>, events: &broadcast::Sender<MonitorEvent>) -> Vec<Group>
+{
+	let mut existing_groups: HashMap<String, Group> = groups.into_iter().map(|group| (group.settings.name.clone(), group)).collect();
+	let mut reconciled_groups = Vec::new();
+	let mut next_number = 0;
+
+	for settings in config.groups()
+	{
+		let first_number = next_number;
+		next_number += settings.clients.len();
+
+		let group = match existing_groups.remove(&settings.name)
+		{
+			Some(mut group) =>
+			{
+				group.reconcile(settings, first_number, filter);
+				group
+			},
+			None =>
+			{
+				let group = Group::new(settings, first_number, filter, reports.clone(), events.clone());
+				tracing::info!("Group {} added with {} clients.", group.settings.name, group.clients.len());
+				group
+			}
+		};
+
+		reconciled_groups.push(group);
+	}
+
+	for group in existing_groups.values()
+	{
+		tracing::info!("Group {} removed.", group.settings.name);
+	}
+
+	reconciled_groups
+}
@@ -0,0 +1,190 @@
+use crate::events::MonitorEvent;
+use crate::monitor::{ClientSnapshot, FleetSnapshot};
+use chrono::{DateTime, Local};
+use reqwest::Url;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, Transport};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
+
+/// The prefix of every topic when the config does not say.
+const DEFAULT_MQTT_TOPIC_PREFIX: &str = "ckb/monitor";
+
+/// The client id the monitor connects to the broker with when the config does not say.
+const DEFAULT_MQTT_CLIENT_ID: &str = "ckb-monitor";
+
+/// How many seconds apart the status of every client is published when the config does not say.
+const DEFAULT_MQTT_STATUS_INTERVAL: u64 = 60;
+
+/// The number of messages that can be queued for the broker, such as while reconnecting, before new ones are dropped.
+const MQTT_QUEUE_CAPACITY: usize = 1024;
+
+/// How often the connection to the broker is checked while nothing is published.
+const MQTT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long to wait before reconnecting after the connection to the broker is lost.
+const MQTT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How events and client status are published to an MQTT broker.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig
+{
+	/// The URL of the broker, such as `mqtt://localhost:1883`, or `mqtts://` to connect with TLS.
+	pub broker_url: Option<String>,
+	/// The client id the monitor connects with, which must be unique on the broker. `ckb-monitor` when omitted.
+	pub client_id: Option<String>,
+	pub username: Option<String>,
+	pub password: Option<String>,
+	/// The prefix of every topic. `ckb/monitor` when omitted.
+	pub topic_prefix: Option<String>,
+	/// How many seconds apart the status of every client is published. 60 when omitted.
+	pub status_interval: Option<u64>,
+}
+
+/// Publishes every event to `<prefix>/events/<event>`, and the status of each client to the retained topic
+/// `<prefix>/client/<number>/status` on an interval. The task is stopped, which disconnects from the broker, when this is
+/// dropped.
+pub struct MqttPublisher
+{
+	task: JoinHandle<()>,
+}
+
+impl MqttPublisher
+{
+	/// Starts publishing the events received from `events`, and the status of the clients in the latest snapshot of
+	/// `fleet`. The connection to the broker is retried for as long as the publisher runs.
+	pub fn spawn(config: MqttConfig, mut events: broadcast::Receiver<MonitorEvent>, fleet: watch::Receiver<Arc<FleetSnapshot>>) -> Result<Self, String>
+	{
+		let options = options(&config)?;
+		let prefix = config.topic_prefix.clone().unwrap_or_else(|| DEFAULT_MQTT_TOPIC_PREFIX.to_string());
+		let status_interval = Duration::from_secs(config.status_interval.unwrap_or(DEFAULT_MQTT_STATUS_INTERVAL));
+		let (client, mut eventloop) = AsyncClient::new(options, MQTT_QUEUE_CAPACITY);
+
+		let task = tokio::spawn(async move
+		{
+			let mut ticker = time::interval(status_interval);
+			ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+			loop
+			{
+				tokio::select!
+				{
+					result = events.recv() =>
+					{
+						let event = match result
+						{
+							Ok(event) => event,
+							Err(RecvError::Lagged(count)) =>
+							{
+								tracing::warn!("{} events were not published to MQTT because it fell behind.", count);
+								continue;
+							},
+							Err(RecvError::Closed) => break,
+						};
+						match serde_json::to_value(&event)
+						{
+							Ok(payload) =>
+							{
+								let topic = format!("{}/events/{}", prefix, payload["event"].as_str().unwrap_or_default());
+								publish(&client, topic, false, payload);
+							},
+							Err(e) => tracing::warn!("Failed to serialize an event for MQTT: {}", e),
+						}
+					},
+					_ = ticker.tick() =>
+					{
+						let fleet = fleet.borrow().clone();
+						for snapshot in fleet.clients.iter().filter(|snapshot| snapshot.reported)
+						{
+							publish(&client, format!("{}/client/{}/status", prefix, snapshot.number), true, status(snapshot, fleet.time));
+						}
+					},
+					// The event loop sends what is queued and keeps the connection alive, so it is polled alongside the rest.
+					notification = eventloop.poll() => match notification
+					{
+						Ok(Event::Incoming(Packet::ConnAck(_))) => tracing::info!("Connected to the MQTT broker."),
+						Ok(_) => {},
+						Err(e) =>
+						{
+							tracing::warn!("Lost the connection to the MQTT broker: {}. Reconnecting in {} seconds.", e, MQTT_RECONNECT_DELAY.as_secs());
+							time::sleep(MQTT_RECONNECT_DELAY).await;
+						},
+					},
+				}
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for MqttPublisher
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the options the broker in the config is connected to with.
+pub fn options(config: &MqttConfig) -> Result<MqttOptions, String>
+{
+	let broker_url = config.broker_url.as_deref().ok_or_else(|| "MQTT has no broker URL.".to_string())?;
+	let url = Url::parse(broker_url).map_err(|e| format!("Invalid MQTT broker URL {}: {}", broker_url, e))?;
+	let tls = match url.scheme()
+	{
+		"mqtt" | "tcp" => false,
+		"mqtts" | "ssl" => true,
+		scheme => return Err(format!("MQTT broker URL {} has an unsupported scheme {}. Use mqtt:// or mqtts://.", broker_url, scheme)),
+	};
+	let host = url.host_str().ok_or_else(|| format!("MQTT broker URL {} has no host.", broker_url))?;
+	let port = url.port().unwrap_or(if tls { 8883 } else { 1883 });
+
+	let mut options = MqttOptions::new(config.client_id.clone().unwrap_or_else(|| DEFAULT_MQTT_CLIENT_ID.to_string()), host, port);
+	options.set_keep_alive(MQTT_KEEP_ALIVE);
+	if let Some(username) = &config.username
+	{
+		options.set_credentials(username, config.password.clone().unwrap_or_default());
+	}
+	if tls
+	{
+		options.set_transport(Transport::tls_with_default_config());
+	}
+	Ok(options)
+}
+
+/// Queues a message for the broker. Messages that do not fit in the queue, such as during a long outage of the broker,
+/// are dropped rather than holding up the monitor.
+fn publish(client: &AsyncClient, topic: String, retain: bool, payload: Value)
+{
+	if let Err(e) = client.try_publish(topic.as_str(), QoS::AtLeastOnce, retain, payload.to_string())
+	{
+		tracing::debug!("Dropped a message to MQTT topic {}: {}", topic, e);
+	}
+}
+
+/// Returns the status published for a client, as of the time of the snapshot it is from.
+fn status(client: &ClientSnapshot, time: DateTime<Local>) -> Value
+{
+	json!(
+	{
+		"client": format!("{}/{}", client.group, client.label.clone().unwrap_or_else(|| client.number.to_string())),
+		"group": client.group,
+		"label": client.label,
+		"url": client.url,
+		"health": client.health,
+		"online": client.is_online,
+		"block_number": client.block_number,
+		"peers": client.peers,
+		"version": client.version,
+		"latency_ms": client.latency.map(|latency| latency.as_millis() as u64),
+		"sync_rate": client.sync_rate,
+		"stalled": client.stalled,
+		"timestamp": time.to_rfc3339(),
+	})
+}
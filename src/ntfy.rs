@@ -0,0 +1,230 @@
+use crate::alerts::{Alert, AlertLimiter, RateLimit, Sink};
+use crate::events::Severity;
+use crate::matrix;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// The public ntfy server, which is used when the config does not name one.
+pub const DEFAULT_NTFY_SERVER: &str = "https://ntfy.sh";
+
+/// How long the server can take to accept a notification before the attempt fails.
+const NTFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How ntfy notifications are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NtfyConfig
+{
+	/// The URL of the ntfy server. `https://ntfy.sh` when omitted.
+	pub server_url: Option<String>,
+	/// The topic notifications are published to. Anyone who knows a topic on a public server can read it, so it should be
+	/// hard to guess.
+	pub topic: Option<String>,
+	/// The access token used when the topic is protected.
+	pub token: Option<String>,
+	/// The ntfy priority of notifications of each severity, from 1 (min) to 5 (max), such as `critical = 5`. Severities
+	/// that are not listed use their default priority: 3 for info, 4 for warning, and 5 for critical.
+	#[serde(default)]
+	pub priorities: HashMap<Severity, u8>,
+	/// The least severe notifications that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether notifications that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe notifications are sent straight away, and the rest are held
+	/// until the next digest. Every notification is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+impl NtfyConfig
+{
+	/// Returns the priority of notifications of a severity.
+	pub fn priority(&self, severity: Severity) -> u8
+	{
+		match self.priorities.get(&severity)
+		{
+			Some(priority) => *priority,
+			None => match severity
+			{
+				Severity::Info => 3,
+				Severity::Warning => 4,
+				Severity::Critical => 5,
+			},
+		}
+	}
+}
+
+/// A notification published to a topic.
+#[derive(Clone, Debug)]
+struct Notification
+{
+	title: String,
+	message: String,
+	priority: u8,
+	tags: &'static str,
+}
+
+/// Publishes notifications to an ntfy topic when clients change state and when summaries are made. The task is stopped
+/// when this is dropped.
+pub struct NtfySink
+{
+	task: JoinHandle<()>,
+}
+
+impl NtfySink
+{
+	/// Starts publishing notifications for the alerts received from `alerts`, retrying failed deliveries according to
+	/// the retry policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: NtfyConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		let topic = match &config.topic
+		{
+			Some(topic) => topic,
+			None => return Err("ntfy needs a topic.".into()),
+		};
+		let topic_url = topic_url(config.server_url.as_deref().unwrap_or(DEFAULT_NTFY_SERVER), topic)?;
+		let client = reqwest::Client::builder().timeout(NTFY_TIMEOUT).build()?;
+
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Ntfy, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Ntfy, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let notification = Notification
+						{
+							title: "Alerts suppressed".to_string(),
+							message: limiter.summary_text(suppressed),
+							priority: config.priority(Severity::Warning),
+							tags: tags(Severity::Warning, false),
+						};
+						tokio::spawn(deliver(client.clone(), topic_url.clone(), config.token.clone(), notification, retry.clone()));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to ntfy because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Ntfy) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let notification = match notification(&alert, &config)
+				{
+					Some(notification) => notification,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				tokio::spawn(deliver(client.clone(), topic_url.clone(), config.token.clone(), notification, retry.clone()));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for NtfySink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the URL that notifications are published to on a topic.
+pub fn topic_url(server_url: &str, topic: &str) -> Result<Url, String>
+{
+	let mut url = Url::parse(server_url).map_err(|e| format!("Invalid ntfy server URL {}: {}", server_url, e))?;
+	url.path_segments_mut()
+		.map_err(|_| format!("Invalid ntfy server URL {}.", server_url))?
+		.pop_if_empty()
+		.push(topic);
+	Ok(url)
+}
+
+/// Returns the notification published for an alert, or `None` for alerts that are not sent to ntfy. The same events
+/// are sent as to Matrix, titled with the client or group they are about and the type of event.
+fn notification(alert: &Alert, config: &NtfyConfig) -> Option<Notification>
+{
+	let message = match &alert.text
+	{
+		Some(text) => text.clone(),
+		None => matrix::message(&alert.event).map(|(body, _)| body)?,
+	};
+
+	Some(Notification { title: alert.title()?, message, priority: config.priority(alert.severity), tags: tags(alert.severity, alert.is_recovery()) })
+}
+
+/// Returns the ntfy tags of a notification, which the ntfy apps show as an emoji before its title.
+fn tags(severity: Severity, is_recovery: bool) -> &'static str
+{
+	match (severity, is_recovery)
+	{
+		(_, true) => "white_check_mark",
+		(Severity::Info, false) => "information_source",
+		(Severity::Warning, false) => "warning",
+		(Severity::Critical, false) => "rotating_light",
+	}
+}
+
+/// Publishes a notification to a topic, retrying transport failures and error statuses according to the retry policy.
+async fn deliver(client: reqwest::Client, topic_url: Url, token: Option<String>, notification: Notification, retry: RetryPolicy)
+{
+	let mut attempt = 0;
+	loop
+	{
+		let mut request = client.post(topic_url.clone())
+			.header("Title", notification.title.as_str())
+			.header("Priority", notification.priority.to_string())
+			.header("Tags", notification.tags)
+			.body(notification.message.clone());
+		if let Some(token) = &token
+		{
+			request = request.bearer_auth(token);
+		}
+
+		let error = match request.send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send a notification to ntfy: {}", error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending a notification to ntfy failed: {}. Retrying in {} ms.", error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
@@ -0,0 +1,371 @@
+use crate::health::Health;
+use crate::monitor::{ClientSnapshot, FleetSnapshot};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
+
+/// The `service.name` the monitor reports when the config does not say.
+const DEFAULT_OTLP_SERVICE_NAME: &str = "ckb-light-client-monitor";
+
+/// How many seconds apart metrics are exported when the config does not say.
+const DEFAULT_OTLP_METRICS_INTERVAL: u64 = 60;
+
+/// How often the spans recorded since the last export are sent to the collector.
+const OTLP_TRACES_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The most spans sent in a single export. Spans are sent early once this many have been recorded.
+const OTLP_MAX_BATCH_SPANS: usize = 512;
+
+/// The number of traces that can be queued for export before new ones are dropped.
+const OTLP_QUEUE_CAPACITY: usize = 1024;
+
+/// How long the collector can take to accept an export before it fails.
+const OTLP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The kind of the spans of a cycle of the scheduler and of the rounds of checks within it, which are internal to the
+/// monitor.
+const SPAN_KIND_INTERNAL: u8 = 1;
+
+/// The kind of the span of a check, which calls the client.
+const SPAN_KIND_CLIENT: u8 = 3;
+
+/// The status code of a span that failed.
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// The aggregation temporality of counters, which report their total since they started.
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+/// How traces and metrics are exported to an OpenTelemetry collector.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConfig
+{
+	/// The base URL of the OTLP/HTTP receiver of the collector, such as `http://localhost:4318`. Traces are posted to
+	/// `/v1/traces` and metrics to `/v1/metrics` under it.
+	pub endpoint: Option<String>,
+	/// Additional headers sent with every export, such as the API key of a hosted collector.
+	#[serde(default)]
+	pub headers: HashMap<String, String>,
+	/// The `service.name` the monitor reports. `ckb-light-client-monitor` when omitted.
+	pub service_name: Option<String>,
+	/// How many seconds apart metrics are exported. 60 when omitted.
+	pub metrics_interval: Option<u64>,
+}
+
+/// The timing of a check, exported as a span of the round of checks it was run in.
+#[derive(Clone, Debug)]
+pub struct CheckSpan
+{
+	pub name: &'static str,
+	pub start: SystemTime,
+	pub end: SystemTime,
+	/// Why the check failed, or `None` if it succeeded.
+	pub error: Option<String>,
+}
+
+/// The timing of a round of checks on a client, exported as a span of the trace of the cycle of the scheduler it was
+/// recorded in.
+#[derive(Clone, Debug)]
+pub struct CycleTrace
+{
+	/// The number of the round since the client started being checked.
+	pub cycle: u64,
+	pub start: SystemTime,
+	pub end: SystemTime,
+	/// The checks that were run in the round, in the order they were run.
+	pub checks: Vec<CheckSpan>,
+}
+
+/// A round of checks on a client that is waiting for the cycle of the scheduler it was recorded in to end.
+struct ClientRound
+{
+	client: String,
+	group: String,
+	health: Health,
+	trace: CycleTrace,
+}
+
+/// Exports each cycle of the scheduler as a trace, with a span for the round of checks on each client that reported in
+/// it and a span for each check, and the latest results of each client as metrics, to an OpenTelemetry collector over
+/// OTLP/HTTP. The task is stopped when this is dropped.
+pub struct OtlpExporter
+{
+	traces: mpsc::Sender<Vec<Value>>,
+	/// The rounds of checks recorded in the current cycle of the scheduler.
+	rounds: Vec<ClientRound>,
+	/// The number of cycles of the scheduler that have been exported.
+	cycle: u64,
+	task: JoinHandle<()>,
+}
+
+impl OtlpExporter
+{
+	/// Starts exporting the rounds of checks that are recorded, and the metrics of the clients in the latest snapshot of
+	/// `fleet`. Counters are reported as totals since `started`, which is kept across reloads.
+	pub fn spawn(config: OtlpConfig, started: SystemTime, fleet: watch::Receiver<Arc<FleetSnapshot>>) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		let endpoint = match &config.endpoint
+		{
+			Some(endpoint) => endpoint,
+			None => return Err("OpenTelemetry export needs an endpoint.".into()),
+		};
+		let traces_url = export_url(endpoint, "traces")?;
+		let metrics_url = export_url(endpoint, "metrics")?;
+		let resource = resource(config.service_name.as_deref().unwrap_or(DEFAULT_OTLP_SERVICE_NAME));
+		let metrics_interval = Duration::from_secs(config.metrics_interval.unwrap_or(DEFAULT_OTLP_METRICS_INTERVAL));
+		let client = reqwest::Client::builder().timeout(OTLP_TIMEOUT).build()?;
+		let (traces, mut trace_receiver) = mpsc::channel::<Vec<Value>>(OTLP_QUEUE_CAPACITY);
+
+		let task = tokio::spawn(async move
+		{
+			let mut spans = Vec::new();
+			let mut traces_ticker = time::interval(OTLP_TRACES_INTERVAL);
+			traces_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+			let mut metrics_ticker = time::interval(metrics_interval);
+			metrics_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+			loop
+			{
+				tokio::select!
+				{
+					received = trace_receiver.recv() =>
+					{
+						match received
+						{
+							Some(trace) => spans.extend(trace),
+							None => break,
+						}
+						if spans.len() < OTLP_MAX_BATCH_SPANS
+						{
+							continue;
+						}
+					},
+					_ = traces_ticker.tick() =>
+					{
+						if spans.is_empty()
+						{
+							continue;
+						}
+					},
+					_ = metrics_ticker.tick() =>
+					{
+						let snapshot = fleet.borrow().clone();
+						let payload = json!({ "resourceMetrics": [{ "resource": resource, "scopeMetrics": [{ "scope": scope(), "metrics": metrics(&snapshot, started) }] }] });
+						tokio::spawn(export(client.clone(), metrics_url.clone(), config.headers.clone(), payload, "metrics"));
+						continue;
+					},
+				}
+
+				let payload = json!({ "resourceSpans": [{ "resource": resource, "scopeSpans": [{ "scope": scope(), "spans": std::mem::take(&mut spans) }] }] });
+				tokio::spawn(export(client.clone(), traces_url.clone(), config.headers.clone(), payload, "traces"));
+			}
+		});
+
+		Ok(Self { traces, rounds: Vec::new(), cycle: 0, task })
+	}
+
+	/// Records a round of checks on a client, to be exported with the trace of the current cycle of the scheduler.
+	pub fn record(&mut self, client: &str, group: &str, health: Health, trace: CycleTrace)
+	{
+		self.rounds.push(ClientRound { client: client.to_string(), group: group.to_string(), health, trace });
+	}
+
+	/// Ends the current cycle of the scheduler, queuing the rounds of checks recorded in it to be exported as one trace.
+	/// Nothing is exported for a cycle in which no client reported, and the trace is dropped if the queue is full.
+	pub fn end_cycle(&mut self)
+	{
+		if self.rounds.is_empty()
+		{
+			return;
+		}
+
+		self.cycle += 1;
+		match self.traces.try_send(spans(self.cycle, std::mem::take(&mut self.rounds)))
+		{
+			Ok(()) | Err(TrySendError::Closed(_)) => {},
+			Err(TrySendError::Full(_)) => tracing::debug!("The trace of cycle {} was not exported because the queue is full.", self.cycle),
+		}
+	}
+}
+
+impl Drop for OtlpExporter
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the URL that a kind of signal, such as `traces`, is posted to on a collector.
+pub fn export_url(endpoint: &str, signal: &str) -> Result<Url, String>
+{
+	let mut url = Url::parse(endpoint).map_err(|e| format!("Invalid OpenTelemetry endpoint {}: {}", endpoint, e))?;
+	url.path_segments_mut()
+		.map_err(|_| format!("Invalid OpenTelemetry endpoint {}.", endpoint))?
+		.pop_if_empty()
+		.extend(["v1", signal]);
+	Ok(url)
+}
+
+/// Posts an export to the collector. Failed exports are not retried, since newer data soon follows.
+async fn export(client: reqwest::Client, url: Url, headers: HashMap<String, String>, payload: Value, signal: &str)
+{
+	let mut request = client.post(url).json(&payload);
+	for (name, value) in headers.iter()
+	{
+		request = request.header(name, value);
+	}
+	if let Err(e) = request.send().await.and_then(|response| response.error_for_status())
+	{
+		tracing::warn!("Failed to export {} to the OpenTelemetry collector: {}", signal, e);
+	}
+}
+
+/// Returns the spans of the trace of a cycle of the scheduler: one for the cycle, one for the round of checks on each
+/// client within it, and one for each check within those.
+fn spans(cycle: u64, rounds: Vec<ClientRound>) -> Vec<Value>
+{
+	let trace_id = format!("{:032x}", rand::random::<u128>());
+	let cycle_span_id = span_id();
+	let start = rounds.iter().map(|round| round.trace.start).min().unwrap_or_else(SystemTime::now);
+	let end = rounds.iter().map(|round| round.trace.end).max().unwrap_or(start);
+
+	let mut spans = vec![json!(
+	{
+		"traceId": trace_id,
+		"spanId": cycle_span_id,
+		"name": "cycle",
+		"kind": SPAN_KIND_INTERNAL,
+		"startTimeUnixNano": unix_nanos(start),
+		"endTimeUnixNano": unix_nanos(end),
+		"attributes":
+		[
+			attribute("ckb.cycle", json!({ "intValue": cycle.to_string() })),
+			attribute("ckb.clients", json!({ "intValue": rounds.len().to_string() })),
+		],
+	})];
+	for ClientRound { client, group, health, trace } in rounds
+	{
+		let round_span_id = span_id();
+		let failed = trace.checks.iter().filter(|check| check.error.is_some()).count();
+		spans.push(json!(
+		{
+			"traceId": trace_id,
+			"spanId": round_span_id,
+			"parentSpanId": cycle_span_id,
+			"name": "checks",
+			"kind": SPAN_KIND_INTERNAL,
+			"startTimeUnixNano": unix_nanos(trace.start),
+			"endTimeUnixNano": unix_nanos(trace.end),
+			"attributes":
+			[
+				attribute("ckb.client", json!({ "stringValue": client })),
+				attribute("ckb.group", json!({ "stringValue": group })),
+				attribute("ckb.client_cycle", json!({ "intValue": trace.cycle.to_string() })),
+				attribute("ckb.health", json!({ "stringValue": health.to_string() })),
+			],
+			"status": if failed > 0 { json!({ "code": STATUS_CODE_ERROR, "message": format!("{} of {} checks failed", failed, trace.checks.len()) }) } else { json!({}) },
+		}));
+		for check in trace.checks
+		{
+			spans.push(json!(
+			{
+				"traceId": trace_id,
+				"spanId": span_id(),
+				"parentSpanId": round_span_id,
+				"name": check.name,
+				"kind": SPAN_KIND_CLIENT,
+				"startTimeUnixNano": unix_nanos(check.start),
+				"endTimeUnixNano": unix_nanos(check.end),
+				"attributes":
+				[
+					attribute("ckb.client", json!({ "stringValue": client })),
+					attribute("ckb.group", json!({ "stringValue": group })),
+				],
+				"status": match check.error
+				{
+					Some(error) => json!({ "code": STATUS_CODE_ERROR, "message": error }),
+					None => json!({}),
+				},
+			}));
+		}
+	}
+	spans
+}
+
+/// Returns the metrics of every client that has reported, as of a snapshot. Counters are totals since `started`.
+fn metrics(snapshot: &FleetSnapshot, started: SystemTime) -> Vec<Value>
+{
+	let clients = snapshot.clients.iter().filter(|client| client.reported).collect::<Vec<_>>();
+	let time = unix_nanos(snapshot.time.into());
+	let start = unix_nanos(started);
+	vec!
+	[
+		json!({ "name": "ckb.light_client.online", "description": "Whether the client is online.", "unit": "1", "gauge": { "dataPoints": data_points(&clients, &time, None, |client| Some(if client.is_online { 1.0 } else { 0.0 })) } }),
+		json!({ "name": "ckb.light_client.peer_count", "description": "The number of peers the client is connected to.", "unit": "{peer}", "gauge": { "dataPoints": data_points(&clients, &time, None, |client| Some(client.peers as f64)) } }),
+		json!({ "name": "ckb.light_client.tip_block_number", "description": "The block number of the tip of the client.", "unit": "1", "gauge": { "dataPoints": data_points(&clients, &time, None, |client| Some(client.block_number as f64)) } }),
+		json!({ "name": "ckb.light_client.lag", "description": "How many blocks the client is behind the tip of its group.", "unit": "{block}", "gauge": { "dataPoints": data_points(&clients, &time, None, |client| client.lag.map(|lag| lag as f64)) } }),
+		json!({ "name": "ckb.light_client.rpc_latency", "description": "How long the client took to respond to its last RPC request.", "unit": "s", "gauge": { "dataPoints": data_points(&clients, &time, None, |client| client.latency.map(|latency| latency.as_secs_f64())) } }),
+		json!({ "name": "ckb.light_client.offline_transitions", "description": "The number of times the client has gone offline.", "unit": "1", "sum": { "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE, "isMonotonic": true, "dataPoints": data_points(&clients, &time, Some(&start), |client| Some(client.offline_transitions as f64)) } }),
+		json!({ "name": "ckb.light_client.check_errors", "description": "The number of checks that have failed or found the client unresponsive.", "unit": "1", "sum": { "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE, "isMonotonic": true, "dataPoints": data_points(&clients, &time, Some(&start), |client| Some(client.check_errors as f64)) } }),
+	]
+}
+
+/// Returns a data point for each client that has a value for a metric, labeled with the client and its group.
+fn data_points(clients: &[&ClientSnapshot], time: &str, start: Option<&str>, value: impl Fn(&ClientSnapshot) -> Option<f64>) -> Vec<Value>
+{
+	clients.iter().filter_map(|client|
+	{
+		let mut point = json!(
+		{
+			"attributes":
+			[
+				attribute("ckb.client", json!({ "stringValue": client.name() })),
+				attribute("ckb.group", json!({ "stringValue": client.group })),
+			],
+			"timeUnixNano": time,
+			"asDouble": value(client)?,
+		});
+		if let Some(start) = start
+		{
+			point["startTimeUnixNano"] = json!(start);
+		}
+		Some(point)
+	}).collect()
+}
+
+/// Returns the resource the monitor reports as, identified by its service name.
+fn resource(service_name: &str) -> Value
+{
+	json!({ "attributes": [attribute("service.name", json!({ "stringValue": service_name }))] })
+}
+
+/// Returns the instrumentation scope of everything the monitor exports.
+fn scope() -> Value
+{
+	json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") })
+}
+
+/// Returns an attribute with a value in the form of OTLP, such as `{ "stringValue": "mainnet" }`.
+fn attribute(key: &str, value: Value) -> Value
+{
+	json!({ "key": key, "value": value })
+}
+
+/// Returns a new random span id.
+fn span_id() -> String
+{
+	format!("{:016x}", rand::random::<u64>())
+}
+
+/// Returns a time as the number of nanoseconds since the Unix epoch, as a string so it keeps its precision in JSON.
+fn unix_nanos(time: SystemTime) -> String
+{
+	time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
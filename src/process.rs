@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::process::Command;
+
+/// The number of clock ticks per second that CPU times in `/proc` are counted in. This is `USER_HZ`, which is 100 on
+/// every mainstream Linux platform.
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// How the operating system process of a locally run client is found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProcessSource
+{
+	/// A fixed process id.
+	Pid(u32),
+	/// A systemd unit, whose main process is looked up each time, so restarts are followed.
+	SystemdUnit(String),
+}
+
+impl fmt::Display for ProcessSource
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			ProcessSource::Pid(pid) => write!(f, "pid {}", pid),
+			ProcessSource::SystemdUnit(unit) => write!(f, "unit {}", unit),
+		}
+	}
+}
+
+/// The resource usage of a client process.
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessStats
+{
+	pub pid: u32,
+	/// The resident set size, in bytes.
+	pub rss_bytes: u64,
+	/// The percentage of one CPU the process used since the previous sample, or `None` on the first sample.
+	pub cpu_percent: Option<f64>,
+	pub open_files: usize,
+}
+
+/// The CPU time a process had used at a point in time, used to calculate its CPU usage between samples.
+#[derive(Clone, Copy, Debug)]
+pub struct CpuSample
+{
+	pid: u32,
+	ticks: u64,
+	time: Instant,
+}
+
+/// Reads the resource usage of a process from `/proc`, calculating its CPU usage from the previous sample if it is of
+/// the same process. Returns the stats along with the sample to pass next time.
+pub async fn sample(source: &ProcessSource, previous: Option<CpuSample>) -> Result<(ProcessStats, CpuSample), Box<dyn Error>>
+{
+	let pid = match source
+	{
+		ProcessSource::Pid(pid) => *pid,
+		ProcessSource::SystemdUnit(unit) => systemd_main_pid(unit).await?,
+	};
+	let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+
+	let status = fs::read_to_string(proc_dir.join("status")).await.map_err(|e| format!("Unable to read the status of process {}: {}", pid, e))?;
+	let rss_kb = status.lines()
+		.find_map(|line| line.strip_prefix("VmRSS:"))
+		.and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+		.unwrap_or_default();
+
+	// The command name in the stat file can contain spaces, so the fields are counted from after it.
+	let stat = fs::read_to_string(proc_dir.join("stat")).await.map_err(|e| format!("Unable to read the CPU time of process {}: {}", pid, e))?;
+	let fields = stat.rsplit_once(')').map(|(_, fields)| fields.split_whitespace().collect::<Vec<_>>()).unwrap_or_default();
+	let ticks = match (fields.get(11).and_then(|utime| utime.parse::<u64>().ok()), fields.get(12).and_then(|stime| stime.parse::<u64>().ok()))
+	{
+		(Some(utime), Some(stime)) => utime + stime,
+		_ => return Err(format!("Unable to parse the CPU time of process {}.", pid).into()),
+	};
+
+	let mut open_files = 0;
+	let mut entries = fs::read_dir(proc_dir.join("fd")).await.map_err(|e| format!("Unable to list the open files of process {}: {}", pid, e))?;
+	while entries.next_entry().await?.is_some()
+	{
+		open_files += 1;
+	}
+
+	let current = CpuSample { pid, ticks, time: Instant::now() };
+	let cpu_percent = previous.filter(|previous| previous.pid == pid).map(|previous|
+	{
+		let elapsed = current.time.duration_since(previous.time).max(Duration::from_millis(1)).as_secs_f64();
+		current.ticks.saturating_sub(previous.ticks) as f64 / CLOCK_TICKS_PER_SECOND / elapsed * 100.0
+	});
+
+	let stats = ProcessStats
+	{
+		pid,
+		rss_bytes: rss_kb * 1024,
+		cpu_percent,
+		open_files,
+	};
+	Ok((stats, current))
+}
+
+/// Returns the main process id of a systemd unit.
+async fn systemd_main_pid(unit: &str) -> Result<u32, Box<dyn Error>>
+{
+	let output = Command::new("systemctl").args(["show", "--property", "MainPID", "--value", unit]).output().await?;
+	let pid = String::from_utf8_lossy(&output.stdout).trim().parse::<u32>().map_err(|e| format!("Unable to read the main pid of unit {}: {}", unit, e))?;
+	if pid == 0
+	{
+		return Err(format!("Unit {} is not running.", unit).into());
+	}
+	Ok(pid)
+}
@@ -0,0 +1,42 @@
+use crate::history::RingBuffer;
+use serde::Serialize;
+use std::time::Duration;
+
+/// The number of recent new blocks that propagation statistics are calculated from.
+pub const PROPAGATION_WINDOW: usize = 100;
+
+/// Statistics of how long after the first client in its group a client reported each new block.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct PropagationStats
+{
+	/// The number of new blocks the statistics were calculated from.
+	pub blocks: usize,
+	/// The median delay.
+	pub p50: Duration,
+	/// The 90th percentile, which only the slowest 10% of new blocks exceed.
+	pub p90: Duration,
+	pub max: Duration,
+}
+
+impl PropagationStats
+{
+	/// Calculates the statistics of the delays in a window, or returns `None` if it is empty.
+	pub fn new(window: &RingBuffer<Duration>) -> Option<Self>
+	{
+		let mut delays = window.iter().copied().collect::<Vec<_>>();
+		if delays.is_empty()
+		{
+			return None;
+		}
+		delays.sort();
+
+		let percentile = |percent: usize| delays[(delays.len() * percent).div_ceil(100) - 1];
+		Some(Self
+		{
+			blocks: delays.len(),
+			p50: percentile(50),
+			p90: percentile(90),
+			max: delays[delays.len() - 1],
+		})
+	}
+}
@@ -0,0 +1,216 @@
+use crate::alerts::{Alert, AlertLimiter, RateLimit, Sink};
+use crate::events::Severity;
+use crate::matrix;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// The endpoint of the Pushover messages API.
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+/// How long Pushover can take to accept a message before the attempt fails.
+const PUSHOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest message Pushover accepts, in characters. Longer messages are cut short.
+const PUSHOVER_MAX_MESSAGE: usize = 1024;
+
+/// The Pushover priority of emergency messages, which repeat until they are acknowledged.
+const EMERGENCY_PRIORITY: i8 = 2;
+
+/// How many seconds apart an emergency message is repeated when the config does not say.
+const DEFAULT_PUSHOVER_RETRY: u64 = 60;
+
+/// How many seconds an emergency message keeps repeating when the config does not say.
+const DEFAULT_PUSHOVER_EXPIRE: u64 = 3600;
+
+/// How Pushover notifications are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PushoverConfig
+{
+	/// The API token of the Pushover application that sends the messages.
+	pub app_token: Option<String>,
+	/// The key of the user or group the messages are sent to.
+	pub user_key: Option<String>,
+	/// The Pushover priority of messages of each severity, from -2 (lowest) to 2 (emergency), such as `warning = 1`.
+	/// Severities that are not listed use their default priority: 0 for info and warning, and 2 for critical.
+	#[serde(default)]
+	pub priorities: HashMap<Severity, i8>,
+	/// How many seconds apart an emergency message is repeated until it is acknowledged, at least 30. 60 when omitted.
+	pub retry: Option<u64>,
+	/// How many seconds an emergency message keeps repeating if it is not acknowledged, at most 10,800. 3,600 when omitted.
+	pub expire: Option<u64>,
+	/// The least severe messages that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether messages that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe messages are sent straight away, and the rest are held
+	/// until the next digest. Every message is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+impl PushoverConfig
+{
+	/// Returns the priority of messages of a severity.
+	pub fn priority(&self, severity: Severity) -> i8
+	{
+		match self.priorities.get(&severity)
+		{
+			Some(priority) => *priority,
+			None => match severity
+			{
+				Severity::Info | Severity::Warning => 0,
+				Severity::Critical => EMERGENCY_PRIORITY,
+			},
+		}
+	}
+
+	/// Returns the form fields of a message with a title and a priority, including how often and for how long it repeats
+	/// if it is an emergency.
+	fn fields(&self, title: String, message: String, priority: i8) -> Vec<(&'static str, String)>
+	{
+		let mut fields = vec!
+		[
+			("token", self.app_token.clone().unwrap_or_default()),
+			("user", self.user_key.clone().unwrap_or_default()),
+			("title", title),
+			("message", message.chars().take(PUSHOVER_MAX_MESSAGE).collect()),
+			("priority", priority.to_string()),
+		];
+		if priority >= EMERGENCY_PRIORITY
+		{
+			fields.push(("retry", self.retry.unwrap_or(DEFAULT_PUSHOVER_RETRY).to_string()));
+			fields.push(("expire", self.expire.unwrap_or(DEFAULT_PUSHOVER_EXPIRE).to_string()));
+		}
+		fields
+	}
+}
+
+/// Sends Pushover messages when clients change state and when summaries are made. The task is stopped when this is
+/// dropped.
+pub struct PushoverSink
+{
+	task: JoinHandle<()>,
+}
+
+impl PushoverSink
+{
+	/// Starts sending messages for the alerts received from `alerts`, retrying failed deliveries according to the retry
+	/// policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: PushoverConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, Box<dyn std::error::Error>>
+	{
+		if config.app_token.is_none() || config.user_key.is_none()
+		{
+			return Err("Pushover needs an app token and a user key.".into());
+		}
+		let client = reqwest::Client::builder().timeout(PUSHOVER_TIMEOUT).build()?;
+
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Pushover, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Pushover, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let fields = config.fields("Alerts suppressed".to_string(), limiter.summary_text(suppressed), config.priority(Severity::Warning));
+						tokio::spawn(deliver(client.clone(), fields, retry.clone()));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to Pushover because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Pushover) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let (title, message) = match message(&alert)
+				{
+					Some(message) => message,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				// Recoveries end an emergency rather than being one.
+				let mut priority = config.priority(alert.severity);
+				if alert.is_recovery()
+				{
+					priority = priority.min(EMERGENCY_PRIORITY - 1);
+				}
+				tokio::spawn(deliver(client.clone(), config.fields(title, message, priority), retry.clone()));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for PushoverSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the title and text of the message sent for an alert, or `None` for alerts that are not sent to Pushover.
+/// The same events are sent as to Matrix, titled with the client or group they are about and the type of event.
+fn message(alert: &Alert) -> Option<(String, String)>
+{
+	let message = match &alert.text
+	{
+		Some(text) => text.clone(),
+		None => matrix::message(&alert.event).map(|(body, _)| body)?,
+	};
+
+	Some((alert.title()?, message))
+}
+
+/// Sends a message to Pushover, retrying transport failures and error statuses according to the retry policy.
+async fn deliver(client: reqwest::Client, fields: Vec<(&'static str, String)>, retry: RetryPolicy)
+{
+	let mut attempt = 0;
+	loop
+	{
+		let error = match client.post(PUSHOVER_URL).form(&fields).send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send a message to Pushover: {}", error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending a message to Pushover failed: {}. Retrying in {} ms.", error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
@@ -0,0 +1,125 @@
+use crate::alerts::{Alert, Sink};
+use crate::digest;
+use crate::events::{MonitorEvent, Severity};
+use crate::matrix;
+use chrono::{Local, NaiveTime};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The most alerts held during quiet hours that are listed in a digest. The rest are only counted.
+const MAX_HELD_ALERTS: usize = 50;
+
+/// Hours of the day during which a destination only sends the most severe alerts straight away, and holds the rest
+/// until the next digest.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHours
+{
+	/// When quiet hours start each day, in local time, such as `"22:00"`.
+	pub start: NaiveTime,
+	/// When quiet hours end each day, in local time, such as `"07:00"`. Quiet hours that end before they start run past
+	/// midnight.
+	pub end: NaiveTime,
+	/// The least severe alerts that are still sent straight away during quiet hours.
+	#[serde(default = "default_quiet_severity")]
+	pub min_severity: Severity,
+}
+
+fn default_quiet_severity() -> Severity
+{
+	Severity::Critical
+}
+
+impl QuietHours
+{
+	/// Returns whether a time of day is within quiet hours.
+	pub fn contains(&self, time: NaiveTime) -> bool
+	{
+		if self.start <= self.end
+		{
+			self.start <= time && time < self.end
+		}
+		else
+		{
+			time >= self.start || time < self.end
+		}
+	}
+}
+
+/// The text of each alert held during quiet hours by each destination, with the time it was received. It is kept apart
+/// from the destinations, so the alerts they hold are not lost when they are restarted by a reload.
+#[derive(Clone, Debug, Default)]
+pub struct HeldAlerts(Arc<Mutex<HashMap<Sink, Vec<String>>>>);
+
+/// Holds the alerts a destination receives during its quiet hours, and adds them to the next digest it sends.
+pub struct QuietQueue
+{
+	hours: Option<QuietHours>,
+	sink: Sink,
+	held: HeldAlerts,
+}
+
+impl QuietQueue
+{
+	/// Creates a queue for a destination, which holds nothing if it has no quiet hours. The alerts it holds are kept in
+	/// `held`, along with any the destination held before it was restarted.
+	pub fn new(hours: Option<QuietHours>, sink: Sink, held: HeldAlerts) -> Self
+	{
+		Self { hours, sink, held }
+	}
+
+	/// Returns the alert to send now, or `None` if it is not wanted or is held until the next digest. A digest is sent
+	/// with the alerts held since the last one, even when the destination would otherwise leave it out.
+	pub fn admit(&mut self, mut alert: Alert, wanted: bool) -> Option<Alert>
+	{
+		if let MonitorEvent::Digest { .. } = &alert.event
+		{
+			let held = self.take_held();
+			if held.is_empty()
+			{
+				return wanted.then_some(alert);
+			}
+			let text = alert.text.take().or_else(|| digest::lines(&alert.event).map(|lines| lines.join("\n")))?;
+			alert.text = Some(format!("{}\n{}", text, held_text(&held)));
+			return Some(alert);
+		}
+		if !wanted
+		{
+			return None;
+		}
+
+		let now = Local::now();
+		match &self.hours
+		{
+			Some(hours) if hours.contains(now.time()) && alert.severity < hours.min_severity =>
+			{
+				let text = alert.text.clone().or_else(|| matrix::message(&alert.event).map(|(body, _)| body)).or_else(|| alert.title())?;
+				let mut held = self.held.0.lock().unwrap();
+				held.entry(self.sink).or_default().push(format!("{} {}", now.format("%H:%M"), text));
+				None
+			},
+			_ => Some(alert),
+		}
+	}
+
+	/// Returns the alerts held since the last digest, and forgets them.
+	fn take_held(&self) -> Vec<String>
+	{
+		let mut held = self.held.0.lock().unwrap();
+		held.remove(&self.sink).unwrap_or_default()
+	}
+}
+
+/// Returns the text listing the alerts held since the last digest.
+fn held_text(held: &[String]) -> String
+{
+	let plural = if held.len() == 1 { "" } else { "s" };
+	let mut lines = vec![format!("{} alert{} held during quiet hours:", held.len(), plural)];
+	lines.extend(held.iter().take(MAX_HELD_ALERTS).cloned());
+	if held.len() > MAX_HELD_ALERTS
+	{
+		lines.push(format!("...and {} more.", held.len() - MAX_HELD_ALERTS));
+	}
+	lines.join("\n")
+}
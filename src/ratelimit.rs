@@ -0,0 +1,69 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// The token bucket shared by every RPC request the monitor sends.
+static LIMITER: OnceLock<Mutex<TokenBucket>> = OnceLock::new();
+
+/// A token bucket that refills at a fixed rate and holds up to one second of tokens.
+struct TokenBucket
+{
+	/// The number of requests allowed per second, or `None` for no limit.
+	rate: Option<f64>,
+	tokens: f64,
+	last_refill: Instant,
+}
+
+impl TokenBucket
+{
+	/// Takes a token if one is available, or returns how long to wait until one will be.
+	fn try_take(&mut self) -> Option<Duration>
+	{
+		let rate = self.rate?;
+
+		let now = Instant::now();
+		let capacity = rate.max(1.0);
+		self.tokens = (self.tokens + (now - self.last_refill).as_secs_f64() * rate).min(capacity);
+		self.last_refill = now;
+
+		if self.tokens >= 1.0
+		{
+			self.tokens -= 1.0;
+			return None;
+		}
+
+		Some(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+	}
+}
+
+/// Returns the shared token bucket, creating it without a limit on first use.
+fn limiter() -> &'static Mutex<TokenBucket>
+{
+	LIMITER.get_or_init(|| Mutex::new(TokenBucket { rate: None, tokens: 0.0, last_refill: Instant::now() }))
+}
+
+/// Sets the maximum number of RPC requests per second across all clients. `None` removes the limit.
+pub fn set_rate(requests_per_second: Option<f64>)
+{
+	let mut bucket = limiter().lock().unwrap();
+	if bucket.rate != requests_per_second
+	{
+		bucket.rate = requests_per_second.filter(|rate| *rate > 0.0);
+		bucket.tokens = bucket.rate.unwrap_or_default().max(1.0);
+		bucket.last_refill = Instant::now();
+	}
+}
+
+/// Waits until the rate limit allows another RPC request to be sent.
+pub async fn acquire()
+{
+	loop
+	{
+		let wait = limiter().lock().unwrap().try_take();
+		match wait
+		{
+			Some(wait) => time::sleep(wait).await,
+			None => return,
+		}
+	}
+}
@@ -0,0 +1,394 @@
+use crate::checks::{GENESIS_CHECK, PEERS_CHECK, RPC_CHECK, TIP_CHECK};
+use crate::client::ClientState;
+use crate::diversity::{self, AsnDatabase};
+use crate::events::MonitorEvent;
+use crate::group::Group;
+use crate::health::Health;
+use crate::propagation::PropagationStats;
+use crate::rpc::H256;
+use crate::version::Version;
+use crate::worker::ClientHandle;
+use num_format::ToFormattedString;
+use rand::seq::SliceRandom;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Reports every lagging client in a group, along with an estimate of how long it will take to catch up, and reports
+/// any whose estimate has grown since the last report.
+pub fn lag(group: &mut Group)
+{
+	let tip_height = group.tip_height();
+	let tip_rate = group.tip_rate().unwrap_or_default();
+	let lag_threshold = group.lag_threshold();
+	let mut events = Vec::new();
+
+	for client in group.clients.iter_mut().filter(|client| client.settings.has_check(TIP_CHECK))
+	{
+		let state = match client.state
+		{
+			Some(state) if state.is_online() && lag_threshold.is_lagging(state.block_number, client.settings.max_block_diff) => state,
+			_ =>
+			{
+				client.eta_minutes = None;
+				continue;
+			}
+		};
+
+		// The client only catches up by however much faster it syncs than the chain grows.
+		let blocks_behind = tip_height.saturating_sub(state.block_number);
+		let eta_minutes = client.history.sync_rate()
+			.map(|sync_rate| sync_rate - tip_rate)
+			.filter(|catch_up_rate| *catch_up_rate > 0.0)
+			.map(|catch_up_rate| blocks_behind as f64 / catch_up_rate);
+
+		events.push(MonitorEvent::Lagging { client: client.to_string(), block_number: state.block_number, blocks_behind, eta_minutes });
+		if let Some(previous_eta_minutes) = client.eta_minutes
+		{
+			if !eta_minutes.is_some_and(|eta_minutes| eta_minutes <= previous_eta_minutes)
+			{
+				events.push(MonitorEvent::EtaGrowing { client: client.to_string(), blocks_behind, eta_minutes, previous_eta_minutes });
+			}
+		}
+		client.eta_minutes = eta_minutes;
+	}
+
+	for event in events
+	{
+		group.emit(event);
+	}
+}
+
+/// Reports every height at which clients in a group have different tip hashes, naming the clients on each side.
+pub fn forks(group: &Group)
+{
+	let mut heights: BTreeMap<u64, BTreeMap<H256, Vec<String>>> = BTreeMap::new();
+	for (client, state) in reported_clients(group).filter(|(client, state)| state.is_online() && client.settings.has_check(TIP_CHECK))
+	{
+		if let Some(tip_hash) = state.tip_hash
+		{
+			heights.entry(state.block_number).or_default().entry(tip_hash).or_default().push(client.name());
+		}
+	}
+
+	for (block_number, branches) in heights.into_iter().filter(|(_, branches)| branches.len() > 1)
+	{
+		group.emit(MonitorEvent::Fork
+		{
+			group: group.settings.name.clone(),
+			block_number,
+			branches: branches.into_iter().map(|(hash, clients)| (hash.to_string(), clients)).collect(),
+		});
+	}
+}
+
+/// Audits the clients in a group against each other at a random recent height, comparing the tip hashes they reported
+/// when they were at that height with the hash of the reference nodes, or of the majority of clients if no reference
+/// node reported it.
+pub fn audit(group: &Group)
+{
+	let heights = group.recorded_tips.iter()
+		.filter(|(_, tips)| tips.clients.len() + usize::from(tips.reference.is_some()) >= 2)
+		.collect::<Vec<_>>();
+	let (block_number, tips) = match heights.choose(&mut rand::thread_rng())
+	{
+		Some((block_number, tips)) => (**block_number, *tips),
+		None => return,
+	};
+
+	let expected = match tips.reference
+	{
+		Some(reference) => reference,
+		None =>
+		{
+			let mut counts: BTreeMap<H256, usize> = BTreeMap::new();
+			for tip_hash in tips.clients.values()
+			{
+				*counts.entry(*tip_hash).or_default() += 1;
+			}
+			match counts.into_iter().find(|(_, count)| count * 2 > tips.clients.len())
+			{
+				Some((tip_hash, _)) => tip_hash,
+				None => return,
+			}
+		},
+	};
+
+	let outliers = tips.clients.iter()
+		.filter(|(_, tip_hash)| **tip_hash != expected)
+		.map(|(client, tip_hash)| (client.clone(), tip_hash.to_string()))
+		.collect::<Vec<_>>();
+	if outliers.is_empty()
+	{
+		tracing::debug!("Clients in group {} agree on block {}.", group.settings.name, block_number);
+		return;
+	}
+
+	group.emit(MonitorEvent::HeaderMismatch { group: group.settings.name.clone(), block_number, expected: expected.to_string(), outliers });
+}
+
+/// Reports every epoch that clients in a group disagree about, naming the clients on each side. Clients in the same
+/// epoch must agree on the block it starts at and how many blocks it has.
+pub fn epochs(group: &Group)
+{
+	let mut epochs: BTreeMap<u64, BTreeMap<(u64, u64), Vec<String>>> = BTreeMap::new();
+	for (client, state) in reported_clients(group).filter(|(client, state)| state.is_online() && client.settings.has_check(TIP_CHECK))
+	{
+		if let Some(epoch) = state.epoch.filter(|epoch| epoch.index <= state.block_number)
+		{
+			epochs.entry(epoch.number).or_default().entry((state.block_number - epoch.index, epoch.length)).or_default().push(client.name());
+		}
+	}
+
+	for (epoch, views) in epochs.into_iter().filter(|(_, views)| views.len() > 1)
+	{
+		group.emit(MonitorEvent::EpochMismatch
+		{
+			group: group.settings.name.clone(),
+			epoch,
+			views: views.into_iter().map(|((start, length), clients)| (format!("starting at block {} with {} blocks", start.to_formatted_string(&num_format::Locale::en), length.to_formatted_string(&num_format::Locale::en)), clients)).collect(),
+		});
+	}
+}
+
+/// Reports the clients in a group below their minimum peer count and a summary of clients with 0 or 1 peers.
+pub fn peers(group: &Group)
+{
+	let clients = reported_clients(group).filter(|(client, state)| state.is_online() && client.settings.has_check(PEERS_CHECK));
+	for (client, state) in clients.clone()
+	{
+		if state.peers < client.settings.min_peers
+		{
+			group.emit(MonitorEvent::LowPeers { client: client.to_string(), peers: state.peers, min_peers: client.settings.min_peers });
+		}
+	}
+
+	let mut peer_0_clients = Vec::new();
+	let mut peer_1_clients = Vec::new();
+	for (client, state) in clients
+	{
+		if state.peers == 0
+		{
+			peer_0_clients.push(client.name());
+		}
+		else if state.peers == 1
+		{
+			peer_1_clients.push(client.name());
+		}
+	}
+	if !peer_0_clients.is_empty() || !peer_1_clients.is_empty()
+	{
+		group.emit(MonitorEvent::PeerSummary { group: group.settings.name.clone(), no_peers: peer_0_clients, one_peer: peer_1_clients });
+	}
+}
+
+/// Reports every remote peer that a large enough fraction of the clients in a group are connected to, since losing that
+/// one peer would cut all of them off at once.
+pub fn shared_peers(group: &Group)
+{
+	let clients = reported_clients(group).filter(|(client, state)| state.is_online() && client.settings.has_check(PEERS_CHECK)).map(|(client, _)| client).collect::<Vec<_>>();
+	if clients.len() < 2
+	{
+		return;
+	}
+
+	let mut peers: BTreeMap<&str, (Option<&str>, Vec<String>)> = BTreeMap::new();
+	for client in clients.iter().copied()
+	{
+		for peer in client.connected_peers.iter()
+		{
+			let (_, peer_clients) = peers.entry(peer.node_id.as_str()).or_insert_with(|| (peer.address(), Vec::new()));
+			peer_clients.push(client.name());
+		}
+	}
+
+	for (node_id, (address, peer_clients)) in peers
+	{
+		if peer_clients.len() > 1 && peer_clients.len() as f64 >= clients.len() as f64 * group.settings.shared_peer_fraction
+		{
+			group.emit(MonitorEvent::SharedPeer
+			{
+				group: group.settings.name.clone(),
+				node_id: node_id.to_string(),
+				address: address.map(str::to_string),
+				total: clients.len(),
+				clients: peer_clients,
+			});
+		}
+	}
+}
+
+/// Reports every client in a group whose peers are all in the same subnet or autonomous system, since they could all be
+/// run or cut off by a single party.
+pub fn peer_diversity(group: &Group, asn_database: Option<&AsnDatabase>)
+{
+	for (client, _) in reported_clients(group).filter(|(client, state)| state.is_online() && client.settings.has_check(PEERS_CHECK))
+	{
+		let ips = client.connected_peers.iter().filter_map(|peer| peer.address().and_then(diversity::peer_ip)).collect::<Vec<_>>();
+		if let Some(network) = diversity::shared_network(&ips, asn_database)
+		{
+			group.emit(MonitorEvent::LowPeerDiversity { client: client.to_string(), peers: ips.len(), network: network.to_string() });
+		}
+	}
+}
+
+/// Reports a summary of the clients in a group that are offline.
+pub fn offline(group: &Group)
+{
+	let offline_clients = reported_clients(group).filter(|(client, state)| client.settings.has_check(RPC_CHECK) && state.health == Health::Offline).map(|(client, _)| client.name()).collect::<Vec<_>>();
+	if !offline_clients.is_empty()
+	{
+		group.emit(MonitorEvent::OfflineSummary { group: group.settings.name.clone(), clients: offline_clients });
+	}
+}
+
+/// Reports every client in a group that takes new tips later or sooner after their timestamps than the reference nodes
+/// do, or than the median client if no reference node has taken one, by more than the maximum clock skew. Lagging
+/// clients are left out, since they take old tips while they catch up.
+pub fn clock_skew(group: &Group)
+{
+	let max_clock_skew = group.settings.max_clock_skew.as_millis() as i64;
+	if max_clock_skew == 0
+	{
+		return;
+	}
+
+	let mut reference_offsets = group.references.iter().filter_map(|reference| reference.state.and_then(|state| state.clock_offset)).collect::<Vec<_>>();
+	reference_offsets.sort();
+	let mut client_offsets = reported_clients(group).filter(|(client, state)| state.is_online() && !client.is_lagging()).filter_map(|(_, state)| state.clock_offset).collect::<Vec<_>>();
+	client_offsets.sort();
+	let (baseline, source) = match (reference_offsets.get(reference_offsets.len() / 2), client_offsets.get(client_offsets.len() / 2))
+	{
+		(Some(offset), _) => (*offset, "the reference nodes"),
+		(None, Some(offset)) => (*offset, "the other clients"),
+		(None, None) => return,
+	};
+
+	for (client, state) in reported_clients(group).filter(|(client, state)| state.is_online() && !client.is_lagging())
+	{
+		if let Some(skew) = state.clock_offset.map(|offset| offset - baseline).filter(|skew| skew.num_milliseconds().abs() > max_clock_skew)
+		{
+			group.emit(MonitorEvent::ClockSkew { client: client.to_string(), skew_seconds: skew.num_milliseconds() as f64 / 1000.0, baseline: source.to_string() });
+		}
+	}
+}
+
+/// Reports how long after the first client in a group each client learns of new blocks, starting with the clients that
+/// are slowest at the 90th percentile.
+pub fn propagation(group: &Group)
+{
+	let mut clients = group.clients.iter().filter_map(|client| PropagationStats::new(&client.propagation_delays).map(|stats| (client.name(), stats))).collect::<Vec<_>>();
+	if clients.is_empty()
+	{
+		return;
+	}
+
+	clients.sort_by_key(|client| Reverse(client.1.p90));
+	group.emit(MonitorEvent::PropagationSummary { group: group.settings.name.clone(), clients });
+}
+
+/// Reports the outages each client in a group has recovered from, starting with the clients that were down the longest.
+pub fn outages(group: &Group)
+{
+	let mut clients = reported_clients(group).filter(|(_, state)| state.outages.outages > 0).map(|(client, state)| (client.name(), state.outages)).collect::<Vec<_>>();
+	if clients.is_empty()
+	{
+		return;
+	}
+
+	clients.sort_by_key(|client| Reverse(client.1.total_downtime));
+	group.emit(MonitorEvent::OutageSummary { group: group.settings.name.clone(), clients });
+}
+
+/// Reports every client in a group running a version older than the minimum version of the group, or a different
+/// version than the majority of the group.
+pub fn versions(group: &Group)
+{
+	let min_version = group.settings.min_version.as_deref().and_then(Version::parse);
+	let versions = group.clients.iter().filter_map(|client| client.version.as_deref().and_then(Version::parse).map(|version| (client, version))).collect::<Vec<_>>();
+
+	let mut counts: BTreeMap<&Version, usize> = BTreeMap::new();
+	for (_, version) in versions.iter()
+	{
+		*counts.entry(version).or_default() += 1;
+	}
+	let majority = counts.into_iter().find(|(_, count)| *count * 2 > versions.len()).map(|(version, _)| version);
+
+	for (client, version) in versions.iter()
+	{
+		if let Some(min_version) = min_version.as_ref().filter(|min_version| version < *min_version)
+		{
+			group.emit(MonitorEvent::OutdatedVersion { client: client.to_string(), version: version.to_string(), min_version: min_version.to_string() });
+		}
+		if let Some(majority) = majority.filter(|majority| version != *majority)
+		{
+			group.emit(MonitorEvent::VersionMismatch { client: client.to_string(), version: version.to_string(), majority: majority.to_string() });
+		}
+	}
+}
+
+/// Reports every client in a group whose genesis block differs from the genesis hash of the group, or from the majority
+/// of the group when no genesis hash is configured, since it is on a different network.
+pub fn networks(group: &Group)
+{
+	let clients = reported_clients(group)
+		.filter(|(client, _)| client.settings.has_check(GENESIS_CHECK))
+		.filter_map(|(client, state)| state.genesis_hash.map(|genesis_hash| (client, genesis_hash)))
+		.collect::<Vec<_>>();
+
+	let expected = match group.settings.genesis_hash
+	{
+		Some(genesis_hash) => genesis_hash,
+		None =>
+		{
+			let mut counts: BTreeMap<H256, usize> = BTreeMap::new();
+			for (_, genesis_hash) in clients.iter()
+			{
+				*counts.entry(*genesis_hash).or_default() += 1;
+			}
+			match counts.into_iter().find(|(_, count)| *count * 2 > clients.len())
+			{
+				Some((genesis_hash, _)) => genesis_hash,
+				None => return,
+			}
+		}
+	};
+
+	for (client, genesis_hash) in clients.into_iter().filter(|(_, genesis_hash)| *genesis_hash != expected)
+	{
+		group.emit(MonitorEvent::WrongNetwork { client: client.to_string(), genesis_hash: genesis_hash.to_string(), expected: expected.to_string() });
+	}
+}
+
+/// Returns the number of clients in a group running each version, most common first.
+fn version_counts(group: &Group) -> Vec<(String, usize)>
+{
+	let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+	for version in group.clients.iter().filter_map(|client| client.version.as_deref())
+	{
+		*counts.entry(version).or_default() += 1;
+	}
+
+	let mut counts = counts.into_iter().map(|(version, count)| (version.to_string(), count)).collect::<Vec<_>>();
+	counts.sort_by_key(|count| Reverse(count.1));
+	counts
+}
+
+/// Reports a final summary of a group, including when each offline client went offline.
+pub fn summary(group: &Group)
+{
+	group.emit(MonitorEvent::GroupSummary
+	{
+		group: group.settings.name.clone(),
+		online: reported_clients(group).filter(|(_, state)| state.is_online()).count(),
+		total: group.clients.len(),
+		highest_block_number: group.highest_block_number,
+		offline_since: reported_clients(group).filter_map(|(client, state)| state.time_offline.map(|time_offline| (client.to_string(), time_offline))).collect(),
+		versions: version_counts(group),
+	});
+}
+
+/// Returns the clients in a group that have reported the results of their checks, along with those results.
+fn reported_clients(group: &Group) -> impl Iterator<Item = (&ClientHandle, ClientState)> + Clone
+{
+	group.clients.iter().filter_map(|client| client.state.map(|state| (client, state)))
+}
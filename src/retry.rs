@@ -0,0 +1,33 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// How failed RPC requests are retried before a failure is reported.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy
+{
+	/// The number of times a failed request is retried. A value of 0 disables retries.
+	pub attempts: u32,
+	/// The delay before the first retry. Each following retry waits twice as long as the one before.
+	pub initial_backoff: Duration,
+	/// The longest delay between retries.
+	pub max_backoff: Duration,
+	/// The fraction of each delay that is randomized, from 0.0 to 1.0, so retries from many clients do not line up.
+	pub jitter: f64,
+}
+
+impl RetryPolicy
+{
+	/// Returns the delay before the specified retry, where 0 is the first retry.
+	pub fn delay(&self, retry: u32) -> Duration
+	{
+		let backoff = self.initial_backoff.saturating_mul(2u32.saturating_pow(retry)).min(self.max_backoff);
+		if self.jitter <= 0.0
+		{
+			return backoff;
+		}
+
+		let jitter = self.jitter.min(1.0);
+		let factor = rand::thread_rng().gen_range((1.0 - jitter)..=(1.0 + jitter));
+		backoff.mul_f64(factor)
+	}
+}
@@ -0,0 +1,540 @@
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// The fields an object returned by the RPC is documented to have, whether or not the monitor uses them, along with the
+/// shapes of the nested objects that are checked too. Strict schema validation compares results with these to notice
+/// when a new release of the client changes its output.
+pub struct Shape
+{
+	pub fields: &'static [(&'static str, Option<&'static Shape>)],
+}
+
+/// A type parsed from an RPC result whose shape is known, so the result can be validated in strict mode.
+pub trait Schema
+{
+	fn shape() -> &'static Shape;
+}
+
+impl<T: Schema> Schema for Vec<T>
+{
+	fn shape() -> &'static Shape
+	{
+		T::shape()
+	}
+}
+
+static LOCAL_NODE_INFO: Shape = Shape { fields: &[("version", None), ("node_id", None), ("active", None), ("addresses", Some(&NODE_ADDRESS)), ("protocols", None), ("connections", None)] };
+static PEER: Shape = Shape { fields: &[("version", None), ("node_id", None), ("addresses", Some(&NODE_ADDRESS)), ("connected_duration", None), ("sync_state", None), ("protocols", Some(&PEER_PROTOCOL))] };
+static PEER_PROTOCOL: Shape = Shape { fields: &[("id", None), ("version", None)] };
+static NODE_ADDRESS: Shape = Shape { fields: &[("address", None), ("score", None)] };
+static HEADER_VIEW: Shape = Shape { fields: &[("compact_target", None), ("dao", None), ("epoch", None), ("extra_hash", None), ("hash", None), ("nonce", None), ("number", None), ("parent_hash", None), ("proposals_hash", None), ("timestamp", None), ("transactions_root", None), ("version", None)] };
+static BLOCK_VIEW: Shape = Shape { fields: &[("header", Some(&HEADER_VIEW)), ("uncles", None), ("transactions", None), ("proposals", None), ("extension", None)] };
+static FETCH_STATUS: Shape = Shape { fields: &[("status", None), ("data", None), ("first_sent", None), ("timestamp", None)] };
+static CELLS_PAGE: Shape = Shape { fields: &[("objects", None), ("last_cursor", None)] };
+static SCRIPT_STATUS: Shape = Shape { fields: &[("script", Some(&SCRIPT)), ("script_type", None), ("block_number", None)] };
+static SCRIPT: Shape = Shape { fields: &[("code_hash", None), ("hash_type", None), ("args", None)] };
+
+impl Schema for LocalNodeInfo
+{
+	fn shape() -> &'static Shape
+	{
+		&LOCAL_NODE_INFO
+	}
+}
+
+impl Schema for Peer
+{
+	fn shape() -> &'static Shape
+	{
+		&PEER
+	}
+}
+
+impl Schema for HeaderView
+{
+	fn shape() -> &'static Shape
+	{
+		&HEADER_VIEW
+	}
+}
+
+impl Schema for BlockView
+{
+	fn shape() -> &'static Shape
+	{
+		&BLOCK_VIEW
+	}
+}
+
+impl Schema for FetchStatus
+{
+	fn shape() -> &'static Shape
+	{
+		&FETCH_STATUS
+	}
+}
+
+impl Schema for CellsPage
+{
+	fn shape() -> &'static Shape
+	{
+		&CELLS_PAGE
+	}
+}
+
+impl Schema for ScriptStatus
+{
+	fn shape() -> &'static Shape
+	{
+		&SCRIPT_STATUS
+	}
+}
+
+/// The information a node returns about itself from the `local_node_info` RPC call.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LocalNodeInfo
+{
+	pub node_id: String,
+	pub version: String,
+}
+
+/// A peer the node is connected to, as returned by the `get_peers` RPC call.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Peer
+{
+	pub node_id: String,
+	/// The version of the software the peer is running.
+	#[serde(default)]
+	pub version: String,
+	#[serde(default)]
+	pub addresses: Vec<NodeAddress>,
+	/// How long the peer has been connected, in milliseconds.
+	#[serde(default, deserialize_with = "hex_u64")]
+	pub connected_duration: u64,
+	/// The protocols the node has opened with the peer.
+	#[serde(default)]
+	pub protocols: Vec<PeerProtocol>,
+}
+
+impl Peer
+{
+	/// Returns the first address the peer is known by, if any.
+	pub fn address(&self) -> Option<&str>
+	{
+		self.addresses.first().map(|address| address.address.as_str())
+	}
+
+	/// Returns true if the peer is the one described by a node id, one of its addresses, or any multiaddr ending in
+	/// `/p2p/` followed by its node id.
+	pub fn matches(&self, expected: &str) -> bool
+	{
+		expected == self.node_id
+			|| self.addresses.iter().any(|address| address.address == expected)
+			|| expected.strip_suffix(self.node_id.as_str()).is_some_and(|prefix| prefix.ends_with("/p2p/"))
+	}
+}
+
+/// A protocol a node has opened with a peer, along with the version of it they agreed on.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PeerProtocol
+{
+	#[serde(deserialize_with = "hex_u64")]
+	pub id: u64,
+	pub version: String,
+}
+
+/// A network address of a peer, such as `/ip4/1.2.3.4/tcp/8115`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NodeAddress
+{
+	pub address: String,
+}
+
+/// A block header, as returned by the `get_tip_header` RPC call.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HeaderView
+{
+	pub hash: H256,
+	/// The hash of the previous block, which the fetch check asks for when no probe is configured.
+	#[serde(default)]
+	pub parent_hash: Option<H256>,
+	#[serde(deserialize_with = "hex_u64")]
+	pub number: u64,
+	/// When the block was mined, in milliseconds since the Unix epoch.
+	#[serde(deserialize_with = "hex_u64")]
+	pub timestamp: u64,
+	/// The epoch of the block, along with its position in it, packed as an epoch number with fraction.
+	#[serde(default, deserialize_with = "hex_u64")]
+	pub epoch: u64,
+}
+
+impl HeaderView
+{
+	/// Returns the epoch of the block.
+	pub fn epoch(&self) -> Epoch
+	{
+		Epoch::from_packed(self.epoch)
+	}
+}
+
+/// The epoch a block is in, along with the position of the block in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Epoch
+{
+	pub number: u64,
+	/// The position of the block in the epoch, starting from 0.
+	pub index: u64,
+	/// The number of blocks in the epoch.
+	pub length: u64,
+}
+
+impl Epoch
+{
+	/// Unpacks an epoch number with fraction, which holds the number in the lowest 24 bits, the index in the next 16,
+	/// and the length in the 16 after that.
+	pub fn from_packed(packed: u64) -> Self
+	{
+		Self
+		{
+			number: packed & 0xff_ffff,
+			index: (packed >> 24) & 0xffff,
+			length: (packed >> 40) & 0xffff,
+		}
+	}
+}
+
+impl fmt::Display for Epoch
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{} ({}/{})", self.number, self.index, self.length)
+	}
+}
+
+/// A block, as returned by the `get_genesis_block` RPC call. Only the header is kept.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockView
+{
+	pub header: HeaderView,
+}
+
+/// The progress of a `fetch_header` or `fetch_transaction` RPC call. The status is `fetched` once the data has been
+/// retrieved, `fetching` or `added` while it is in progress, and `not_found` if no peer has it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FetchStatus
+{
+	pub status: String,
+}
+
+/// A page of cells, as returned by the `get_cells` RPC call. The cells themselves are not used, so they are not parsed.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CellsPage
+{
+	pub objects: Vec<Value>,
+}
+
+/// A script, as used in the scripts a light client is set to filter for.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Script
+{
+	pub code_hash: H256,
+	pub hash_type: String,
+	pub args: String,
+}
+
+impl fmt::Display for Script
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{} ({}) {}", self.code_hash, self.hash_type, self.args)
+	}
+}
+
+/// A script a light client is filtering for, as returned by the `get_scripts` RPC call.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScriptStatus
+{
+	pub script: Script,
+	/// Whether the script is used as a `lock` or `type` script.
+	pub script_type: String,
+	/// The block number the client has synced the script to.
+	#[serde(deserialize_with = "hex_u64")]
+	pub block_number: u64,
+}
+
+/// A 32 byte hash, such as a block hash, given in the CKB RPC as a `0x` prefixed hexadecimal string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct H256(pub [u8; 32]);
+
+impl fmt::Display for H256
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "0x")?;
+		for byte in self.0.iter()
+		{
+			write!(f, "{:02x}", byte)?;
+		}
+		Ok(())
+	}
+}
+
+impl<'de> Deserialize<'de> for H256
+{
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+	}
+}
+
+impl FromStr for H256
+{
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err>
+	{
+		let digits = value.strip_prefix("0x").ok_or_else(|| format!("missing 0x prefix: {}", value))?;
+		if digits.len() != 64
+		{
+			return Err(format!("expected 32 bytes: {}", value));
+		}
+		// Checking every digit first keeps the slices below on character boundaries, and rejects the sign that
+		// `from_str_radix` would otherwise accept.
+		if !digits.bytes().all(|byte| byte.is_ascii_hexdigit())
+		{
+			return Err(format!("invalid hex hash: {}", value));
+		}
+
+		let mut bytes = [0u8; 32];
+		for (index, byte) in bytes.iter_mut().enumerate()
+		{
+			*byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).map_err(|e| format!("invalid hex hash {}: {}", value, e))?;
+		}
+		Ok(Self(bytes))
+	}
+}
+
+/// A single JSON-RPC response, holding either a result or an error.
+#[derive(Debug, Deserialize)]
+struct Response
+{
+	#[serde(default)]
+	result: Option<Value>,
+	#[serde(default)]
+	error: Option<RpcError>,
+}
+
+/// The error member of a JSON-RPC response.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RpcError
+{
+	pub code: i64,
+	pub message: String,
+	/// Further details of the error, such as the underlying cause reported by the node.
+	#[serde(default)]
+	pub data: Option<Value>,
+}
+
+impl RpcError
+{
+	/// Returns the meaning of the error code if it is a standard JSON-RPC code or one of the codes returned by CKB
+	/// nodes and light clients.
+	pub fn name(&self) -> Option<&'static str>
+	{
+		let name = match self.code
+		{
+			-32700 => "parse error",
+			-32600 => "invalid request",
+			-32601 => "method not found",
+			-32602 => "invalid params",
+			-32603 => "internal error",
+			-1 => "CKB internal error",
+			-2 => "deprecated",
+			-3 => "invalid",
+			-4 => "RPC module is disabled",
+			-5 => "DAO error",
+			-6 => "integer overflow",
+			-7 => "config error",
+			-101 => "P2P failed to broadcast",
+			-200 => "database error",
+			-201 => "chain index is inconsistent",
+			-202 => "database is corrupt",
+			-301 => "transaction failed to resolve",
+			-302 => "transaction failed to verify",
+			_ => return None,
+		};
+		Some(name)
+	}
+
+	/// Returns true if the error means the node does not serve the method at all, rather than failing to serve it.
+	pub fn is_unsupported(&self) -> bool
+	{
+		matches!(self.code, -32601 | -4)
+	}
+}
+
+impl fmt::Display for RpcError
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "RPC error {}", self.code)?;
+		if let Some(name) = self.name()
+		{
+			write!(f, " ({})", name)?;
+		}
+		write!(f, ": {}", self.message)?;
+		match &self.data
+		{
+			None | Some(Value::Null) => Ok(()),
+			Some(Value::String(data)) => write!(f, " ({})", data),
+			Some(data) => write!(f, " ({})", data),
+		}
+	}
+}
+
+/// Why the result of a JSON-RPC response could not be used.
+#[derive(Clone, Debug)]
+pub enum ResponseError
+{
+	/// The node returned an error instead of a result.
+	Rpc(RpcError),
+	/// There was no response, or it was not in the expected format.
+	Invalid(String),
+	/// The result has fields that are not part of its known shape, listed by their paths.
+	SchemaDrift(Vec<String>),
+}
+
+impl fmt::Display for ResponseError
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Self::Rpc(e) => write!(f, "{}", e),
+			Self::Invalid(e) => write!(f, "{}", e),
+			Self::SchemaDrift(fields) => write!(f, "schema drift: unexpected fields {}", fields.join(", ")),
+		}
+	}
+}
+
+/// Returns the path of every field in a result that is not part of its shape, such as `result[].sync_state.foo`. The
+/// elements of arrays are checked against the same shape, and each unknown field is only listed once.
+pub fn unknown_fields(result: &Value, shape: &Shape) -> Vec<String>
+{
+	let mut unknown = BTreeSet::new();
+	collect_unknown_fields(result, shape, "result", &mut unknown);
+	unknown.into_iter().collect()
+}
+
+/// Adds the path of every field in a value that is not part of its shape to `unknown`.
+fn collect_unknown_fields(value: &Value, shape: &Shape, path: &str, unknown: &mut BTreeSet<String>)
+{
+	match value
+	{
+		Value::Array(items) =>
+		{
+			let path = format!("{}[]", path);
+			for item in items.iter()
+			{
+				collect_unknown_fields(item, shape, &path, unknown);
+			}
+		},
+		Value::Object(fields) =>
+		{
+			for (name, field) in fields.iter()
+			{
+				match shape.fields.iter().find(|(known, _)| known == name)
+				{
+					None => { unknown.insert(format!("{}.{}", path, name)); },
+					Some((_, Some(nested))) => collect_unknown_fields(field, nested, &format!("{}.{}", path, name), unknown),
+					Some((_, None)) => {},
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// Builds a JSON-RPC request for a method that takes no parameters.
+pub fn request(id: u64, method: &str) -> Value
+{
+	request_with_params(id, method, json!([]))
+}
+
+/// Builds a JSON-RPC request for a method with the specified parameters.
+pub fn request_with_params(id: u64, method: &str, params: Value) -> Value
+{
+	json!(
+	{
+		"id": id,
+		"jsonrpc": "2.0",
+		"method": method,
+		"params": params
+	})
+}
+
+/// Splits the body of a JSON-RPC batch response into the individual responses, keyed by their request ids.
+/// Responses in a batch may arrive in any order, so this is how each is matched to its request.
+pub fn batch_responses(body: Value) -> Result<HashMap<u64, Value>, String>
+{
+	match body
+	{
+		Value::Array(items) => Ok(items.into_iter().filter_map(|item| item["id"].as_u64().map(|id| (id, item))).collect()),
+		_ => Err("the response is not a JSON-RPC batch".to_string()),
+	}
+}
+
+/// Parses the result of a single JSON-RPC response, returning the error the node returned, or a description of the
+/// problem if there is no response or the result is not in the expected format.
+pub fn parse_result<T>(response: Option<Value>) -> Result<T, ResponseError>
+where
+	T: DeserializeOwned,
+{
+	let result = take_result(response)?;
+	serde_json::from_value(result).map_err(|e| ResponseError::Invalid(format!("unexpected result: {}", e)))
+}
+
+/// Parses the result of a single JSON-RPC response like `parse_result`, but first checks it against the known shape
+/// of its type, returning the fields that are not part of it as schema drift.
+pub fn parse_result_strict<T>(response: Option<Value>) -> Result<T, ResponseError>
+where
+	T: DeserializeOwned + Schema,
+{
+	let result = take_result(response)?;
+	let unknown = unknown_fields(&result, T::shape());
+	if !unknown.is_empty()
+	{
+		return Err(ResponseError::SchemaDrift(unknown));
+	}
+	serde_json::from_value(result).map_err(|e| ResponseError::Invalid(format!("unexpected result: {}", e)))
+}
+
+/// Returns the result member of a single JSON-RPC response, or the error the node returned instead.
+fn take_result(response: Option<Value>) -> Result<Value, ResponseError>
+{
+	let response = response.ok_or_else(|| ResponseError::Invalid("no response".to_string()))?;
+	let response: Response = serde_json::from_value(response).map_err(|e| ResponseError::Invalid(format!("invalid JSON-RPC response: {}", e)))?;
+
+	if let Some(error) = response.error
+	{
+		return Err(ResponseError::Rpc(error));
+	}
+
+	response.result.ok_or_else(|| ResponseError::Invalid("the response has no result".to_string()))
+}
+
+/// Deserializes a `0x` prefixed hexadecimal string, as used for all numbers in the CKB RPC, into a `u64`.
+fn hex_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let value = String::deserialize(deserializer)?;
+	let digits = value.strip_prefix("0x").ok_or_else(|| de::Error::custom(format!("missing 0x prefix: {}", value)))?;
+	u64::from_str_radix(digits, 16).map_err(|e| de::Error::custom(format!("invalid hex number {}: {}", value, e)))
+}
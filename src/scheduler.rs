@@ -0,0 +1,239 @@
+use chrono::Local;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The delay used when a cron schedule has no upcoming times, after which it is checked again.
+const IDLE_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often a check runs, either at a fixed interval or at the times matched by one or more cron expressions.
+///
+/// In a config file this is written as a number of seconds (`60`), a cron expression (`"0 */5 * * * *"`), or a list of
+/// cron expressions whose matching times are combined. Cron expressions include a leading seconds field.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "ScheduleConfig")]
+pub enum Schedule
+{
+	Interval(Duration),
+	Cron(Vec<cron::Schedule>),
+}
+
+/// The raw forms a schedule can take in a config file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScheduleConfig
+{
+	Seconds(u64),
+	Expression(String),
+	Expressions(Vec<String>),
+}
+
+impl Schedule
+{
+	/// Creates a schedule that runs every specified number of seconds.
+	pub fn from_secs(secs: u64) -> Self
+	{
+		Self::Interval(Duration::from_secs(secs))
+	}
+
+	/// Returns the delay from now until the next time the schedule should run.
+	pub fn next_delay(&self) -> Duration
+	{
+		match self
+		{
+			Self::Interval(interval) => *interval,
+			Self::Cron(schedules) =>
+			{
+				let now = Local::now();
+				schedules.iter()
+					.filter_map(|schedule| schedule.upcoming(Local).next())
+					.min()
+					.and_then(|next| (next - now).to_std().ok())
+					.unwrap_or(IDLE_DELAY)
+			}
+		}
+	}
+
+	/// Returns the time between runs. For cron schedules this is the time between the next two matching times.
+	pub fn period(&self) -> Duration
+	{
+		match self
+		{
+			Self::Interval(interval) => *interval,
+			Self::Cron(schedules) =>
+			{
+				let mut upcoming = schedules.iter().flat_map(|schedule| schedule.upcoming(Local).take(2)).collect::<Vec<_>>();
+				upcoming.sort();
+				upcoming.dedup();
+				match (upcoming.first(), upcoming.get(1))
+				{
+					(Some(first), Some(second)) => (*second - *first).to_std().unwrap_or(IDLE_DELAY),
+					_ => IDLE_DELAY,
+				}
+			}
+		}
+	}
+
+	/// Returns the delay from now until the first run. Intervals run immediately, while cron schedules wait for their
+	/// next match.
+	pub fn first_delay(&self) -> Duration
+	{
+		match self
+		{
+			Self::Interval(_) => Duration::ZERO,
+			Self::Cron(_) => self.next_delay(),
+		}
+	}
+}
+
+impl TryFrom<ScheduleConfig> for Schedule
+{
+	type Error = String;
+
+	fn try_from(config: ScheduleConfig) -> Result<Self, Self::Error>
+	{
+		match config
+		{
+			ScheduleConfig::Seconds(secs) => Ok(Self::from_secs(secs)),
+			ScheduleConfig::Expression(expression) => parse_cron(&[expression]),
+			ScheduleConfig::Expressions(expressions) => parse_cron(&expressions),
+		}
+	}
+}
+
+impl FromStr for Schedule
+{
+	type Err = String;
+
+	/// Parses a number of seconds or a single cron expression.
+	fn from_str(s: &str) -> Result<Self, Self::Err>
+	{
+		match s.trim().parse::<u64>()
+		{
+			Ok(secs) => Ok(Self::from_secs(secs)),
+			Err(_) => parse_cron(&[s.to_string()]),
+		}
+	}
+}
+
+impl fmt::Display for Schedule
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Self::Interval(interval) => write!(f, "every {}s", interval.as_secs()),
+			Self::Cron(schedules) =>
+			{
+				let expressions = schedules.iter().map(|schedule| schedule.to_string()).collect::<Vec<_>>();
+				write!(f, "cron {}", expressions.join(" | "))
+			}
+		}
+	}
+}
+
+/// Parses a list of cron expressions into a schedule.
+fn parse_cron(expressions: &[String]) -> Result<Schedule, String>
+{
+	if expressions.is_empty()
+	{
+		return Err("A schedule must have at least one cron expression.".to_string());
+	}
+
+	let schedules = expressions.iter()
+		.map(|expression| cron::Schedule::from_str(expression).map_err(|e| format!("Invalid cron expression '{}': {}", expression, e)))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	Ok(Schedule::Cron(schedules))
+}
+
+/// Tracks when a scheduled check is next due.
+pub struct Timer
+{
+	schedule: Schedule,
+	/// The fraction of the period of the schedule that each run is delayed by, used to stagger clients.
+	offset: f64,
+	next_run: Instant,
+}
+
+impl Timer
+{
+	/// Creates a new `Timer` for the specified schedule.
+	pub fn new(schedule: Schedule) -> Self
+	{
+		Self::staggered(schedule, 0.0)
+	}
+
+	/// Creates a new `Timer` whose runs are delayed by the specified fraction of the period of the schedule.
+	pub fn staggered(schedule: Schedule, offset: f64) -> Self
+	{
+		let next_run = Instant::now() + schedule.first_delay() + offset_delay(&schedule, offset);
+
+		Self
+		{
+			schedule,
+			offset,
+			next_run,
+		}
+	}
+
+	/// Returns the time the check is next due.
+	pub fn next_run(&self) -> Instant
+	{
+		self.next_run
+	}
+
+	/// Returns true if the check is due at the specified time.
+	pub fn is_due(&self, now: Instant) -> bool
+	{
+		self.next_run <= now
+	}
+
+	/// Schedules the next run according to the schedule, after a run that finished at the specified time.
+	pub fn schedule_next(&mut self, now: Instant)
+	{
+		self.next_run = match &self.schedule
+		{
+			// Intervals advance from the previous due time so checks keep a fixed cadence regardless of how long they take.
+			// Any runs that were missed while checks were running are skipped.
+			Schedule::Interval(interval) if !interval.is_zero() =>
+			{
+				let next_run = self.next_run + *interval;
+				if next_run > now
+				{
+					next_run
+				}
+				else
+				{
+					let behind = (now - next_run).as_nanos() % interval.as_nanos();
+					now + *interval - Duration::from_nanos(behind as u64)
+				}
+			},
+			_ => now + self.schedule.next_delay() + offset_delay(&self.schedule, self.offset),
+		};
+	}
+
+	/// Replaces the schedule and offset, keeping the current due time unless the new schedule would run sooner.
+	pub fn set_schedule(&mut self, schedule: Schedule, offset: f64)
+	{
+		let next_run = Instant::now() + schedule.next_delay() + offset_delay(&schedule, offset);
+		if next_run < self.next_run
+		{
+			self.next_run = next_run;
+		}
+		self.schedule = schedule;
+		self.offset = offset;
+	}
+}
+
+/// Returns the delay that is the specified fraction of the period of a schedule.
+fn offset_delay(schedule: &Schedule, offset: f64) -> Duration
+{
+	if offset <= 0.0
+	{
+		return Duration::ZERO;
+	}
+
+	schedule.period().mul_f64(offset.min(1.0))
+}
@@ -0,0 +1,248 @@
+use crate::alerts::{self, Alert, AlertLimiter, RateLimit, Sink};
+use crate::dependency;
+use crate::digest;
+use crate::events::{MonitorEvent, Severity};
+use crate::fleet;
+use crate::halt;
+use crate::health::Health;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use num_format::ToFormattedString;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// The Slack Web API method used to post messages with a bot token.
+const POST_MESSAGE_URL: &str = "https://slack.com/api/chat.postMessage";
+
+/// How long Slack can take to accept a message before the attempt fails.
+const SLACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How Slack notifications are sent, using either an incoming webhook or a bot token.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlackConfig
+{
+	/// An incoming webhook URL. Messages are posted to the channel it was created for.
+	pub webhook_url: Option<String>,
+	/// A bot token that posts messages with `chat.postMessage`.
+	pub bot_token: Option<String>,
+	/// The channel messages are posted to with a bot token when their severity has no channel of its own.
+	pub channel: Option<String>,
+	/// Where messages of each severity are posted instead: a channel with a bot token, or another incoming webhook URL.
+	#[serde(default)]
+	pub channels: HashMap<Severity, String>,
+	/// The least severe messages that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether messages that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe messages are sent straight away, and the rest are held
+	/// until the next digest. Every message is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+/// Posts formatted messages to Slack when clients change state and when fleet summaries are made. The task is stopped
+/// when this is dropped.
+pub struct SlackSink
+{
+	task: JoinHandle<()>,
+}
+
+impl SlackSink
+{
+	/// Starts posting messages for the alerts received from `alerts`, retrying failed deliveries according to the
+	/// retry policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: SlackConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, reqwest::Error>
+	{
+		let client = reqwest::Client::builder().timeout(SLACK_TIMEOUT).build()?;
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Slack, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Slack, held);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let text = format!("{} {}", emoji(Severity::Warning), limiter.summary_text(suppressed));
+						tokio::spawn(deliver(client.clone(), config.clone(), None, text, retry.clone()));
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to Slack because it fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Slack) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let text = match message(&alert.event)
+				{
+					Some(text) => alert.text.unwrap_or(text),
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				let text = format!("{} {}", emoji(alert.severity), text);
+				let route = config.channels.get(&alert.severity).cloned();
+				tokio::spawn(deliver(client.clone(), config.clone(), route, text, retry.clone()));
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for SlackSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Returns the Slack formatted text of an event, or `None` for events that are not sent to Slack. Only changes in the
+/// state of a client and fleet summaries are sent, so a channel is not flooded by reports that repeat at every check,
+/// such as forks or offline clients.
+pub fn message(event: &MonitorEvent) -> Option<String>
+{
+	let text = match event
+	{
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } =>
+		{
+			let reason = reason.as_deref().unwrap_or_default();
+			let duration = duration_seconds.to_formatted_string(&num_format::Locale::en);
+			match (from, to)
+			{
+				(_, Health::Offline) => format!("*{}* is offline: {}", client, reason),
+				(Health::Offline, Health::Online) => format!("*{}* is back online after {} seconds offline.", client, duration),
+				(Health::Offline, Health::Degraded) => format!("*{}* is responding again but is degraded: {}", client, reason),
+				(_, Health::Degraded) => format!("*{}* is degraded: {}", client, reason),
+				(Health::Degraded, Health::Online) => format!("*{}* has recovered after {} seconds degraded.", client, duration),
+				_ => return None,
+			}
+		},
+		MonitorEvent::Flapping { client, transitions, window_seconds } => format!("*{}* is flapping after changing health {} times in {} seconds.", client, transitions, window_seconds),
+		MonitorEvent::FlappingStopped { client, health, .. } => format!("*{}* has stopped flapping and is {}.", client, health),
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } => format!("*{}* is lagging {} blocks behind at block {}.", client, blocks_behind.to_formatted_string(&num_format::Locale::en), block_number.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::CaughtUp { client, block_number } => format!("*{}* has caught up at block {}.", client, block_number.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::SyncStalled { client, block_number, blocks_behind, cycles } => format!("*{}* has stalled at block {} for {} cycles while {} blocks behind.", client, block_number.to_formatted_string(&num_format::Locale::en), cycles, blocks_behind.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::SyncResumed { client, blocks_per_minute } => format!("*{}* is syncing again at {:.1} blocks per minute.", client, blocks_per_minute),
+		MonitorEvent::TipFrozen { client, block_number, cycles } => format!("*{}* has reported block {} for {} consecutive cycles.", client, block_number.to_formatted_string(&num_format::Locale::en), cycles),
+		MonitorEvent::TipUnfrozen { client, block_number } => format!("*{}* has moved on to block {}.", client, block_number.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::StillOngoing { client, condition, minutes } => format!("*{}* is still {} after {} minutes.", client, condition, minutes.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::LowPeersStarted { client, peers, min_peers } => format!("*{}* has {} peers, below its minimum of {}.", client, peers, min_peers),
+		MonitorEvent::PeersRestored { client, peers } => format!("*{}* is back to {} peers.", client, peers),
+		MonitorEvent::Escalated { client, condition, minutes, severity } => format!("*{}* has been {} for {} minutes and has escalated to {}.", client, condition, minutes.to_formatted_string(&num_format::Locale::en), severity),
+		MonitorEvent::AlertsAggregated { event_type, host, clients } => alerts::aggregated_text(event_type, host.as_deref(), clients),
+		MonitorEvent::OutageSummary { group, clients } =>
+		{
+			let clients = clients.iter()
+				.map(|(client, outages)| format!("• *{}*: {} outages, {} seconds offline, {} seconds to recover on average", client, outages.outages, outages.total_downtime.as_secs().to_formatted_string(&num_format::Locale::en), outages.mean_recovery().unwrap_or_default().as_secs().to_formatted_string(&num_format::Locale::en)))
+				.collect::<Vec<_>>();
+			format!("Outages in group *{}*:\n{}", group, clients.join("\n"))
+		},
+		MonitorEvent::GroupSummary { group, online, total, highest_block_number, versions, .. } =>
+		{
+			let mut text = format!("Group *{}* has {} of {} clients online at block {}.", group, online, total, highest_block_number.to_formatted_string(&num_format::Locale::en));
+			for (version, clients) in versions.iter()
+			{
+				text.push_str(&format!("\n• {} clients running {}", clients, version));
+			}
+			text
+		},
+		MonitorEvent::Digest { .. } => digest::lines(event)?.join("\n• "),
+		MonitorEvent::FleetDegraded { .. } | MonitorEvent::FleetRecovered { .. } => fleet::text(event)?,
+		MonitorEvent::ChainHalted { .. } | MonitorEvent::ChainResumed { .. } => halt::text(event)?,
+		MonitorEvent::ParentDown { .. } | MonitorEvent::ParentRecovered { .. } => dependency::text(event)?,
+		_ => return None,
+	};
+
+	Some(text)
+}
+
+/// Returns the emoji shortcode that starts messages of a severity.
+fn emoji(severity: Severity) -> &'static str
+{
+	match severity
+	{
+		Severity::Info => ":large_green_circle:",
+		Severity::Warning => ":warning:",
+		Severity::Critical => ":red_circle:",
+	}
+}
+
+/// Posts a message to Slack, retrying transport failures and error statuses according to the retry policy. Errors
+/// reported by the Slack API, such as an unknown channel, are not retried.
+async fn deliver(client: reqwest::Client, config: SlackConfig, route: Option<String>, text: String, retry: RetryPolicy)
+{
+	let request = match (&config.bot_token, route)
+	{
+		(Some(bot_token), route) =>
+		{
+			let channel = route.or_else(|| config.channel.clone());
+			client.post(POST_MESSAGE_URL).bearer_auth(bot_token).json(&json!({ "channel": channel, "text": text }))
+		},
+		(None, Some(webhook_url)) => client.post(webhook_url).json(&json!({ "text": text })),
+		(None, None) => match &config.webhook_url
+		{
+			Some(webhook_url) => client.post(webhook_url).json(&json!({ "text": text })),
+			None => return,
+		},
+	};
+
+	let mut attempt = 0;
+	loop
+	{
+		let error = match request.try_clone().map(|request| request.send())
+		{
+			Some(sent) => match sent.await
+			{
+				Ok(response) if response.status().is_success() => match response.json::<Value>().await
+				{
+					// Incoming webhooks respond with plain text, and the Web API with a JSON object holding `ok`.
+					Ok(body) if body["ok"] == Value::Bool(false) =>
+					{
+						tracing::error!("Slack rejected a message: {}", body["error"].as_str().unwrap_or("unknown error"));
+						return;
+					},
+					_ => return,
+				},
+				Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+				Err(e) => e.without_url().to_string(),
+			},
+			None => return,
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send a message to Slack: {}", error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending a message to Slack failed: {}. Retrying in {} ms.", error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
@@ -0,0 +1,243 @@
+use crate::rpc::{self, HeaderView};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+/// The URL scheme used for subscriptions over the newline delimited TCP RPC.
+const TCP_SCHEME: &str = "tcp://";
+
+/// The URL schemes used for subscriptions over the WebSocket RPC.
+const WEBSOCKET_SCHEMES: [&str; 2] = ["ws://", "wss://"];
+
+/// The subscription topic that pushes the header of every new tip.
+const NEW_TIP_HEADER_TOPIC: &str = "new_tip_header";
+
+/// The id of the `subscribe` request, used to find its response among the notifications.
+const SUBSCRIBE_REQUEST_ID: u64 = 1;
+
+/// How long to wait before reconnecting after a subscription is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// The number of updates that can be queued before the subscription waits for the client task to catch up.
+const UPDATE_CHANNEL_CAPACITY: usize = 16;
+
+/// A change in a tip subscription, sent to the task of the client it belongs to.
+#[derive(Clone, Debug)]
+pub enum TipUpdate
+{
+	/// The subscription was accepted, so tips will be pushed from now on.
+	Connected,
+	/// The client pushed a new tip header.
+	Tip(HeaderView),
+	/// The connection was lost or could not be made. It is retried after a delay.
+	Disconnected(String),
+	/// The client does not support subscriptions. It is not retried.
+	Unsupported(String),
+}
+
+/// Why a subscription ended.
+#[derive(Debug)]
+enum SubscriptionError
+{
+	Disconnected(String),
+	Unsupported(String),
+}
+
+impl From<std::io::Error> for SubscriptionError
+{
+	fn from(e: std::io::Error) -> Self
+	{
+		Self::Disconnected(e.to_string())
+	}
+}
+
+impl From<tungstenite::Error> for SubscriptionError
+{
+	fn from(e: tungstenite::Error) -> Self
+	{
+		Self::Disconnected(e.to_string())
+	}
+}
+
+/// Returns true if the URL uses a transport that supports subscriptions.
+pub fn is_supported(url: &str) -> bool
+{
+	url.starts_with(TCP_SCHEME) || WEBSOCKET_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// A persistent connection to the subscription RPC of a client, over which it pushes each new tip header. The
+/// connection is made by its own task, which reconnects whenever it is lost until the subscription is dropped.
+pub struct TipSubscription
+{
+	pub url: String,
+	updates: mpsc::Receiver<TipUpdate>,
+	task: JoinHandle<()>,
+}
+
+impl TipSubscription
+{
+	/// Spawns a task that subscribes to new tip headers at the specified `tcp://`, `ws://`, or `wss://` URL.
+	pub fn spawn(url: String) -> Self
+	{
+		let (sender, updates) = mpsc::channel(UPDATE_CHANNEL_CAPACITY);
+
+		Self
+		{
+			task: tokio::spawn(run(url.clone(), sender)),
+			url,
+			updates,
+		}
+	}
+
+	/// Waits for the next update, returning `None` once the subscription has ended for good.
+	pub async fn recv(&mut self) -> Option<TipUpdate>
+	{
+		self.updates.recv().await
+	}
+}
+
+impl Drop for TipSubscription
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+impl fmt::Display for TipSubscription
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}", self.url)
+	}
+}
+
+/// Keeps a subscription open, reconnecting after a delay whenever it is lost, until the client turns out not to
+/// support subscriptions or the receiver is dropped.
+async fn run(url: String, updates: mpsc::Sender<TipUpdate>)
+{
+	loop
+	{
+		let result = match url.strip_prefix(TCP_SCHEME)
+		{
+			Some(address) => subscribe_tcp(address, &updates).await,
+			None => subscribe_websocket(&url, &updates).await,
+		};
+
+		let update = match result
+		{
+			SubscriptionError::Disconnected(e) => TipUpdate::Disconnected(e),
+			SubscriptionError::Unsupported(e) =>
+			{
+				let _ = updates.send(TipUpdate::Unsupported(e)).await;
+				return;
+			},
+		};
+		if updates.send(update).await.is_err()
+		{
+			return;
+		}
+
+		time::sleep(RECONNECT_DELAY).await;
+	}
+}
+
+/// Subscribes over the TCP RPC, which sends one JSON message per line, and forwards updates until the connection ends.
+async fn subscribe_tcp(address: &str, updates: &mpsc::Sender<TipUpdate>) -> SubscriptionError
+{
+	let result: Result<(), SubscriptionError> = async
+	{
+		let stream = TcpStream::connect(address).await?;
+		let (reader, mut writer) = stream.into_split();
+		writer.write_all(format!("{}\n", subscribe_request()).as_bytes()).await?;
+
+		let mut lines = BufReader::new(reader).lines();
+		while let Some(line) = lines.next_line().await?
+		{
+			forward(&line, updates).await?;
+		}
+
+		Ok(())
+	}.await;
+
+	result.err().unwrap_or_else(|| SubscriptionError::Disconnected("the connection was closed".to_string()))
+}
+
+/// Subscribes over the WebSocket RPC and forwards updates until the connection ends.
+async fn subscribe_websocket(url: &str, updates: &mpsc::Sender<TipUpdate>) -> SubscriptionError
+{
+	let result: Result<(), SubscriptionError> = async
+	{
+		let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+		socket.send(Message::Text(subscribe_request().to_string())).await?;
+
+		while let Some(message) = socket.next().await
+		{
+			match message?
+			{
+				Message::Text(text) => forward(&text, updates).await?,
+				Message::Close(_) => break,
+				_ => {},
+			}
+		}
+
+		Ok(())
+	}.await;
+
+	result.err().unwrap_or_else(|| SubscriptionError::Disconnected("the connection was closed".to_string()))
+}
+
+/// Builds the request that subscribes to new tip headers.
+fn subscribe_request() -> Value
+{
+	json!(
+	{
+		"id": SUBSCRIBE_REQUEST_ID,
+		"jsonrpc": "2.0",
+		"method": "subscribe",
+		"params": [NEW_TIP_HEADER_TOPIC]
+	})
+}
+
+/// Parses a message from the server and sends any update it holds to the client task.
+async fn forward(text: &str, updates: &mpsc::Sender<TipUpdate>) -> Result<(), SubscriptionError>
+{
+	let message: Value = serde_json::from_str(text).map_err(|e| SubscriptionError::Disconnected(format!("invalid message: {}", e)))?;
+
+	let update = if message["id"].as_u64() == Some(SUBSCRIBE_REQUEST_ID)
+	{
+		rpc::parse_result::<Value>(Some(message)).map_err(|e| SubscriptionError::Unsupported(e.to_string()))?;
+		TipUpdate::Connected
+	}
+	else if message["method"] == "subscribe"
+	{
+		// The header is sent as a JSON encoded string rather than an object.
+		let header = match &message["params"]["result"]
+		{
+			Value::String(header) => serde_json::from_str::<HeaderView>(header),
+			header => serde_json::from_value::<HeaderView>(header.clone()),
+		};
+		match header
+		{
+			Ok(header) => TipUpdate::Tip(header),
+			Err(e) =>
+			{
+				tracing::warn!("Received an invalid tip header: {}", e);
+				return Ok(());
+			}
+		}
+	}
+	else
+	{
+		return Ok(());
+	};
+
+	updates.send(update).await.map_err(|_| SubscriptionError::Disconnected("the client is no longer being checked".to_string()))
+}
@@ -0,0 +1,121 @@
+use crate::config::ClientConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The label that assigns the targets in a target group to a client group.
+const GROUP_LABEL: &str = "group";
+
+/// The label that gives the targets in a target group a human-readable name.
+const CLIENT_LABEL: &str = "label";
+
+/// A set of endpoints in a Prometheus `file_sd` style targets file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TargetGroup
+{
+	pub targets: Vec<String>,
+	#[serde(default)]
+	pub labels: HashMap<String, String>,
+}
+
+impl TargetGroup
+{
+	/// Returns the name of the client group the targets belong to, if one is set.
+	pub fn group(&self) -> Option<&str>
+	{
+		self.labels.get(GROUP_LABEL).map(String::as_str)
+	}
+
+	/// Returns a client config for each target. When there is more than one target, each label is suffixed with its index.
+	pub fn clients(&self) -> Vec<ClientConfig>
+	{
+		let label = self.labels.get(CLIENT_LABEL);
+
+		self.targets.iter().enumerate().map(|(index, target)|
+		{
+			ClientConfig
+			{
+				endpoint: target.clone(),
+				label: label.map(|label| if self.targets.len() == 1 { label.clone() } else { format!("{}-{}", label, index) }),
+				..Default::default()
+			}
+		}).collect()
+	}
+}
+
+/// Loads the target groups from a targets file. Files ending in `.yaml` or `.yml` are parsed as YAML, and all others as JSON.
+/// A missing file is treated as having no targets.
+pub fn load_targets(path: &Path) -> Result<Vec<TargetGroup>, Box<dyn Error>>
+{
+	if !path.exists()
+	{
+		tracing::warn!("Targets file {} not found.", path.display());
+		return Ok(Vec::new());
+	}
+
+	let contents = fs::read_to_string(path)?;
+	let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+	let target_groups: Result<Vec<TargetGroup>, String> = match extension.as_str()
+	{
+		"yaml" | "yml" => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+		_ => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+	};
+
+	target_groups.map_err(|e| format!("Failed to parse targets file {}: {}", path.display(), e).into())
+}
+
+/// Polls a targets file for changes by comparing its modification time.
+pub struct TargetsWatcher
+{
+	path: PathBuf,
+	interval: Duration,
+	modified: Option<SystemTime>,
+	next_poll: Instant,
+}
+
+impl TargetsWatcher
+{
+	/// Creates a new `TargetsWatcher` that polls the file every specified number of seconds.
+	pub fn new(path: PathBuf, interval: u64) -> Self
+	{
+		let interval = Duration::from_secs(interval);
+		let modified = modified_time(&path);
+
+		Self
+		{
+			path,
+			interval,
+			modified,
+			next_poll: Instant::now() + interval,
+		}
+	}
+
+	/// Polls the file if a poll is due, returning true if it has changed since the last poll.
+	pub fn poll(&mut self) -> bool
+	{
+		let now = Instant::now();
+		if now < self.next_poll
+		{
+			return false;
+		}
+		self.next_poll = now + self.interval;
+
+		let modified = modified_time(&self.path);
+		if modified == self.modified
+		{
+			return false;
+		}
+		self.modified = modified;
+
+		true
+	}
+}
+
+/// Returns the modification time of a file, or `None` if it cannot be read.
+fn modified_time(path: &Path) -> Option<SystemTime>
+{
+	fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
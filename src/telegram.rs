@@ -0,0 +1,276 @@
+use crate::alerts::{self, Alert, AlertLimiter, Condition, RateLimit, Sink};
+use crate::dependency;
+use crate::digest;
+use crate::events::{MonitorEvent, Severity};
+use crate::fleet;
+use crate::halt;
+use crate::health::Health;
+use crate::quiet::{HeldAlerts, QuietHours, QuietQueue};
+use crate::retry::RetryPolicy;
+use num_format::ToFormattedString;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+/// The Telegram Bot API, which the bot token and method are appended to.
+const BOT_API_URL: &str = "https://api.telegram.org/bot";
+
+/// How long Telegram can take to accept a message before the attempt fails.
+const TELEGRAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest message Telegram accepts, in characters. Longer batches are split.
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// How Telegram notifications are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig
+{
+	/// The token of the bot that sends the messages.
+	pub bot_token: Option<String>,
+	/// The chats messages are sent to, as numeric ids or `@channel` usernames.
+	#[serde(default)]
+	pub chat_ids: Vec<String>,
+	/// The number of seconds to collect events for after the first, before sending them all as one message. Each event
+	/// is sent on its own when 0.
+	#[serde(default)]
+	pub batch_seconds: u64,
+	/// The least severe messages that are sent.
+	#[serde(default)]
+	pub min_severity: Severity,
+	/// Whether messages that a client has recovered, such as coming back online, are left out.
+	#[serde(default)]
+	pub mute_recoveries: bool,
+	/// The hours of the day during which only the most severe messages are sent straight away, and the rest are held
+	/// until the next digest. Every message is sent straight away when omitted.
+	pub quiet_hours: Option<QuietHours>,
+}
+
+/// Sends a Telegram message to each chat when a client goes offline, starts lagging, or recovers. The task is stopped
+/// when this is dropped.
+pub struct TelegramSink
+{
+	task: JoinHandle<()>,
+}
+
+impl TelegramSink
+{
+	/// Starts sending messages for the alerts received from `alerts`, retrying failed deliveries according to the
+	/// retry policy. Once the rate limit is reached, the alerts held back are summarized when its window ends.
+	pub fn spawn(config: TelegramConfig, retry: RetryPolicy, rate_limit: Option<RateLimit>, held: HeldAlerts, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, reqwest::Error>
+	{
+		let client = reqwest::Client::builder().timeout(TELEGRAM_TIMEOUT).build()?;
+		let task = tokio::spawn(async move
+		{
+			let bot_token = match &config.bot_token
+			{
+				Some(bot_token) => bot_token.clone(),
+				None => return,
+			};
+			let batch = Duration::from_secs(config.batch_seconds);
+			let mut limiter = AlertLimiter::new(Sink::Telegram, rate_limit);
+			let mut quiet = QuietQueue::new(config.quiet_hours.clone(), Sink::Telegram, held);
+
+			loop
+			{
+				let first = tokio::select!
+				{
+					first = next_message(&mut alerts, &mut quiet, &config) => match first
+					{
+						Some(first) => first,
+						None => break,
+					},
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let text = format!("{} {}", emoji(Severity::Warning), limiter.summary_text(suppressed));
+						for chat_id in config.chat_ids.iter()
+						{
+							tokio::spawn(deliver(client.clone(), bot_token.clone(), chat_id.clone(), text.clone(), retry.clone()));
+						}
+						continue;
+					},
+				};
+
+				// Collect whatever else arrives in the batch window, so one bad cycle sends one message.
+				let mut lines = vec![first];
+				let deadline = Instant::now() + batch;
+				while !batch.is_zero()
+				{
+					match time::timeout_at(deadline, next_message(&mut alerts, &mut quiet, &config)).await
+					{
+						Ok(Some(line)) => lines.push(line),
+						_ => break,
+					}
+				}
+				lines.retain(|_| limiter.allow());
+
+				for text in split_message(&lines)
+				{
+					for chat_id in config.chat_ids.iter()
+					{
+						tokio::spawn(deliver(client.clone(), bot_token.clone(), chat_id.clone(), text.clone(), retry.clone()));
+					}
+				}
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for TelegramSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Waits for the next alert that is sent to Telegram and returns its text, or `None` once there will be no more. Alerts
+/// held during quiet hours are not returned until the next digest.
+async fn next_message(alerts: &mut broadcast::Receiver<Alert>, quiet: &mut QuietQueue, config: &TelegramConfig) -> Option<String>
+{
+	loop
+	{
+		match alerts.recv().await
+		{
+			Ok(alert) =>
+			{
+				let wanted = alert.severity >= config.min_severity && alert.is_routed_to(Sink::Telegram) && !(config.mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				if let Some(text) = message(&alert.event)
+				{
+					return Some(format!("{} {}", emoji(alert.severity), alert.text.unwrap_or(text)));
+				}
+			},
+			Err(RecvError::Lagged(count)) => tracing::warn!("{} events were not sent to Telegram because it fell behind.", count),
+			Err(RecvError::Closed) => return None,
+		}
+	}
+}
+
+/// Returns the text of an event, or `None` for events that are not sent to Telegram. Only clients going offline,
+/// becoming degraded, lagging, or stalling, recovering from each, reminders and escalations of those that last, and
+/// digests, fleet conditions, chain halts, and parents going down are sent.
+pub fn message(event: &MonitorEvent) -> Option<String>
+{
+	let text = match event
+	{
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } =>
+		{
+			let reason = reason.as_deref().unwrap_or_default();
+			let duration = duration_seconds.to_formatted_string(&num_format::Locale::en);
+			match (from, to)
+			{
+				(_, Health::Offline) => format!("{} is offline: {}", client, reason),
+				(Health::Offline, Health::Online) => format!("{} is back online after {} seconds offline.", client, duration),
+				(Health::Offline, Health::Degraded) => format!("{} is responding again but is degraded: {}", client, reason),
+				(_, Health::Degraded) => format!("{} is degraded: {}", client, reason),
+				(Health::Degraded, Health::Online) => format!("{} has recovered after {} seconds degraded.", client, duration),
+				_ => return None,
+			}
+		},
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } => format!("{} is lagging {} blocks behind at block {}.", client, blocks_behind.to_formatted_string(&num_format::Locale::en), block_number.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::CaughtUp { client, block_number } => format!("{} has caught up at block {}.", client, block_number.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::SyncStalled { client, block_number, blocks_behind, .. } => format!("{} has stalled at block {} while {} blocks behind.", client, block_number.to_formatted_string(&num_format::Locale::en), blocks_behind.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::SyncResumed { client, blocks_per_minute } => format!("{} is syncing again at {:.1} blocks per minute.", client, blocks_per_minute),
+		MonitorEvent::LowPeersStarted { client, peers, min_peers } => format!("{} has {} peers, below its minimum of {}.", client, peers, min_peers),
+		MonitorEvent::PeersRestored { client, peers } => format!("{} is back to {} peers.", client, peers),
+		MonitorEvent::StillOngoing { client, condition, minutes } if is_sent(condition) => format!("{} is still {} after {} minutes.", client, condition, minutes.to_formatted_string(&num_format::Locale::en)),
+		MonitorEvent::Escalated { client, condition, minutes, severity } if is_sent(condition) => format!("{} has been {} for {} minutes and has escalated to {}.", client, condition, minutes.to_formatted_string(&num_format::Locale::en), severity),
+		MonitorEvent::AlertsAggregated { event_type, host, clients } if matches!(event_type.as_str(), "offline" | "online" | "degraded" | "recovered" | "lagging" | "caught_up" | "stalled" | "sync_resumed" | "low_peers" | "peers_restored" | "still_ongoing" | "escalated") => alerts::aggregated_text(event_type, host.as_deref(), clients),
+		MonitorEvent::Digest { .. } => digest::lines(event)?.join("\n"),
+		MonitorEvent::FleetDegraded { .. } | MonitorEvent::FleetRecovered { .. } => fleet::text(event)?,
+		MonitorEvent::ChainHalted { .. } | MonitorEvent::ChainResumed { .. } => halt::text(event)?,
+		MonitorEvent::ParentDown { .. } | MonitorEvent::ParentRecovered { .. } => dependency::text(event)?,
+		_ => return None,
+	};
+
+	Some(text)
+}
+
+/// Returns whether reminders and escalations of a condition are sent to Telegram, which are those of the conditions
+/// whose start is sent.
+fn is_sent(condition: &Condition) -> bool
+{
+	matches!(condition, Condition::Offline | Condition::Degraded | Condition::Lagging | Condition::Stalled | Condition::LowPeers)
+}
+
+/// Returns the emoji that starts messages of a severity.
+fn emoji(severity: Severity) -> &'static str
+{
+	match severity
+	{
+		Severity::Info => "🟢",
+		Severity::Warning => "🟡",
+		Severity::Critical => "🔴",
+	}
+}
+
+/// Joins lines into as few messages as fit within the Telegram limit. A single line that is too long is truncated.
+fn split_message(lines: &[String]) -> Vec<String>
+{
+	let mut messages = Vec::new();
+	let mut current = String::new();
+	for line in lines.iter()
+	{
+		let line = line.chars().take(MAX_MESSAGE_LENGTH).collect::<String>();
+		if !current.is_empty() && current.chars().count() + 1 + line.chars().count() > MAX_MESSAGE_LENGTH
+		{
+			messages.push(std::mem::take(&mut current));
+		}
+		if !current.is_empty()
+		{
+			current.push('\n');
+		}
+		current.push_str(&line);
+	}
+	if !current.is_empty()
+	{
+		messages.push(current);
+	}
+
+	messages
+}
+
+/// Sends a message to a chat, retrying transport failures and error statuses according to the retry policy. The bot
+/// token is part of the URL, so it is left out of any error that is logged.
+async fn deliver(client: reqwest::Client, bot_token: String, chat_id: String, text: String, retry: RetryPolicy)
+{
+	let url = format!("{}{}/sendMessage", BOT_API_URL, bot_token);
+	let payload = json!({ "chat_id": chat_id, "text": text });
+
+	let mut attempt = 0;
+	loop
+	{
+		let error = match client.post(&url).json(&payload).send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) =>
+			{
+				let status = response.status().as_u16();
+				let body = response.json::<Value>().await.unwrap_or_default();
+				format!("HTTP status {}: {}", status, body["description"].as_str().unwrap_or("no description"))
+			},
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send a message to Telegram chat {}: {}", chat_id, error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending a message to Telegram chat {} failed: {}. Retrying in {} ms.", chat_id, error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
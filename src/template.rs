@@ -0,0 +1,131 @@
+use crate::alerts;
+use crate::events::{MonitorEvent, Severity};
+use crate::monitor::FleetSnapshot;
+use handlebars::Handlebars;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// The templates that replace the text of alerts of each event type, compiled once so rendering an alert is cheap.
+#[derive(Default)]
+pub struct Templates
+{
+	registry: Handlebars<'static>,
+}
+
+impl Templates
+{
+	/// Compiles the templates, keyed by event type, returning a description of the first that is invalid.
+	pub fn new(templates: &HashMap<String, String>) -> Result<Self, String>
+	{
+		let mut registry = Handlebars::new();
+		// Alerts are chat messages rather than HTML, so values are inserted as they are.
+		registry.register_escape_fn(handlebars::no_escape);
+		for (event_type, template) in templates.iter()
+		{
+			registry.register_template_string(event_type, template).map_err(|e| format!("The {} alert template is invalid: {}", event_type, e))?;
+		}
+
+		Ok(Self { registry })
+	}
+
+	/// Renders the text of an alert from the template of its event type, or returns `None` if there is no template for
+	/// it or it cannot be rendered.
+	pub fn render(&self, event: &MonitorEvent, severity: Severity, fleet: &FleetSnapshot) -> Option<String>
+	{
+		let event_type = alerts::event_type(event)?;
+		if !self.registry.has_template(event_type)
+		{
+			return None;
+		}
+
+		match self.registry.render(event_type, &variables(event, event_type, severity, fleet))
+		{
+			Ok(text) => Some(text),
+			Err(e) =>
+			{
+				tracing::warn!("Failed to render the {} alert template: {}", event_type, e);
+				None
+			},
+		}
+	}
+}
+
+/// Returns the variables available to the template of an event: the fields of the event, the label, endpoint, and
+/// group of the client it is about, and the number of clients online in its group and in the whole fleet.
+fn variables(event: &MonitorEvent, event_type: &str, severity: Severity, fleet: &FleetSnapshot) -> Value
+{
+	let fields = match event
+	{
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } => json!({ "client": client, "from": from.to_string(), "to": to.to_string(), "reason": reason, "duration_seconds": duration_seconds, "duration_minutes": duration_seconds / 60 }),
+		MonitorEvent::Flapping { client, transitions, window_seconds } => json!({ "client": client, "transitions": transitions, "window_seconds": window_seconds }),
+		MonitorEvent::FlappingStopped { client, health, .. } => json!({ "client": client, "health": health.to_string() }),
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } => json!({ "client": client, "block_number": block_number, "lag": blocks_behind }),
+		MonitorEvent::CaughtUp { client, block_number } => json!({ "client": client, "block_number": block_number }),
+		MonitorEvent::SyncStalled { client, block_number, blocks_behind, cycles } => json!({ "client": client, "block_number": block_number, "lag": blocks_behind, "cycles": cycles }),
+		MonitorEvent::SyncResumed { client, blocks_per_minute } => json!({ "client": client, "blocks_per_minute": format!("{:.1}", blocks_per_minute) }),
+		MonitorEvent::TipFrozen { client, block_number, cycles } => json!({ "client": client, "block_number": block_number, "cycles": cycles }),
+		MonitorEvent::TipUnfrozen { client, block_number } => json!({ "client": client, "block_number": block_number }),
+		MonitorEvent::LowPeersStarted { client, peers, min_peers } => json!({ "client": client, "peers": peers, "min_peers": min_peers }),
+		MonitorEvent::PeersRestored { client, peers } => json!({ "client": client, "peers": peers }),
+		MonitorEvent::StillOngoing { client, condition, minutes } | MonitorEvent::Escalated { client, condition, minutes, .. } => json!({ "client": client, "condition": condition.to_string(), "minutes": minutes }),
+		MonitorEvent::AlertsAggregated { event_type, host, clients } => json!({ "event_type": event_type, "host": host, "clients": clients, "count": clients.len() }),
+		MonitorEvent::OutageSummary { clients, .. } =>
+		{
+			let clients = clients.iter()
+				.map(|(client, outages)| json!({ "client": client, "outages": outages.outages, "downtime_seconds": outages.total_downtime.as_secs(), "mean_recovery_seconds": outages.mean_recovery().unwrap_or_default().as_secs() }))
+				.collect::<Vec<_>>();
+			json!({ "clients": clients })
+		},
+		MonitorEvent::GroupSummary { online, total, highest_block_number, versions, .. } =>
+		{
+			let versions = versions.iter().map(|(version, clients)| json!({ "version": version, "clients": clients })).collect::<Vec<_>>();
+			json!({ "online": online, "total": total, "highest_block_number": highest_block_number, "versions": versions })
+		},
+		MonitorEvent::Digest { since, incidents, uptime, laggards, degraded } =>
+		{
+			let uptime = uptime.iter().map(|(client, percent)| json!({ "client": client, "percent": format!("{:.2}", percent) })).collect::<Vec<_>>();
+			let laggards = laggards.iter().map(|(client, lag)| json!({ "client": client, "lag": lag })).collect::<Vec<_>>();
+			let degraded = degraded.iter().map(|(client, health)| json!({ "client": client, "health": health.to_string() })).collect::<Vec<_>>();
+			json!({ "since": since.format("%Y-%m-%d %H:%M").to_string(), "incidents": incidents, "uptime": uptime, "laggards": laggards, "degraded": degraded })
+		},
+		MonitorEvent::FleetDegraded { condition, group, reason, .. } => json!({ "condition": condition, "group": group, "reason": reason }),
+		MonitorEvent::ChainHalted { block_number, seconds, .. } => json!({ "block_number": block_number, "seconds": seconds, "minutes": seconds / 60 }),
+		MonitorEvent::ChainResumed { block_number, halted_seconds, .. } => json!({ "block_number": block_number, "duration_seconds": halted_seconds, "duration_minutes": halted_seconds / 60 }),
+		MonitorEvent::ParentDown { parent, clients } => json!({ "parent": parent.to_string(), "clients": clients, "count": clients.len() }),
+		MonitorEvent::ParentRecovered { parent, duration_seconds } => json!({ "parent": parent.to_string(), "duration_seconds": duration_seconds, "duration_minutes": duration_seconds / 60 }),
+		MonitorEvent::FleetRecovered { condition, group, duration_seconds } => json!({ "condition": condition, "group": group, "duration_seconds": duration_seconds, "duration_minutes": duration_seconds / 60 }),
+		_ => json!({}),
+	};
+	let mut variables = match fields
+	{
+		Value::Object(fields) => fields,
+		_ => Map::new(),
+	};
+	variables.insert("event".to_string(), json!(event_type));
+	variables.insert("severity".to_string(), json!(severity.to_string()));
+
+	if let Some(client) = variables.get("client").and_then(Value::as_str).and_then(|client| fleet.client(client))
+	{
+		variables.insert("label".to_string(), json!(client.label.clone().unwrap_or_else(|| client.number.to_string())));
+		variables.insert("endpoint".to_string(), json!(client.url));
+	}
+
+	if let Some(group) = alerts::group(event)
+	{
+		let clients = fleet.clients.iter().filter(|client| client.group == group);
+		let (online, total) = clients.fold((0, 0), |(online, total), client| (online + client.is_online as usize, total + 1));
+		variables.insert("group".to_string(), json!(group));
+		variables.entry("online").or_insert(json!(online));
+		variables.entry("total").or_insert(json!(total));
+	}
+
+	let highest_block_number = fleet.clients.iter().map(|client| client.block_number).max().unwrap_or_default();
+	variables.insert("fleet".to_string(), json!(
+	{
+		"online": fleet.clients.iter().filter(|client| client.is_online).count(),
+		"total": fleet.clients.len(),
+		"highest_block_number": highest_block_number,
+	}));
+
+	Value::Object(variables)
+}
@@ -0,0 +1,705 @@
+use crate::alertmanager::{alerts_url, is_label_name};
+use crate::alerts::{self, Sink};
+use crate::checks::{self, CELLS_CHECK};
+use crate::config::{Config, GroupSettings, LagMode};
+use crate::diversity::AsnDatabase;
+use crate::endpoint::{self, Transport};
+use crate::events::Severity;
+use crate::filter::ClientFilter;
+use crate::gotify::message_url;
+use crate::heartbeat;
+use crate::matrix::room_url;
+use crate::mqtt;
+use crate::ntfy::{topic_url, DEFAULT_NTFY_SERVER};
+use crate::otlp::export_url;
+use crate::scheduler::Schedule;
+use crate::subscription;
+use crate::template::Templates;
+use crate::version::Version;
+use regex::Regex;
+use reqwest::Url;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Checks the config for problems that would prevent the monitor from running correctly, returning a description of each.
+pub async fn validate(config: &Config) -> Vec<String>
+{
+	let mut errors = Vec::new();
+	let groups = config.groups();
+
+	// Group names must be present and unique.
+	let mut group_names = HashSet::new();
+	for group in groups.iter()
+	{
+		if group.name.trim().is_empty()
+		{
+			errors.push("A group has an empty name.".to_string());
+		}
+		else if !group_names.insert(group.name.as_str())
+		{
+			errors.push(format!("Group {} is configured more than once.", group.name));
+		}
+	}
+
+	// Every client in a sequential port range needs a port.
+	for name in config.overflowing_port_ranges()
+	{
+		errors.push(format!("The port range of group {} runs past port {}.", name, u16::MAX));
+	}
+
+	// Retry jitter is a fraction of the delay.
+	if !(0.0..=1.0).contains(&config.retry_jitter)
+	{
+		errors.push(format!("The retry jitter must be between 0.0 and 1.0, not {}.", config.retry_jitter));
+	}
+
+	// The ASN database must be readable.
+	if let Some(path) = &config.asn_database
+	{
+		if let Err(e) = AsnDatabase::open(path)
+		{
+			errors.push(e.to_string());
+		}
+	}
+
+	// Webhooks are posted to over HTTP.
+	for url in config.webhook_urls.iter().filter(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+	{
+		errors.push(format!("Webhook {} is not an http:// or https:// URL.", url));
+	}
+	if config.webhook_secret.as_deref().is_some_and(str::is_empty)
+	{
+		errors.push("The webhook secret is empty.".to_string());
+	}
+
+	// Slack needs exactly one way to post, and a bot token needs a channel for every severity.
+	if let Some(slack) = &config.slack
+	{
+		match (&slack.webhook_url, &slack.bot_token)
+		{
+			(Some(_), Some(_)) => errors.push("Slack has both a webhook URL and a bot token.".to_string()),
+			(None, None) => errors.push("Slack has neither a webhook URL nor a bot token.".to_string()),
+			(None, Some(_)) =>
+			{
+				for severity in [Severity::Info, Severity::Warning, Severity::Critical].into_iter().filter(|severity| *severity >= slack.min_severity)
+				{
+					if slack.channel.is_none() && !slack.channels.contains_key(&severity)
+					{
+						errors.push(format!("Slack has no channel for {} messages.", severity));
+					}
+				}
+			},
+			(Some(_), None) =>
+			{
+				for (severity, webhook_url) in slack.channels.iter().filter(|(_, webhook_url)| !webhook_url.starts_with("https://"))
+				{
+					errors.push(format!("Slack {} messages are routed to {}, which is not an incoming webhook URL.", severity, webhook_url));
+				}
+			},
+		}
+	}
+
+	// Discord needs a webhook to post to.
+	if let Some(discord) = &config.discord
+	{
+		match &discord.webhook_url
+		{
+			Some(webhook_url) if !webhook_url.starts_with("https://") => errors.push(format!("Discord webhook {} is not an https:// URL.", webhook_url)),
+			Some(_) => {},
+			None => errors.push("Discord has no webhook URL.".to_string()),
+		}
+	}
+
+	// Telegram needs a bot and somewhere to send its messages.
+	if let Some(telegram) = &config.telegram
+	{
+		if telegram.bot_token.is_none()
+		{
+			errors.push("Telegram has no bot token.".to_string());
+		}
+		if telegram.chat_ids.is_empty()
+		{
+			errors.push("Telegram has no chat ids to send messages to.".to_string());
+		}
+	}
+
+	// Matrix needs a homeserver, an account on it, and a room to post to.
+	if let Some(matrix) = &config.matrix
+	{
+		match &matrix.homeserver_url
+		{
+			Some(homeserver_url) => if let Err(e) = room_url(homeserver_url, "")
+			{
+				errors.push(e);
+			},
+			None => errors.push("Matrix has no homeserver URL.".to_string()),
+		}
+		if matrix.access_token.is_none()
+		{
+			errors.push("Matrix has no access token.".to_string());
+		}
+		match &matrix.room_id
+		{
+			Some(room_id) if !room_id.starts_with('!') => errors.push(format!("Matrix room id {} does not start with !. Use the internal id of the room rather than its alias.", room_id)),
+			Some(_) => {},
+			None => errors.push("Matrix has no room id.".to_string()),
+		}
+	}
+
+	// ntfy needs a topic to publish to, and priorities it understands.
+	if let Some(ntfy) = &config.ntfy
+	{
+		match &ntfy.topic
+		{
+			Some(topic) => if let Err(e) = topic_url(ntfy.server_url.as_deref().unwrap_or(DEFAULT_NTFY_SERVER), topic)
+			{
+				errors.push(e);
+			},
+			None => errors.push("ntfy has no topic.".to_string()),
+		}
+		for (severity, priority) in ntfy.priorities.iter().filter(|(_, priority)| !(1..=5).contains(*priority))
+		{
+			errors.push(format!("The ntfy priority of {} notifications is {}, which is not between 1 and 5.", severity, priority));
+		}
+	}
+
+	// Pushover needs an application to send from and someone to send to, and limits how emergencies repeat.
+	if let Some(pushover) = &config.pushover
+	{
+		if pushover.app_token.is_none()
+		{
+			errors.push("Pushover has no app token.".to_string());
+		}
+		if pushover.user_key.is_none()
+		{
+			errors.push("Pushover has no user key.".to_string());
+		}
+		for (severity, priority) in pushover.priorities.iter().filter(|(_, priority)| !(-2..=2).contains(*priority))
+		{
+			errors.push(format!("The Pushover priority of {} messages is {}, which is not between -2 and 2.", severity, priority));
+		}
+		if pushover.retry.is_some_and(|retry| retry < 30)
+		{
+			errors.push("The Pushover retry must be at least 30 seconds.".to_string());
+		}
+		if pushover.expire.is_some_and(|expire| expire == 0 || expire > 10800)
+		{
+			errors.push("The Pushover expire must be between 1 and 10,800 seconds.".to_string());
+		}
+	}
+
+	// Gotify needs a server and an application on it to send from.
+	if let Some(gotify) = &config.gotify
+	{
+		match &gotify.server_url
+		{
+			Some(server_url) => if let Err(e) = message_url(server_url)
+			{
+				errors.push(e);
+			},
+			None => errors.push("Gotify has no server URL.".to_string()),
+		}
+		if gotify.app_token.is_none()
+		{
+			errors.push("Gotify has no app token.".to_string());
+		}
+		for (severity, priority) in gotify.priorities.iter().filter(|(_, priority)| **priority > 10)
+		{
+			errors.push(format!("The Gotify priority of {} messages is {}, which is not between 0 and 10.", severity, priority));
+		}
+	}
+
+	// Alertmanager needs a URL, and the labels of alerts must be valid Prometheus label names.
+	if let Some(alertmanager) = &config.alertmanager
+	{
+		match &alertmanager.url
+		{
+			Some(url) => if let Err(e) = alerts_url(url)
+			{
+				errors.push(e);
+			},
+			None => errors.push("Alertmanager has no URL.".to_string()),
+		}
+		if alertmanager.password.is_some() && alertmanager.username.is_none()
+		{
+			errors.push("Alertmanager has a password but no username.".to_string());
+		}
+		for name in alertmanager.labels.keys().filter(|name| !is_label_name(name))
+		{
+			errors.push(format!("The Alertmanager label {} is not a valid label name.", name));
+		}
+		for name in alertmanager.labels.keys().filter(|name| ["alertname", "severity", "client", "group", "condition"].contains(&name.as_str()))
+		{
+			errors.push(format!("The Alertmanager label {} is set by the monitor and cannot be configured.", name));
+		}
+	}
+
+	// MQTT needs a broker it can connect to.
+	if let Some(mqtt_config) = &config.mqtt
+	{
+		if let Err(e) = mqtt::options(mqtt_config)
+		{
+			errors.push(e);
+		}
+		if mqtt_config.status_interval == Some(0)
+		{
+			errors.push("The MQTT status interval must be greater than 0.".to_string());
+		}
+		if mqtt_config.password.is_some() && mqtt_config.username.is_none()
+		{
+			errors.push("MQTT has a password but no username.".to_string());
+		}
+	}
+
+	// OpenTelemetry export needs the endpoint of a collector.
+	if let Some(otlp) = &config.otlp
+	{
+		match &otlp.endpoint
+		{
+			Some(endpoint) => if let Err(e) = export_url(endpoint, "traces")
+			{
+				errors.push(e);
+			},
+			None => errors.push("OpenTelemetry export has no endpoint.".to_string()),
+		}
+		if otlp.metrics_interval == Some(0)
+		{
+			errors.push("The OpenTelemetry metrics interval must be greater than 0.".to_string());
+		}
+	}
+
+	// The heartbeat needs a URL to ping.
+	if let Some(heartbeat_config) = &config.heartbeat
+	{
+		match &heartbeat_config.url
+		{
+			Some(url) => if let Err(e) = heartbeat::parse_url(url)
+			{
+				errors.push(e);
+			},
+			None => errors.push("The heartbeat has no URL.".to_string()),
+		}
+		if let Some(Err(e)) = heartbeat_config.fail_url.as_deref().map(heartbeat::parse_url)
+		{
+			errors.push(e);
+		}
+		if heartbeat_config.interval == Some(0)
+		{
+			errors.push("The heartbeat interval must be greater than 0.".to_string());
+		}
+	}
+
+	// A condition that escalates immediately would never be alerted as a warning.
+	for (condition, seconds) in config.escalate_after.iter()
+	{
+		if *seconds == 0
+		{
+			errors.push(format!("escalate_after.{} must be greater than 0.", condition.name()));
+		}
+	}
+
+	// The exec sink needs a program to run, and time for it to run.
+	if let Some(exec) = &config.exec
+	{
+		if exec.command.is_empty()
+		{
+			errors.push("The exec command is empty.".to_string());
+		}
+		if exec.timeout == Some(0)
+		{
+			errors.push("The exec timeout must be greater than 0.".to_string());
+		}
+	}
+
+	// Alerts held back by the rate limit are summarized when its window ends, so the window needs a length.
+	if config.alert_rate_limit > 0 && config.alert_rate_window == 0
+	{
+		errors.push("alert_rate_window must be greater than 0 when alert_rate_limit is set.".to_string());
+	}
+
+	if matches!(config.digest_schedule, Some(Schedule::Interval(interval)) if interval == Duration::ZERO)
+	{
+		errors.push("The digest schedule is an interval of 0 seconds.".to_string());
+	}
+
+	// Alerts held during quiet hours are sent with the next digest, so there has to be one.
+	let quiet_hours = [
+		(Sink::Webhooks, config.webhook_quiet_hours.as_ref()),
+		(Sink::Slack, config.slack.as_ref().and_then(|slack| slack.quiet_hours.as_ref())),
+		(Sink::Discord, config.discord.as_ref().and_then(|discord| discord.quiet_hours.as_ref())),
+		(Sink::Telegram, config.telegram.as_ref().and_then(|telegram| telegram.quiet_hours.as_ref())),
+		(Sink::Matrix, config.matrix.as_ref().and_then(|matrix| matrix.quiet_hours.as_ref())),
+		(Sink::Exec, config.exec.as_ref().and_then(|exec| exec.quiet_hours.as_ref())),
+		(Sink::Ntfy, config.ntfy.as_ref().and_then(|ntfy| ntfy.quiet_hours.as_ref())),
+		(Sink::Pushover, config.pushover.as_ref().and_then(|pushover| pushover.quiet_hours.as_ref())),
+		(Sink::Gotify, config.gotify.as_ref().and_then(|gotify| gotify.quiet_hours.as_ref())),
+		(Sink::Desktop, config.desktop.as_ref().and_then(|desktop| desktop.quiet_hours.as_ref())),
+	];
+	// With routes, digests only reach the destinations of the first route that matches them.
+	let digest_route = config.alert_routes.iter().find(|route| route.matches(None, Severity::Info, Some("digest")));
+	for (sink, hours) in quiet_hours.iter().filter_map(|(sink, hours)| hours.map(|hours| (sink, hours)))
+	{
+		if config.digest_schedule.is_none()
+		{
+			errors.push(format!("The quiet hours of {} need a digest_schedule to send the alerts they hold.", sink));
+		}
+		if !config.alert_routes.is_empty() && digest_route.is_none_or(|route| !route.sinks.contains(sink))
+		{
+			errors.push(format!("The quiet hours of {} hold alerts until the next digest, but no alert route sends digests to it.", sink));
+		}
+		if hours.start == hours.end
+		{
+			errors.push(format!("The quiet hours of {} start and end at the same time.", sink));
+		}
+	}
+
+	// Fleet conditions need a unique name, a known group if they have one, and a threshold to cross.
+	let mut fleet_condition_names = HashSet::new();
+	for condition in config.fleet_conditions.iter()
+	{
+		if condition.name.trim().is_empty()
+		{
+			errors.push("A fleet condition has an empty name.".to_string());
+		}
+		else if !fleet_condition_names.insert(condition.name.as_str())
+		{
+			errors.push(format!("Fleet condition {} is configured more than once.", condition.name));
+		}
+		if let Some(group) = condition.group.as_ref().filter(|group| !groups.iter().any(|existing| &existing.name == *group))
+		{
+			errors.push(format!("Fleet condition {} is over group {}, which is not configured.", condition.name, group));
+		}
+		if condition.max_offline_percent.is_none() && condition.min_clients_with_peers.is_none()
+		{
+			errors.push(format!("Fleet condition {} has no max_offline_percent or min_clients_with_peers.", condition.name));
+		}
+		if condition.max_offline_percent.is_some_and(|percent| !(0.0..100.0).contains(&percent))
+		{
+			errors.push(format!("The max_offline_percent of fleet condition {} must be at least 0 and less than 100.", condition.name));
+		}
+		if condition.min_peers.is_some() && condition.min_clients_with_peers.is_none()
+		{
+			errors.push(format!("Fleet condition {} has min_peers but no min_clients_with_peers.", condition.name));
+		}
+	}
+
+	// Alert routes can only match known groups and event types, and send to destinations that are configured.
+	for route in config.alert_routes.iter()
+	{
+		if let Some(group) = &route.group
+		{
+			if !groups.iter().any(|existing| &existing.name == group)
+			{
+				errors.push(format!("An alert route matches group {}, which is not configured.", group));
+			}
+		}
+		for event in route.events.iter().filter(|event| !alerts::EVENT_TYPES.contains(&event.as_str()))
+		{
+			errors.push(format!("An alert route matches unknown event type {}. Expected one of: {}", event, alerts::EVENT_TYPES.join(", ")));
+		}
+		if route.events.iter().any(|event| event == "aggregated")
+		{
+			errors.push("An alert route matches aggregated alerts, which go wherever the alerts they collapse were routed.".to_string());
+		}
+		if route.sinks.is_empty()
+		{
+			errors.push("An alert route has no sinks.".to_string());
+		}
+		for sink in route.sinks.iter()
+		{
+			let configured = match sink
+			{
+				Sink::Webhooks => !config.webhook_urls.is_empty(),
+				Sink::Slack => config.slack.is_some(),
+				Sink::Discord => config.discord.is_some(),
+				Sink::Telegram => config.telegram.is_some(),
+				Sink::Matrix => config.matrix.is_some(),
+				Sink::Exec => config.exec.is_some(),
+				Sink::Ntfy => config.ntfy.is_some(),
+				Sink::Pushover => config.pushover.is_some(),
+				Sink::Gotify => config.gotify.is_some(),
+				Sink::Alertmanager => config.alertmanager.is_some(),
+				Sink::Desktop => config.desktop.is_some(),
+			};
+			if !configured
+			{
+				errors.push(format!("An alert route sends to {}, which is not configured.", sink));
+			}
+		}
+	}
+	if !config.alert_routes.is_empty()
+	{
+		for group in groups.iter().filter(|group| group.name.contains('/'))
+		{
+			errors.push(format!("Group {} contains a /, so alert routes cannot tell it apart from the names of its clients.", group.name));
+		}
+	}
+
+	// Severity rules can only compare values and durations that the events of their type have.
+	for rule in config.severity_rules.iter()
+	{
+		if !alerts::EVENT_TYPES.contains(&rule.event.as_str()) || matches!(rule.event.as_str(), "still_ongoing" | "escalated" | "aggregated")
+		{
+			errors.push(format!("A severity rule is for event type {}, which cannot be given a severity. Reminders and escalations take the severity of their condition, and aggregated alerts the highest of the alerts they collapse.", rule.event));
+			continue;
+		}
+		if (rule.above.is_some() || rule.below.is_some()) && !alerts::MEASURED_EVENT_TYPES.contains(&rule.event.as_str())
+		{
+			errors.push(format!("A severity rule has a threshold for {} events, which have no value. Expected one of: {}", rule.event, alerts::MEASURED_EVENT_TYPES.join(", ")));
+		}
+		if let (Some(above), Some(below)) = (rule.above, rule.below)
+		{
+			if above.saturating_add(1) >= below
+			{
+				errors.push(format!("A severity rule for {} events can never match, since nothing is above {} and below {}.", rule.event, above, below));
+			}
+		}
+		if rule.after.is_some() && !alerts::CONDITION_EVENT_TYPES.contains(&rule.event.as_str())
+		{
+			errors.push(format!("A severity rule waits for {} events to last, which do not start a condition. Expected one of: {}", rule.event, alerts::CONDITION_EVENT_TYPES.join(", ")));
+		}
+	}
+
+	// Alert templates must be for known event types and compile.
+	for event_type in config.alert_templates.keys().filter(|event_type| !alerts::EVENT_TYPES.contains(&event_type.as_str()))
+	{
+		errors.push(format!("There is an alert template for unknown event type {}. Expected one of: {}", event_type, alerts::EVENT_TYPES.join(", ")));
+	}
+	if let Err(e) = Templates::new(&config.alert_templates)
+	{
+		errors.push(e);
+	}
+
+	// The rate limit must allow some requests.
+	if let Some(max_requests_per_second) = config.max_requests_per_second
+	{
+		if max_requests_per_second <= 0.0
+		{
+			errors.push(format!("The maximum requests per second must be greater than 0, not {}.", max_requests_per_second));
+		}
+	}
+
+	// Schedules must not run continuously, and checks can only be spread within a single interval.
+	for group in groups.iter()
+	{
+		if !(0.0..=1.0).contains(&group.check_spread)
+		{
+			errors.push(format!("Group {} has a check spread that is not between 0.0 and 1.0: {}", group.name, group.check_spread));
+		}
+
+		for (check, schedule) in [("check", &group.check_interval), ("rpc", &group.rpc_interval), ("peers", &group.peers_interval), ("tip", &group.tip_interval), ("genesis", &group.genesis_interval), ("audit", &group.audit_interval), ("summary", &group.summary_interval)]
+		{
+			if matches!(schedule, Schedule::Interval(interval) if *interval == Duration::ZERO)
+			{
+				errors.push(format!("Group {} has a {} interval of 0 seconds.", group.name, check));
+			}
+		}
+		if group.lag_mode == LagMode::Outlier && (!group.outlier_threshold.is_finite() || group.outlier_threshold <= 0.0)
+		{
+			errors.push(format!("Group {} has an outlier threshold that is not positive: {}", group.name, group.outlier_threshold));
+		}
+		if group.stall_cycles >= group.history_size
+		{
+			errors.push(format!("Group {} keeps {} results per client, which is not enough to detect a stall of {} cycles.", group.name, group.history_size, group.stall_cycles));
+		}
+		if !(0.0..=1.0).contains(&group.shared_peer_fraction)
+		{
+			errors.push(format!("Group {} has a shared peer fraction that is not between 0.0 and 1.0: {}", group.name, group.shared_peer_fraction));
+		}
+		if let Some(min_version) = &group.min_version
+		{
+			if Version::parse(min_version).is_none()
+			{
+				errors.push(format!("Group {} has a minimum version that is not a version number: {}", group.name, min_version));
+			}
+		}
+		if group.degraded_interval == Some(Duration::ZERO)
+		{
+			errors.push(format!("Group {} has a degraded interval of 0 seconds.", group.name));
+		}
+	}
+
+	// Reference endpoints must be valid HTTP URLs.
+	for group in groups.iter()
+	{
+		for reference in group.references.iter()
+		{
+			match Url::parse(&reference.url)
+			{
+				Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {},
+				Ok(url) => errors.push(format!("Group {} has a reference endpoint with an unsupported scheme: {}", group.name, url.scheme())),
+				Err(e) => errors.push(format!("Group {} has an invalid reference endpoint {}: {}", group.name, reference.url, e)),
+			}
+		}
+	}
+
+	// Endpoints must be valid URLs that resolve, and no two clients may share the same host and port or socket.
+	let mut addresses: HashMap<(String, u16), String> = HashMap::new();
+	let mut number = 0;
+	for group in groups.iter()
+	{
+		for client in group.clients.iter()
+		{
+			let name = format!("{}/{}", group.name, client.label.clone().unwrap_or_else(|| number.to_string()));
+			number += 1;
+
+			for check in client.checks.iter().filter(|check| checks::find(check).is_none())
+			{
+				errors.push(format!("Client {} has an unknown check: {}", name, check));
+			}
+
+			if !(0.0..=100.0).contains(&client.min_disk_free_percent)
+			{
+				errors.push(format!("Client {} has a minimum free disk percentage that is not between 0 and 100: {}", name, client.min_disk_free_percent));
+			}
+			if client.data_dir.is_some() && client.disk_interval == Duration::ZERO
+			{
+				errors.push(format!("Client {} has a disk interval of 0 seconds.", name));
+			}
+			if let Some(data_dir) = client.data_dir.as_ref().filter(|data_dir| !data_dir.is_dir())
+			{
+				errors.push(format!("Client {} has a data directory that does not exist: {}", name, data_dir.display()));
+			}
+
+			for pattern in client.log_patterns.iter()
+			{
+				if let Err(e) = Regex::new(pattern)
+				{
+					errors.push(format!("Client {} has an invalid log pattern {}: {}", name, pattern, e));
+				}
+			}
+
+			if client.has_check(CELLS_CHECK) && client.canary_script.is_none()
+			{
+				errors.push(format!("Client {} runs the cells check without a canary_script.", name));
+			}
+
+			if client.max_peer_churn < 0.0
+			{
+				errors.push(format!("Client {} has a negative maximum peer churn: {}", name, client.max_peer_churn));
+			}
+			if let Some(min_peer_version) = &client.min_peer_version
+			{
+				if Version::parse(min_peer_version).is_none()
+				{
+					errors.push(format!("Client {} has a minimum peer version that is not a version number: {}", name, min_peer_version));
+				}
+			}
+
+			if client.health.offline_after == 0 || client.health.recover_after == 0
+			{
+				errors.push(format!("Client {} must need at least one round of checks to change health.", name));
+			}
+			if client.health.flap_threshold == 1
+			{
+				errors.push(format!("Client {} would be flapping after a single change in health. Use a flap threshold of 0 to disable flapping.", name));
+			}
+			if client.health.flap_threshold > 0 && client.health.flap_window.is_zero()
+			{
+				errors.push(format!("Client {} has a flap window of 0 seconds.", name));
+			}
+
+			if let Some(subscription) = &client.subscription
+			{
+				if !subscription::is_supported(subscription)
+				{
+					errors.push(format!("Client {} has a subscription endpoint that is not a tcp:// or ws:// URL: {}", name, subscription));
+				}
+			}
+
+			if client.max_latency == Some(Duration::ZERO)
+			{
+				errors.push(format!("Client {} has a maximum latency of 0 milliseconds.", name));
+			}
+
+			if client.connect_timeout == Duration::ZERO || client.request_timeout == Duration::ZERO
+			{
+				errors.push(format!("Client {} has a timeout of 0 seconds.", name));
+			}
+
+			// Unix socket endpoints are identified by their path rather than a host and port.
+			if let Transport::Unix(path) = Transport::new(&client.url)
+			{
+				if !path.exists()
+				{
+					errors.push(format!("Client {} has a unix socket that does not exist: {}", name, path.display()));
+				}
+				if let Some(other) = addresses.insert((path.display().to_string(), 0), name.clone())
+				{
+					errors.push(format!("Clients {} and {} both use {}.", other, name, path.display()));
+				}
+				continue;
+			}
+
+			let url = match Url::parse(&client.url)
+			{
+				Ok(url) => url,
+				Err(e) =>
+				{
+					errors.push(format!("Client {} has an invalid endpoint {}: {}", name, client.url, e));
+					continue;
+				}
+			};
+
+			if url.scheme() != "http" && url.scheme() != "https"
+			{
+				errors.push(format!("Client {} has an unsupported endpoint scheme: {}", name, url.scheme()));
+				continue;
+			}
+
+			let (host, port) = match (url.host_str(), url.port_or_known_default())
+			{
+				(Some(host), Some(port)) => (host.to_string(), port),
+				_ =>
+				{
+					errors.push(format!("Client {} has an endpoint without a host and port: {}", name, client.url));
+					continue;
+				}
+			};
+
+			if let Some(other) = addresses.insert((host.clone(), port), name.clone())
+			{
+				errors.push(format!("Clients {} and {} both use {}:{}.", other, name, host, port));
+			}
+
+			let resolved = tokio::net::lookup_host((host.as_str(), port)).await;
+			if let Err(e) = resolved
+			{
+				errors.push(format!("Client {} has a host that could not be resolved: {}: {}", name, host, e));
+			}
+
+			if url.scheme() == "https"
+			{
+				if let Err(e) = endpoint::http_client(client)
+				{
+					errors.push(format!("Client {} has invalid TLS settings: {}", name, e));
+				}
+			}
+		}
+	}
+
+	errors
+}
+
+/// Prints the resolved fleet as a table, leaving out any clients excluded by the filter.
+pub fn print_fleet(groups: &[GroupSettings], filter: &ClientFilter)
+{
+	let mut number = 0;
+	for group in groups.iter()
+	{
+		println!("Group {} (rpc: {}, peers: {}, tip: {})", group.name, group.rpc_interval, group.peers_interval, group.tip_interval);
+		println!("  {:>6}  {:<20}  {:<40}  {:>14}  {:>9}", "Number", "Label", "Endpoint", "Max Block Diff", "Min Peers");
+
+		for client in group.clients.iter()
+		{
+			if filter.includes(number, client.label.as_deref())
+			{
+				let label = client.label.as_deref().unwrap_or("-");
+				println!("  {:>6}  {:<20}  {:<40}  {:>14}  {:>9}", number, label, client.url, client.max_block_diff, client.min_peers);
+			}
+			number += 1;
+		}
+
+		println!();
+	}
+}
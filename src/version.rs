@@ -0,0 +1,77 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// The release version of a client, parsed from the version string it reports, such as `0.3.6 (b6d0d9d 2024-01-24)`.
+/// Only the numeric components are kept, so builds of the same release compare as equal.
+#[derive(Clone, Debug)]
+pub struct Version
+{
+	components: Vec<u64>,
+}
+
+impl Version
+{
+	/// Parses the release version from the start of a version string, or returns `None` if it does not start with one.
+	pub fn parse(version: &str) -> Option<Self>
+	{
+		let release = version.split_whitespace().next()?;
+		let release = release.strip_prefix('v').unwrap_or(release);
+		let release = release.split(['-', '+']).next()?;
+
+		let components = release.split('.').map(|component| component.parse::<u64>().ok()).collect::<Option<Vec<_>>>()?;
+		Some(Self { components })
+	}
+
+	/// Returns the components with any trailing zeros removed, so `0.3` and `0.3.0` are equal.
+	fn significant(&self) -> &[u64]
+	{
+		let length = self.components.iter().rposition(|component| *component != 0).map_or(0, |index| index + 1);
+		&self.components[..length]
+	}
+}
+
+impl FromStr for Version
+{
+	type Err = String;
+
+	fn from_str(version: &str) -> Result<Self, Self::Err>
+	{
+		Self::parse(version).ok_or_else(|| format!("invalid version: {}", version))
+	}
+}
+
+impl PartialEq for Version
+{
+	fn eq(&self, other: &Self) -> bool
+	{
+		self.significant() == other.significant()
+	}
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version
+{
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+	{
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Version
+{
+	fn cmp(&self, other: &Self) -> Ordering
+	{
+		self.significant().cmp(other.significant())
+	}
+}
+
+impl fmt::Display for Version
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		let components = self.components.iter().map(|component| component.to_string()).collect::<Vec<_>>();
+		write!(f, "{}", components.join("."))
+	}
+}
@@ -0,0 +1,323 @@
+use crate::alerts::{self, Alert, AlertLimiter, Condition, RateLimit, Sink};
+use crate::dependency;
+use crate::digest;
+use crate::events::{MonitorEvent, Severity};
+use crate::fleet;
+use crate::halt;
+use crate::health::Health;
+use crate::quiet::QuietQueue;
+use crate::retry::RetryPolicy;
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// How long a webhook can take to accept a payload before the attempt fails.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The header that holds the signature of a payload when a webhook secret is configured.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Posts a JSON payload to each webhook whenever a client goes offline, comes back online, starts lagging, or catches
+/// up. The task is stopped when this is dropped.
+pub struct WebhookSink
+{
+	task: JoinHandle<()>,
+}
+
+impl WebhookSink
+{
+	/// Starts posting the alerts received from `alerts` to each of the URLs, retrying failed deliveries according to
+	/// the retry policy. Recoveries are left out if `mute_recoveries` is set, each payload is signed with `secret` if one
+	/// is given, and alerts during quiet hours are held by `quiet` until the next digest. Once the rate limit is reached,
+	/// the alerts held back are summarized when its window ends.
+	pub fn spawn(urls: Vec<String>, retry: RetryPolicy, mute_recoveries: bool, secret: Option<String>, mut quiet: QuietQueue, rate_limit: Option<RateLimit>, mut alerts: broadcast::Receiver<Alert>) -> Result<Self, reqwest::Error>
+	{
+		let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+		let task = tokio::spawn(async move
+		{
+			let mut limiter = AlertLimiter::new(Sink::Webhooks, rate_limit);
+			loop
+			{
+				let result = tokio::select!
+				{
+					result = alerts.recv() => result,
+					_ = limiter.summary_due() =>
+					{
+						let suppressed = limiter.end_window();
+						let payload = json!(
+						{
+							"event": "suppressed",
+							"severity": Severity::Warning.to_string(),
+							"details": { "count": suppressed },
+							"timestamp": Local::now().to_rfc3339(),
+							"message": limiter.summary_text(suppressed),
+						});
+						for url in urls.iter()
+						{
+							tokio::spawn(deliver(client.clone(), url.clone(), payload.clone(), secret.clone(), retry.clone()));
+						}
+						continue;
+					},
+				};
+				let alert = match result
+				{
+					Ok(alert) => alert,
+					Err(RecvError::Lagged(count)) =>
+					{
+						tracing::warn!("{} events were not sent to webhooks because they fell behind.", count);
+						continue;
+					},
+					Err(RecvError::Closed) => break,
+				};
+				let wanted = alert.is_routed_to(Sink::Webhooks) && !(mute_recoveries && alert.is_recovery());
+				let alert = match quiet.admit(alert, wanted)
+				{
+					Some(alert) => alert,
+					None => continue,
+				};
+				let payload = match payload(&alert)
+				{
+					Some(payload) => payload,
+					None => continue,
+				};
+				if !limiter.allow()
+				{
+					continue;
+				}
+
+				// Each delivery is retried on its own task, so a slow webhook does not hold up the others.
+				for url in urls.iter()
+				{
+					tokio::spawn(deliver(client.clone(), url.clone(), payload.clone(), secret.clone(), retry.clone()));
+				}
+			}
+		});
+
+		Ok(Self { task })
+	}
+}
+
+impl Drop for WebhookSink
+{
+	fn drop(&mut self)
+	{
+		self.task.abort();
+	}
+}
+
+/// Builds the payload posted to webhooks for an alert, holding the client, the type of event, its severity, its
+/// details, the time it was sent, and its text if it has a template. Digests and fleet conditions are about the whole
+/// fleet, so they have no client. Returns `None` for events that are not sent to webhooks.
+pub fn payload(alert: &Alert) -> Option<Value>
+{
+	let (client, event_type, details) = match &alert.event
+	{
+		MonitorEvent::AlertsAggregated { event_type, host, clients } if matches!(event_type.as_str(), "offline" | "online" | "lagging" | "caught_up" | "still_ongoing" | "escalated") =>
+		{
+			// Collapsed alerts are about many clients rather than one, so they have an incident for each client.
+			let incidents = aggregated_condition(event_type).map(|condition| clients.iter().map(|client| format!("{}/{}", client, condition.name())).collect::<Vec<_>>()).unwrap_or_default();
+			return Some(json!(
+			{
+				"clients": clients,
+				"event": "aggregated",
+				"severity": alert.severity.to_string(),
+				"details": { "event_type": event_type, "host": host },
+				"timestamp": Local::now().to_rfc3339(),
+				"resolved": alert.is_recovery(),
+				"incidents": incidents,
+				"message": alert.text.clone().unwrap_or_else(|| alerts::aggregated_text(event_type, host.as_deref(), clients)),
+			}));
+		},
+		MonitorEvent::Digest { since, incidents, uptime, laggards, degraded } =>
+		{
+			let uptime = uptime.iter().map(|(client, percent)| json!({ "client": client, "percent": percent })).collect::<Vec<_>>();
+			// The clients that are lagging or unhealthy carry the incident of their ongoing condition.
+			let laggards = laggards.iter().map(|(client, lag)| json!({ "client": client, "blocks_behind": lag, "incident": format!("{}/{}", client, Condition::Lagging.name()) })).collect::<Vec<_>>();
+			let degraded = degraded.iter().map(|(client, health)|
+			{
+				let condition = if *health == Health::Offline { Condition::Offline } else { Condition::Degraded };
+				json!({ "client": client, "health": health.to_string(), "incident": format!("{}/{}", client, condition.name()) })
+			}).collect::<Vec<_>>();
+			return Some(json!(
+			{
+				"event": "digest",
+				"severity": alert.severity.to_string(),
+				"details": { "since": since.to_rfc3339(), "incidents": incidents, "uptime": uptime, "laggards": laggards, "degraded": degraded },
+				"timestamp": Local::now().to_rfc3339(),
+				"resolved": alert.is_recovery(),
+				"message": alert.text.clone().unwrap_or_else(|| digest::lines(&alert.event).unwrap_or_default().join("\n")),
+			}));
+		},
+		MonitorEvent::FleetDegraded { condition, group, .. } | MonitorEvent::FleetRecovered { condition, group, .. } =>
+		{
+			// Fleet conditions are about many clients, so they have no client, and their incident is named after them.
+			let details = match &alert.event
+			{
+				MonitorEvent::FleetDegraded { reason, .. } => json!({ "condition": condition, "group": group, "reason": reason }),
+				MonitorEvent::FleetRecovered { duration_seconds, .. } => json!({ "condition": condition, "group": group, "duration_seconds": duration_seconds }),
+				_ => return None,
+			};
+			return Some(json!(
+			{
+				"event": alerts::event_type(&alert.event),
+				"severity": alert.severity.to_string(),
+				"details": details,
+				"timestamp": Local::now().to_rfc3339(),
+				"resolved": alert.is_recovery(),
+				"incident": format!("fleet/{}", condition),
+				"message": alert.text.clone().unwrap_or_else(|| fleet::text(&alert.event).unwrap_or_default()),
+			}));
+		},
+		MonitorEvent::ChainHalted { group, block_number, .. } | MonitorEvent::ChainResumed { group, block_number, .. } =>
+		{
+			let details = match &alert.event
+			{
+				MonitorEvent::ChainHalted { seconds, .. } => json!({ "group": group, "block_number": block_number, "seconds": seconds }),
+				MonitorEvent::ChainResumed { halted_seconds, .. } => json!({ "group": group, "block_number": block_number, "duration_seconds": halted_seconds }),
+				_ => return None,
+			};
+			return Some(json!(
+			{
+				"event": alerts::event_type(&alert.event),
+				"severity": alert.severity.to_string(),
+				"details": details,
+				"timestamp": Local::now().to_rfc3339(),
+				"resolved": alert.is_recovery(),
+				"incident": format!("{}/chain_halted", group),
+				"message": alert.text.clone().unwrap_or_else(|| halt::text(&alert.event).unwrap_or_default()),
+			}));
+		},
+		MonitorEvent::ParentDown { parent, .. } | MonitorEvent::ParentRecovered { parent, .. } =>
+		{
+			let details = match &alert.event
+			{
+				MonitorEvent::ParentDown { clients, .. } => json!({ "parent": parent, "clients": clients, "count": clients.len() }),
+				MonitorEvent::ParentRecovered { duration_seconds, .. } => json!({ "parent": parent, "duration_seconds": duration_seconds }),
+				_ => return None,
+			};
+			return Some(json!(
+			{
+				"event": alerts::event_type(&alert.event),
+				"severity": alert.severity.to_string(),
+				"details": details,
+				"timestamp": Local::now().to_rfc3339(),
+				"resolved": alert.is_recovery(),
+				"incident": parent.incident(),
+				"message": alert.text.clone().unwrap_or_else(|| dependency::text(&alert.event).unwrap_or_default()),
+			}));
+		},
+		MonitorEvent::HealthChanged { client, from, to, reason, duration_seconds } =>
+		{
+			let event_type = match (from, to)
+			{
+				(_, Health::Offline) => "offline",
+				(Health::Offline, _) => "online",
+				_ => return None,
+			};
+			(client, event_type, json!({ "from": from.to_string(), "to": to.to_string(), "reason": reason, "duration_seconds": duration_seconds }))
+		},
+		MonitorEvent::LagStarted { client, block_number, blocks_behind } => (client, "lagging", json!({ "block_number": block_number, "blocks_behind": blocks_behind })),
+		MonitorEvent::CaughtUp { client, block_number } => (client, "caught_up", json!({ "block_number": block_number })),
+		MonitorEvent::StillOngoing { client, condition, minutes } if matches!(condition, Condition::Offline | Condition::Lagging) => (client, "still_ongoing", json!({ "condition": condition.to_string(), "minutes": minutes })),
+		MonitorEvent::Escalated { client, condition, minutes, .. } if matches!(condition, Condition::Offline | Condition::Lagging) => (client, "escalated", json!({ "condition": condition.to_string(), "minutes": minutes })),
+		_ => return None,
+	};
+
+	let mut payload = json!(
+	{
+		"client": client,
+		"event": event_type,
+		"severity": alert.severity.to_string(),
+		"details": details,
+		"timestamp": Local::now().to_rfc3339(),
+		"resolved": alert.is_recovery(),
+	});
+	// The incident lets receivers pair the alert that a condition ended with the one that it started.
+	if let Some(condition) = alerts::condition(&alert.event)
+	{
+		payload["incident"] = Value::String(format!("{}/{}", client, condition.name()));
+	}
+	if let Some(text) = &alert.text
+	{
+		payload["message"] = Value::String(text.clone());
+	}
+
+	Some(payload)
+}
+
+/// Returns the condition that an aggregated type of event starts or ends, or `None` for reminders and escalations,
+/// which are collapsed whatever their condition.
+fn aggregated_condition(event_type: &str) -> Option<Condition>
+{
+	match event_type
+	{
+		"offline" | "online" => Some(Condition::Offline),
+		"lagging" | "caught_up" => Some(Condition::Lagging),
+		_ => None,
+	}
+}
+
+/// Returns the signature of a payload body under a secret, as `sha256=` followed by the HMAC-SHA256 of the body in hex.
+/// Receivers compute the same over the body they received and compare it with the signature header.
+pub fn signature(secret: &str, body: &[u8]) -> String
+{
+	let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+	mac.update(body);
+	let digest = mac.finalize().into_bytes();
+	format!("sha256={}", digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+/// Posts a payload to a webhook, retrying transport failures and error statuses according to the retry policy. If a
+/// secret is given, the payload is signed in the signature header. Only the origin of the URL is logged, since webhook
+/// URLs often hold a token.
+pub async fn deliver(client: reqwest::Client, url: String, payload: Value, secret: Option<String>, retry: RetryPolicy)
+{
+	// The body is serialized once, so the signature covers exactly the bytes that are sent.
+	let body = payload.to_string();
+	let signature = secret.map(|secret| signature(&secret, body.as_bytes()));
+
+	let mut attempt = 0;
+	loop
+	{
+		let mut request = client.post(&url).header(CONTENT_TYPE, "application/json").body(body.clone());
+		if let Some(signature) = &signature
+		{
+			request = request.header(SIGNATURE_HEADER, signature);
+		}
+
+		let error = match request.send().await
+		{
+			Ok(response) if response.status().is_success() => return,
+			Ok(response) => format!("HTTP status {}", response.status().as_u16()),
+			Err(e) => e.without_url().to_string(),
+		};
+		if attempt >= retry.attempts
+		{
+			tracing::error!("Failed to send an event to webhook {}: {}", redact(&url), error);
+			return;
+		}
+
+		let delay = retry.delay(attempt);
+		tracing::debug!("Sending an event to webhook {} failed: {}. Retrying in {} ms.", redact(&url), error, delay.as_millis());
+		time::sleep(delay).await;
+		attempt += 1;
+	}
+}
+
+/// Returns a URL with everything after its origin replaced, such as `https://discord.com/...`, so it can be logged
+/// without the token its path or query may hold.
+fn redact(url: &str) -> String
+{
+	match Url::parse(url)
+	{
+		Ok(url) => format!("{}/...", url.origin().ascii_serialization()),
+		Err(_) => "...".to_string(),
+	}
+}
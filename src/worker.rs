@@ -0,0 +1,423 @@
+use crate::checks::{self, Check, GENESIS_CHECK, PEERS_CHECK, RPC_CHECK, TIP_CHECK};
+use crate::client::{Client, ClientState};
+use crate::config::ClientSettings;
+use crate::health::Health;
+use crate::history::{History, RingBuffer};
+use crate::otlp::CycleTrace;
+use crate::propagation::PROPAGATION_WINDOW;
+use crate::rpc::Peer;
+use crate::scheduler::{Schedule, Timer};
+use crate::subscription::{TipSubscription, TipUpdate};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::Instrument;
+
+/// How long a client task waits before looking again when it has no checks enabled.
+const IDLE_DELAY: Duration = Duration::from_secs(60);
+
+/// The checks that run at the degraded interval, which are those that tell whether a client has recovered.
+const PROBE_CHECKS: [&str; 2] = [RPC_CHECK, TIP_CHECK];
+
+/// The schedules that each type of check on a client runs on.
+#[derive(Clone, Debug)]
+pub struct CheckSchedules
+{
+	/// The schedule of any check without a schedule of its own.
+	pub default: Schedule,
+	pub rpc: Schedule,
+	pub peers: Schedule,
+	pub tip: Schedule,
+	pub genesis: Schedule,
+	/// The fraction of each period that the checks are delayed by, so clients are not all checked at once.
+	pub offset: f64,
+	/// How often to check the client while it is offline or lagging, if more often than usual.
+	pub degraded_interval: Option<Duration>,
+}
+
+impl CheckSchedules
+{
+	/// Returns the schedule of the check with the specified name.
+	pub fn schedule(&self, check: &str) -> Schedule
+	{
+		match check
+		{
+			RPC_CHECK => self.rpc.clone(),
+			PEERS_CHECK => self.peers.clone(),
+			TIP_CHECK => self.tip.clone(),
+			GENESIS_CHECK => self.genesis.clone(),
+			_ => self.default.clone(),
+		}
+	}
+}
+
+/// A check on a client, along with the timer that tracks when it is next due.
+struct ScheduledCheck
+{
+	check: Arc<dyn Check>,
+	timer: Timer,
+}
+
+/// The results of a round of checks on a client, sent from its task to the aggregator.
+#[derive(Clone, Debug)]
+pub struct ClientReport
+{
+	pub group: String,
+	pub url: String,
+	pub state: ClientState,
+	/// The version string the client last reported, which is kept out of the state so it can stay `Copy`.
+	pub version: Option<String>,
+	/// The peers the client was connected to at its last peers check.
+	pub connected_peers: Vec<Peer>,
+	/// The timing of the round of checks, or `None` if no check was run, such as for a tip pushed by the client.
+	pub trace: Option<CycleTrace>,
+	/// Whether the tip was fetched this round, either by the tip check or by being pushed by the client. Other rounds
+	/// carry the tip from an earlier round.
+	pub tip_checked: bool,
+}
+
+/// A change sent to a running client task.
+enum ClientCommand
+{
+	Update
+	{
+		number: usize,
+		settings: Box<ClientSettings>,
+		schedules: CheckSchedules,
+	},
+	/// Whether the client is lagging behind the highest client in its group, which only the aggregator knows.
+	SetLagging(bool),
+}
+
+/// A handle to a client that is being checked by its own task. The task is stopped when the handle is dropped.
+pub struct ClientHandle
+{
+	pub number: usize,
+	pub group: String,
+	pub settings: ClientSettings,
+	/// The results of the most recent checks reported by the task, or `None` before the first report.
+	pub state: Option<ClientState>,
+	/// The recent results reported by the task, oldest first.
+	pub history: History,
+	/// Whether the client is behind and has not advanced for the number of cycles that counts as stalled.
+	pub stalled: bool,
+	/// The block number the client last reported while online.
+	pub unchanged_block: u64,
+	/// The number of consecutive online reports, ignoring offline ones, in which the client has reported the same block
+	/// number.
+	pub unchanged_cycles: usize,
+	/// Whether the client has reported the same block number for the number of cycles that counts as frozen.
+	pub frozen: bool,
+	/// The estimated number of minutes until the client catches up, as of the last lag report.
+	pub eta_minutes: Option<f64>,
+	/// The version string reported by the task, or `None` before the client has reported one.
+	pub version: Option<String>,
+	/// The peers reported by the task.
+	pub connected_peers: Vec<Peer>,
+	/// How long after the first client in the group the client reported each recent new block.
+	pub propagation_delays: RingBuffer<Duration>,
+	/// The highest block number the client has reported, used to tell when it reports a new block.
+	pub highest_reported: u64,
+	lagging: bool,
+	commands: mpsc::UnboundedSender<ClientCommand>,
+	task: JoinHandle<()>,
+}
+
+impl ClientHandle
+{
+	/// Spawns a task that checks the client on the specified schedules and sends its results to `reports`, keeping up to
+	/// `history_size` of them.
+	pub fn spawn(client: Client, schedules: CheckSchedules, reports: mpsc::Sender<ClientReport>, history_size: usize) -> Self
+	{
+		let (commands, command_receiver) = mpsc::unbounded_channel();
+		let span = tracing::info_span!("client", client = client.number, group = %client.group, endpoint = %client.settings.url);
+
+		Self
+		{
+			number: client.number,
+			group: client.group.clone(),
+			settings: client.settings.clone(),
+			state: None,
+			history: History::new(history_size),
+			stalled: false,
+			unchanged_block: 0,
+			unchanged_cycles: 0,
+			frozen: false,
+			eta_minutes: None,
+			version: None,
+			connected_peers: Vec::new(),
+			propagation_delays: RingBuffer::new(PROPAGATION_WINDOW),
+			highest_reported: 0,
+			lagging: false,
+			commands,
+			task: tokio::spawn(run(client, schedules, command_receiver, reports).instrument(span)),
+		}
+	}
+
+	/// Sends new settings and schedules to the task. The state of the client is preserved.
+	pub fn update(&mut self, number: usize, settings: ClientSettings, schedules: CheckSchedules)
+	{
+		self.number = number;
+		self.settings = settings.clone();
+		if self.commands.send(ClientCommand::Update { number, settings: Box::new(settings), schedules }).is_err()
+		{
+			tracing::error!("Client {} is no longer running and could not be updated.", self);
+		}
+	}
+
+	/// Tells the task whether the client is lagging, so it can be checked more often until it catches up.
+	pub fn set_lagging(&mut self, lagging: bool)
+	{
+		if lagging != self.lagging
+		{
+			self.lagging = lagging;
+			let _ = self.commands.send(ClientCommand::SetLagging(lagging));
+		}
+	}
+
+	/// Returns true if the aggregator considers the client to be lagging.
+	pub fn is_lagging(&self) -> bool
+	{
+		self.lagging
+	}
+
+	/// Stops the task, cancelling any check that is in progress.
+	pub fn stop(&self)
+	{
+		self.task.abort();
+	}
+
+	/// Returns the label of the client, or its number if it has no label.
+	pub fn name(&self) -> String
+	{
+		match &self.settings.label
+		{
+			Some(label) => label.clone(),
+			None => self.number.to_string(),
+		}
+	}
+}
+
+impl Drop for ClientHandle
+{
+	fn drop(&mut self)
+	{
+		self.stop();
+	}
+}
+
+impl fmt::Display for ClientHandle
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{}/{}", self.group, self.name())
+	}
+}
+
+/// Checks a client whenever one of its checks is due until the handle is dropped or the aggregator stops listening.
+/// While the client is offline or lagging, its rpc and tip checks also run at the degraded interval, if one is set.
+/// While a tip subscription is connected, tips pushed by the client replace the tip check.
+async fn run(mut client: Client, mut schedules: CheckSchedules, mut commands: mpsc::UnboundedReceiver<ClientCommand>, reports: mpsc::Sender<ClientReport>)
+{
+	let mut scheduled_checks = schedule_checks(&client, &schedules, Vec::new());
+	let mut lagging = false;
+	let mut next_probe: Option<Instant> = None;
+	let mut subscription = subscribe(&client);
+	let mut subscribed = false;
+	let mut cycle: u64 = 0;
+
+	loop
+	{
+		let next_check = scheduled_checks.iter().map(|scheduled| scheduled.timer.next_run()).min().unwrap_or_else(|| Instant::now() + IDLE_DELAY);
+		let next_run = next_probe.map_or(next_check, |next_probe| next_probe.min(next_check));
+
+		tokio::select!
+		{
+			_ = time::sleep_until(next_run.into()) =>
+			{
+				let now = Instant::now();
+				let is_probe = next_probe.is_some_and(|next_probe| next_probe <= now);
+				let due_checks = scheduled_checks.iter()
+					.filter(|scheduled| scheduled.timer.is_due(now) || (is_probe && PROBE_CHECKS.contains(&scheduled.check.name())))
+					.filter(|scheduled| !(subscribed && scheduled.check.name() == TIP_CHECK))
+					.map(|scheduled| scheduled.check.clone())
+					.collect::<Vec<_>>();
+
+				cycle += 1;
+				let start = SystemTime::now();
+				let spans = async
+				{
+					tracing::debug!("Checking client {}.", client);
+					client.run_checks(&due_checks).await
+				}.instrument(tracing::info_span!("checks", cycle)).await;
+				let tip_checked = due_checks.iter().any(|check| check.name() == TIP_CHECK);
+				let trace = Some(CycleTrace { cycle, start, end: SystemTime::now(), checks: spans }).filter(|trace| !trace.checks.is_empty());
+
+				let now = Instant::now();
+				for scheduled in scheduled_checks.iter_mut()
+				{
+					if scheduled.timer.is_due(now)
+					{
+						scheduled.timer.schedule_next(now);
+					}
+				}
+				next_probe = next_degraded_probe(&client, lagging, &schedules, now, next_probe);
+
+				let report = ClientReport { group: client.group.clone(), url: client.settings.url.clone(), state: client.state(), version: client.version.clone(), connected_peers: client.connected_peers.clone(), trace, tip_checked };
+				if reports.send(report).await.is_err()
+				{
+					break;
+				}
+			},
+			update = next_tip_update(&mut subscription) =>
+			{
+				match update
+				{
+					Some(TipUpdate::Connected) =>
+					{
+						tracing::info!("Client {} is pushing new tips. Tip polling is paused.", client);
+						subscribed = true;
+					},
+					Some(TipUpdate::Tip(header)) =>
+					{
+						client.update_tip(&header, None);
+						let report = ClientReport { group: client.group.clone(), url: client.settings.url.clone(), state: client.state(), version: client.version.clone(), connected_peers: client.connected_peers.clone(), trace: None, tip_checked: true };
+						if reports.send(report).await.is_err()
+						{
+							break;
+						}
+					},
+					Some(TipUpdate::Disconnected(e)) =>
+					{
+						if subscribed
+						{
+							tracing::warn!("Client {} lost its tip subscription ({}). Falling back to polling.", client, e);
+						}
+						else
+						{
+							tracing::debug!("Client {} could not subscribe to new tips: {}", client, e);
+						}
+						subscribed = false;
+					},
+					Some(TipUpdate::Unsupported(e)) =>
+					{
+						tracing::warn!("Client {} does not support tip subscriptions ({}). Falling back to polling.", client, e);
+						subscribed = false;
+						subscription = None;
+					},
+					None =>
+					{
+						subscribed = false;
+						subscription = None;
+					},
+				}
+			},
+			command = commands.recv() =>
+			{
+				match command
+				{
+					Some(ClientCommand::Update { number, settings, schedules: new_schedules }) =>
+					{
+						client.number = number;
+						client.update_settings(*settings);
+						if subscription.as_ref().map(|subscription| &subscription.url) != subscription_url(&client)
+						{
+							subscription = subscribe(&client);
+							subscribed = false;
+						}
+						schedules = new_schedules;
+						scheduled_checks = schedule_checks(&client, &schedules, scheduled_checks);
+						next_probe = next_degraded_probe(&client, lagging, &schedules, Instant::now(), None);
+					},
+					Some(ClientCommand::SetLagging(is_lagging)) =>
+					{
+						lagging = is_lagging;
+						next_probe = next_degraded_probe(&client, lagging, &schedules, Instant::now(), next_probe);
+					},
+					None => break,
+				}
+			}
+		}
+	}
+}
+
+/// Starts a tip subscription for the client if it has a subscription endpoint and runs the tip check.
+fn subscribe(client: &Client) -> Option<TipSubscription>
+{
+	subscription_url(client).cloned().map(TipSubscription::spawn)
+}
+
+/// Returns the URL to subscribe to new tips at, or `None` if the client has no subscription endpoint or tip check.
+fn subscription_url(client: &Client) -> Option<&String>
+{
+	client.settings.subscription.as_ref().filter(|_| client.settings.has_check(TIP_CHECK))
+}
+
+/// Waits for the next update from a tip subscription, or forever if there is none.
+async fn next_tip_update(subscription: &mut Option<TipSubscription>) -> Option<TipUpdate>
+{
+	match subscription
+	{
+		Some(subscription) => subscription.recv().await,
+		None => std::future::pending().await,
+	}
+}
+
+/// Returns the checks enabled for a client along with their timers. The timers of existing checks are kept, with their
+/// schedules updated, so a config reload does not reset when they are due.
+fn schedule_checks(client: &Client, schedules: &CheckSchedules, mut existing: Vec<ScheduledCheck>) -> Vec<ScheduledCheck>
+{
+	let mut scheduled_checks = Vec::new();
+
+	for name in client.settings.checks.iter()
+	{
+		let schedule = schedules.schedule(name);
+		match existing.iter().position(|scheduled| scheduled.check.name() == name)
+		{
+			Some(index) =>
+			{
+				let mut scheduled = existing.swap_remove(index);
+				scheduled.timer.set_schedule(schedule, schedules.offset);
+				scheduled_checks.push(scheduled);
+			},
+			None => match checks::find(name)
+			{
+				Some(check) => scheduled_checks.push(ScheduledCheck { check, timer: Timer::staggered(schedule, schedules.offset) }),
+				None => tracing::warn!("Client {} has an unknown check: {}", client, name),
+			},
+		}
+	}
+
+	scheduled_checks
+}
+
+/// Returns when a degraded client should next be probed, or `None` if it is healthy or has no degraded interval. A probe
+/// that is already scheduled is kept unless it has passed.
+fn next_degraded_probe(client: &Client, lagging: bool, schedules: &CheckSchedules, now: Instant, next_probe: Option<Instant>) -> Option<Instant>
+{
+	let degraded_interval = schedules.degraded_interval?;
+	if client.health.health() == Health::Online && !lagging
+	{
+		if next_probe.is_some()
+		{
+			tracing::debug!("Client {} has recovered. Returning to its normal schedule.", client);
+		}
+		return None;
+	}
+
+	match next_probe
+	{
+		Some(next_probe) if next_probe > now => Some(next_probe),
+		_ =>
+		{
+			if next_probe.is_none()
+			{
+				tracing::debug!("Client {} is degraded. Checking every {} seconds until it recovers.", client, degraded_interval.as_secs());
+			}
+			Some(now + degraded_interval)
+		}
+	}
+}